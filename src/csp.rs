@@ -0,0 +1,555 @@
+//! Restricting where elements can load resources from, using Content Security Policy
+//! directives, for [`Builder::content_security_policy`].
+//!
+//! [`Builder::content_security_policy`]: ../struct.Builder.html#method.content_security_policy
+
+use crate::url_policy::HostPattern;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use url::Url;
+
+/// A set of [Content Security Policy] fetch directives, which restrict where the elements in
+/// a fragment can load images, media, frames and plugins from.
+///
+/// It is written the same way as a `Content-Security-Policy` header, and each directive
+/// covers these attributes:
+///
+/// | Directive     | Attributes                                                              |
+/// |---------------|-------------------------------------------------------------------------|
+/// | `img-src`     | `img` `src` and `srcset`, `source` `srcset`, `video` `poster`, `input` `src`, `link` `imagesrcset`, SVG `image` `href`, `url()` in `style` |
+/// | `media-src`   | `audio`, `video`, `source` and `track` `src`                            |
+/// | `frame-src`   | `iframe` and `frame` `src`                                              |
+/// | `object-src`  | `object` `data`, `embed` `src`                                          |
+/// | `default-src` | all of the above, unless they have their own directive                  |
+///
+/// Other directives are ignored, the same way browsers ignore directives they don't know.
+/// Only attributes that hold URLs, as configured by [`url_attributes`], are checked, along
+/// with URLs in `style` attributes when [`filter_style_properties`] is set.
+///
+/// Each directive holds a list of sources:
+///
+/// * `'none'` matches nothing
+/// * `'self'` matches relative URLs that stay on the document's own host, since the
+///   sanitizer doesn't know the document's URL
+/// * `*` matches any `http`, `https`, `ws` or `wss` URL
+/// * a scheme, like `https:` or `data:`, matches any URL with that scheme
+/// * a host, like `https://cdn.example`, `*.example.com:*` or `example.com/images/`, matches
+///   URLs on that host, on the default port unless another one is given; a path ending in `/`
+///   matches everything under it, and any other path only matches itself
+///
+/// Like in browsers, an `http` source also matches `https`, and a host without a scheme
+/// matches both. Other quoted keywords, like `'unsafe-inline'`, don't apply to these
+/// directives and are ignored.
+///
+/// [Content Security Policy]: https://www.w3.org/TR/CSP3/
+/// [`url_attributes`]: struct.Builder.html#method.url_attributes
+/// [`filter_style_properties`]: struct.Builder.html#method.filter_style_properties
+///
+/// # Examples
+///
+///     use ammonia::{Builder, ContentSecurityPolicy};
+///
+///     # fn main() -> Result<(), ammonia::CspError> {
+///     let csp = ContentSecurityPolicy::parse("img-src https://cdn.example; media-src 'none'")?;
+///     let a = Builder::default()
+///         .content_security_policy(Some(csp))
+///         .clean("<img src=https://cdn.example/a.png><img src=https://example.com/b.png>")
+///         .to_string();
+///     assert_eq!(a, "<img src=\"https://cdn.example/a.png\"><img>");
+///     # Ok(())
+///     # }
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct ContentSecurityPolicy {
+    directives: Vec<(Directive, Vec<Source>)>,
+}
+
+/// What to do with an element when one of its URLs violates the
+/// [`content_security_policy`][content_security_policy].
+///
+/// [content_security_policy]: struct.Builder.html#method.content_security_policy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum CspViolationAction {
+    /// Remove the attribute, and keep the element. For a `srcset`, only the URL that
+    /// violates the policy is removed from the list.
+    RemoveAttribute,
+    /// Remove the element and everything inside it.
+    RemoveElement,
+}
+
+/// An error from parsing a [`ContentSecurityPolicy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CspError {
+    /// A source in one of the supported directives is not valid.
+    InvalidSource {
+        /// The name of the directive.
+        directive: String,
+        /// The source that could not be parsed.
+        source: String,
+    },
+}
+
+impl Display for CspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CspError::InvalidSource { directive, source } => {
+                write!(f, "`{source}` in `{directive}` is not a valid source")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CspError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Directive {
+    Default,
+    Img,
+    Media,
+    Frame,
+    Object,
+}
+
+impl Directive {
+    fn from_name(name: &str) -> Option<Directive> {
+        match &*name.to_ascii_lowercase() {
+            "default-src" => Some(Directive::Default),
+            "img-src" => Some(Directive::Img),
+            "media-src" => Some(Directive::Media),
+            "frame-src" => Some(Directive::Frame),
+            "object-src" => Some(Directive::Object),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Directive::Default => "default-src",
+            Directive::Img => "img-src",
+            Directive::Media => "media-src",
+            Directive::Frame => "frame-src",
+            Directive::Object => "object-src",
+        }
+    }
+
+    /// Returns the directive that covers a URL attribute, if any does.
+    fn for_attribute(element: &str, attribute: &str) -> Option<Directive> {
+        match (element, attribute) {
+            ("img", "src" | "srcset")
+            | ("source", "srcset")
+            | ("video", "poster")
+            | ("input", "src")
+            | ("link", "imagesrcset")
+            | ("image", "href")
+            | (_, "style") => Some(Directive::Img),
+            ("audio" | "video" | "source" | "track", "src") => Some(Directive::Media),
+            ("iframe" | "frame", "src") => Some(Directive::Frame),
+            ("object", "data") | ("embed", "src") => Some(Directive::Object),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Source {
+    None,
+    SelfOrigin,
+    Any,
+    Scheme(String),
+    Host {
+        scheme: Option<String>,
+        host: HostPattern,
+        port: Port,
+        path: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Port {
+    Default,
+    Any,
+    Exact(u16),
+}
+
+impl Source {
+    pub(crate) fn parse(source: &str) -> Option<Source> {
+        if source.starts_with('\'') {
+            return Some(match &*source.to_ascii_lowercase() {
+                "'none'" => Source::None,
+                "'self'" => Source::SelfOrigin,
+                // Nonces, hashes and the `unsafe-` keywords only apply to scripts and styles.
+                _ => return None,
+            });
+        }
+        if source == "*" {
+            return Some(Source::Any);
+        }
+        if let Some(scheme) = source.strip_suffix(':') {
+            return is_scheme(scheme).then(|| Source::Scheme(scheme.to_ascii_lowercase()));
+        }
+        let (scheme, rest) = match source.split_once("://") {
+            Some((scheme, rest)) if is_scheme(scheme) => (Some(scheme.to_ascii_lowercase()), rest),
+            Some(_) => return None,
+            None => (None, source),
+        };
+        let (host_port, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], Some(rest[i..].to_owned())),
+            None => (rest, None),
+        };
+        // IPv6 addresses contain colons, so the port can only come after the brackets.
+        let port_start = if host_port.starts_with('[') {
+            host_port.find(']')? + 1
+        } else {
+            0
+        };
+        let (host, port) = match host_port[port_start..].find(':') {
+            Some(i) => (
+                &host_port[..port_start + i],
+                match &host_port[port_start + i + 1..] {
+                    "*" => Port::Any,
+                    port if port.bytes().all(|b| b.is_ascii_digit()) => {
+                        Port::Exact(port.parse().ok()?)
+                    }
+                    _ => return None,
+                },
+            ),
+            None => (host_port, Port::Default),
+        };
+        Some(Source::Host {
+            scheme,
+            host: HostPattern::parse(host).ok()?,
+            port,
+            path,
+        })
+    }
+
+    pub(crate) fn matches(&self, url: &Url, same_origin: bool) -> bool {
+        match self {
+            Source::None => false,
+            Source::SelfOrigin => same_origin,
+            Source::Any => matches!(url.scheme(), "http" | "https" | "ws" | "wss"),
+            Source::Scheme(scheme) => scheme_matches(scheme, url.scheme()),
+            Source::Host {
+                scheme,
+                host,
+                port,
+                path,
+            } => {
+                let scheme_ok = match scheme {
+                    Some(scheme) => scheme_matches(scheme, url.scheme()),
+                    None => matches!(url.scheme(), "http" | "https"),
+                };
+                let port_ok = match *port {
+                    Port::Default => url.port().is_none(),
+                    Port::Any => true,
+                    Port::Exact(port) => url.port_or_known_default() == Some(port),
+                };
+                let path_ok = match path {
+                    Some(path) if path.ends_with('/') => url.path().starts_with(&path[..]),
+                    Some(path) => url.path() == path,
+                    None => true,
+                };
+                scheme_ok
+                    && port_ok
+                    && path_ok
+                    && url.host().is_some_and(|h| host.matches(&h, None))
+            }
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::None => f.write_str("'none'"),
+            Source::SelfOrigin => f.write_str("'self'"),
+            Source::Any => f.write_str("*"),
+            Source::Scheme(scheme) => write!(f, "{scheme}:"),
+            Source::Host {
+                scheme,
+                host,
+                port,
+                path,
+            } => {
+                if let Some(scheme) = scheme {
+                    write!(f, "{scheme}://")?;
+                }
+                write!(f, "{host}")?;
+                match port {
+                    Port::Default => {}
+                    Port::Any => f.write_str(":*")?,
+                    Port::Exact(port) => write!(f, ":{port}")?,
+                }
+                f.write_str(path.as_deref().unwrap_or(""))
+            }
+        }
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+}
+
+/// Like browsers, insecure sources also match their secure upgrades.
+fn scheme_matches(source: &str, url: &str) -> bool {
+    source == url || matches!((source, url), ("http", "https") | ("ws", "wss"))
+}
+
+impl ContentSecurityPolicy {
+    /// Parses a policy, written the same way as a `Content-Security-Policy` header.
+    ///
+    /// If a directive appears more than once, only the first one counts.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{ContentSecurityPolicy, CspError};
+    ///
+    ///     assert!(ContentSecurityPolicy::parse("frame-src https://www.youtube-nocookie.com").is_ok());
+    ///     assert_eq!(
+    ///         ContentSecurityPolicy::parse("img-src https://example.com:http"),
+    ///         Err(CspError::InvalidSource {
+    ///             directive: "img-src".to_string(),
+    ///             source: "https://example.com:http".to_string(),
+    ///         }),
+    ///     );
+    pub fn parse(policy: &str) -> Result<Self, CspError> {
+        let mut directives: Vec<(Directive, Vec<Source>)> = Vec::new();
+        for directive in policy.split(';') {
+            let mut tokens = directive.split_ascii_whitespace();
+            let Some(directive) = tokens.next().and_then(Directive::from_name) else {
+                continue;
+            };
+            if directives.iter().any(|(d, _)| *d == directive) {
+                continue;
+            }
+            let mut sources = Vec::new();
+            for token in tokens {
+                match Source::parse(token) {
+                    // An empty list already matches nothing.
+                    Some(Source::None) => {}
+                    Some(source) => sources.push(source),
+                    None if token.starts_with('\'') => {}
+                    None => {
+                        return Err(CspError::InvalidSource {
+                            directive: directive.name().to_owned(),
+                            source: token.to_owned(),
+                        })
+                    }
+                }
+            }
+            directives.push((directive, sources));
+        }
+        Ok(ContentSecurityPolicy { directives })
+    }
+
+    /// Checks a URL in an attribute against the directive that covers it.
+    ///
+    /// `same_origin` is `true` for relative URLs that stay on the document's own host.
+    /// Returns the name of the directive if the URL violates it.
+    pub(crate) fn check(
+        &self,
+        element: &str,
+        attribute: &str,
+        url: &Url,
+        same_origin: bool,
+    ) -> Option<&'static str> {
+        let directive = Directive::for_attribute(element, attribute)?;
+        let (directive, sources) = self
+            .directives
+            .iter()
+            .find(|(d, _)| *d == directive)
+            .or_else(|| {
+                self.directives
+                    .iter()
+                    .find(|(d, _)| *d == Directive::Default)
+            })?;
+        if sources
+            .iter()
+            .any(|source| source.matches(url, same_origin))
+        {
+            None
+        } else {
+            Some(directive.name())
+        }
+    }
+}
+
+impl FromStr for ContentSecurityPolicy {
+    type Err = CspError;
+    fn from_str(policy: &str) -> Result<Self, CspError> {
+        ContentSecurityPolicy::parse(policy)
+    }
+}
+
+impl Display for ContentSecurityPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (directive, sources)) in self.directives.iter().enumerate() {
+            if i != 0 {
+                f.write_str("; ")?;
+            }
+            f.write_str(directive.name())?;
+            if sources.is_empty() {
+                f.write_str(" 'none'")?;
+            }
+            for source in sources {
+                write!(f, " {source}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for ContentSecurityPolicy {
+    type Error = CspError;
+    fn try_from(policy: String) -> Result<Self, CspError> {
+        ContentSecurityPolicy::parse(&policy)
+    }
+}
+
+impl From<ContentSecurityPolicy> for String {
+    fn from(policy: ContentSecurityPolicy) -> String {
+        policy.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(policy: &str, element: &str, attribute: &str, url: &str) -> Option<&'static str> {
+        let policy = ContentSecurityPolicy::parse(policy).unwrap();
+        policy.check(element, attribute, &Url::parse(url).unwrap(), false)
+    }
+
+    #[test]
+    fn directives() {
+        let policy = "default-src 'none'; img-src https://cdn.example; frame-src *";
+        assert_eq!(
+            check(policy, "img", "src", "https://cdn.example/a.png"),
+            None
+        );
+        assert_eq!(
+            check(policy, "img", "srcset", "https://example.com/a.png"),
+            Some("img-src")
+        );
+        assert_eq!(check(policy, "iframe", "src", "https://example.com/"), None);
+        assert_eq!(
+            check(policy, "video", "src", "https://cdn.example/a.webm"),
+            Some("default-src")
+        );
+        assert_eq!(check(policy, "a", "href", "https://example.com/"), None);
+        // Only the first of each directive counts.
+        assert_eq!(
+            check(
+                "img-src 'none'; img-src *",
+                "img",
+                "src",
+                "https://example.com/"
+            ),
+            Some("img-src")
+        );
+        assert_eq!(
+            check("script-src 'none'", "img", "src", "https://example.com/"),
+            None
+        );
+    }
+
+    #[test]
+    fn sources() {
+        let img = |policy, url| check(policy, "img", "src", url).is_none();
+        assert!(img("img-src https:", "https://example.com/"));
+        assert!(!img("img-src https:", "http://example.com/"));
+        assert!(img("img-src http:", "https://example.com/"));
+        assert!(img("img-src data:", "data:image/png;base64,AAAA"));
+        assert!(!img("img-src *", "data:image/png;base64,AAAA"));
+        assert!(img("img-src *.Example.com", "http://a.example.com/"));
+        assert!(!img("img-src *.example.com", "https://example.com/"));
+        assert!(!img("img-src example.com", "ftp://example.com/"));
+        assert!(!img("img-src example.com", "https://example.com:8443/"));
+        assert!(img("img-src example.com:8443", "https://example.com:8443/"));
+        assert!(img("img-src example.com:*", "https://example.com:8443/"));
+        assert!(img(
+            "img-src https://example.com:443",
+            "https://example.com/"
+        ));
+        assert!(img(
+            "img-src bücher.example",
+            "https://xn--bcher-kva.example/"
+        ));
+        assert!(img(
+            "img-src example.com/img/",
+            "https://example.com/img/a.png"
+        ));
+        assert!(!img(
+            "img-src example.com/img/",
+            "https://example.com/a.png"
+        ));
+        assert!(img(
+            "img-src example.com/a.png",
+            "https://example.com/a.png"
+        ));
+        assert!(!img(
+            "img-src example.com/a.png",
+            "https://example.com/a.png/b"
+        ));
+        assert!(img("img-src [::1]:8080", "http://[::1]:8080/"));
+        assert!(img(
+            "img-src 'unsafe-inline' https:",
+            "https://example.com/"
+        ));
+    }
+
+    #[test]
+    fn same_origin() {
+        let policy = ContentSecurityPolicy::parse("img-src 'self'").unwrap();
+        let url = Url::parse("https://relative.invalid/a.png").unwrap();
+        assert_eq!(policy.check("img", "src", &url, true), None);
+        assert_eq!(policy.check("img", "src", &url, false), Some("img-src"));
+    }
+
+    #[test]
+    fn invalid_sources() {
+        for source in [
+            "1http:",
+            "https://",
+            "example.com:x",
+            "-a://b",
+            "[::1",
+            "a.com:99999",
+        ] {
+            assert!(
+                ContentSecurityPolicy::parse(&format!("img-src {source}")).is_err(),
+                "{source}"
+            );
+        }
+        // Unknown directives aren't parsed at all.
+        assert!(ContentSecurityPolicy::parse("report-uri /csp-report;;").is_ok());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let policy = ContentSecurityPolicy::parse(
+            "IMG-SRC 'self' HTTPS: *.Bücher.example:* https://[::1]:8080/a/; media-src; report-uri /x",
+        )
+        .unwrap();
+        let output = policy.to_string();
+        assert_eq!(
+            output,
+            "img-src 'self' https: *.xn--bcher-kva.example:* https://[::1]:8080/a/; media-src 'none'"
+        );
+        assert_eq!(ContentSecurityPolicy::parse(&output).unwrap(), policy);
+    }
+}
@@ -0,0 +1,140 @@
+//! Sanitizing CSS declarations outside of HTML, for [`Builder::css_sanitizer`].
+//!
+//! [`Builder::css_sanitizer`]: ../struct.Builder.html#method.css_sanitizer
+
+use std::fmt::{self, Display};
+
+use crate::style::StyleRejection;
+use crate::Builder;
+
+/// Sanitizes CSS declaration lists that aren't in HTML, like a "custom style" field, the
+/// same way as the `style` attributes cleaned by a [`Builder`].
+///
+/// Created by [`Builder::css_sanitizer`].
+///
+/// [`Builder::css_sanitizer`]: struct.Builder.html#method.css_sanitizer
+#[derive(Clone, Copy, Debug)]
+pub struct CssSanitizer<'b, 'a> {
+    builder: &'b Builder<'a>,
+}
+
+/// A declaration that was removed by a [`CssSanitizer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RejectedDeclaration {
+    /// Its property is not in [`filter_style_properties`][filter_style_properties].
+    ///
+    /// [filter_style_properties]: struct.Builder.html#method.filter_style_properties
+    Property {
+        /// The name of the rejected property.
+        property: String,
+    },
+    /// Its value doesn't match the rule for its property in
+    /// [`style_value_rules`][style_value_rules].
+    ///
+    /// [style_value_rules]: struct.Builder.html#method.style_value_rules
+    Value {
+        /// The name of the property.
+        property: String,
+        /// The rejected value, as it was in the input.
+        value: String,
+    },
+    /// A URL in its value was rejected by the same checks as URL attributes, like
+    /// [`url_schemes`][url_schemes].
+    ///
+    /// [url_schemes]: struct.Builder.html#method.url_schemes
+    Url {
+        /// The name of the property.
+        property: String,
+        /// The rejected URL, as it was in the input.
+        url: String,
+    },
+}
+
+impl Display for RejectedDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectedDeclaration::Property { property } => {
+                write!(f, "the `{property}` property is not allowed")
+            }
+            RejectedDeclaration::Value { property, .. } => {
+                write!(f, "the value of the `{property}` property is not allowed")
+            }
+            RejectedDeclaration::Url { property, .. } => {
+                write!(f, "the URL in the `{property}` property is not allowed")
+            }
+        }
+    }
+}
+
+impl From<StyleRejection<'_>> for RejectedDeclaration {
+    fn from(rejection: StyleRejection<'_>) -> Self {
+        match rejection {
+            StyleRejection::Property(property) => RejectedDeclaration::Property {
+                property: property.to_string(),
+            },
+            StyleRejection::Value { property, value } => RejectedDeclaration::Value {
+                property: property.to_string(),
+                value: value.to_string(),
+            },
+            StyleRejection::Url { property, url } => RejectedDeclaration::Url {
+                property: property.to_string(),
+                url: url.to_string(),
+            },
+        }
+    }
+}
+
+impl<'b, 'a> CssSanitizer<'b, 'a> {
+    pub(crate) fn new(builder: &'b Builder<'a>) -> Self {
+        CssSanitizer { builder }
+    }
+
+    /// Sanitizes a declaration list, like `color: red; width: 10px`, and returns it
+    /// normalised.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     use maplit::hashset;
+    ///
+    ///     let mut builder = Builder::default();
+    ///     builder.filter_style_properties(hashset!["color"]);
+    ///     let css = builder.css_sanitizer().clean("color : red; position: fixed; }");
+    ///     assert_eq!(css, "color:red");
+    pub fn clean(&self, css: &str) -> String {
+        self.clean_with_rejections(css).0
+    }
+
+    /// Sanitizes a declaration list, and also returns the well-formed declarations that
+    /// were removed, in order. Malformed declarations are dropped without being listed.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, RejectedDeclaration, StyleValueRule};
+    ///     use maplit::hashset;
+    ///
+    ///     let mut builder = Builder::default();
+    ///     builder
+    ///         .filter_style_properties(hashset!["width", "background"])
+    ///         .style_value_rule("width", StyleValueRule::new().numbers(0.0, 800.0, ["px"]));
+    ///     let (css, rejected) = builder
+    ///         .css_sanitizer()
+    ///         .clean_with_rejections("width: 9999px; background: url(javascript:x); top: 0");
+    ///     assert_eq!(css, "");
+    ///     assert_eq!(rejected, [
+    ///         RejectedDeclaration::Value { property: "width".into(), value: "9999px".into() },
+    ///         RejectedDeclaration::Url { property: "background".into(), url: "javascript:x".into() },
+    ///         RejectedDeclaration::Property { property: "top".into() },
+    ///     ]);
+    pub fn clean_with_rejections(&self, css: &str) -> (String, Vec<RejectedDeclaration>) {
+        let mut rejected = Vec::new();
+        let css = self.builder.clean_style_declarations(
+            "style",
+            css,
+            &mut |rejection, _| rejected.push(rejection.into()),
+            &mut None,
+        );
+        (css, rejected)
+    }
+}
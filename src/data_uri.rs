@@ -0,0 +1,352 @@
+//! Checking `data:` URLs, for [`Builder::data_uri_policy`].
+//!
+//! https://fetch.spec.whatwg.org/#data-urls
+//!
+//! [`Builder::data_uri_policy`]: ../struct.Builder.html#method.data_uri_policy
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use url::{Position, Url};
+
+/// Which `data:` URLs are allowed, by [`Builder::data_uri_policy`].
+///
+/// [`Builder::data_uri_policy`]: struct.Builder.html#method.data_uri_policy
+///
+/// # Examples
+///
+///     use ammonia::DataUriPolicy;
+///
+///     let policy = DataUriPolicy::new()
+///         .mime_types(["image/png", "image/jpeg"])
+///         .max_size(64 * 1024)
+///         .check_magic_bytes(true);
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct DataUriPolicy {
+    mime_types: BTreeSet<String>,
+    max_size: usize,
+    check_magic_bytes: bool,
+}
+
+/// Why a `data:` URL was rejected by a [`DataUriPolicy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DataUriRejection {
+    /// The MIME type, which is included here, is not in the allowlist.
+    MimeType(String),
+    /// The decoded data is larger than the maximum size.
+    TooLarge,
+    /// The base64 or percent-encoded data could not be decoded.
+    InvalidEncoding,
+    /// The data doesn't start with the magic bytes of its MIME type.
+    MagicBytes,
+}
+
+impl Display for DataUriRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataUriRejection::MimeType(mime_type) => {
+                write!(f, "the `{mime_type}` type is not allowed")
+            }
+            DataUriRejection::TooLarge => f.write_str("it is too large"),
+            DataUriRejection::InvalidEncoding => f.write_str("it is not encoded correctly"),
+            DataUriRejection::MagicBytes => f.write_str("its content doesn't match its type"),
+        }
+    }
+}
+
+impl Default for DataUriPolicy {
+    fn default() -> Self {
+        DataUriPolicy {
+            mime_types: ["image/gif", "image/jpeg", "image/png", "image/webp"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            max_size: 1024 * 1024,
+            check_magic_bytes: true,
+        }
+    }
+}
+
+impl DataUriPolicy {
+    /// Allows PNG, JPEG, GIF and WebP images up to 1 MiB, and checks their magic bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the MIME types that are allowed, like `image/png`.
+    ///
+    /// Parameters, like the `charset` in `text/plain;charset=utf-8`, are ignored when
+    /// comparing types. Be careful with types that can run scripts when they're opened,
+    /// like `text/html` or `image/svg+xml`.
+    ///
+    /// # Defaults
+    ///
+    /// ```notest
+    /// image/gif, image/jpeg, image/png, image/webp
+    /// ```
+    pub fn mime_types<'b, I: IntoIterator<Item = &'b str>>(mut self, value: I) -> Self {
+        self.mime_types = value
+            .into_iter()
+            .map(|mime_type| mime_type.to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Sets the largest allowed size of the decoded data, in bytes.
+    ///
+    /// # Defaults
+    ///
+    /// 1 MiB
+    pub fn max_size(mut self, value: usize) -> Self {
+        self.max_size = value;
+        self
+    }
+
+    /// Checks that the data starts with the magic bytes, or file signature, of its MIME type.
+    ///
+    /// The signatures of GIF, JPEG, PNG, WebP, AVIF, BMP and ICO images are known.
+    /// If this is on, data of any other type is rejected, because it can't be checked.
+    ///
+    /// # Defaults
+    ///
+    /// `true`
+    pub fn check_magic_bytes(mut self, value: bool) -> Self {
+        self.check_magic_bytes = value;
+        self
+    }
+
+    /// Checks a parsed `data:` URL against the policy.
+    pub(crate) fn check(&self, url: &Url) -> Result<(), DataUriRejection> {
+        // The fragment isn't part of the data.
+        let (header, body) = url[Position::BeforePath..Position::AfterQuery]
+            .split_once(',')
+            .ok_or(DataUriRejection::InvalidEncoding)?;
+        let (mime_type, base64) = parse_header(header);
+        if !self.mime_types.contains(&mime_type) {
+            return Err(DataUriRejection::MimeType(mime_type));
+        }
+        let mut data = percent_decode(body);
+        if base64 {
+            // Every 4 characters of base64 are 3 bytes, so this is enough to rule out
+            // huge payloads without decoding them.
+            if data.len() / 4 * 3 > self.max_size.saturating_add(3) {
+                return Err(DataUriRejection::TooLarge);
+            }
+            data = base64_decode(&data).ok_or(DataUriRejection::InvalidEncoding)?;
+        }
+        if data.len() > self.max_size {
+            return Err(DataUriRejection::TooLarge);
+        }
+        if self.check_magic_bytes && !has_magic_bytes(&mime_type, &data) {
+            return Err(DataUriRejection::MagicBytes);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the lowercase MIME type, without parameters, and whether the data is base64.
+fn parse_header(header: &str) -> (String, bool) {
+    let mut header = header.trim_matches(is_whitespace);
+    let mut base64 = false;
+    if let Some((rest, last)) = header.rsplit_once(';') {
+        if last
+            .trim_matches(is_whitespace)
+            .eq_ignore_ascii_case("base64")
+        {
+            base64 = true;
+            header = rest;
+        }
+    }
+    let essence = header.split(';').next().unwrap_or("");
+    let mut mime_type = essence.trim_matches(is_whitespace).to_ascii_lowercase();
+    if mime_type.is_empty() {
+        mime_type = "text/plain".to_owned();
+    }
+    (mime_type, base64)
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0c' | '\r' | ' ')
+}
+
+fn percent_decode(input: &str) -> Vec<u8> {
+    let input = input.as_bytes();
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let hex = input
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (input[i], hex) {
+            (b'%', Some(byte)) => {
+                output.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+/// Decodes base64 the same way browsers do, ignoring whitespace and optional padding.
+///
+/// https://infra.spec.whatwg.org/#forgiving-base64-decode
+fn base64_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut input: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|&b| !is_whitespace(char::from(b)))
+        .collect();
+    if input.len() % 4 == 0 {
+        for _ in 0..2 {
+            if input.last() == Some(&b'=') {
+                input.pop();
+            }
+        }
+    }
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() / 4 * 3 + 2);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for b in input {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+fn has_magic_bytes(mime_type: &str, data: &[u8]) -> bool {
+    match mime_type {
+        "image/png" => data.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "image/jpeg" => data.starts_with(b"\xff\xd8\xff"),
+        "image/gif" => data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a"),
+        "image/webp" => data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP"),
+        "image/avif" => {
+            data.get(4..8) == Some(b"ftyp") && matches!(data.get(8..12), Some(b"avif" | b"avis"))
+        }
+        "image/bmp" => data.starts_with(b"BM"),
+        "image/x-icon" | "image/vnd.microsoft.icon" => data.starts_with(b"\0\0\x01\0"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+
+    fn check(policy: &DataUriPolicy, url: &str) -> Result<(), DataUriRejection> {
+        policy.check(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn images() {
+        let policy = DataUriPolicy::new();
+        assert_eq!(
+            check(&policy, &format!("data:image/png;base64,{PNG}")),
+            Ok(())
+        );
+        assert_eq!(
+            check(&policy, &format!("DATA:Image/PNG ; BASE64 ,{PNG}#x")),
+            Ok(())
+        );
+        assert_eq!(
+            check(
+                &policy,
+                &format!("data:image/png;base64,\n{}\n{}", &PNG[..40], &PNG[40..])
+            ),
+            Ok(())
+        );
+        assert_eq!(check(&policy, "data:image/gif,GIF89a%01%00"), Ok(()));
+    }
+
+    #[test]
+    fn rejections() {
+        let policy = DataUriPolicy::new();
+        assert_eq!(
+            check(
+                &policy,
+                "data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg=="
+            ),
+            Err(DataUriRejection::MimeType("text/html".to_string()))
+        );
+        assert_eq!(
+            check(&policy, "data:image/svg+xml,<svg onload=alert(1)>"),
+            Err(DataUriRejection::MimeType("image/svg+xml".to_string()))
+        );
+        assert_eq!(
+            check(&policy, "data:,hello"),
+            Err(DataUriRejection::MimeType("text/plain".to_string()))
+        );
+        assert_eq!(
+            check(&policy, "data:image/png;base64,iVBORw0KGgo!"),
+            Err(DataUriRejection::InvalidEncoding)
+        );
+        assert_eq!(
+            check(&policy, "data:image/png;base64,iVBORw0KGgoA="),
+            Err(DataUriRejection::InvalidEncoding)
+        );
+        assert_eq!(
+            check(&policy, "data:image/png;base64,PHNjcmlwdD4="),
+            Err(DataUriRejection::MagicBytes)
+        );
+        assert_eq!(
+            check(
+                &policy.clone().max_size(10),
+                &format!("data:image/png;base64,{PNG}")
+            ),
+            Err(DataUriRejection::TooLarge)
+        );
+        assert_eq!(
+            check(
+                &policy.clone().max_size(usize::MAX),
+                &format!("data:image/png;base64,{PNG}")
+            ),
+            Ok(())
+        );
+        let policy = policy.mime_types(["text/plain"]);
+        assert_eq!(
+            check(&policy, "data:,hello"),
+            Err(DataUriRejection::MagicBytes)
+        );
+        assert_eq!(
+            check(&policy.check_magic_bytes(false), "data:,hello"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(base64_decode(b"aGk="), Some(b"hi".to_vec()));
+        assert_eq!(base64_decode(b"aGk"), Some(b"hi".to_vec()));
+        assert_eq!(base64_decode(b" a G k = "), Some(b"hi".to_vec()));
+        assert_eq!(base64_decode(b"aGk=="), None);
+        assert_eq!(base64_decode(b"a"), None);
+        assert_eq!(base64_decode(b"a=Gk"), None);
+        assert_eq!(base64_decode(b""), Some(Vec::new()));
+    }
+}
@@ -0,0 +1,376 @@
+//! Escapers for putting untrusted text into contexts that [`clean_text`] doesn't cover.
+//!
+//! [`clean_text`]: ../fn.clean_text.html
+
+use std::fmt::Write;
+
+/// Turn an arbitrary string into the contents of a JavaScript string literal.
+///
+/// The result can go between single quotes, double quotes or backticks, in a `<script>` tag
+/// or in a quoted event handler attribute. ASCII letters, digits, spaces, `,`, `.` and `_`
+/// are passed through, and so is all non-ASCII text except the line separators U+2028 and
+/// U+2029. Everything else is written as a `\xHH` or `\uHHHH` escape, so the result never
+/// contains a quote, a backslash, a line break or anything that HTML treats specially, like
+/// `</script>` or `<!--`.
+///
+/// # Warnings
+///
+/// This only protects the string literal. The result must be quoted, and it must not be
+/// passed to `eval`, `innerHTML` or similar, which would interpret it again.
+///
+/// # Examples
+///
+///     use ammonia::escape_js_string;
+///
+///     let untrusted = "Robert\"); abuse();//</script>";
+///     let html = format!("<script>invoke(\"{}\")</script>", escape_js_string(untrusted));
+///     assert_eq!(
+///         html,
+///         "<script>invoke(\"Robert\\x22\\x29\\x3b abuse\\x28\\x29\\x3b\\x2f\\x2f\\x3c\\x2fscript\\x3e\")</script>"
+///     );
+pub fn escape_js_string(src: &str) -> String {
+    let mut ret_val = String::with_capacity(src.len());
+    for c in src.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | ' ' | ',' | '.' | '_' => ret_val.push(c),
+            '\0'..='\x7f' => write!(ret_val, "\\x{:02x}", c as u32).unwrap(),
+            // these end a line, even inside a string, in older JavaScript engines
+            '\u{2028}' | '\u{2029}' => write!(ret_val, "\\u{:04x}", c as u32).unwrap(),
+            _ => ret_val.push(c),
+        }
+    }
+    ret_val
+}
+
+/// Turn an arbitrary string into the contents of a CSS string.
+///
+/// The result can go between single or double quotes, in a `<style>` tag or in a quoted
+/// `style` attribute. ASCII letters, digits and spaces are passed through, and so is all
+/// non-ASCII text. Everything else is written as a hexadecimal escape followed by a space,
+/// which ends the escape and is not part of the string.
+///
+/// # Warnings
+///
+/// This only protects the string. Putting the result in a `url()` or an unquoted value,
+/// or using it as a property name, is not safe.
+///
+/// # Examples
+///
+///     use ammonia::escape_css_string;
+///
+///     let untrusted = "\"; } body { display: none";
+///     let html = format!("<style>q::before {{ content: \"{}\" }}</style>", escape_css_string(untrusted));
+///     assert_eq!(
+///         html,
+///         "<style>q::before { content: \"\\22 \\3b  \\7d  body \\7b  display\\3a  none\" }</style>"
+///     );
+pub fn escape_css_string(src: &str) -> String {
+    let mut ret_val = String::with_capacity(src.len());
+    for c in src.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | ' ' => ret_val.push(c),
+            // CSS reads an escaped NUL as U+FFFD anyway, so say so
+            '\0' => ret_val.push_str("\\fffd "),
+            '\x01'..='\x7f' => write!(ret_val, "\\{:x} ", c as u32).unwrap(),
+            _ => ret_val.push(c),
+        }
+    }
+    ret_val
+}
+
+/// Turn an arbitrary string into a URL component, like a query parameter or a path segment.
+///
+/// Every byte of the UTF-8 encoded string is percent-encoded, except ASCII letters, digits,
+/// `-`, `.`, `_` and `~`. This is stricter than JavaScript's `encodeURIComponent`, which
+/// also leaves `!`, `'`, `(`, `)` and `*` alone, so the result can also go in an unquoted
+/// attribute.
+///
+/// # Warnings
+///
+/// This does not make a whole URL safe. Untrusted text at the start of an `href` can still
+/// pick the scheme, like `javascript:`, so only use this for the parts that come after a
+/// trusted prefix.
+///
+/// # Examples
+///
+///     use ammonia::escape_url_component;
+///
+///     let untrusted = "rust & \"ammonia\"";
+///     let html = format!("<a href=\"/search?q={}\">search</a>", escape_url_component(untrusted));
+///     assert_eq!(html, "<a href=\"/search?q=rust%20%26%20%22ammonia%22\">search</a>");
+pub fn escape_url_component(src: &str) -> String {
+    let mut ret_val = String::with_capacity(src.len());
+    for byte in src.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                ret_val.push(byte as char)
+            }
+            _ => write!(ret_val, "%{:02X}", byte).unwrap(),
+        }
+    }
+    ret_val
+}
+
+/// Turn an arbitrary string into the value of a quoted HTML attribute.
+///
+/// Unlike [`clean_text`], which also has to work in unquoted attributes, this only escapes
+/// the characters that matter between quotes: `&`, both kinds of quote, `<`, `>`, carriage
+/// returns and NUL.
+/// Spaces, `/`, `=` and everything else stay readable. The result works between single or
+/// double quotes, and as element text.
+///
+/// # Warnings
+///
+/// The attribute value must be quoted. Like [`clean_text`], this does not make text safe for
+/// attributes with their own syntax, like `href`, `style`, `srcset` or event handlers.
+///
+/// [`clean_text`]: fn.clean_text.html
+///
+/// # Examples
+///
+///     use ammonia::escape_quoted_attribute;
+///
+///     let untrusted = "Tom & Jerry's \"show\" <3";
+///     let html = format!("<abbr title=\"{}\">T&amp;J</abbr>", escape_quoted_attribute(untrusted));
+///     assert_eq!(
+///         html,
+///         "<abbr title=\"Tom &amp; Jerry&#39;s &quot;show&quot; &lt;3\">T&amp;J</abbr>"
+///     );
+pub fn escape_quoted_attribute(src: &str) -> String {
+    let mut ret_val = String::with_capacity(src.len());
+    for c in src.chars() {
+        let replacement = match c {
+            // starts an entity reference
+            '&' => "&amp;",
+            // ends an attribute value surrounded by the same kind of quote
+            '"' => "&quot;",
+            '\'' => "&#39;",
+            // harmless between quotes, but some serializers and old browsers get them wrong
+            '<' => "&lt;",
+            '>' => "&gt;",
+            // would be turned into a newline by the parser
+            '\r' => "&#13;",
+            // a spec-compliant browser will perform this replacement anyway, but the middleware might not
+            '\0' => "&#65533;",
+            _ => {
+                ret_val.push(c);
+                continue;
+            }
+        };
+        ret_val.push_str(replacement);
+    }
+    ret_val
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html5ever::buffer_queue::BufferQueue;
+    use html5ever::tendril::StrTendril;
+    use html5ever::tokenizer::states::RawKind;
+    use html5ever::tokenizer::{
+        Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+    };
+    use std::cell::{Cell, RefCell};
+
+    /// Pieces that are likely to break out of something, mixed with some harmless text.
+    #[rustfmt::skip]
+    const PIECES: &[&str] = &[
+        "\"", "'", "`", "<", ">", "/", "\\", "&", "&amp;", "&quot", "=", " ", "\t", "\n", "\r",
+        "\x0c", "\0", "%", "%2", "+", "#", ";", ":", "{", "}", "(", ")", "*/", "/*", "${",
+        "<!--", "-->", "</script>", "</style", "<script>", "<svg>", "]]>", "\u{2028}", "\u{2029}",
+        "\u{feff}", "\u{fffd}", "é", "日本", "😀", "a", "Z", "09", "javascript:", "\\0", "\\22",
+    ];
+
+    /// A small xorshift generator, so that failures can be reproduced.
+    struct Rng(Cell<u64>);
+
+    impl Rng {
+        fn next(&self, n: usize) -> usize {
+            let mut x = self.0.get();
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            self.0.set(x);
+            (x % n as u64) as usize
+        }
+    }
+
+    fn fuzz(check: impl Fn(&str)) {
+        let rng = Rng(Cell::new(0x2545_f491_4f6c_dd1d));
+        for _ in 0..2000 {
+            let len = rng.next(12);
+            let input: String = (0..len).map(|_| PIECES[rng.next(PIECES.len())]).collect();
+            check(&input);
+        }
+        for piece in PIECES {
+            check(piece);
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Tok {
+        Tag(TagKind, String, Vec<(String, String)>),
+        Text(String),
+        Other(String),
+    }
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<Tok>>);
+
+    impl TokenSink for Recorder {
+        type Handle = ();
+        fn process_token(&self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+            let mut tokens = self.0.borrow_mut();
+            match token {
+                Token::TagToken(Tag {
+                    kind, name, attrs, ..
+                }) => {
+                    let attrs = attrs
+                        .into_iter()
+                        .map(|attr| (attr.name.local.to_string(), attr.value.to_string()))
+                        .collect();
+                    tokens.push(Tok::Tag(kind, name.to_string(), attrs));
+                    if kind == TagKind::StartTag {
+                        match &*name {
+                            "script" => return TokenSinkResult::RawData(RawKind::ScriptData),
+                            "style" => return TokenSinkResult::RawData(RawKind::Rawtext),
+                            _ => {}
+                        }
+                    }
+                }
+                Token::CharacterTokens(text) => match tokens.last_mut() {
+                    Some(Tok::Text(previous)) => previous.push_str(&text),
+                    _ => tokens.push(Tok::Text(text.to_string())),
+                },
+                Token::NullCharacterToken => tokens.push(Tok::Other("NUL".to_owned())),
+                Token::EOFToken | Token::ParseError(_) => {}
+                other => tokens.push(Tok::Other(format!("{other:?}"))),
+            }
+            TokenSinkResult::Continue
+        }
+    }
+
+    fn tokenize(html: &str) -> Vec<Tok> {
+        let input = BufferQueue::default();
+        input.push_back(StrTendril::from(html));
+        let tok = Tokenizer::new(Recorder::default(), TokenizerOpts::default());
+        let _ = tok.feed(&input);
+        tok.end();
+        tok.sink.0.take()
+    }
+
+    fn start(name: &str, attrs: &[(&str, &str)]) -> Tok {
+        let attrs = attrs
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        Tok::Tag(TagKind::StartTag, name.to_owned(), attrs)
+    }
+
+    fn end(name: &str) -> Tok {
+        Tok::Tag(TagKind::EndTag, name.to_owned(), Vec::new())
+    }
+
+    fn text(text: &str) -> Tok {
+        Tok::Text(text.to_owned())
+    }
+
+    /// Reads back the escapes that `escape_js_string` writes.
+    fn unescape_js(escaped: &str) -> String {
+        let mut ret_val = String::new();
+        let mut rest = escaped;
+        while let Some(i) = rest.find('\\') {
+            ret_val.push_str(&rest[..i]);
+            let len = if rest[i + 1..].starts_with('x') { 2 } else { 4 };
+            let code = u32::from_str_radix(&rest[i + 2..i + 2 + len], 16).unwrap();
+            ret_val.push(char::from_u32(code).unwrap());
+            rest = &rest[i + 2 + len..];
+        }
+        ret_val.push_str(rest);
+        ret_val
+    }
+
+    #[test]
+    fn js_string() {
+        fuzz(|input| {
+            let escaped = escape_js_string(input);
+            assert!(
+                !escaped.contains(|c: char| "\"'`\n\r<>&\u{2028}\u{2029}".contains(c)),
+                "{escaped:?}"
+            );
+            assert_eq!(unescape_js(&escaped), input);
+            let source = format!("var s = \"{escaped}\";");
+            assert_eq!(
+                tokenize(&format!("<script>{source}</script>")),
+                [start("script", &[]), text(&source), end("script")]
+            );
+            assert_eq!(
+                tokenize(&format!("<p onclick='go(\"{escaped}\")'>")),
+                [start("p", &[("onclick", &format!("go(\"{escaped}\")"))])]
+            );
+        });
+    }
+
+    #[test]
+    fn css_string() {
+        fuzz(|input| {
+            let escaped = escape_css_string(input);
+            let quoted = format!("\"{escaped}\"");
+            let mut parser_input = cssparser::ParserInput::new(&quoted);
+            let mut parser = cssparser::Parser::new(&mut parser_input);
+            match parser.next() {
+                Ok(cssparser::Token::QuotedString(value)) => {
+                    assert_eq!(&**value, input.replace('\0', "\u{fffd}"))
+                }
+                other => panic!("{escaped:?} is {other:?}"),
+            }
+            assert!(parser.is_exhausted());
+            let sheet = format!("q::before {{ content: {quoted} }}");
+            assert_eq!(
+                tokenize(&format!("<style>{sheet}</style>")),
+                [start("style", &[]), text(&sheet), end("style")]
+            );
+            let declaration = format!("content: {quoted}");
+            assert_eq!(
+                tokenize(&format!("<q style='{declaration}'>")),
+                [start("q", &[("style", &declaration)])]
+            );
+        });
+    }
+
+    #[test]
+    fn url_component() {
+        fuzz(|input| {
+            let escaped = escape_url_component(input);
+            let query = format!("q={escaped}");
+            let decoded: Vec<_> = url::form_urlencoded::parse(query.as_bytes()).collect();
+            assert_eq!(decoded, [("q".into(), input.into())]);
+            let href = format!("/search?{query}");
+            assert_eq!(
+                tokenize(&format!("<a href={href}>x</a>")),
+                [start("a", &[("href", &href)]), text("x"), end("a")]
+            );
+        });
+    }
+
+    #[test]
+    fn quoted_attribute() {
+        fuzz(|input| {
+            let escaped = escape_quoted_attribute(input);
+            let value = input.replace('\0', "\u{fffd}");
+            for quote in ["\"", "'"] {
+                assert_eq!(
+                    tokenize(&format!("<p title={quote}{escaped}{quote}>x</p>")),
+                    [start("p", &[("title", &value)]), text("x"), end("p")]
+                );
+            }
+            let tokens = tokenize(&format!("<p>{escaped}</p>"));
+            if value.is_empty() {
+                assert_eq!(tokens, [start("p", &[]), end("p")]);
+            } else {
+                assert_eq!(tokens, [start("p", &[]), text(&value), end("p")]);
+            }
+        });
+    }
+}
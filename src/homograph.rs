@@ -0,0 +1,209 @@
+//! Detecting internationalized host names that imitate other ones, for
+//! [`Builder::homograph_action`].
+//!
+//! This is a simplified version of the "highly restrictive" profile from
+//! [Unicode Technical Standard #39], which browsers use to decide whether to show a host
+//! in Unicode or as punycode.
+//!
+//! [`Builder::homograph_action`]: ../struct.Builder.html#method.homograph_action
+//! [Unicode Technical Standard #39]: https://www.unicode.org/reports/tr39/#Restriction_Level_Detection
+
+/// What to do with a URL whose host mixes scripts or imitates a Latin name, like
+/// `аpple.com` with a Cyrillic `а`.
+///
+/// See [`Builder::homograph_action`][homograph_action].
+///
+/// [homograph_action]: struct.Builder.html#method.homograph_action
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum HomographAction {
+    /// Remove the attribute. For a `srcset`, only the URL with the confusable host is
+    /// removed from the list.
+    RemoveAttribute,
+    /// Rewrite the URL with its host in punycode, like `https://xn--pple-43d.com/`,
+    /// so readers can see that it isn't the host it looks like.
+    Punycode,
+    /// Keep the URL as it is, and only record it in the report.
+    Report,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Georgian,
+    Hangul,
+    Hiragana,
+    Katakana,
+    Bopomofo,
+    Han,
+    Other,
+}
+
+/// Returns the script of a letter, or `None` for characters that are used with every
+/// script, like digits, hyphens and combining marks.
+fn script(c: char) -> Option<Script> {
+    let script = match c {
+        '0'..='9' | '-' | '\u{0300}'..='\u{036F}' | '\u{30FC}' => return None,
+        'a'..='z' | 'A'..='Z' => Script::Latin,
+        '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => Script::Latin,
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Script::Greek,
+        '\u{0400}'..='\u{052F}' | '\u{1C80}'..='\u{1C8F}' | '\u{2DE0}'..='\u{2DFF}' => {
+            Script::Cyrillic
+        }
+        '\u{A640}'..='\u{A69F}' => Script::Cyrillic,
+        '\u{0530}'..='\u{058F}' => Script::Armenian,
+        '\u{0590}'..='\u{05FF}' => Script::Hebrew,
+        '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' | '\u{08A0}'..='\u{08FF}' => {
+            Script::Arabic
+        }
+        '\u{0900}'..='\u{097F}' => Script::Devanagari,
+        '\u{0E00}'..='\u{0E7F}' => Script::Thai,
+        '\u{10A0}'..='\u{10FF}' => Script::Georgian,
+        '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => {
+            Script::Hangul
+        }
+        '\u{3040}'..='\u{309F}' => Script::Hiragana,
+        '\u{30A0}'..='\u{30FF}' => Script::Katakana,
+        '\u{3100}'..='\u{312F}' => Script::Bopomofo,
+        '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' => Script::Han,
+        '\u{20000}'..='\u{2FFFF}' => Script::Han,
+        _ => Script::Other,
+    };
+    Some(script)
+}
+
+/// Cyrillic and Greek letters that look like lowercase Latin ones.
+fn looks_latin(c: char) -> bool {
+    matches!(
+        c,
+        'а' | 'с'
+            | 'ԁ'
+            | 'е'
+            | 'һ'
+            | 'і'
+            | 'ј'
+            | 'к'
+            | 'ӏ'
+            | 'о'
+            | 'п'
+            | 'р'
+            | 'ԛ'
+            | 'г'
+            | 'ѕ'
+            | 'у'
+            | 'ү'
+            | 'ѵ'
+            | 'ԝ'
+            | 'ѡ'
+            | 'х'
+            | 'α'
+            | 'ι'
+            | 'κ'
+            | 'ν'
+            | 'ο'
+            | 'ρ'
+            | 'υ'
+            | 'χ'
+    )
+}
+
+/// Returns `true` if the host, as serialized by [`url::Url`], has a label that mixes
+/// scripts, or that is entirely made of Cyrillic or Greek letters that look Latin.
+///
+/// Latin can be mixed with Han and the Japanese or Korean scripts, since they are
+/// commonly written together.
+pub(crate) fn is_confusable(host: &str) -> bool {
+    if !host
+        .split('.')
+        .any(|label| label.len() >= 4 && label[..4].eq_ignore_ascii_case("xn--"))
+    {
+        return false;
+    }
+    let (host, result) = idna::domain_to_unicode(host);
+    if result.is_err() {
+        return true;
+    }
+    let labels: Vec<Vec<Script>> = host
+        .split('.')
+        .map(|label| {
+            let mut scripts: Vec<Script> = label.chars().filter_map(script).collect();
+            scripts.sort_by_key(|&script| script as u8);
+            scripts.dedup();
+            scripts
+        })
+        .collect();
+    let tld = labels.last().map(Vec::as_slice).unwrap_or_default();
+    host.split('.')
+        .zip(&labels)
+        .any(|(label, scripts)| match scripts[..] {
+            [] | [Script::Latin] => false,
+            [only @ (Script::Cyrillic | Script::Greek)] => {
+                tld != [only] && label.chars().all(|c| script(c).is_none() || looks_latin(c))
+            }
+            [_] => false,
+            _ => !is_allowed_mix(scripts),
+        })
+}
+
+fn is_allowed_mix(scripts: &[Script]) -> bool {
+    use Script::*;
+    let allowed: &[&[Script]] = &[
+        &[Latin, Hiragana, Katakana, Han],
+        &[Latin, Bopomofo, Han],
+        &[Latin, Hangul, Han],
+    ];
+    allowed
+        .iter()
+        .any(|allowed| scripts.iter().all(|script| allowed.contains(script)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn confusable(url: &str) -> bool {
+        is_confusable(Url::parse(url).unwrap().host_str().unwrap())
+    }
+
+    #[test]
+    fn mixed_scripts() {
+        assert!(confusable("https://\u{430}pple.com/"));
+        assert!(confusable("https://p\u{430}ypal.com/"));
+        assert!(confusable("https://xn--pple-43d.com/"));
+        assert!(confusable("https://www.g\u{3bf}\u{3bf}gle.com/"));
+        assert!(!confusable("https://example.com/"));
+        assert!(!confusable("https://bücher.example/"));
+        assert!(!confusable("https://東京タワー.jp/"));
+        assert!(!confusable("https://ソニーsony.jp/"));
+        assert!(!confusable("https://한국abc.kr/"));
+    }
+
+    #[test]
+    fn whole_script() {
+        assert!(confusable(
+            "https://\u{430}\u{440}\u{440}\u{4cf}\u{435}.com/"
+        ));
+        assert!(confusable("https://\u{441}\u{43e}\u{43e}\u{440}.com/"));
+        assert!(!confusable(
+            "https://\u{43f}\u{440}\u{438}\u{432}\u{435}\u{442}.com/"
+        ));
+        assert!(!confusable(
+            "https://\u{441}\u{43e}\u{43e}\u{440}.\u{440}\u{444}/"
+        ));
+        assert!(!confusable(
+            "https://\u{3b5}\u{3bb}\u{3bb}\u{3ac}\u{3b4}\u{3b1}.com/"
+        ));
+    }
+}
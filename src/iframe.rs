@@ -0,0 +1,211 @@
+//! Embedding `<iframe>` elements from trusted providers, for [`Builder::allow_iframes`].
+//!
+//! [`Builder::allow_iframes`]: ../struct.Builder.html#method.allow_iframes
+
+use crate::csp::Source;
+use crate::CspError;
+use std::fmt::{self, Display};
+use url::Url;
+
+/// A site whose pages can be embedded with an `<iframe>`, like a video player.
+///
+/// The provider lists the URLs that the iframe's `src` may point at, written the same way as
+/// `frame-src` sources in a [`ContentSecurityPolicy`], like `https://www.youtube.com/embed/`.
+/// It also decides the values of the `sandbox`, `allow` and `referrerpolicy` attributes,
+/// which are always set on its iframes, replacing whatever the input had.
+///
+/// [`ContentSecurityPolicy`]: struct.ContentSecurityPolicy.html
+///
+/// # Examples
+///
+///     use ammonia::{Builder, IframeProvider};
+///
+///     # fn main() -> Result<(), ammonia::CspError> {
+///     let maps = IframeProvider::new(["https://maps.example.com/embed/"])?
+///         .sandbox("allow-scripts")
+///         .referrer_policy("no-referrer");
+///     let a = Builder::default()
+///         .allow_iframes([maps])
+///         .clean("<iframe src=https://maps.example.com/embed/x width=300 srcdoc=x></iframe>")
+///         .to_string();
+///     assert_eq!(
+///         a,
+///         "<iframe src=\"https://maps.example.com/embed/x\" width=\"300\" \
+///          sandbox=\"allow-scripts\" allow=\"\" referrerpolicy=\"no-referrer\"></iframe>"
+///     );
+///     # Ok(())
+///     # }
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(deny_unknown_fields)
+)]
+pub struct IframeProvider {
+    src: Vec<SrcPattern>,
+    sandbox: String,
+    allow: String,
+    referrer_policy: String,
+}
+
+/// One of the URL patterns of an [`IframeProvider`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+struct SrcPattern(Source);
+
+impl SrcPattern {
+    fn parse(pattern: &str) -> Result<Self, CspError> {
+        Source::parse(pattern)
+            .map(SrcPattern)
+            .ok_or_else(|| CspError::InvalidSource {
+                directive: "frame-src".to_owned(),
+                source: pattern.to_owned(),
+            })
+    }
+}
+
+impl Display for SrcPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl TryFrom<String> for SrcPattern {
+    type Error = CspError;
+    fn try_from(pattern: String) -> Result<Self, CspError> {
+        SrcPattern::parse(&pattern)
+    }
+}
+
+impl From<SrcPattern> for String {
+    fn from(pattern: SrcPattern) -> String {
+        pattern.to_string()
+    }
+}
+
+impl IframeProvider {
+    /// Creates a provider whose iframes can point at any of these URL patterns.
+    ///
+    /// Its iframes get `sandbox="allow-scripts allow-same-origin"`, an empty `allow`,
+    /// and `referrerpolicy="strict-origin-when-cross-origin"`, until they are changed.
+    ///
+    /// Returns an error if a pattern is not a valid `frame-src` source.
+    pub fn new<'b, I: IntoIterator<Item = &'b str>>(src: I) -> Result<Self, CspError> {
+        Ok(IframeProvider {
+            src: src
+                .into_iter()
+                .map(SrcPattern::parse)
+                .collect::<Result<_, _>>()?,
+            sandbox: "allow-scripts allow-same-origin".to_owned(),
+            allow: String::new(),
+            referrer_policy: "strict-origin-when-cross-origin".to_owned(),
+        })
+    }
+
+    /// YouTube's video player, including its privacy-enhanced mode.
+    ///
+    ///     use ammonia::{Builder, IframeProvider};
+    ///
+    ///     let a = Builder::default()
+    ///         .allow_iframes([IframeProvider::youtube()])
+    ///         .clean("<iframe src=https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ></iframe>\
+    ///                 <iframe src=https://www.youtube.com/watch?v=dQw4w9WgXcQ></iframe>")
+    ///         .to_string();
+    ///     assert!(a.starts_with("<iframe src=\"https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ\" sandbox="));
+    ///     assert_eq!(a.matches("<iframe").count(), 1);
+    pub fn youtube() -> Self {
+        IframeProvider::new([
+            "https://www.youtube.com/embed/",
+            "https://www.youtube-nocookie.com/embed/",
+        ])
+        .expect("the YouTube patterns are valid")
+        .sandbox("allow-scripts allow-same-origin allow-presentation allow-popups")
+        .allow("encrypted-media; picture-in-picture; fullscreen")
+    }
+
+    /// Vimeo's video player.
+    pub fn vimeo() -> Self {
+        IframeProvider::new(["https://player.vimeo.com/video/"])
+            .expect("the Vimeo patterns are valid")
+            .sandbox("allow-scripts allow-same-origin allow-presentation allow-popups")
+            .allow("fullscreen; picture-in-picture")
+    }
+
+    /// Sets the value of the `sandbox` attribute, which is a space-separated list of the
+    /// things the embedded page is allowed to do, like `allow-scripts`.
+    pub fn sandbox(mut self, value: &str) -> Self {
+        self.sandbox = value.to_owned();
+        self
+    }
+
+    /// Sets the value of the `allow` attribute, which is a permissions policy,
+    /// like `fullscreen; picture-in-picture`.
+    pub fn allow(mut self, value: &str) -> Self {
+        self.allow = value.to_owned();
+        self
+    }
+
+    /// Sets the value of the `referrerpolicy` attribute, like `no-referrer`.
+    pub fn referrer_policy(mut self, value: &str) -> Self {
+        self.referrer_policy = value.to_owned();
+        self
+    }
+
+    /// Returns `true` if an iframe from this provider can point at this URL.
+    pub fn matches(&self, url: &Url) -> bool {
+        self.src.iter().any(|pattern| pattern.0.matches(url, false))
+    }
+
+    /// The attributes that are set on every iframe from this provider.
+    pub(crate) fn forced_attributes(&self) -> [(&'static str, &str); 3] {
+        [
+            ("sandbox", &self.sandbox),
+            ("allow", &self.allow),
+            ("referrerpolicy", &self.referrer_policy),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(provider: &IframeProvider, url: &str) -> bool {
+        provider.matches(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn presets() {
+        let youtube = IframeProvider::youtube();
+        assert!(matches(&youtube, "https://www.youtube.com/embed/x?start=1"));
+        assert!(matches(
+            &youtube,
+            "https://www.youtube-nocookie.com/embed/x"
+        ));
+        assert!(!matches(&youtube, "http://www.youtube.com/embed/x"));
+        assert!(!matches(&youtube, "https://www.youtube.com/watch?v=x"));
+        assert!(!matches(
+            &youtube,
+            "https://www.youtube.com.evil.example/embed/x"
+        ));
+        let vimeo = IframeProvider::vimeo();
+        assert!(matches(&vimeo, "https://player.vimeo.com/video/1"));
+        assert!(!matches(&vimeo, "https://vimeo.com/1"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert_eq!(
+            IframeProvider::new(["https://example.com", "https://"]),
+            Err(CspError::InvalidSource {
+                directive: "frame-src".to_string(),
+                source: "https://".to_string(),
+            })
+        );
+        assert!(IframeProvider::new(["'unsafe-inline'"]).is_err());
+    }
+}
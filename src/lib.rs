@@ -12,8 +12,9 @@
 //! so it is extremely resilient to syntactic obfuscation.
 //!
 //! Ammonia parses its input exactly according to the HTML5 specification;
-//! it will not linkify bare URLs, insert line or paragraph breaks, or convert `(C)` into &copy;.
+//! it will not insert line or paragraph breaks, or convert `(C)` into &copy;.
 //! If you want that, use a markup processor before running the sanitizer, like [pulldown-cmark].
+//! Bare URLs are only turned into links if [`Builder::linkify`] is turned on.
 //!
 //! # Examples
 //!
@@ -33,7 +34,38 @@ pub mod rcdom;
 #[cfg(not(ammonia_unstable))]
 mod rcdom;
 
+mod csp;
+mod css;
+mod data_uri;
+mod escape;
+mod homograph;
+mod iframe;
+mod link_text;
+mod linkify;
+mod srcset;
+mod stream;
 mod style;
+mod style_sheet;
+mod text;
+mod url_policy;
+
+#[cfg(feature = "serde")]
+mod policy;
+
+#[cfg(feature = "serde")]
+pub use policy::{Policy, UrlRelativePolicy};
+pub use csp::{ContentSecurityPolicy, CspError, CspViolationAction};
+pub use css::{CssSanitizer, RejectedDeclaration};
+pub use data_uri::{DataUriPolicy, DataUriRejection};
+pub use escape::{escape_css_string, escape_js_string, escape_quoted_attribute, escape_url_component};
+pub use homograph::HomographAction;
+pub use iframe::IframeProvider;
+pub use link_text::LinkTextAction;
+pub use text::{TextOptions, TruncateOptions};
+pub use style::StyleValueRule;
+pub use style_sheet::StyleSheetPolicy;
+pub use url_policy::UrlPolicy;
+use url_policy::HostPattern;
 
 use html5ever::interface::Attribute;
 use html5ever::serialize::{serialize, SerializeOpts};
@@ -41,9 +73,9 @@ use html5ever::tree_builder::{NodeOrText, TreeSink};
 use html5ever::{driver as html, local_name, ns, Namespace, QualName};
 use maplit::{hashmap, hashset};
 use std::sync::LazyLock;
-use rcdom::{Handle, NodeData, RcDom, SerializableHandle};
+use rcdom::{Handle, Node, NodeData, RcDom, SerializableHandle};
 use std::borrow::{Borrow, Cow};
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display};
@@ -63,6 +95,10 @@ pub use url;
 
 static AMMONIA: LazyLock<Builder<'static>> = LazyLock::new(Builder::default);
 
+/// A stand-in for the document's URL, to find the host named by a scheme-relative URL.
+static RELATIVE_URL_BASE: LazyLock<Url> =
+    LazyLock::new(|| Url::parse("https://relative.invalid/").unwrap());
+
 /// Clean HTML with a conservative set of defaults.
 ///
 /// * [tags](struct.Builder.html#defaults)
@@ -96,7 +132,7 @@ pub fn clean(src: &str) -> String {
 /// # Warnings
 ///
 /// This function cannot be used to package strings into a `<script>` or `<style>` tag;
-/// you need a JavaScript or CSS escaper to do that.
+/// use [`escape_js_string`] or [`escape_css_string`] to do that.
 ///
 ///     // DO NOT DO THIS
 ///     # use ammonia::clean_text;
@@ -127,7 +163,8 @@ pub fn clean(src: &str) -> String {
 ///
 /// It also does not make user text safe for HTML attribute microsyntaxes such as `class` or `id`.
 /// Only use this function for places where HTML accepts unrestricted text such as `title` attributes
-/// and paragraph contents.
+/// and paragraph contents. Query parameters in a URL can be escaped with [`escape_url_component`],
+/// and [`escape_quoted_attribute`] gives more readable output when the attribute is quoted.
 pub fn clean_text(src: &str) -> String {
     let mut ret_val = String::with_capacity(max(4, src.len()));
     for c in src.chars() {
@@ -179,11 +216,11 @@ pub fn is_html(input: &str) -> bool {
     let santok = SanitizationTokenizer::new();
     let mut chunk = ByteTendril::new();
     chunk.push_slice(input.as_bytes());
-    let mut input = BufferQueue::default();
+    let input = BufferQueue::default();
     input.push_back(chunk.try_reinterpret().unwrap());
 
     let tok = Tokenizer::new(santok, Default::default());
-    let _ = tok.feed(&mut input);
+    let _ = tok.feed(&input);
     tok.end();
     tok.sink.was_sanitized.get()
 }
@@ -238,7 +275,9 @@ impl TokenSink for SanitizationTokenizer {
 /// # Panics
 ///
 /// Running [`clean`] or [`clean_from_reader`] may cause a panic if the builder is
-/// configured with any of these (contradictory) settings:
+/// configured with any of these (contradictory) settings. To find out about them
+/// without panicking, use [`validate`] or [`try_clean`]; each setting below corresponds
+/// to a [`PolicyError`] variant.
 ///
 ///  * The `rel` attribute is added to [`generic_attributes`] or the
 ///    [`tag_attributes`] for the `<a>` tag, and [`link_rel`] is not set to `None`.
@@ -345,6 +384,9 @@ impl TokenSink for SanitizationTokenizer {
 ///
 /// [`clean`]: #method.clean
 /// [`clean_from_reader`]: #method.clean_from_reader
+/// [`validate`]: #method.validate
+/// [`try_clean`]: #method.try_clean
+/// [`PolicyError`]: enum.PolicyError.html
 /// [`generic_attributes`]: #method.generic_attributes
 /// [`tag_attributes`]: #method.tag_attributes
 /// [`generic_attributes`]: #method.generic_attributes
@@ -362,14 +404,37 @@ pub struct Builder<'a> {
     set_tag_attribute_values: HashMap<&'a str, HashMap<&'a str, &'a str>>,
     generic_attributes: HashSet<&'a str>,
     url_schemes: HashSet<&'a str>,
+    url_attributes: HashMap<&'a str, HashSet<&'a str>>,
+    generic_url_attributes: HashSet<&'a str>,
+    url_policies: HashMap<&'a str, HashMap<&'a str, UrlPolicy>>,
+    generic_url_policy: Option<UrlPolicy>,
+    content_security_policy: Option<ContentSecurityPolicy>,
+    csp_violation_action: CspViolationAction,
+    iframe_providers: Vec<IframeProvider>,
+    data_uri_policy: Option<DataUriPolicy>,
+    homograph_action: Option<HomographAction>,
+    link_text_mismatch: Option<LinkTextAction>,
     url_relative: UrlRelative<'a>,
     attribute_filter: Option<Box<dyn AttributeFilter>>,
+    element_filter: Option<Box<dyn ElementFilter>>,
+    absolute_url_rewriter: Option<Box<dyn AbsoluteUrlRewriter>>,
+    absolute_url_rewriter_exempt_hosts: Vec<HostPattern>,
     link_rel: Option<&'a str>,
     allowed_classes: HashMap<&'a str, HashSet<&'a str>>,
     strip_comments: bool,
     id_prefix: Option<&'a str>,
     generic_attribute_prefixes: Option<HashSet<&'a str>>,
     style_properties: Option<HashSet<&'a str>>,
+    style_value_rules: HashMap<&'a str, StyleValueRule>,
+    style_sheets: Option<StyleSheetPolicy>,
+    rename_tags: HashMap<&'a str, &'a str>,
+    rename_tag_attribute_values: HashMap<&'a str, HashMap<&'a str, &'a str>>,
+    max_depth: Option<usize>,
+    depth_limit_action: DepthLimitAction,
+    max_nodes: Option<usize>,
+    max_attributes: Option<usize>,
+    max_output_bytes: Option<usize>,
+    linkify: bool,
 }
 
 impl<'a> Default for Builder<'a> {
@@ -473,6 +538,15 @@ impl<'a> Default for Builder<'a> {
             "wtai",
             "xmpp"
         ];
+        let url_attributes = hashmap![
+            "a" => hashset!["ping"],
+            "button" => hashset!["formaction"],
+            "form" => hashset!["action"],
+            "input" => hashset!["formaction"],
+            "object" => hashset!["data"],
+            "video" => hashset!["poster"],
+        ];
+        let generic_url_attributes = hashset!["href", "src"];
         let allowed_classes = hashmap![];
 
         Builder {
@@ -483,14 +557,37 @@ impl<'a> Default for Builder<'a> {
             set_tag_attribute_values,
             generic_attributes,
             url_schemes,
+            url_attributes,
+            generic_url_attributes,
+            url_policies: hashmap![],
+            generic_url_policy: None,
+            content_security_policy: None,
+            csp_violation_action: CspViolationAction::RemoveAttribute,
+            iframe_providers: Vec::new(),
+            data_uri_policy: None,
+            homograph_action: None,
+            link_text_mismatch: None,
             url_relative: UrlRelative::PassThrough,
             attribute_filter: None,
+            element_filter: None,
+            absolute_url_rewriter: None,
+            absolute_url_rewriter_exempt_hosts: Vec::new(),
             link_rel: Some("noopener noreferrer"),
             allowed_classes,
             strip_comments: true,
             id_prefix: None,
             generic_attribute_prefixes: None,
             style_properties: None,
+            style_value_rules: hashmap![],
+            style_sheets: None,
+            rename_tags: hashmap![],
+            rename_tag_attribute_values: hashmap![],
+            max_depth: None,
+            depth_limit_action: DepthLimitAction::Unwrap,
+            max_nodes: None,
+            max_attributes: None,
+            max_output_bytes: None,
+            linkify: false,
         }
     }
 }
@@ -1239,6 +1336,13 @@ impl<'a> Builder<'a> {
 
     /// Sets the URL schemes permitted on `href` and `src` attributes.
     ///
+    /// The other attributes that hold URLs, configured with [`url_attributes`] and
+    /// [`generic_url_attributes`], are checked the same way.
+    ///
+    /// The same check applies to every URL in a `srcset` (on `img` and `source`) or an
+    /// `imagesrcset` (on `link`). URLs that fail are dropped from the list, and the rest are
+    /// kept; the attribute is only removed if none are left.
+    ///
     /// # Examples
     ///
     ///     use ammonia::Builder;
@@ -1266,6 +1370,9 @@ impl<'a> Builder<'a> {
     /// openpgp4fpr, sip, sms, smsto, ssh, tel, url,
     /// webcal, wtai, xmpp
     /// ```
+    ///
+    /// [`url_attributes`]: #method.url_attributes
+    /// [`generic_url_attributes`]: #method.generic_url_attributes
     pub fn url_schemes(&mut self, value: HashSet<&'a str>) -> &mut Self {
         self.url_schemes = value;
         self
@@ -1321,179 +1428,23 @@ impl<'a> Builder<'a> {
         self.url_schemes.clone()
     }
 
-    /// Configures the behavior for relative URLs: pass-through, resolve-with-base, or deny.
-    ///
-    /// # Examples
-    ///
-    ///     use ammonia::{Builder, UrlRelative};
-    ///
-    ///     let a = Builder::new().url_relative(UrlRelative::PassThrough)
-    ///         .clean("<a href=/>Home</a>")
-    ///         .to_string();
-    ///
-    ///     // See `link_rel` for information on the rel="noopener noreferrer" attribute
-    ///     // in the cleaned HTML.
-    ///     assert_eq!(
-    ///       a,
-    ///       "<a href=\"/\" rel=\"noopener noreferrer\">Home</a>");
-    ///
-    /// # Defaults
-    ///
-    /// ```notest
-    /// UrlRelative::PassThrough
-    /// ```
-    pub fn url_relative(&mut self, value: UrlRelative<'a>) -> &mut Self {
-        self.url_relative = value;
-        self
-    }
-
-    /// Allows rewriting of all attributes using a callback.
-    ///
-    /// The callback takes name of the element, attribute and its value.
-    /// Returns `None` to remove the attribute, or a value to use.
-    ///
-    /// Rewriting of attributes with URLs is done before `url_relative()`.
-    ///
-    /// # Panics
-    ///
-    /// If more than one callback is set.
-    ///
-    /// # Examples
-    ///
-    /// ```rust
-    /// use ammonia::Builder;
-    /// let a = Builder::new()
-    ///     .attribute_filter(|element, attribute, value| {
-    ///         match (element, attribute) {
-    ///             ("img", "src") => None,
-    ///             _ => Some(value.into())
-    ///         }
-    ///     })
-    ///     .link_rel(None)
-    ///     .clean("<a href=/><img alt=Home src=foo></a>")
-    ///     .to_string();
-    /// assert_eq!(a,
-    ///     r#"<a href="/"><img alt="Home"></a>"#);
-    /// ```
-    pub fn attribute_filter<'cb, CallbackFn>(&mut self, callback: CallbackFn) -> &mut Self
-    where
-        CallbackFn: for<'u> Fn(&str, &str, &'u str) -> Option<Cow<'u, str>> + Send + Sync + 'static,
-    {
-        assert!(
-            self.attribute_filter.is_none(),
-            "attribute_filter can be set only once"
-        );
-        self.attribute_filter = Some(Box::new(callback));
-        self
-    }
-
-    /// Returns `true` if the relative URL resolver is set to `Deny`.
-    ///
-    /// # Examples
-    ///
-    ///     use ammonia::{Builder, UrlRelative};
-    ///     let mut a = Builder::default();
-    ///     a.url_relative(UrlRelative::Deny);
-    ///     assert!(a.is_url_relative_deny());
-    ///     a.url_relative(UrlRelative::PassThrough);
-    ///     assert!(!a.is_url_relative_deny());
-    pub fn is_url_relative_deny(&self) -> bool {
-        matches!(self.url_relative, UrlRelative::Deny)
-    }
-
-    /// Returns `true` if the relative URL resolver is set to `PassThrough`.
-    ///
-    /// # Examples
-    ///
-    ///     use ammonia::{Builder, UrlRelative};
-    ///     let mut a = Builder::default();
-    ///     a.url_relative(UrlRelative::Deny);
-    ///     assert!(!a.is_url_relative_pass_through());
-    ///     a.url_relative(UrlRelative::PassThrough);
-    ///     assert!(a.is_url_relative_pass_through());
-    pub fn is_url_relative_pass_through(&self) -> bool {
-        matches!(self.url_relative, UrlRelative::PassThrough)
-    }
-
-    /// Returns `true` if the relative URL resolver is set to `Custom`.
-    ///
-    /// # Examples
-    ///
-    ///     use ammonia::{Builder, UrlRelative};
-    ///     use std::borrow::Cow;
-    ///     fn test(a: &str) -> Option<Cow<str>> { None }
-    ///     # fn main() {
-    ///     let mut a = Builder::default();
-    ///     a.url_relative(UrlRelative::Custom(Box::new(test)));
-    ///     assert!(a.is_url_relative_custom());
-    ///     a.url_relative(UrlRelative::PassThrough);
-    ///     assert!(!a.is_url_relative_custom());
-    ///     a.url_relative(UrlRelative::Deny);
-    ///     assert!(!a.is_url_relative_custom());
-    ///     # }
-    pub fn is_url_relative_custom(&self) -> bool {
-        matches!(self.url_relative, UrlRelative::Custom(_))
-    }
-
-    /// Configures a `rel` attribute that will be added on links.
-    ///
-    /// If `rel` is in the generic or tag attributes, this must be set to `None`.
-    /// Common `rel` values to include:
-    ///
-    /// * `noopener`: This prevents [a particular type of XSS attack],
-    ///   and should usually be turned on for untrusted HTML.
-    /// * `noreferrer`: This prevents the browser from [sending the source URL]
-    ///   to the website that is linked to.
-    /// * `nofollow`: This prevents search engines from [using this link for
-    ///   ranking], which disincentivizes spammers.
-    ///
-    /// To turn on rel-insertion, call this function with a space-separated list.
-    /// Ammonia does not parse rel-attributes;
-    /// it just puts the given string into the attribute directly.
-    ///
-    /// [a particular type of XSS attack]: https://mathiasbynens.github.io/rel-noopener/
-    /// [sending the source URL]: https://en.wikipedia.org/wiki/HTTP_referer
-    /// [using this link for ranking]: https://en.wikipedia.org/wiki/Nofollow
-    ///
-    /// # Examples
-    ///
-    ///     use ammonia::Builder;
-    ///
-    ///     let a = Builder::new().link_rel(None)
-    ///         .clean("<a href=https://rust-lang.org/>Rust</a>")
-    ///         .to_string();
-    ///     assert_eq!(
-    ///       a,
-    ///       "<a href=\"https://rust-lang.org/\">Rust</a>");
-    ///
-    /// # Defaults
-    ///
-    /// ```notest
-    /// Some("noopener noreferrer")
-    /// ```
-    pub fn link_rel(&mut self, value: Option<&'a str>) -> &mut Self {
-        self.link_rel = value;
-        self
-    }
-
-    /// Returns the settings for links' `rel` attribute, if one is set.
+    /// Sets the attributes that hold a URL on specific tags.
     ///
-    /// # Examples
+    /// The value is structured as a map from tag names to a set of attribute names.
     ///
-    ///     use ammonia::{Builder, UrlRelative};
-    ///     let mut a = Builder::default();
-    ///     a.link_rel(Some("a b"));
-    ///     assert_eq!(a.get_link_rel(), Some("a b"));
-    pub fn get_link_rel(&self) -> Option<&str> {
-        self.link_rel
-    }
-
-    /// Sets the CSS classes that are allowed on specific tags.
+    /// The URLs in these attributes, and in the [`generic_url_attributes`], are checked
+    /// against [`url_schemes`] and rewritten by [`url_relative`]. Attributes that aren't
+    /// in either are treated as plain text, so adding an attribute that can hold a URL to
+    /// [`tag_attributes`] without adding it here lets any scheme through.
     ///
-    /// The values is structured as a map from tag names to a set of class names.
+    /// Attributes are matched by their local name, so `href` also covers `xlink:href`.
+    /// `srcset` on `img` and `source`, and `imagesrcset` on `link`, hold a list of URLs,
+    /// and are always handled separately.
     ///
-    /// If the `class` attribute is itself whitelisted for a tag, then adding entries to
-    /// this map will cause a panic.
+    /// [`generic_url_attributes`]: #method.generic_url_attributes
+    /// [`url_schemes`]: #method.url_schemes
+    /// [`url_relative`]: #method.url_relative
+    /// [`tag_attributes`]: #method.tag_attributes
     ///
     /// # Examples
     ///
@@ -1501,35 +1452,47 @@ impl<'a> Builder<'a> {
     ///     use maplit::{hashmap, hashset};
     ///
     ///     # fn main() {
-    ///     let allowed_classes = hashmap![
-    ///         "code" => hashset!["rs", "ex", "c", "cxx", "js"]
+    ///     let url_attributes = hashmap![
+    ///         "blockquote" => hashset!["cite"]
     ///     ];
     ///     let a = Builder::new()
-    ///         .allowed_classes(allowed_classes)
-    ///         .clean("<code class=rs>fn main() {}</code>")
+    ///         .url_attributes(url_attributes)
+    ///         .clean("<blockquote cite=\"javascript:alert(1)\">quote</blockquote>")
     ///         .to_string();
-    ///     assert_eq!(
-    ///       a,
-    ///       "<code class=\"rs\">fn main() {}</code>");
+    ///     assert_eq!(a, "<blockquote>quote</blockquote>");
     ///     # }
     ///
     /// # Defaults
     ///
-    /// The set of allowed classes is empty by default.
-    pub fn allowed_classes(&mut self, value: HashMap<&'a str, HashSet<&'a str>>) -> &mut Self {
-        self.allowed_classes = value;
+    /// ```notest
+    /// a =>
+    ///     ping
+    /// button =>
+    ///     formaction
+    /// form =>
+    ///     action
+    /// input =>
+    ///     formaction
+    /// object =>
+    ///     data
+    /// video =>
+    ///     poster
+    /// ```
+    pub fn url_attributes(&mut self, value: HashMap<&'a str, HashSet<&'a str>>) -> &mut Self {
+        self.url_attributes = value;
         self
     }
 
-    /// Add additonal whitelisted classes without overwriting old ones.
+    /// Add additional tag-specific URL attributes without overwriting old ones.
     ///
     /// # Examples
     ///
     ///     let a = ammonia::Builder::default()
-    ///         .add_allowed_classes("a", &["onebox"])
-    ///         .clean("<a href=/ class=onebox>mess</span>").to_string();
-    ///     assert_eq!("<a href=\"/\" class=\"onebox\" rel=\"noopener noreferrer\">mess</a>", a);
-    pub fn add_allowed_classes<
+    ///         .add_tag_attributes("img", &["longdesc"])
+    ///         .add_url_attributes("img", &["longdesc"])
+    ///         .clean("<img longdesc=\"javascript:alert(1)\"><img longdesc=\"/desc\">").to_string();
+    ///     assert_eq!("<img><img longdesc=\"/desc\">", a);
+    pub fn add_url_attributes<
         T: 'a + ?Sized + Borrow<str>,
         U: 'a + ?Sized + Borrow<str>,
         I: IntoIter<Item = &'a T>,
@@ -1538,25 +1501,26 @@ impl<'a> Builder<'a> {
         tag: &'a U,
         it: I,
     ) -> &mut Self {
-        self.allowed_classes
+        self.url_attributes
             .entry(tag.borrow())
             .or_default()
             .extend(it.into_iter().map(Borrow::borrow));
         self
     }
 
-    /// Remove already-whitelisted attributes.
+    /// Remove already-registered tag-specific URL attributes.
     ///
     /// Does nothing if the attribute is already gone.
     ///
     /// # Examples
     ///
     ///     let a = ammonia::Builder::default()
-    ///         .add_allowed_classes("span", &["active"])
-    ///         .rm_allowed_classes("span", &["active"])
-    ///         .clean("<span class=active>").to_string();
-    ///     assert_eq!("<span class=\"\"></span>", a);
-    pub fn rm_allowed_classes<
+    ///         .add_tags(&["form"])
+    ///         .add_tag_attributes("form", &["action"])
+    ///         .rm_url_attributes("form", &["action"])
+    ///         .clean("<form action=\"tel:123\"></form>").to_string();
+    ///     assert_eq!("<form action=\"tel:123\"></form>", a);
+    pub fn rm_url_attributes<
         'b,
         'c,
         T: 'b + ?Sized + Borrow<str>,
@@ -1567,7 +1531,7 @@ impl<'a> Builder<'a> {
         tag: &'c U,
         it: I,
     ) -> &mut Self {
-        if let Some(tag) = self.allowed_classes.get_mut(tag.borrow()) {
+        if let Some(tag) = self.url_attributes.get_mut(tag.borrow()) {
             for i in it {
                 tag.remove(i.borrow());
             }
@@ -1575,709 +1539,3253 @@ impl<'a> Builder<'a> {
         self
     }
 
-    /// Returns a copy of the set of whitelisted class attributes.
+    /// Returns a copy of the set of tag-specific URL attributes.
     ///
     /// # Examples
-    ///
     ///     use maplit::{hashmap, hashset};
     ///
-    ///     let allowed_classes = hashmap![
-    ///         "my-tag" => hashset!["my-class-1", "my-class-2"]
+    ///     let url_attributes = hashmap![
+    ///         "my-tag" => hashset!["my-url-1", "my-url-2"]
     ///     ];
     ///
     ///     let mut b = ammonia::Builder::default();
-    ///     b.allowed_classes(Clone::clone(&allowed_classes));
-    ///     assert_eq!(allowed_classes, b.clone_allowed_classes());
-    pub fn clone_allowed_classes(&self) -> HashMap<&'a str, HashSet<&'a str>> {
-        self.allowed_classes.clone()
+    ///     b.url_attributes(Clone::clone(&url_attributes));
+    ///     assert_eq!(url_attributes, b.clone_url_attributes());
+    pub fn clone_url_attributes(&self) -> HashMap<&'a str, HashSet<&'a str>> {
+        self.url_attributes.clone()
     }
 
-    /// Configures the handling of HTML comments.
+    /// Sets the attributes that hold a URL on any tag.
     ///
-    /// If this option is false, comments will be preserved.
+    /// These get the same treatment as the [`url_attributes`].
+    ///
+    /// [`url_attributes`]: #method.url_attributes
     ///
     /// # Examples
     ///
     ///     use ammonia::Builder;
+    ///     use maplit::hashset;
     ///
-    ///     let a = Builder::new().strip_comments(false)
-    ///         .clean("<!-- yes -->")
+    ///     # fn main() {
+    ///     let a = Builder::new()
+    ///         .add_generic_attributes(&["data-href"])
+    ///         .generic_url_attributes(hashset!["href", "src", "data-href"])
+    ///         .clean("<span data-href=\"javascript:alert(1)\">x</span>")
     ///         .to_string();
-    ///     assert_eq!(
-    ///       a,
-    ///       "<!-- yes -->");
+    ///     assert_eq!(a, "<span>x</span>");
+    ///     # }
     ///
     /// # Defaults
     ///
-    /// `true`
-    pub fn strip_comments(&mut self, value: bool) -> &mut Self {
-        self.strip_comments = value;
+    /// ```notest
+    /// href, src
+    /// ```
+    pub fn generic_url_attributes(&mut self, value: HashSet<&'a str>) -> &mut Self {
+        self.generic_url_attributes = value;
         self
     }
 
-    /// Returns `true` if comment stripping is turned on.
+    /// Add additional generic URL attributes without overwriting old ones.
     ///
     /// # Examples
     ///
-    ///     let mut a = ammonia::Builder::new();
-    ///     a.strip_comments(true);
-    ///     assert!(a.will_strip_comments());
-    ///     a.strip_comments(false);
-    ///     assert!(!a.will_strip_comments());
-    pub fn will_strip_comments(&self) -> bool {
-        self.strip_comments
+    ///     let a = ammonia::Builder::default()
+    ///         .add_generic_attributes(&["itemid"])
+    ///         .add_generic_url_attributes(&["itemid"])
+    ///         .clean("<span itemid=\"javascript:alert(1)\">x</span>").to_string();
+    ///     assert_eq!("<span>x</span>", a);
+    pub fn add_generic_url_attributes<T: 'a + ?Sized + Borrow<str>, I: IntoIter<Item = &'a T>>(
+        &mut self,
+        it: I,
+    ) -> &mut Self {
+        self.generic_url_attributes
+            .extend(it.into_iter().map(Borrow::borrow));
+        self
     }
 
-    /// Prefixes all "id" attribute values with a given string.  Note that the tag and
-    /// attribute themselves must still be whitelisted.
+    /// Remove already-registered generic URL attributes.
     ///
-    /// # Examples
+    /// Does nothing if the attribute is already gone.
     ///
-    ///     use ammonia::Builder;
-    ///     use maplit::hashset;
+    /// # Examples
     ///
-    ///     # fn main() {
-    ///     let attributes = hashset!["id"];
-    ///     let a = Builder::new()
-    ///         .generic_attributes(attributes)
-    ///         .id_prefix(Some("safe-"))
-    ///         .clean("<b id=42>")
-    ///         .to_string();
-    ///     assert_eq!(a, "<b id=\"safe-42\"></b>");
-    ///     # }
+    ///     let a = ammonia::Builder::default()
+    ///         .rm_generic_url_attributes(&["href"])
+    ///         .clean("<a href=\"tel:123\">call</a>").to_string();
+    ///     assert_eq!("<a href=\"tel:123\" rel=\"noopener noreferrer\">call</a>", a);
+    pub fn rm_generic_url_attributes<
+        'b,
+        T: 'b + ?Sized + Borrow<str>,
+        I: IntoIter<Item = &'b T>,
+    >(
+        &mut self,
+        it: I,
+    ) -> &mut Self {
+        for i in it {
+            self.generic_url_attributes.remove(i.borrow());
+        }
+        self
+    }
 
+    /// Returns a copy of the set of generic URL attributes.
     ///
-    /// # Defaults
+    /// # Examples
     ///
-    /// `None`
-    pub fn id_prefix(&mut self, value: Option<&'a str>) -> &mut Self {
-        self.id_prefix = value;
-        self
+    ///     use maplit::hashset;
+    ///
+    ///     let generic_url_attributes = hashset!["my-url-1", "my-url-2"];
+    ///
+    ///     let mut b = ammonia::Builder::default();
+    ///     b.generic_url_attributes(Clone::clone(&generic_url_attributes));
+    ///     assert_eq!(generic_url_attributes, b.clone_generic_url_attributes());
+    pub fn clone_generic_url_attributes(&self) -> HashSet<&'a str> {
+        self.generic_url_attributes.clone()
     }
 
-    /// Only allows the specified properties in `style` attributes.
+    /// Sets the hosts that URLs can point at, for specific attributes on specific tags.
     ///
-    /// Irrelevant if `style` is not an allowed attribute.
+    /// The value is structured as a map from tag names to a map from attribute names to
+    /// a [`UrlPolicy`]. The policy is checked along with the [`url_schemes`], and URLs that
+    /// fail it are removed. Attributes without a tag-specific policy use the
+    /// [`generic_url_policy`], if there is one.
     ///
-    /// Note that if style filtering is enabled style properties will be normalised e.g.
-    /// invalid declarations and @rules will be removed, with only syntactically valid
-    /// declarations kept.
+    /// [`UrlPolicy`]: struct.UrlPolicy.html
+    /// [`url_schemes`]: #method.url_schemes
+    /// [`generic_url_policy`]: #method.generic_url_policy
     ///
     /// # Examples
     ///
-    ///     use ammonia::Builder;
-    ///     use maplit::hashset;
+    ///     use ammonia::{Builder, UrlPolicy};
+    ///     use maplit::hashmap;
     ///
-    ///     # fn main() {
-    ///     let attributes = hashset!["style"];
-    ///     let properties = hashset!["color"];
+    ///     # fn main() -> Result<(), url::ParseError> {
+    ///     let policies = hashmap![
+    ///         "img" => hashmap![
+    ///             "src" => UrlPolicy::new().allow_hosts(["*.ourcdn.com"])?,
+    ///         ],
+    ///     ];
     ///     let a = Builder::new()
-    ///         .generic_attributes(attributes)
-    ///         .filter_style_properties(properties)
-    ///         .clean("<p style=\"font-weight: heavy; color: red\">my html</p>")
+    ///         .url_policies(policies)
+    ///         .clean("<img src=https://example.com/a.png><a href=https://example.com/>link</a>")
     ///         .to_string();
-    ///     assert_eq!(a, "<p style=\"color:red\">my html</p>");
+    ///     assert_eq!(a, "<img><a href=\"https://example.com/\" rel=\"noopener noreferrer\">link</a>");
+    ///     # Ok(())
     ///     # }
-    pub fn filter_style_properties(&mut self, value: HashSet<&'a str>) -> &mut Self {
-        self.style_properties = Some(value);
+    ///
+    /// # Defaults
+    ///
+    /// No hosts are restricted by default.
+    pub fn url_policies(&mut self, value: HashMap<&'a str, HashMap<&'a str, UrlPolicy>>) -> &mut Self {
+        self.url_policies = value;
         self
     }
 
-    /// Constructs a [`Builder`] instance configured with the [default options].
+    /// Sets the hosts that URLs can point at for one attribute on one tag,
+    /// replacing any policy it already had.
     ///
     /// # Examples
     ///
-    ///     use ammonia::{Builder, Url, UrlRelative};
-    ///     # use std::error::Error;
-    ///
-    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
-    ///     let input = "<!-- comments will be stripped -->This is an <a href=.>Ammonia</a> example using <a href=struct.Builder.html#method.new onclick=xss>the <code onmouseover=xss>new()</code> function</a>.";
-    ///     let output = "This is an <a href=\"https://docs.rs/ammonia/1.0/ammonia/\" rel=\"noopener noreferrer\">Ammonia</a> example using <a href=\"https://docs.rs/ammonia/1.0/ammonia/struct.Builder.html#method.new\" rel=\"noopener noreferrer\">the <code>new()</code> function</a>.";
+    ///     use ammonia::UrlPolicy;
     ///
-    ///     let result = Builder::new() // <--
-    ///         .url_relative(UrlRelative::RewriteWithBase(Url::parse("https://docs.rs/ammonia/1.0/ammonia/")?))
-    ///         .clean(input)
-    ///         .to_string();
-    ///     assert_eq!(result, output);
+    ///     # fn main() -> Result<(), url::ParseError> {
+    ///     let a = ammonia::Builder::default()
+    ///         .url_policy("a", "href", UrlPolicy::new().deny_hosts(["*.phishing.example"])?)
+    ///         .clean("<a href=https://login.phishing.example/>log in</a>").to_string();
+    ///     assert_eq!("<a rel=\"noopener noreferrer\">log in</a>", a);
     ///     # Ok(())
     ///     # }
-    ///     # fn main() { do_main().unwrap() }
+    pub fn url_policy<T: 'a + ?Sized + Borrow<str>, U: 'a + ?Sized + Borrow<str>>(
+        &mut self,
+        tag: &'a T,
+        attribute: &'a U,
+        policy: UrlPolicy,
+    ) -> &mut Self {
+        self.url_policies
+            .entry(tag.borrow())
+            .or_default()
+            .insert(attribute.borrow(), policy);
+        self
+    }
+
+    /// Remove an already-configured tag-specific URL policy.
     ///
-    /// [default options]: fn.clean.html
-    /// [`Builder`]: struct.Builder.html
-    pub fn new() -> Self {
-        Self::default()
+    /// Does nothing if the attribute has no policy.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::UrlPolicy;
+    ///
+    ///     # fn main() -> Result<(), url::ParseError> {
+    ///     let a = ammonia::Builder::default()
+    ///         .url_policy("a", "href", UrlPolicy::new().deny_hosts(["example.com"])?)
+    ///         .rm_url_policy("a", "href")
+    ///         .clean("<a href=https://example.com/>link</a>").to_string();
+    ///     assert_eq!("<a href=\"https://example.com/\" rel=\"noopener noreferrer\">link</a>", a);
+    ///     # Ok(())
+    ///     # }
+    pub fn rm_url_policy<'b, T: 'b + ?Sized + Borrow<str>, U: 'b + ?Sized + Borrow<str>>(
+        &mut self,
+        tag: &'b T,
+        attribute: &'b U,
+    ) -> &mut Self {
+        if let Some(attributes) = self.url_policies.get_mut(tag.borrow()) {
+            attributes.remove(attribute.borrow());
+        }
+        self
     }
 
-    /// Constructs a [`Builder`] instance configured with no allowed tags.
+    /// Returns a copy of the map of tag-specific URL policies.
     ///
     /// # Examples
     ///
-    ///     use ammonia::{Builder, Url, UrlRelative};
-    ///     # use std::error::Error;
+    ///     use ammonia::UrlPolicy;
+    ///     use maplit::hashmap;
     ///
-    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
-    ///     let input = "<!-- comments will be stripped -->This is an <a href=.>Ammonia</a> example using <a href=struct.Builder.html#method.new onclick=xss>the <code onmouseover=xss>empty()</code> function</a>.";
-    ///     let output = "This is an Ammonia example using the empty() function.";
+    ///     let policies = hashmap!["img" => hashmap!["src" => UrlPolicy::new()]];
     ///
-    ///     let result = Builder::empty() // <--
-    ///         .url_relative(UrlRelative::RewriteWithBase(Url::parse("https://docs.rs/ammonia/1.0/ammonia/")?))
-    ///         .clean(input)
+    ///     let mut b = ammonia::Builder::default();
+    ///     b.url_policies(Clone::clone(&policies));
+    ///     assert_eq!(policies, b.clone_url_policies());
+    pub fn clone_url_policies(&self) -> HashMap<&'a str, HashMap<&'a str, UrlPolicy>> {
+        self.url_policies.clone()
+    }
+
+    /// Sets the hosts that URLs can point at, for URL attributes without a
+    /// [tag-specific policy](#method.url_policies).
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, UrlPolicy};
+    ///
+    ///     # fn main() -> Result<(), url::ParseError> {
+    ///     let a = Builder::new()
+    ///         .generic_url_policy(Some(UrlPolicy::new().allow_hosts(["example.com"])?))
+    ///         .clean("<a href=https://example.com/>ok</a><a href=//evil.example/>bad</a>")
     ///         .to_string();
-    ///     assert_eq!(result, output);
+    ///     assert_eq!(a, "<a href=\"https://example.com/\" rel=\"noopener noreferrer\">ok</a><a rel=\"noopener noreferrer\">bad</a>");
     ///     # Ok(())
     ///     # }
-    ///     # fn main() { do_main().unwrap() }
     ///
-    /// [default options]: fn.clean.html
-    /// [`Builder`]: struct.Builder.html
-    pub fn empty() -> Self {
-        Self {
-            tags: hashset![],
-            ..Self::default()
-        }
+    /// # Defaults
+    ///
+    /// `None`, so no hosts are restricted.
+    pub fn generic_url_policy(&mut self, value: Option<UrlPolicy>) -> &mut Self {
+        self.generic_url_policy = value;
+        self
     }
 
-    /// Sanitizes an HTML fragment in a string according to the configured options.
+    /// Restricts where elements can load images, media, frames and plugins from,
+    /// using Content Security Policy directives like `img-src`.
+    ///
+    /// URLs that violate the policy are handled according to [`csp_violation_action`],
+    /// and listed as [`Removal::CspViolation`] by [`clean_with_report`].
+    /// See [`ContentSecurityPolicy`] for the directives and sources that are supported.
+    ///
+    /// [`csp_violation_action`]: #method.csp_violation_action
+    /// [`Removal::CspViolation`]: enum.Removal.html#variant.CspViolation
+    /// [`clean_with_report`]: #method.clean_with_report
+    /// [`ContentSecurityPolicy`]: struct.ContentSecurityPolicy.html
     ///
     /// # Examples
     ///
-    ///     use ammonia::{Builder, Url, UrlRelative};
-    ///     # use std::error::Error;
+    ///     use ammonia::{Builder, ContentSecurityPolicy};
     ///
-    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
-    ///     let input = "<!-- comments will be stripped -->This is an <a href=.>Ammonia</a> example using <a href=struct.Builder.html#method.new onclick=xss>the <code onmouseover=xss>new()</code> function</a>.";
-    ///     let output = "This is an <a href=\"https://docs.rs/ammonia/1.0/ammonia/\" rel=\"noopener noreferrer\">Ammonia</a> example using <a href=\"https://docs.rs/ammonia/1.0/ammonia/struct.Builder.html#method.new\" rel=\"noopener noreferrer\">the <code>new()</code> function</a>.";
+    ///     # fn main() -> Result<(), ammonia::CspError> {
+    ///     let csp = ContentSecurityPolicy::parse(
+    ///         "img-src https://cdn.example; media-src 'none'; frame-src https://www.youtube-nocookie.com",
+    ///     )?;
+    ///     let a = Builder::default()
+    ///         .add_tags(&["audio"])
+    ///         .content_security_policy(Some(csp))
+    ///         .clean("<img src=/a.png><audio src=https://cdn.example/a.ogg></audio>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<img><audio></audio>");
+    ///     # Ok(())
+    ///     # }
     ///
-    ///     let result = Builder::new()
-    ///         .url_relative(UrlRelative::RewriteWithBase(Url::parse("https://docs.rs/ammonia/1.0/ammonia/")?))
-    ///         .clean(input)
-    ///         .to_string(); // <--
-    ///     assert_eq!(result, output);
+    /// # Defaults
+    ///
+    /// `None`, so no directives apply.
+    pub fn content_security_policy(&mut self, value: Option<ContentSecurityPolicy>) -> &mut Self {
+        self.content_security_policy = value;
+        self
+    }
+
+    /// Configures what happens to an element when one of its URLs violates the
+    /// [`content_security_policy`].
+    ///
+    /// [`content_security_policy`]: #method.content_security_policy
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, ContentSecurityPolicy, CspViolationAction};
+    ///
+    ///     # fn main() -> Result<(), ammonia::CspError> {
+    ///     let a = Builder::default()
+    ///         .content_security_policy(Some(ContentSecurityPolicy::parse("img-src https:")?))
+    ///         .csp_violation_action(CspViolationAction::RemoveElement)
+    ///         .clean("<p><img src=http://example.com/a.png alt=a> text</p>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<p> text</p>");
     ///     # Ok(())
     ///     # }
-    ///     # fn main() { do_main().unwrap() }
-    pub fn clean(&self, src: &str) -> Document {
-        let parser = Self::make_parser();
-        let dom = parser.one(src);
-        self.clean_dom(dom)
+    ///
+    /// # Defaults
+    ///
+    /// `CspViolationAction::RemoveAttribute`
+    pub fn csp_violation_action(&mut self, value: CspViolationAction) -> &mut Self {
+        self.csp_violation_action = value;
+        self
     }
 
-    /// Sanitizes an HTML fragment from a reader according to the configured options.
+    /// Allows `<iframe>` elements that embed pages from these providers.
     ///
-    /// The input should be in UTF-8 encoding, otherwise the decoding is lossy, just
-    /// like when using [`String::from_utf8_lossy`].
+    /// Adding `iframe` to the [`tags`] lets it point anywhere, and keeps whatever
+    /// `sandbox` or `srcdoc` it came with. With this option, an iframe is only kept if
+    /// its `src` matches one of the providers, after [`url_relative`] and the
+    /// [`attribute_filter`] have run. Its `srcdoc` is dropped, and its `sandbox`,
+    /// `allow` and `referrerpolicy` attributes are replaced with the provider's.
+    /// Besides those, iframes can have `src`, `width`, `height`, `title` and `loading`
+    /// attributes, and the ones allowed for every tag.
     ///
-    /// To avoid consuming the reader, a mutable reference can be passed to this method.
+    /// Iframes that don't match any provider are removed, and reported as
+    /// [`Removal::Iframe`].
+    ///
+    /// [`tags`]: #method.tags
+    /// [`url_relative`]: #method.url_relative
+    /// [`attribute_filter`]: #method.attribute_filter
+    /// [`Removal::Iframe`]: enum.Removal.html#variant.Iframe
     ///
     /// # Examples
     ///
-    ///     use ammonia::Builder;
-    ///     # use std::error::Error;
+    ///     use ammonia::{Builder, IframeProvider};
     ///
-    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
-    ///     let a = Builder::new()
-    ///         .clean_from_reader(&b"<!-- no -->"[..])? // notice the `b`
+    ///     let a = Builder::default()
+    ///         .allow_iframes([IframeProvider::youtube(), IframeProvider::vimeo()])
+    ///         .clean("<iframe src=https://player.vimeo.com/video/1 sandbox=allow-top-navigation></iframe>\
+    ///                 <iframe src=https://example.com/></iframe>")
     ///         .to_string();
-    ///     assert_eq!(a, "");
-    ///     # Ok(()) }
-    ///     # fn main() { do_main().unwrap() }
+    ///     assert_eq!(
+    ///         a,
+    ///         "<iframe src=\"https://player.vimeo.com/video/1\" \
+    ///          sandbox=\"allow-scripts allow-same-origin allow-presentation allow-popups\" \
+    ///          allow=\"fullscreen; picture-in-picture\" \
+    ///          referrerpolicy=\"strict-origin-when-cross-origin\"></iframe>"
+    ///     );
     ///
-    /// [`String::from_utf8_lossy`]: https://doc.rust-lang.org/std/string/struct.String.html#method.from_utf8_lossy
-    pub fn clean_from_reader<R>(&self, mut src: R) -> io::Result<Document>
-    where
-        R: io::Read,
-    {
-        let parser = Self::make_parser().from_utf8();
-        let dom = parser.read_from(&mut src)?;
-        Ok(self.clean_dom(dom))
+    /// # Defaults
+    ///
+    /// No providers, so iframes are only kept if they are in [`tags`].
+    pub fn allow_iframes<I: IntoIterator<Item = IframeProvider>>(&mut self, providers: I) -> &mut Self {
+        self.iframe_providers = providers.into_iter().collect();
+        self
     }
 
-    /// Clean a post-parsing DOM.
+    /// Allows `data:` URLs in images that pass a [`DataUriPolicy`], even though `data` isn't
+    /// in [`url_schemes`].
     ///
-    /// This is not a public API because RcDom isn't really stable.
-    /// We want to be able to take breaking changes to html5ever itself
-    /// without having to break Ammonia's API.
-    fn clean_dom(&self, dom: RcDom) -> Document {
-        let mut stack = Vec::new();
-        let mut removed = Vec::new();
-        let link_rel = self
-            .link_rel
-            .map(|link_rel| format_tendril!("{}", link_rel));
-        if link_rel.is_some() {
-            assert!(self.generic_attributes.get("rel").is_none());
-            assert!(self
-                .tag_attributes
-                .get("a")
-                .and_then(|a| a.get("rel"))
-                .is_none());
-        }
-        assert!(self.allowed_classes.is_empty() || !self.generic_attributes.contains("class"));
-        for tag_name in self.allowed_classes.keys() {
-            assert!(self
-                .tag_attributes
-                .get(tag_name)
-                .and_then(|a| a.get("class"))
-                .is_none());
-        }
-        for tag_name in &self.clean_content_tags {
-            assert!(!self.tags.contains(tag_name), "`{tag_name}` appears in `clean_content_tags` and in `tags` at the same time");
-            assert!(!self.tag_attributes.contains_key(tag_name), "`{tag_name}` appears in `clean_content_tags` and in `tag_attributes` at the same time");
-        }
-        let body = {
-            let children = dom.document.children.borrow();
-            children[0].clone()
-        };
-        stack.extend(
-            mem::take(&mut *body.children.borrow_mut())
-                .into_iter()
-                .rev(),
-        );
-        // This design approach is used to prevent pathological content from producing
-        // a stack overflow. The `stack` contains to-be-cleaned nodes, while `remove`,
-        // of course, contains nodes that need to be dropped (we can't just drop them,
-        // because they could have a very deep child tree).
-        while let Some(mut node) = stack.pop() {
-            if matches!(node.data, NodeData::Element { ref name, .. } if &*name.local == "selectedcontent" && name.ns == ns!(html)) &&
-                self.is_within(node.clone(), ns!(html), "select")
-            {
-                for sub in node.children.borrow_mut().iter_mut() {
-                    sub.parent.replace(None);
-                }
-                *node.children.borrow_mut() = Vec::new();
-            }
-            let parent = node.parent
-                .replace(None).expect("a node in the DOM will have a parent, except the root, which is not processed")
-                .upgrade().expect("a node's parent will be pointed to by its parent (or the root pointer), and will not be dropped");
-            if self.clean_node_content(&node) || !self.check_expected_namespace(&parent, &node) {
-                removed.push(node);
-                continue;
-            }
-            let pass = self.clean_child(&mut node);
-            if pass {
-                self.adjust_node_attributes(&mut node, &link_rel, self.id_prefix);
-                dom.append(&parent.clone(), NodeOrText::AppendNode(node.clone()));
-            } else {
-                for sub in node.children.borrow_mut().iter_mut() {
-                    sub.parent.replace(Some(Rc::downgrade(&parent)));
-                }
-            }
-            stack.extend(
-                mem::take(&mut *node.children.borrow_mut())
-                    .into_iter()
-                    .rev(),
-            );
-            if !pass {
-                removed.push(node);
-            }
-        }
-        // Now, imperatively clean up all of the child nodes.
-        // Otherwise, we could wind up with a DoS, either caused by a memory leak,
-        // or caused by a stack overflow.
-        while let Some(node) = removed.pop() {
-            removed.extend_from_slice(&mem::take(&mut *node.children.borrow_mut())[..]);
-        }
-        Document(dom)
+    /// This applies to `<img src>`, `<img srcset>`, `<source srcset>` and `url()` in CSS.
+    /// Everywhere else, like `<a href>` or `<iframe src>`, `data:` URLs are still only allowed
+    /// if `data` is in [`url_schemes`].
+    ///
+    /// Adding `data` to [`url_schemes`] lets through every `data:` URL, including
+    /// `data:text/html` and `data:image/svg+xml` documents that can run scripts. With a
+    /// policy, only types from its allowlist are kept, their base64 has to be valid,
+    /// they can't be too large, and their magic bytes can be checked against the type.
+    /// The rest are removed and reported as [`Removal::DataUri`].
+    ///
+    /// Allowed `data:` URLs have no host, so the [`url_policies`] don't apply to them,
+    /// but the [`content_security_policy`] does.
+    ///
+    /// [`url_schemes`]: #method.url_schemes
+    /// [`url_policies`]: #method.url_policies
+    /// [`content_security_policy`]: #method.content_security_policy
+    /// [`Removal::DataUri`]: enum.Removal.html#variant.DataUri
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, DataUriPolicy};
+    ///
+    ///     let png = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+    ///     let input = format!("<img src=\"{png}\"><img src=\"data:image/svg+xml,<svg/>\">");
+    ///     let a = Builder::default()
+    ///         .data_uri_policy(Some(DataUriPolicy::new()))
+    ///         .clean(&input)
+    ///         .to_string();
+    ///     assert_eq!(a, format!("<img src=\"{png}\"><img>"));
+    ///
+    /// # Defaults
+    ///
+    /// `None`, so `data:` URLs are only allowed if `data` is in [`url_schemes`].
+    pub fn data_uri_policy(&mut self, value: Option<DataUriPolicy>) -> &mut Self {
+        self.data_uri_policy = value;
+        self
     }
 
-    fn is_within(&self, mut child: Handle, ns: Namespace, tag: &str) -> bool {
-        while let Some(parent) = child.parent.take() {
-            child.parent.set(Some(parent.clone()));
-            match child.data {
-                NodeData::Element { ref name, .. } if name.ns == ns && &*name.local == tag => return true,
-                _ => {
-                    if let Some(parent) = parent.upgrade() {
-                        child = parent;
-                    } else {
-                        return false;
-                    }
-                }
-            }
-        }
-        false
+    /// Detects URLs whose host looks like another one, and handles them according to the
+    /// given [`HomographAction`].
+    ///
+    /// A host is confusable if one of its labels mixes scripts, like `аpple.com` with a
+    /// Cyrillic `а`, or is entirely made of Cyrillic or Greek letters that look Latin, like
+    /// `аррӏе.com`, unless the top-level domain is in the same script. Latin can be mixed
+    /// with Han and the Japanese or Korean scripts. Relative URLs are never confusable.
+    ///
+    /// Confusable URLs are reported as [`Removal::Homograph`], even if they are kept.
+    /// This check runs after the [`url_policies`] and the [`content_security_policy`],
+    /// so a URL they reject is only reported once.
+    ///
+    /// [`url_policies`]: #method.url_policies
+    /// [`content_security_policy`]: #method.content_security_policy
+    /// [`Removal::Homograph`]: enum.Removal.html#variant.Homograph
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, HomographAction};
+    ///
+    ///     let a = Builder::default()
+    ///         .homograph_action(Some(HomographAction::Punycode))
+    ///         .clean("<a href=\"https://\u{430}pple.com/\">apple.com</a>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<a href=\"https://xn--pple-43d.com/\" rel=\"noopener noreferrer\">apple.com</a>");
+    ///
+    /// # Defaults
+    ///
+    /// `None`, so hosts aren't checked.
+    pub fn homograph_action(&mut self, value: Option<HomographAction>) -> &mut Self {
+        self.homograph_action = value;
+        self
     }
 
-    /// Returns `true` if a node and all its content should be removed.
-    fn clean_node_content(&self, node: &Handle) -> bool {
-        match node.data {
-            NodeData::Text { .. }
-            | NodeData::Comment { .. }
-            | NodeData::Doctype { .. }
-            | NodeData::Document
-            | NodeData::ProcessingInstruction { .. } => false,
-            NodeData::Element { ref name, .. } => self.clean_content_tags.contains(&*name.local),
-        }
+    /// Detects links whose text looks like a URL on another host than their `href`, and
+    /// handles them according to the given [`LinkTextAction`].
+    ///
+    /// The text is compared after cleaning, so it's what the reader will see. It counts as
+    /// a URL if it's an absolute URL, or a host name or IP address with an optional path, like
+    /// `bank.example/login`. A leading `www.` is ignored, and the `href` may be on a
+    /// subdomain of the host in the text, but an IP address only matches itself.
+    /// Scheme-relative links, like `//bank.example/`, are compared by their host, while links
+    /// to relative URLs, or to URLs without a host, like `mailto:`, are left alone. The `href`
+    /// is taken before [`url_relative`] or [`absolute_url_rewriter`] change it, so a
+    /// redirector doesn't hide where the link goes.
+    ///
+    /// Each mismatched link is reported as [`Removal::LinkText`]. [`clean_stream`], which
+    /// writes a link out before its text has been read, panics if this is set.
+    ///
+    /// [`url_relative`]: #method.url_relative
+    /// [`absolute_url_rewriter`]: #method.absolute_url_rewriter
+    /// [`clean_stream`]: #method.clean_stream
+    /// [`Removal::LinkText`]: enum.Removal.html#variant.LinkText
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, LinkTextAction};
+    ///
+    ///     let a = Builder::default()
+    ///         .link_text_mismatch(Some(LinkTextAction::ShowDestination))
+    ///         .clean("<a href=\"https://evil.example/\">https://<b>bank.example</b>/</a>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<a href=\"https://evil.example/\" rel=\"noopener noreferrer\">https://evil.example/</a>");
+    ///
+    /// # Defaults
+    ///
+    /// `None`, so link text isn't checked.
+    pub fn link_text_mismatch(&mut self, value: Option<LinkTextAction>) -> &mut Self {
+        self.link_text_mismatch = value;
+        self
     }
 
-    /// Remove unwanted attributes, and check if the node should be kept or not.
+    /// Configures the behavior for relative URLs: pass-through, resolve-with-base, or deny.
     ///
-    /// The root node doesn't need cleaning because we create the root node ourselves,
-    /// and it doesn't get serialized, and ... it just exists to give the parser
-    /// a context (in this case, a div-like block context).
-    fn clean_child(&self, child: &mut Handle) -> bool {
-        match child.data {
-            NodeData::Text { .. } => true,
-            NodeData::Comment { .. } => !self.strip_comments,
-            NodeData::Doctype { .. }
-            | NodeData::Document
-            | NodeData::ProcessingInstruction { .. } => false,
-            NodeData::Element {
-                ref name,
-                ref attrs,
-                ..
-            } => {
-                if self.tags.contains(&*name.local) {
-                    let attr_filter = |attr: &html5ever::Attribute| {
-                        let whitelisted = self.generic_attributes.contains(&*attr.name.local)
-                            || self.generic_attribute_prefixes.as_ref().map(|prefixes| {
-                                prefixes.iter().any(|&p| attr.name.local.starts_with(p))
-                            }) == Some(true)
-                            || self
-                                .tag_attributes
-                                .get(&*name.local)
-                                .map(|ta| ta.contains(&*attr.name.local))
-                                == Some(true)
-                            || self
-                                .tag_attribute_values
-                                .get(&*name.local)
-                                .and_then(|tav| tav.get(&*attr.name.local))
-                                .map(|vs| {
-                                    let attr_val = attr.value.to_lowercase();
-                                    vs.iter().any(|v| v.to_lowercase() == attr_val)
-                                })
-                                == Some(true);
-                        if !whitelisted {
-                            // If the class attribute is not whitelisted,
-                            // but there is a whitelisted set of allowed_classes,
-                            // do not strip out the class attribute.
-                            // Banned classes will be filtered later.
-                            &*attr.name.local == "class"
-                                && self.allowed_classes.contains_key(&*name.local)
-                        } else if is_url_attr(&name.local, &attr.name.local) {
-                            let url = Url::parse(&attr.value);
-                            if let Ok(url) = url {
-                                self.url_schemes.contains(url.scheme())
-                            } else if url == Err(url::ParseError::RelativeUrlWithoutBase) {
-                                !matches!(self.url_relative, UrlRelative::Deny)
-                            } else {
-                                false
-                            }
-                        } else {
-                            true
-                        }
-                    };
-                    attrs.borrow_mut().retain(attr_filter);
-                    true
-                } else {
-                    false
-                }
-            }
-        }
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, UrlRelative};
+    ///
+    ///     let a = Builder::new().url_relative(UrlRelative::PassThrough)
+    ///         .clean("<a href=/>Home</a>")
+    ///         .to_string();
+    ///
+    ///     // See `link_rel` for information on the rel="noopener noreferrer" attribute
+    ///     // in the cleaned HTML.
+    ///     assert_eq!(
+    ///       a,
+    ///       "<a href=\"/\" rel=\"noopener noreferrer\">Home</a>");
+    ///
+    /// # Defaults
+    ///
+    /// ```notest
+    /// UrlRelative::PassThrough
+    /// ```
+    pub fn url_relative(&mut self, value: UrlRelative<'a>) -> &mut Self {
+        self.url_relative = value;
+        self
     }
 
-    // Check for unexpected namespace changes.
-    //
-    // The issue happens if developers added to the list of allowed tags any
-    // tag which is parsed in RCDATA state, PLAINTEXT state or RAWTEXT state,
-    // that is:
-    //
-    // * title
-    // * textarea
-    // * xmp
-    // * iframe
-    // * noembed
-    // * noframes
-    // * plaintext
-    // * noscript
-    // * style
-    // * script
-    //
-    // An example in the wild is Plume, that allows iframe [1].  So in next
-    // examples I'll assume the following policy:
-    //
-    //     Builder::new()
-    //        .add_tags(&["iframe"])
-    //
-    // In HTML namespace `<iframe>` is parsed specially; that is, its content is
-    // treated as text. For instance, the following html:
-    //
-    //     <iframe><a>test
-    //
-    // Is parsed into the following DOM tree:
-    //
-    //     iframe
-    //     └─ #text: <a>test
-    //
-    // So iframe cannot have any children other than a text node.
-    //
-    // The same is not true, though, in "foreign content"; that is, within
-    // <svg> or <math> tags. The following html:
-    //
-    //     <svg><iframe><a>test
-    //
-    // is parsed differently:
-    //
-    //    svg
-    //    └─ iframe
-    //       └─ a
-    //          └─ #text: test
-    //
-    // So in SVG namespace iframe can have children.
-    //
-    // Ammonia disallows <svg> but it keeps its content after deleting it. And
-    // the parser internally keeps track of the namespace of the element. So
-    // assume we have the following snippet:
-    //
-    //     <svg><iframe><a title="</iframe><img src onerror=alert(1)>">test
-    //
-    // It is parsed into:
-    //
-    //     svg
-    //     └─ iframe
-    //        └─ a title="</iframe><img src onerror=alert(1)>"
-    //           └─ #text: test
-    //
-    // This DOM tree is harmless from ammonia point of view because the piece
-    // of code that looks like XSS is in a title attribute. Hence, the
-    // resulting "safe" HTML from ammonia would be:
-    //
-    //     <iframe><a title="</iframe><img src onerror=alert(1)>" rel="noopener
-    // noreferrer">test</a></iframe>
-    //
-    // However, at this point, the information about namespace is lost, which
-    // means that the browser will parse this snippet into:
-    //
-    //     ├─ iframe
-    //     │  └─ #text: <a title="
-    //     ├─ img src="" onerror="alert(1)"
-    //     └─ #text: " rel="noopener noreferrer">test
-    //
-    // Leading to XSS.
-    //
-    // To solve this issue, check for unexpected namespace switches after cleanup.
-    // Elements which change namespace at an unexpected point are removed.
-    // This function returns `true` if `child` should be kept, and `false` if it
-    // should be removed.
-    //
-    // [1]: https://github.com/Plume-org/Plume/blob/main/plume-models/src/safe_string.rs#L21
-    fn check_expected_namespace(&self, parent: &Handle, child: &Handle) -> bool {
-        let (parent, child) = match (&parent.data, &child.data) {
-            (NodeData::Element { name: pn, .. }, NodeData::Element { name: cn, .. }) => (pn, cn),
-            _ => return true,
-        };
-        // The only way to switch from html to svg is with the <svg> tag
-        if parent.ns == ns!(html) && child.ns == ns!(svg) {
-            child.local == local_name!("svg")
-        // The only way to switch from html to mathml is with the <math> tag
-        } else if parent.ns == ns!(html) && child.ns == ns!(mathml) {
-            child.local == local_name!("math")
-        // The only way to switch from mathml to svg/html is with a text integration point
-        } else if parent.ns == ns!(mathml) && child.ns != ns!(mathml) {
-            // https://html.spec.whatwg.org/#mathml
-            matches!(
-                &*parent.local,
-                "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml"
-            ) && if child.ns == ns!(html) { is_html_tag(&child.local) } else { true }
-        // The only way to switch from svg to mathml/html is with an html integration point
-        } else if parent.ns == ns!(svg) && child.ns != ns!(svg) {
-            // https://html.spec.whatwg.org/#svg-0
-            matches!(&*parent.local, "foreignObject")
-                && if child.ns == ns!(html) { is_html_tag(&child.local) } else { true }
-        } else if child.ns == ns!(svg) {
-            is_svg_tag(&child.local)
-        } else if child.ns == ns!(mathml) {
-            is_mathml_tag(&child.local)
-        } else if child.ns == ns!(html) {
-            is_html_tag(&child.local)
-        } else {
-            // There are no other supported ways to switch namespace
-            parent.ns == child.ns
-        }
+    /// Allows rewriting of all attributes using a callback.
+    ///
+    /// The callback takes name of the element, attribute and its value.
+    /// Returns `None` to remove the attribute, or a value to use.
+    ///
+    /// Rewriting of attributes with URLs is done before `url_relative()`.
+    ///
+    /// # Panics
+    ///
+    /// If more than one callback is set.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use ammonia::Builder;
+    /// let a = Builder::new()
+    ///     .attribute_filter(|element, attribute, value| {
+    ///         match (element, attribute) {
+    ///             ("img", "src") => None,
+    ///             _ => Some(value.into())
+    ///         }
+    ///     })
+    ///     .link_rel(None)
+    ///     .clean("<a href=/><img alt=Home src=foo></a>")
+    ///     .to_string();
+    /// assert_eq!(a,
+    ///     r#"<a href="/"><img alt="Home"></a>"#);
+    /// ```
+    pub fn attribute_filter<'cb, CallbackFn>(&mut self, callback: CallbackFn) -> &mut Self
+    where
+        CallbackFn: for<'u> Fn(&str, &str, &'u str) -> Option<Cow<'u, str>> + Send + Sync + 'static,
+    {
+        assert!(
+            self.attribute_filter.is_none(),
+            "attribute_filter can be set only once"
+        );
+        self.attribute_filter = Some(Box::new(callback));
+        self
     }
 
-    /// Add and transform special-cased attributes and elements.
+    /// Decides whether to keep each element using a callback.
     ///
-    /// This function handles:
+    /// The callback takes the name of the element and its attributes, after all other
+    /// attribute cleaning has been done (including [`attribute_filter`] and relative
+    /// URL rewriting). It returns an [`ElementAction`] that keeps the element,
+    /// unwraps it (removing the element but keeping its children), or removes it
+    /// along with everything inside it.
     ///
-    /// * relative URL rewriting
-    /// * adding `<a rel>` attributes
-    /// * filtering out banned style properties
-    /// * filtering out banned classes
-    fn adjust_node_attributes(
-        &self,
-        child: &mut Handle,
-        link_rel: &Option<StrTendril>,
-        id_prefix: Option<&'a str>,
-    ) {
-        if let NodeData::Element {
-            ref name,
-            ref attrs,
-            ..
-        } = child.data
-        {
-            if let Some(set_attrs) = self.set_tag_attribute_values.get(&*name.local) {
-                let mut attrs = attrs.borrow_mut();
-                for (&set_name, &set_value) in set_attrs {
-                    // set the value of the attribute if the attribute is already present
-                    if let Some(attr) = attrs.iter_mut().find(|attr| &*attr.name.local == set_name)
-                    {
-                        if &*attr.value != set_value {
-                            attr.value = set_value.into();
-                        }
-                    } else {
-                        // otherwise, add the attribute
-                        let attr = Attribute {
-                            name: QualName::new(None, ns!(), set_name.into()),
-                            value: set_value.into(),
-                        };
-                        attrs.push(attr);
-                    }
-                }
-            }
-            if let Some(ref link_rel) = *link_rel {
-                if &*name.local == "a" {
-                    attrs.borrow_mut().push(Attribute {
-                        name: QualName::new(None, ns!(), local_name!("rel")),
-                        value: link_rel.clone(),
-                    })
-                }
-            }
-            if let Some(ref id_prefix) = id_prefix {
-                for attr in &mut *attrs.borrow_mut() {
-                    if &attr.name.local == "id" && !attr.value.starts_with(id_prefix) {
-                        attr.value = format_tendril!("{}{}", id_prefix, attr.value);
-                    }
-                }
-            }
-            if let Some(ref attr_filter) = self.attribute_filter {
-                let mut drop_attrs = Vec::new();
-                let mut attrs = attrs.borrow_mut();
-                for (i, attr) in &mut attrs.iter_mut().enumerate() {
-                    let replace_with = if let Some(new) =
-                        attr_filter.filter(&name.local, &attr.name.local, &attr.value)
-                    {
-                        if *new != *attr.value {
-                            Some(format_tendril!("{}", new))
-                        } else {
-                            None // no need to replace the attr if filter returned the same value
-                        }
-                    } else {
-                        drop_attrs.push(i);
-                        None
-                    };
-                    if let Some(replace_with) = replace_with {
-                        attr.value = replace_with;
-                    }
-                }
-                for i in drop_attrs.into_iter().rev() {
-                    attrs.swap_remove(i);
-                }
-            }
-            {
-                let mut drop_attrs = Vec::new();
-                let mut attrs = attrs.borrow_mut();
-                for (i, attr) in attrs.iter_mut().enumerate() {
-                    if is_url_attr(&name.local, &attr.name.local) && is_url_relative(&attr.value) {
-                        let new_value = self.url_relative.evaluate(&attr.value);
-                        if let Some(new_value) = new_value {
-                            attr.value = new_value;
-                        } else {
-                            drop_attrs.push(i);
-                        }
-                    }
-                }
-                // Swap remove scrambles the vector after the current point.
-                // We will not do anything except with items before the current point.
-                // The `rev()` is, as such, necessary for correctness.
-                // We could use regular `remove(usize)` and a forward iterator,
-                // but that's slower.
-                for i in drop_attrs.into_iter().rev() {
-                    attrs.swap_remove(i);
-                }
-            }
-            if let Some(allowed_values) = &self.style_properties {
-                for attr in &mut *attrs.borrow_mut() {
-                    if &attr.name.local == "style" {
-                        attr.value = style::filter_style_attribute(&attr.value, allowed_values).into();
-                    }
-                }
-            }
-            if let Some(allowed_values) = self.allowed_classes.get(&*name.local) {
-                for attr in &mut *attrs.borrow_mut() {
-                    if &attr.name.local == "class" {
-                        let mut classes = vec![];
-                        // https://html.spec.whatwg.org/#global-attributes:classes-2
-                        for class in attr.value.split_ascii_whitespace() {
-                            if allowed_values.contains(class) {
-                                classes.push(class.to_owned());
-                            }
-                        }
-                        attr.value = format_tendril!("{}", classes.join(" "));
-                    }
-                }
-            }
+    /// Elements that are not in [`tags`] are removed before the callback sees them.
+    ///
+    /// [`attribute_filter`]: #method.attribute_filter
+    /// [`tags`]: #method.tags
+    ///
+    /// # Panics
+    ///
+    /// If more than one callback is set.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use ammonia::{Builder, ElementAction};
+    /// let a = Builder::new()
+    ///     .element_filter(|element, attributes| {
+    ///         let attr = |name| attributes.iter().find(|&&(n, _)| n == name).map(|&(_, v)| v);
+    ///         match element {
+    ///             "img" if !attr("src").is_some_and(|src| src.starts_with("https://cdn.example.com/")) => {
+    ///                 ElementAction::Remove
+    ///             }
+    ///             "a" if attr("href").is_none() => ElementAction::Unwrap,
+    ///             _ => ElementAction::Keep,
+    ///         }
+    ///     })
+    ///     .link_rel(None)
+    ///     .clean("<a href=javascript:alert(1)>link</a> <img src=https://evil.example/x.png><img src=https://cdn.example.com/y.png>")
+    ///     .to_string();
+    /// assert_eq!(a,
+    ///     r#"link <img src="https://cdn.example.com/y.png">"#);
+    /// ```
+    pub fn element_filter<CallbackFn>(&mut self, callback: CallbackFn) -> &mut Self
+    where
+        CallbackFn: Fn(&str, &[(&str, &str)]) -> ElementAction + Send + Sync + 'static,
+    {
+        assert!(
+            self.element_filter.is_none(),
+            "element_filter can be set only once"
+        );
+        self.element_filter = Some(Box::new(callback));
+        self
+    }
+
+    /// Rewrites every absolute URL using a callback, like sending outbound links through
+    /// a redirector.
+    ///
+    /// The callback takes the name of the element and attribute, and the parsed URL.
+    /// Returns `None` to remove the attribute, or a value to use. Each URL in a `srcset`
+    /// is rewritten on its own, and only that URL is removed if the callback returns `None`.
+    ///
+    /// It runs after every other URL check, and after [`url_relative`], so it also sees
+    /// relative URLs that were resolved against a base. Relative URLs that are left
+    /// relative are not passed to it, but scheme-relative ones like `//example.com/` are,
+    /// resolved as `https:`. URLs on one of the
+    /// [`absolute_url_rewriter_exempt_hosts`] are left alone.
+    ///
+    /// # Panics
+    ///
+    /// If more than one callback is set.
+    ///
+    /// [`url_relative`]: #method.url_relative
+    /// [`absolute_url_rewriter_exempt_hosts`]: #method.absolute_url_rewriter_exempt_hosts
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use ammonia::{escape_url_component, Builder};
+    /// # fn main() -> Result<(), url::ParseError> {
+    /// let a = Builder::new()
+    ///     .absolute_url_rewriter(|element, _attribute, url| match element {
+    ///         "a" => Some(format!("https://out.example.com/?u={}", escape_url_component(url.as_str())).into()),
+    ///         _ => Some(url.as_str().into()),
+    ///     })
+    ///     .absolute_url_rewriter_exempt_hosts(["example.com", "*.example.com"])?
+    ///     .link_rel(None)
+    ///     .clean("<a href=https://rust-lang.org/>a</a><a href=https://www.example.com/>b</a>")
+    ///     .to_string();
+    /// assert_eq!(a,
+    ///     r#"<a href="https://out.example.com/?u=https%3A%2F%2Frust-lang.org%2F">a</a><a href="https://www.example.com/">b</a>"#);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn absolute_url_rewriter<CallbackFn>(&mut self, callback: CallbackFn) -> &mut Self
+    where
+        CallbackFn: for<'u> Fn(&str, &str, &'u Url) -> Option<Cow<'u, str>> + Send + Sync + 'static,
+    {
+        assert!(
+            self.absolute_url_rewriter.is_none(),
+            "absolute_url_rewriter can be set only once"
+        );
+        self.absolute_url_rewriter = Some(Box::new(callback));
+        self
+    }
+
+    /// Exempts URLs on these hosts from the [`absolute_url_rewriter`], in addition to any
+    /// that were already exempt.
+    ///
+    /// The patterns are the same as for a [`UrlPolicy`], like `example.com` or
+    /// `*.example.com`. URLs without a host, like `mailto:` URLs, are never exempt.
+    ///
+    /// Returns an error if a pattern isn't a valid host.
+    ///
+    /// [`absolute_url_rewriter`]: #method.absolute_url_rewriter
+    /// [`UrlPolicy`]: struct.UrlPolicy.html
+    ///
+    /// # Defaults
+    ///
+    /// No hosts are exempt.
+    pub fn absolute_url_rewriter_exempt_hosts<'b, I: IntoIterator<Item = &'b str>>(
+        &mut self,
+        patterns: I,
+    ) -> Result<&mut Self, url::ParseError> {
+        for pattern in patterns {
+            self.absolute_url_rewriter_exempt_hosts
+                .push(HostPattern::parse(pattern)?);
         }
+        Ok(self)
     }
 
-    /// Initializes an HTML fragment parser.
+    /// Returns `true` if the relative URL resolver is set to `Deny`.
     ///
-    /// Ammonia conforms to the HTML5 fragment parsing rules,
-    /// by parsing the given fragment as if it were included in a <div> tag.
-    fn make_parser() -> html::Parser<RcDom> {
-        html::parse_fragment(
-            RcDom::default(),
-            html::ParseOpts::default(),
-            QualName::new(None, ns!(html), local_name!("div")),
-            vec![],
-            false,
-        )
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, UrlRelative};
+    ///     let mut a = Builder::default();
+    ///     a.url_relative(UrlRelative::Deny);
+    ///     assert!(a.is_url_relative_deny());
+    ///     a.url_relative(UrlRelative::PassThrough);
+    ///     assert!(!a.is_url_relative_deny());
+    pub fn is_url_relative_deny(&self) -> bool {
+        matches!(self.url_relative, UrlRelative::Deny)
     }
-}
 
-/// Given an element name and attribute name, determine if the given attribute contains a URL.
-fn is_url_attr(element: &str, attr: &str) -> bool {
-    attr == "href"
-        || attr == "src"
-        || (element == "form" && attr == "action")
-        || (element == "object" && attr == "data")
-        || ((element == "button" || element == "input") && attr == "formaction")
-        || (element == "a" && attr == "ping")
-        || (element == "video" && attr == "poster")
-}
+    /// Returns `true` if the relative URL resolver is set to `PassThrough`.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, UrlRelative};
+    ///     let mut a = Builder::default();
+    ///     a.url_relative(UrlRelative::Deny);
+    ///     assert!(!a.is_url_relative_pass_through());
+    ///     a.url_relative(UrlRelative::PassThrough);
+    ///     assert!(a.is_url_relative_pass_through());
+    pub fn is_url_relative_pass_through(&self) -> bool {
+        matches!(self.url_relative, UrlRelative::PassThrough)
+    }
 
-fn is_html_tag(element: &str) -> bool {
-    (!is_svg_tag(element) && !is_mathml_tag(element))
-        || matches!(
-            element,
-            "title" | "style" | "font" | "a" | "script" | "span"
-        )
-}
+    /// Returns `true` if the relative URL resolver is set to `Custom`.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, UrlRelative};
+    ///     use std::borrow::Cow;
+    ///     fn test(a: &str) -> Option<Cow<str>> { None }
+    ///     # fn main() {
+    ///     let mut a = Builder::default();
+    ///     a.url_relative(UrlRelative::Custom(Box::new(test)));
+    ///     assert!(a.is_url_relative_custom());
+    ///     a.url_relative(UrlRelative::PassThrough);
+    ///     assert!(!a.is_url_relative_custom());
+    ///     a.url_relative(UrlRelative::Deny);
+    ///     assert!(!a.is_url_relative_custom());
+    ///     # }
+    pub fn is_url_relative_custom(&self) -> bool {
+        matches!(self.url_relative, UrlRelative::Custom(_))
+    }
 
-/// Given an element name, check if it's SVG
-fn is_svg_tag(element: &str) -> bool {
-    // https://svgwg.org/svg2-draft/eltindex.html
-    matches!(
-        element,
-        "a" | "animate"
-            | "animateMotion"
-            | "animateTransform"
-            | "circle"
-            | "clipPath"
-            | "defs"
-            | "desc"
-            | "discard"
-            | "ellipse"
-            | "feBlend"
-            | "feColorMatrix"
-            | "feComponentTransfer"
-            | "feComposite"
-            | "feConvolveMatrix"
-            | "feDiffuseLighting"
-            | "feDisplacementMap"
-            | "feDistantLight"
-            | "feDropShadow"
-            | "feFlood"
-            | "feFuncA"
+    /// Configures a `rel` attribute that will be added on links.
+    ///
+    /// If `rel` is in the generic or tag attributes, this must be set to `None`.
+    /// Common `rel` values to include:
+    ///
+    /// * `noopener`: This prevents [a particular type of XSS attack],
+    ///   and should usually be turned on for untrusted HTML.
+    /// * `noreferrer`: This prevents the browser from [sending the source URL]
+    ///   to the website that is linked to.
+    /// * `nofollow`: This prevents search engines from [using this link for
+    ///   ranking], which disincentivizes spammers.
+    ///
+    /// To turn on rel-insertion, call this function with a space-separated list.
+    /// Ammonia does not parse rel-attributes;
+    /// it just puts the given string into the attribute directly.
+    ///
+    /// [a particular type of XSS attack]: https://mathiasbynens.github.io/rel-noopener/
+    /// [sending the source URL]: https://en.wikipedia.org/wiki/HTTP_referer
+    /// [using this link for ranking]: https://en.wikipedia.org/wiki/Nofollow
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     let a = Builder::new().link_rel(None)
+    ///         .clean("<a href=https://rust-lang.org/>Rust</a>")
+    ///         .to_string();
+    ///     assert_eq!(
+    ///       a,
+    ///       "<a href=\"https://rust-lang.org/\">Rust</a>");
+    ///
+    /// # Defaults
+    ///
+    /// ```notest
+    /// Some("noopener noreferrer")
+    /// ```
+    pub fn link_rel(&mut self, value: Option<&'a str>) -> &mut Self {
+        self.link_rel = value;
+        self
+    }
+
+    /// Returns the settings for links' `rel` attribute, if one is set.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, UrlRelative};
+    ///     let mut a = Builder::default();
+    ///     a.link_rel(Some("a b"));
+    ///     assert_eq!(a.get_link_rel(), Some("a b"));
+    pub fn get_link_rel(&self) -> Option<&str> {
+        self.link_rel
+    }
+
+    /// Sets the CSS classes that are allowed on specific tags.
+    ///
+    /// The values is structured as a map from tag names to a set of class names.
+    ///
+    /// If the `class` attribute is itself whitelisted for a tag, then adding entries to
+    /// this map will cause a panic.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     use maplit::{hashmap, hashset};
+    ///
+    ///     # fn main() {
+    ///     let allowed_classes = hashmap![
+    ///         "code" => hashset!["rs", "ex", "c", "cxx", "js"]
+    ///     ];
+    ///     let a = Builder::new()
+    ///         .allowed_classes(allowed_classes)
+    ///         .clean("<code class=rs>fn main() {}</code>")
+    ///         .to_string();
+    ///     assert_eq!(
+    ///       a,
+    ///       "<code class=\"rs\">fn main() {}</code>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// The set of allowed classes is empty by default.
+    pub fn allowed_classes(&mut self, value: HashMap<&'a str, HashSet<&'a str>>) -> &mut Self {
+        self.allowed_classes = value;
+        self
+    }
+
+    /// Add additonal whitelisted classes without overwriting old ones.
+    ///
+    /// # Examples
+    ///
+    ///     let a = ammonia::Builder::default()
+    ///         .add_allowed_classes("a", &["onebox"])
+    ///         .clean("<a href=/ class=onebox>mess</span>").to_string();
+    ///     assert_eq!("<a href=\"/\" class=\"onebox\" rel=\"noopener noreferrer\">mess</a>", a);
+    pub fn add_allowed_classes<
+        T: 'a + ?Sized + Borrow<str>,
+        U: 'a + ?Sized + Borrow<str>,
+        I: IntoIter<Item = &'a T>,
+    >(
+        &mut self,
+        tag: &'a U,
+        it: I,
+    ) -> &mut Self {
+        self.allowed_classes
+            .entry(tag.borrow())
+            .or_default()
+            .extend(it.into_iter().map(Borrow::borrow));
+        self
+    }
+
+    /// Remove already-whitelisted attributes.
+    ///
+    /// Does nothing if the attribute is already gone.
+    ///
+    /// # Examples
+    ///
+    ///     let a = ammonia::Builder::default()
+    ///         .add_allowed_classes("span", &["active"])
+    ///         .rm_allowed_classes("span", &["active"])
+    ///         .clean("<span class=active>").to_string();
+    ///     assert_eq!("<span class=\"\"></span>", a);
+    pub fn rm_allowed_classes<
+        'b,
+        'c,
+        T: 'b + ?Sized + Borrow<str>,
+        U: 'c + ?Sized + Borrow<str>,
+        I: IntoIter<Item = &'b T>,
+    >(
+        &mut self,
+        tag: &'c U,
+        it: I,
+    ) -> &mut Self {
+        if let Some(tag) = self.allowed_classes.get_mut(tag.borrow()) {
+            for i in it {
+                tag.remove(i.borrow());
+            }
+        }
+        self
+    }
+
+    /// Returns a copy of the set of whitelisted class attributes.
+    ///
+    /// # Examples
+    ///
+    ///     use maplit::{hashmap, hashset};
+    ///
+    ///     let allowed_classes = hashmap![
+    ///         "my-tag" => hashset!["my-class-1", "my-class-2"]
+    ///     ];
+    ///
+    ///     let mut b = ammonia::Builder::default();
+    ///     b.allowed_classes(Clone::clone(&allowed_classes));
+    ///     assert_eq!(allowed_classes, b.clone_allowed_classes());
+    pub fn clone_allowed_classes(&self) -> HashMap<&'a str, HashSet<&'a str>> {
+        self.allowed_classes.clone()
+    }
+
+    /// Configures the handling of HTML comments.
+    ///
+    /// If this option is false, comments will be preserved.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     let a = Builder::new().strip_comments(false)
+    ///         .clean("<!-- yes -->")
+    ///         .to_string();
+    ///     assert_eq!(
+    ///       a,
+    ///       "<!-- yes -->");
+    ///
+    /// # Defaults
+    ///
+    /// `true`
+    pub fn strip_comments(&mut self, value: bool) -> &mut Self {
+        self.strip_comments = value;
+        self
+    }
+
+    /// Returns `true` if comment stripping is turned on.
+    ///
+    /// # Examples
+    ///
+    ///     let mut a = ammonia::Builder::new();
+    ///     a.strip_comments(true);
+    ///     assert!(a.will_strip_comments());
+    ///     a.strip_comments(false);
+    ///     assert!(!a.will_strip_comments());
+    pub fn will_strip_comments(&self) -> bool {
+        self.strip_comments
+    }
+
+    /// Prefixes all "id" attribute values with a given string.  Note that the tag and
+    /// attribute themselves must still be whitelisted.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     use maplit::hashset;
+    ///
+    ///     # fn main() {
+    ///     let attributes = hashset!["id"];
+    ///     let a = Builder::new()
+    ///         .generic_attributes(attributes)
+    ///         .id_prefix(Some("safe-"))
+    ///         .clean("<b id=42>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<b id=\"safe-42\"></b>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// `None`
+    pub fn id_prefix(&mut self, value: Option<&'a str>) -> &mut Self {
+        self.id_prefix = value;
+        self
+    }
+
+    /// Only allows the specified properties in `style` attributes.
+    ///
+    /// Irrelevant if `style` is not an allowed attribute, except that it also applies to
+    /// the rules in `<style>` elements kept by [`style_sheets`].
+    ///
+    /// Note that if style filtering is enabled style properties will be normalised e.g.
+    /// invalid declarations and @rules will be removed, with only syntactically valid
+    /// declarations kept. Property names are lowercased, except for custom properties, and
+    /// only the last declaration for each property is kept, unless an earlier one is
+    /// `!important`.
+    ///
+    /// A shorthand is only kept if it's allowed itself, so allowing `margin-left` doesn't let
+    /// `margin` through. Instead, the shorthands that set one value per side, like `margin`,
+    /// `padding`, `inset` and `border-color`, are split into the longhands that are allowed:
+    /// `margin: 0 auto` becomes `margin-left:auto`. Others, like `background` or `font`,
+    /// are removed.
+    ///
+    /// Each URL in a kept declaration, like the one in `background: url(a.png)`, gets the
+    /// same checks and rewriting as a URL attribute: [`url_schemes`], [`url_relative`], and
+    /// so on. If the URL is rejected, the whole declaration is removed.
+    ///
+    /// [`url_schemes`]: #method.url_schemes
+    /// [`url_relative`]: #method.url_relative
+    /// [`style_sheets`]: #method.style_sheets
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     use maplit::hashset;
+    ///
+    ///     # fn main() {
+    ///     let attributes = hashset!["style"];
+    ///     let properties = hashset!["color"];
+    ///     let a = Builder::new()
+    ///         .generic_attributes(attributes)
+    ///         .filter_style_properties(properties)
+    ///         .clean("<p style=\"font-weight: heavy; color: red\">my html</p>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<p style=\"color:red\">my html</p>");
+    ///     # }
+    pub fn filter_style_properties(&mut self, value: HashSet<&'a str>) -> &mut Self {
+        self.style_properties = Some(value);
+        self
+    }
+
+    /// Restricts the values of some style properties.
+    ///
+    /// The value is structured as a map from property names to a [`StyleValueRule`].
+    /// Declarations whose value doesn't match the rule for their property are removed.
+    /// Properties without a rule can have any value, as long as they are in
+    /// [`filter_style_properties`], which has to be set for this to do anything.
+    ///
+    /// [`StyleValueRule`]: struct.StyleValueRule.html
+    /// [`filter_style_properties`]: #method.filter_style_properties
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, StyleValueRule};
+    ///     use maplit::{hashmap, hashset};
+    ///
+    ///     # fn main() {
+    ///     let rules = hashmap![
+    ///         "width" => StyleValueRule::new().keywords(["auto"]).numbers(0.0, 800.0, ["px"]),
+    ///         "background" => StyleValueRule::new().colors(true),
+    ///     ];
+    ///     let a = Builder::new()
+    ///         .generic_attributes(hashset!["style"])
+    ///         .filter_style_properties(hashset!["width", "background", "color"])
+    ///         .style_value_rules(rules)
+    ///         .clean("<p style=\"width: 99999px; background: url(https://tracker.example/pixel); color: red\">a</p>\
+    ///                 <p style=\"width: 200px; background: #fff\">b</p>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<p style=\"color:red\">a</p><p style=\"width:200px;background:#fff\">b</p>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// No values are restricted by default.
+    pub fn style_value_rules(&mut self, value: HashMap<&'a str, StyleValueRule>) -> &mut Self {
+        self.style_value_rules = value;
+        self
+    }
+
+    /// Restricts the values of one style property, replacing any rule it already had.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::StyleValueRule;
+    ///     use maplit::hashset;
+    ///
+    ///     let a = ammonia::Builder::default()
+    ///         .add_generic_attributes(&["style"])
+    ///         .filter_style_properties(hashset!["color"])
+    ///         .style_value_rule("color", StyleValueRule::new().colors(true))
+    ///         .clean("<b style=\"color: var(--x)\">a</b><b style=\"color: rgb(0 0 0 / 50%)\">b</b>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<b style=\"\">a</b><b style=\"color:rgb(0 0 0 / 50%)\">b</b>");
+    pub fn style_value_rule<T: 'a + ?Sized + Borrow<str>>(
+        &mut self,
+        property: &'a T,
+        rule: StyleValueRule,
+    ) -> &mut Self {
+        self.style_value_rules.insert(property.borrow(), rule);
+        self
+    }
+
+    /// Remove an already-configured style value rule.
+    ///
+    /// Does nothing if the property has no rule.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::StyleValueRule;
+    ///     use maplit::hashset;
+    ///
+    ///     let a = ammonia::Builder::default()
+    ///         .add_generic_attributes(&["style"])
+    ///         .filter_style_properties(hashset!["color"])
+    ///         .style_value_rule("color", StyleValueRule::new())
+    ///         .rm_style_value_rule("color")
+    ///         .clean("<b style=\"color: red\">a</b>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<b style=\"color:red\">a</b>");
+    pub fn rm_style_value_rule<'b, T: 'b + ?Sized + Borrow<str>>(&mut self, property: &'b T) -> &mut Self {
+        self.style_value_rules.remove(property.borrow());
+        self
+    }
+
+    /// Returns a copy of the map of style value rules.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::StyleValueRule;
+    ///     use maplit::hashmap;
+    ///
+    ///     let rules = hashmap!["color" => StyleValueRule::new().colors(true)];
+    ///
+    ///     let mut b = ammonia::Builder::default();
+    ///     b.style_value_rules(Clone::clone(&rules));
+    ///     assert_eq!(rules, b.clone_style_value_rules());
+    pub fn clone_style_value_rules(&self) -> HashMap<&'a str, StyleValueRule> {
+        self.style_value_rules.clone()
+    }
+
+    /// Keeps `<style>` elements, and cleans the style sheets in them according to the
+    /// given [`StyleSheetPolicy`].
+    ///
+    /// This keeps HTML `<style>` elements even though they're not in [`tags`] and are in
+    /// [`clean_content_tags`], and filters their attributes like any other element's,
+    /// with [`generic_attributes`] and [`tag_attributes`]. Their content is
+    /// parsed into rules. A rule is kept if its selector is allowed by the policy, and
+    /// its declarations are filtered the same way as a `style` attribute, by
+    /// [`filter_style_properties`] and [`style_value_rules`], with its URLs checked like
+    /// URL attributes. Without [`filter_style_properties`], every property is allowed.
+    /// At-rules are removed unless the policy allows them, and `@import` and `@font-face`
+    /// are always removed.
+    ///
+    /// The sheet is written out normalised, and anything that's removed is reported as
+    /// [`Removal::StyleSheetRule`] or [`Removal::StyleProperty`].
+    ///
+    /// [`tags`]: #method.tags
+    /// [`clean_content_tags`]: #method.clean_content_tags
+    /// [`generic_attributes`]: #method.generic_attributes
+    /// [`tag_attributes`]: #method.tag_attributes
+    /// [`filter_style_properties`]: #method.filter_style_properties
+    /// [`style_value_rules`]: #method.style_value_rules
+    /// [`Removal::StyleSheetRule`]: enum.Removal.html#variant.StyleSheetRule
+    /// [`Removal::StyleProperty`]: enum.Removal.html#variant.StyleProperty
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, StyleSheetPolicy};
+    ///     use maplit::hashset;
+    ///
+    ///     let a = Builder::default()
+    ///         .filter_style_properties(hashset!["color", "margin"])
+    ///         .style_sheets(Some(
+    ///             StyleSheetPolicy::new()
+    ///                 .selectors(["p", ".note"])
+    ///                 .scope(Some("#post-123"))
+    ///                 .at_rules(["media"]),
+    ///         ))
+    ///         .clean("<style>@import url(https://evil.example/x.css); \
+    ///                 p.note { color: red; position: fixed } \
+    ///                 @media (max-width: 600px) { p, a[href] { margin: 0 } }</style>")
+    ///         .to_string();
+    ///     assert_eq!(
+    ///         a,
+    ///         "<style>#post-123 p.note{color:red}\
+    ///          @media (max-width : 600px){#post-123 p{margin:0}}</style>"
+    ///     );
+    ///
+    /// # Defaults
+    ///
+    /// `None`, so `<style>` elements are handled like any other element.
+    pub fn style_sheets(&mut self, value: Option<StyleSheetPolicy>) -> &mut Self {
+        self.style_sheets = value;
+        self
+    }
+
+    /// Sets the tags that are renamed before sanitizing.
+    ///
+    /// The value is structured as a map from the original tag name to the new tag name.
+    /// Renaming happens before any other check, so the renamed element must be allowed by
+    /// [`tags`], and its attributes by [`tag_attributes`] and the other attribute settings,
+    /// under its *new* name. A rename can't be used to sneak an element past the whitelist.
+    ///
+    /// Only HTML elements are renamed; elements inside `<svg>` or `<math>` are left alone.
+    /// Renames are not chained: a tag is looked up in this map once.
+    ///
+    /// The new name must be an element whose children the browser parses like any other
+    /// markup, such as `span`, `strong`, `div`, `p`, `blockquote` or `h2`, or a custom
+    /// element name like `x-note`. Elements like `textarea`, `title`, `xmp`, `table`,
+    /// `select`, `svg` or `template` would change the meaning of the element's children
+    /// when the output is parsed again, so [`validate`] rejects them unless the new name is
+    /// in [`clean_content_tags`].
+    ///
+    /// [`tags`]: #method.tags
+    /// [`tag_attributes`]: #method.tag_attributes
+    /// [`validate`]: #method.validate
+    /// [`clean_content_tags`]: #method.clean_content_tags
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     use maplit::hashmap;
+    ///
+    ///     # fn main() {
+    ///     let renames = hashmap![
+    ///         "b" => "strong",
+    ///         "i" => "em",
+    ///         "font" => "span",
+    ///     ];
+    ///     let a = Builder::new()
+    ///         .rename_tags(renames)
+    ///         .clean("<b>bold</b> <i>italic</i> <font color=red>red</font>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<strong>bold</strong> <em>italic</em> <span>red</span>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// No tags are renamed by default.
+    pub fn rename_tags(&mut self, value: HashMap<&'a str, &'a str>) -> &mut Self {
+        self.rename_tags = value;
+        self
+    }
+
+    /// Add a tag rename without overwriting the other ones.
+    ///
+    /// # Examples
+    ///
+    ///     let a = ammonia::Builder::default()
+    ///         .rename_tag("b", "strong")
+    ///         .clean("<b>bold</b>").to_string();
+    ///     assert_eq!("<strong>bold</strong>", a);
+    pub fn rename_tag<T: 'a + ?Sized + Borrow<str>, U: 'a + ?Sized + Borrow<str>>(
+        &mut self,
+        from: &'a T,
+        to: &'a U,
+    ) -> &mut Self {
+        self.rename_tags.insert(from.borrow(), to.borrow());
+        self
+    }
+
+    /// Remove already-configured tag renames.
+    ///
+    /// Does nothing if the tag isn't renamed.
+    ///
+    /// # Examples
+    ///
+    ///     let a = ammonia::Builder::default()
+    ///         .rename_tag("b", "strong")
+    ///         .rm_rename_tags(&["b"])
+    ///         .clean("<b>bold</b>").to_string();
+    ///     assert_eq!("<b>bold</b>", a);
+    pub fn rm_rename_tags<'b, T: 'b + ?Sized + Borrow<str>, I: IntoIter<Item = &'b T>>(
+        &mut self,
+        it: I,
+    ) -> &mut Self {
+        for i in it {
+            self.rename_tags.remove(i.borrow());
+        }
+        self
+    }
+
+    /// Returns a copy of the map of tag renames.
+    ///
+    /// # Examples
+    ///
+    ///     use maplit::hashmap;
+    ///
+    ///     let renames = hashmap!["b" => "strong"];
+    ///
+    ///     let mut b = ammonia::Builder::default();
+    ///     b.rename_tags(Clone::clone(&renames));
+    ///     assert_eq!(renames, b.clone_rename_tags());
+    pub fn clone_rename_tags(&self) -> HashMap<&'a str, &'a str> {
+        self.rename_tags.clone()
+    }
+
+    /// Add an attribute value to set on an element when it is renamed.
+    ///
+    /// The tag is the *original* name, as used in [`rename_tags`]. The attribute is set
+    /// at the same time as the tag is renamed, replacing any existing value, so it is
+    /// still subject to the whitelist for the new tag name.
+    ///
+    /// [`rename_tags`]: #method.rename_tags
+    ///
+    /// # Examples
+    ///
+    ///     let a = ammonia::Builder::default()
+    ///         .rename_tag("center", "div")
+    ///         .rename_tag_attribute_value("center", "class", "center")
+    ///         .add_allowed_classes("div", &["center"])
+    ///         .clean("<center>middle</center>").to_string();
+    ///     assert_eq!("<div class=\"center\">middle</div>", a);
+    pub fn rename_tag_attribute_value<
+        T: 'a + ?Sized + Borrow<str>,
+        A: 'a + ?Sized + Borrow<str>,
+        V: 'a + ?Sized + Borrow<str>,
+    >(
+        &mut self,
+        tag: &'a T,
+        attribute: &'a A,
+        value: &'a V,
+    ) -> &mut Self {
+        self.rename_tag_attribute_values
+            .entry(tag.borrow())
+            .or_default()
+            .insert(attribute.borrow(), value.borrow());
+        self
+    }
+
+    /// Returns a copy of the attribute values that are set on renamed elements.
+    ///
+    /// # Examples
+    ///
+    ///     use maplit::hashmap;
+    ///
+    ///     let mut b = ammonia::Builder::default();
+    ///     b.rename_tag_attribute_value("center", "class", "center");
+    ///     assert_eq!(
+    ///         hashmap!["center" => hashmap!["class" => "center"]],
+    ///         b.clone_rename_tag_attribute_values(),
+    ///     );
+    pub fn clone_rename_tag_attribute_values(&self) -> HashMap<&'a str, HashMap<&'a str, &'a str>> {
+        self.rename_tag_attribute_values.clone()
+    }
+
+    /// Limits how deeply elements can be nested in the output.
+    ///
+    /// An element that would end up with `value` or more elements around it is handled
+    /// according to [`depth_limit_action`]: by default it is unwrapped, so its content is
+    /// flattened into the deepest allowed element. Text is never removed by this limit.
+    ///
+    /// Only elements that are kept count towards the depth, so unwrapped elements don't
+    /// use up any of it.
+    ///
+    /// [`depth_limit_action`]: #method.depth_limit_action
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     # fn main() {
+    ///     let a = Builder::new()
+    ///         .max_depth(Some(2))
+    ///         .clean("<div><p><b><i>deep</i></b></p></div>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<div><p>deep</p></div>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// `None`
+    pub fn max_depth(&mut self, value: Option<usize>) -> &mut Self {
+        self.max_depth = value;
+        self
+    }
+
+    /// Sets what happens to elements nested deeper than [`max_depth`] allows.
+    ///
+    /// [`max_depth`]: #method.max_depth
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, DepthLimitAction};
+    ///
+    ///     # fn main() {
+    ///     let a = Builder::new()
+    ///         .max_depth(Some(2))
+    ///         .depth_limit_action(DepthLimitAction::Remove)
+    ///         .clean("<div><p>shallow<b><i>deep</i></b></p></div>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<div><p>shallow</p></div>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// `DepthLimitAction::Unwrap`
+    pub fn depth_limit_action(&mut self, value: DepthLimitAction) -> &mut Self {
+        self.depth_limit_action = value;
+        self
+    }
+
+    /// Limits how many nodes (elements, text and comments) the output can have.
+    ///
+    /// Once the limit is reached, the rest of the fragment is dropped. Every element
+    /// that was already written is still closed.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     # fn main() {
+    ///     let a = Builder::new()
+    ///         .max_nodes(Some(3))
+    ///         .clean("<p>one</p><p>two</p>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<p>one</p><p></p>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// `None`
+    pub fn max_nodes(&mut self, value: Option<usize>) -> &mut Self {
+        self.max_nodes = value;
+        self
+    }
+
+    /// Limits how many attributes each element can keep.
+    ///
+    /// Attributes are counted after the whitelist has been applied, and the first ones
+    /// in the source are kept. Attributes that ammonia adds itself, like the ones from
+    /// [`link_rel`] and [`set_tag_attribute_values`], don't count towards the limit.
+    ///
+    /// [`link_rel`]: #method.link_rel
+    /// [`set_tag_attribute_values`]: #method.set_tag_attribute_values
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     # fn main() {
+    ///     let a = Builder::new()
+    ///         .max_attributes(Some(1))
+    ///         .clean("<img src=a.png alt=a title=b>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<img src=\"a.png\">");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// `None`
+    pub fn max_attributes(&mut self, value: Option<usize>) -> &mut Self {
+        self.max_attributes = value;
+        self
+    }
+
+    /// Limits the length of the serialized output, in bytes.
+    ///
+    /// The output is cut short at the last point where it, and the end tags of every
+    /// element still open there, fit in the limit. Text can be cut in the middle, but
+    /// never in the middle of a character or character reference, and tags are always
+    /// written whole, so the result is still well-formed HTML.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     # fn main() {
+    ///     let a = Builder::new()
+    ///         .max_output_bytes(Some(20))
+    ///         .clean("<p>some <b>bold</b> text</p>")
+    ///         .to_string();
+    ///     assert_eq!(a, "<p>some <b>b</b></p>");
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// `None`
+    pub fn max_output_bytes(&mut self, value: Option<usize>) -> &mut Self {
+        self.max_output_bytes = value;
+        self
+    }
+
+    /// Turns bare URLs and email addresses in text into links.
+    ///
+    /// URLs are found by a scheme from [`url_schemes`] followed by `://`, or by a `www.`
+    /// prefix, which links to `https`. Email addresses link to `mailto`, if that scheme is
+    /// allowed. Text inside `<a>`, `<pre>` and `<code>` is left alone, as is text in
+    /// elements whose contents aren't HTML, like `<svg>` or `<textarea>`.
+    ///
+    /// Links are found in the parsed text, after character references are decoded, so the
+    /// addresses never need escaping by hand. The generated `<a href>` elements are then
+    /// sanitized like any other element, so they need `a` and `href` to be allowed, get
+    /// [`link_rel`] added, are checked against [`url_schemes`], and go through every other
+    /// setting including [`element_filter`] and the limits.
+    ///
+    /// [`url_schemes`]: #method.url_schemes
+    /// [`link_rel`]: #method.link_rel
+    /// [`element_filter`]: #method.element_filter
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     # fn main() {
+    ///     let a = Builder::new()
+    ///         .linkify(true)
+    ///         .clean("See www.rust-lang.org, or <code>https://example.com/</code>.")
+    ///         .to_string();
+    ///     assert_eq!(
+    ///         a,
+    ///         "See <a href=\"https://www.rust-lang.org\" rel=\"noopener noreferrer\">www.rust-lang.org</a>, or <code>https://example.com/</code>."
+    ///     );
+    ///     # }
+    ///
+    /// # Defaults
+    ///
+    /// `false`
+    pub fn linkify(&mut self, value: bool) -> &mut Self {
+        self.linkify = value;
+        self
+    }
+
+    /// Returns `true` if bare URLs will be turned into links.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     let mut a = Builder::default();
+    ///     a.linkify(true);
+    ///     assert!(a.will_linkify());
+    pub fn will_linkify(&self) -> bool {
+        self.linkify
+    }
+
+    /// Constructs a [`Builder`] instance configured with the [default options].
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, Url, UrlRelative};
+    ///     # use std::error::Error;
+    ///
+    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
+    ///     let input = "<!-- comments will be stripped -->This is an <a href=.>Ammonia</a> example using <a href=struct.Builder.html#method.new onclick=xss>the <code onmouseover=xss>new()</code> function</a>.";
+    ///     let output = "This is an <a href=\"https://docs.rs/ammonia/1.0/ammonia/\" rel=\"noopener noreferrer\">Ammonia</a> example using <a href=\"https://docs.rs/ammonia/1.0/ammonia/struct.Builder.html#method.new\" rel=\"noopener noreferrer\">the <code>new()</code> function</a>.";
+    ///
+    ///     let result = Builder::new() // <--
+    ///         .url_relative(UrlRelative::RewriteWithBase(Url::parse("https://docs.rs/ammonia/1.0/ammonia/")?))
+    ///         .clean(input)
+    ///         .to_string();
+    ///     assert_eq!(result, output);
+    ///     # Ok(())
+    ///     # }
+    ///     # fn main() { do_main().unwrap() }
+    ///
+    /// [default options]: fn.clean.html
+    /// [`Builder`]: struct.Builder.html
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a [`Builder`] instance configured with no allowed tags.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, Url, UrlRelative};
+    ///     # use std::error::Error;
+    ///
+    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
+    ///     let input = "<!-- comments will be stripped -->This is an <a href=.>Ammonia</a> example using <a href=struct.Builder.html#method.new onclick=xss>the <code onmouseover=xss>empty()</code> function</a>.";
+    ///     let output = "This is an Ammonia example using the empty() function.";
+    ///
+    ///     let result = Builder::empty() // <--
+    ///         .url_relative(UrlRelative::RewriteWithBase(Url::parse("https://docs.rs/ammonia/1.0/ammonia/")?))
+    ///         .clean(input)
+    ///         .to_string();
+    ///     assert_eq!(result, output);
+    ///     # Ok(())
+    ///     # }
+    ///     # fn main() { do_main().unwrap() }
+    ///
+    /// [default options]: fn.clean.html
+    /// [`Builder`]: struct.Builder.html
+    pub fn empty() -> Self {
+        Self {
+            tags: hashset![],
+            ..Self::default()
+        }
+    }
+
+    /// Sanitizes an HTML fragment in a string according to the configured options.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, Url, UrlRelative};
+    ///     # use std::error::Error;
+    ///
+    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
+    ///     let input = "<!-- comments will be stripped -->This is an <a href=.>Ammonia</a> example using <a href=struct.Builder.html#method.new onclick=xss>the <code onmouseover=xss>new()</code> function</a>.";
+    ///     let output = "This is an <a href=\"https://docs.rs/ammonia/1.0/ammonia/\" rel=\"noopener noreferrer\">Ammonia</a> example using <a href=\"https://docs.rs/ammonia/1.0/ammonia/struct.Builder.html#method.new\" rel=\"noopener noreferrer\">the <code>new()</code> function</a>.";
+    ///
+    ///     let result = Builder::new()
+    ///         .url_relative(UrlRelative::RewriteWithBase(Url::parse("https://docs.rs/ammonia/1.0/ammonia/")?))
+    ///         .clean(input)
+    ///         .to_string(); // <--
+    ///     assert_eq!(result, output);
+    ///     # Ok(())
+    ///     # }
+    ///     # fn main() { do_main().unwrap() }
+    pub fn clean(&self, src: &str) -> Document {
+        let parser = Self::make_parser();
+        let dom = parser.one(src);
+        self.clean_dom(dom, &mut None)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Sanitizes an HTML fragment in a string, or returns an error if the options contradict
+    /// each other.
+    ///
+    /// This is the same as [`clean`], except that it returns the error that [`validate`]
+    /// would return instead of panicking.
+    ///
+    /// [`clean`]: #method.clean
+    /// [`validate`]: #method.validate
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, PolicyError};
+    ///
+    ///     let result = Builder::new()
+    ///         .add_clean_content_tags(&["aside"])
+    ///         .try_clean("<aside>no</aside>");
+    ///     assert_eq!(
+    ///         result.unwrap_err(),
+    ///         PolicyError::CleanContentTagInTags { tag: "aside".to_string() },
+    ///     );
+    pub fn try_clean(&self, src: &str) -> Result<Document, PolicyError> {
+        self.validate()?;
+        let parser = Self::make_parser();
+        let dom = parser.one(src);
+        self.clean_dom(dom, &mut None)
+    }
+
+    /// Checks the options for contradictory settings.
+    ///
+    /// [`clean`] panics when given a configuration that this function rejects,
+    /// so applications that build their configuration at run time can call this
+    /// once at startup to fail fast. See the [panics section][panics] for a list of
+    /// the settings that are checked.
+    ///
+    /// [`clean`]: #method.clean
+    /// [panics]: #panics
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, PolicyError};
+    ///
+    ///     let mut b = Builder::new();
+    ///     assert_eq!(b.validate(), Ok(()));
+    ///     b.add_generic_attributes(&["rel"]);
+    ///     assert_eq!(b.validate(), Err(PolicyError::GenericRelWithLinkRel));
+    ///     b.link_rel(None);
+    ///     assert_eq!(b.validate(), Ok(()));
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        if self.link_rel.is_some() {
+            if self.generic_attributes.contains("rel") {
+                return Err(PolicyError::GenericRelWithLinkRel);
+            }
+            if self
+                .tag_attributes
+                .get("a")
+                .is_some_and(|a| a.contains("rel"))
+            {
+                return Err(PolicyError::TagRelWithLinkRel);
+            }
+        }
+        if !self.allowed_classes.is_empty() && self.generic_attributes.contains("class") {
+            return Err(PolicyError::GenericClassWithAllowedClasses);
+        }
+        for &tag in self.allowed_classes.keys() {
+            if self
+                .tag_attributes
+                .get(tag)
+                .is_some_and(|a| a.contains("class"))
+            {
+                return Err(PolicyError::TagClassWithAllowedClasses { tag: tag.to_owned() });
+            }
+        }
+        for &tag in &self.clean_content_tags {
+            if self.tags.contains(tag) {
+                return Err(PolicyError::CleanContentTagInTags { tag: tag.to_owned() });
+            }
+            if self.tag_attributes.contains_key(tag) {
+                return Err(PolicyError::CleanContentTagInTagAttributes { tag: tag.to_owned() });
+            }
+        }
+        for &tag in self.rename_tags.values() {
+            if !is_safe_rename_target(tag) && !self.clean_content_tags.contains(tag) {
+                return Err(PolicyError::UnsafeRenameTarget { tag: tag.to_owned() });
+            }
+        }
+        if let Some(scope) = self
+            .style_sheets
+            .as_ref()
+            .and_then(StyleSheetPolicy::invalid_scope)
+        {
+            return Err(PolicyError::StyleSheetScope { scope: scope.to_owned() });
+        }
+        Ok(())
+    }
+
+    /// Sanitizes an HTML fragment in a string, and reports everything that was removed.
+    ///
+    /// The returned [`SanitizeReport`] lists every element, attribute, URL, style property
+    /// and class that the configured options caused to be dropped, in document order.
+    /// The sanitized [`Document`] is the same one that [`clean`] would have returned.
+    ///
+    /// [`clean`]: #method.clean
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, Removal};
+    ///
+    ///     let (document, report) = Builder::new()
+    ///         .clean_with_report("<script>alert(1)</script><img src=x onerror=alert(1)>");
+    ///     assert_eq!(document.to_string(), "<img src=\"x\">");
+    ///     assert_eq!(report.removals(), &[
+    ///         Removal::ElementContent { name: "script".to_string() },
+    ///         Removal::Attribute { element: "img".to_string(), attribute: "onerror".to_string() },
+    ///     ]);
+    pub fn clean_with_report(&self, src: &str) -> (Document, SanitizeReport) {
+        let parser = Self::make_parser();
+        let dom = parser.one(src);
+        let mut report = Some(SanitizeReport::default());
+        let document = self
+            .clean_dom(dom, &mut report)
+            .unwrap_or_else(|e| panic!("{e}"));
+        (document, report.unwrap_or_default())
+    }
+
+    /// Sanitizes an HTML fragment from a reader according to the configured options.
+    ///
+    /// The input should be in UTF-8 encoding, otherwise the decoding is lossy, just
+    /// like when using [`String::from_utf8_lossy`].
+    ///
+    /// To avoid consuming the reader, a mutable reference can be passed to this method.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     # use std::error::Error;
+    ///
+    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
+    ///     let a = Builder::new()
+    ///         .clean_from_reader(&b"<!-- no -->"[..])? // notice the `b`
+    ///         .to_string();
+    ///     assert_eq!(a, "");
+    ///     # Ok(()) }
+    ///     # fn main() { do_main().unwrap() }
+    ///
+    /// [`String::from_utf8_lossy`]: https://doc.rust-lang.org/std/string/struct.String.html#method.from_utf8_lossy
+    pub fn clean_from_reader<R>(&self, mut src: R) -> io::Result<Document>
+    where
+        R: io::Read,
+    {
+        let parser = Self::make_parser().from_utf8();
+        let dom = parser.read_from(&mut src)?;
+        Ok(self
+            .clean_dom(dom, &mut None)
+            .unwrap_or_else(|e| panic!("{e}")))
+    }
+
+    /// Sanitizes an HTML fragment from a reader, writing the result to a writer as it goes.
+    ///
+    /// Unlike [`clean_from_reader`], this never holds the whole fragment in memory. It runs the
+    /// HTML tokenizer directly, and only keeps track of the elements that are currently open,
+    /// so memory use depends on how deeply the input is nested rather than on how long it is.
+    /// This makes it a better fit for very large documents.
+    ///
+    /// The same options are applied, but the output is not always identical to what [`clean`]
+    /// returns. Without a full tree builder:
+    ///
+    /// * markup that the HTML parser would rearrange, like misnested formatting elements or
+    ///   text inside a `<table>` but outside of its cells, is written out in the order it
+    ///   was found;
+    /// * the content of a `<template>` that isn't allowed is kept, the way the content of any
+    ///   other element that isn't allowed is, while [`clean`] drops it;
+    /// * table parts, like `<tr>`, inside an SVG or MathML element that allows HTML content
+    ///   are dropped, where the parser would close the foreign content instead.
+    ///
+    /// End tags are added so that every element that gets written is also closed. The
+    /// test suite checks, on generated input that mixes foreign content, raw text elements and
+    /// tables, that the output is as safe as what [`clean`] returns: cleaning it again with
+    /// the same options removes nothing whenever the same holds for [`clean`].
+    ///
+    /// The input should be in UTF-8 encoding, otherwise the decoding is lossy, just
+    /// like when using [`String::from_utf8_lossy`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from reading `src` or writing `dst`. Whatever was written
+    /// before that is sanitized, but may have unclosed elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`clean`], and if [`link_text_mismatch`] is set, since a
+    /// link is written out before its text has been read.
+    ///
+    /// [`clean`]: #method.clean
+    /// [`clean_from_reader`]: #method.clean_from_reader
+    /// [`link_text_mismatch`]: #method.link_text_mismatch
+    /// [`String::from_utf8_lossy`]: https://doc.rust-lang.org/std/string/struct.String.html#method.from_utf8_lossy
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     # use std::error::Error;
+    ///
+    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
+    ///     let mut output = Vec::new();
+    ///     Builder::new().clean_stream(
+    ///         &b"<p onclick=alert(1)>Hello<script>alert(2)</script>"[..],
+    ///         &mut output,
+    ///     )?;
+    ///     assert_eq!(output, b"<p>Hello</p>");
+    ///     # Ok(()) }
+    ///     # fn main() { do_main().unwrap() }
+    pub fn clean_stream<R, W>(&self, src: R, dst: W) -> io::Result<()>
+    where
+        R: io::Read,
+        W: io::Write,
+    {
+        self.validate().unwrap_or_else(|e| panic!("{e}"));
+        if self.link_text_mismatch.is_some() {
+            panic!("{}", PolicyError::NotStreamable { option: "link_text_mismatch" });
+        }
+        stream::clean_stream(self, src, dst)
+    }
+
+    /// Returns a [`CssSanitizer`], which cleans CSS declarations that aren't in HTML, like
+    /// a user's "custom style" field, the same way as `style` attributes.
+    ///
+    /// It uses the same [`filter_style_properties`] and [`style_value_rules`], and checks
+    /// URLs like the ones in a `<style>` element, with [`url_schemes`], [`url_relative`]
+    /// and so on. Unlike for `style` attributes, every property is allowed if
+    /// [`filter_style_properties`] isn't set, but the declarations are still normalised
+    /// and their URLs checked.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`clean`].
+    ///
+    /// [`filter_style_properties`]: #method.filter_style_properties
+    /// [`style_value_rules`]: #method.style_value_rules
+    /// [`url_schemes`]: #method.url_schemes
+    /// [`url_relative`]: #method.url_relative
+    /// [`clean`]: #method.clean
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, UrlRelative};
+    ///     use maplit::hashset;
+    ///
+    ///     let mut builder = Builder::default();
+    ///     builder
+    ///         .filter_style_properties(hashset!["color", "background"])
+    ///         .url_relative(UrlRelative::Deny);
+    ///     let css = builder.css_sanitizer();
+    ///     assert_eq!(
+    ///         css.clean("color: red; background: url(https://example.com/a.png); top: 0"),
+    ///         "color:red;background:url(https://example.com/a.png)"
+    ///     );
+    ///     assert_eq!(css.clean("background: url(/a.png)"), "");
+    pub fn css_sanitizer(&self) -> CssSanitizer<'_, 'a> {
+        self.validate().unwrap_or_else(|e| panic!("{e}"));
+        CssSanitizer::new(self)
+    }
+
+    /// Clean a post-parsing DOM.
+    ///
+    /// This is not a public API because RcDom isn't really stable.
+    /// We want to be able to take breaking changes to html5ever itself
+    /// without having to break Ammonia's API.
+    ///
+    /// If `report` is `Some`, everything that gets removed is recorded in it.
+    ///
+    /// Fails without touching the DOM if the options contradict each other.
+    fn clean_dom(
+        &self,
+        dom: RcDom,
+        report: &mut Option<SanitizeReport>,
+    ) -> Result<Document, PolicyError> {
+        self.validate()?;
+        let mut stack = Vec::new();
+        let mut removed = Vec::new();
+        let link_rel = self
+            .link_rel
+            .map(|link_rel| format_tendril!("{}", link_rel));
+        let body = {
+            let children = dom.document.children.borrow();
+            children[0].clone()
+        };
+        // Each node is paired with the number of elements it ends up inside of,
+        // and whether text inside it can be linkified.
+        stack.extend(
+            mem::take(&mut *body.children.borrow_mut())
+                .into_iter()
+                .rev()
+                .map(|child| (child, 0, self.linkify)),
+        );
+        let mut nodes = 0;
+        // Kept `<a href>` elements, to check their text once it has been cleaned.
+        let mut links = Vec::new();
+        // This design approach is used to prevent pathological content from producing
+        // a stack overflow. The `stack` contains to-be-cleaned nodes, while `remove`,
+        // of course, contains nodes that need to be dropped (we can't just drop them,
+        // because they could have a very deep child tree).
+        while let Some((mut node, depth, linkify)) = stack.pop() {
+            let child_linkify = linkify
+                && match node.data {
+                    NodeData::Element { ref name, .. } => linkify::linkify_inside(name),
+                    _ => true,
+                };
+            if matches!(node.data, NodeData::Element { ref name, .. } if &*name.local == "selectedcontent" && name.ns == ns!(html)) &&
+                self.is_within(node.clone(), ns!(html), "select")
+            {
+                for sub in node.children.borrow_mut().iter_mut() {
+                    sub.parent.replace(None);
+                }
+                *node.children.borrow_mut() = Vec::new();
+            }
+            let parent = node.parent
+                .replace(None).expect("a node in the DOM will have a parent, except the root, which is not processed")
+                .upgrade().expect("a node's parent will be pointed to by its parent (or the root pointer), and will not be dropped");
+            if let Some(renamed) = self.rename_node(&node) {
+                node = renamed;
+            }
+            if self.clean_node_content(&node) {
+                record(report, || Removal::ElementContent {
+                    name: element_name(&node),
+                });
+                removed.push(node);
+                continue;
+            }
+            if !self.check_expected_namespace(&parent, &node) {
+                record(report, || Removal::NamespaceSwitch {
+                    name: element_name(&node),
+                });
+                removed.push(node);
+                continue;
+            }
+            if linkify {
+                if let Some(nodes) = self.linkify_text(&node, &parent) {
+                    // The new nodes are cleaned like any other, but aren't linkified again.
+                    stack.extend(nodes.into_iter().rev().map(|node| (node, depth, false)));
+                    continue;
+                }
+            }
+            let mut pass = match self.clean_child(&mut node, report) {
+                ElementAction::Keep => true,
+                ElementAction::Unwrap => false,
+                ElementAction::Remove => {
+                    removed.push(node);
+                    continue;
+                }
+            };
+            // The link text is compared with where the link really goes, not with where
+            // `absolute_url_rewriter` sends it.
+            let href = self.link_text_mismatch.and_then(|_| link_href(&node));
+            if pass {
+                self.adjust_node_attributes(&mut node, &link_rel, self.id_prefix, report);
+                if !self.embed_iframe(&node, report) {
+                    removed.push(node);
+                    continue;
+                }
+                self.clean_style_element(&node, &mut removed, report);
+                match self.filter_element(&node) {
+                    ElementAction::Keep => {}
+                    ElementAction::Unwrap => {
+                        record(report, || Removal::ElementFilter {
+                            name: element_name(&node),
+                            action: ElementAction::Unwrap,
+                        });
+                        pass = false;
+                    }
+                    ElementAction::Remove => {
+                        record(report, || Removal::ElementFilter {
+                            name: element_name(&node),
+                            action: ElementAction::Remove,
+                        });
+                        removed.push(node);
+                        continue;
+                    }
+                }
+            }
+            if pass
+                && self.max_depth.is_some_and(|max_depth| depth >= max_depth)
+                && matches!(node.data, NodeData::Element { .. })
+            {
+                record(report, || Removal::DepthLimit {
+                    name: element_name(&node),
+                    action: self.depth_limit_action,
+                });
+                match self.depth_limit_action {
+                    DepthLimitAction::Unwrap => pass = false,
+                    DepthLimitAction::Remove => {
+                        removed.push(node);
+                        continue;
+                    }
+                }
+            }
+            if pass {
+                if self.max_nodes.is_some_and(|max_nodes| nodes >= max_nodes) {
+                    record(report, || Removal::NodeLimit);
+                    removed.push(node);
+                    removed.extend(stack.drain(..).map(|(node, _, _)| node));
+                    break;
+                }
+                nodes += 1;
+                dom.append(&parent.clone(), NodeOrText::AppendNode(node.clone()));
+                if let Some(href) = href {
+                    links.push((node.clone(), href));
+                }
+            } else {
+                for sub in node.children.borrow_mut().iter_mut() {
+                    sub.parent.replace(Some(Rc::downgrade(&parent)));
+                }
+            }
+            let child_depth = if pass { depth + 1 } else { depth };
+            stack.extend(
+                mem::take(&mut *node.children.borrow_mut())
+                    .into_iter()
+                    .rev()
+                    .map(|child| (child, child_depth, child_linkify)),
+            );
+            if !pass {
+                removed.push(node);
+            }
+        }
+        if let Some(action) = self.link_text_mismatch {
+            // Innermost links first, so an outer link sees the text an inner one ends up with.
+            for (link, href) in links.into_iter().rev() {
+                self.check_link_text(&link, href, action, &mut removed, report);
+            }
+        }
+        if let Some(max_output_bytes) = self.max_output_bytes {
+            if truncate_output(&body, max_output_bytes, &mut removed) {
+                record(report, || Removal::OutputLimit);
+            }
+        }
+        // Now, imperatively clean up all of the child nodes.
+        // Otherwise, we could wind up with a DoS, either caused by a memory leak,
+        // or caused by a stack overflow.
+        while let Some(node) = removed.pop() {
+            removed.extend_from_slice(&mem::take(&mut *node.children.borrow_mut())[..]);
+        }
+        Ok(Document(dom))
+    }
+
+    fn is_within(&self, mut child: Handle, ns: Namespace, tag: &str) -> bool {
+        while let Some(parent) = child.parent.take() {
+            child.parent.set(Some(parent.clone()));
+            match child.data {
+                NodeData::Element { ref name, .. } if name.ns == ns && &*name.local == tag => return true,
+                _ => {
+                    if let Some(parent) = parent.upgrade() {
+                        child = parent;
+                    } else {
+                        return false;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Splits a text node at the URLs and email addresses in it, returning the pieces
+    /// with links as new `<a href>` elements, or `None` if there are no links.
+    ///
+    /// The pieces have `parent` as their parent, but aren't in its list of children yet.
+    fn linkify_text(&self, node: &Handle, parent: &Handle) -> Option<Vec<Handle>> {
+        let NodeData::Text { ref contents } = node.data else {
+            return None;
+        };
+        let contents = contents.borrow();
+        let links = linkify::find_links(&contents, &self.url_schemes);
+        if links.is_empty() {
+            return None;
+        }
+        let text = |text: &str| {
+            Node::new(NodeData::Text {
+                contents: RefCell::new(text.into()),
+            })
+        };
+        let mut nodes = Vec::new();
+        let mut start = 0;
+        for link in links {
+            if link.range.start > start {
+                nodes.push(text(&contents[start..link.range.start]));
+            }
+            let a = Node::new(NodeData::Element {
+                name: QualName::new(None, ns!(html), local_name!("a")),
+                attrs: RefCell::new(vec![Attribute {
+                    name: QualName::new(None, ns!(), local_name!("href")),
+                    value: link.href.into(),
+                }]),
+                template_contents: RefCell::new(None),
+                mathml_annotation_xml_integration_point: false,
+            });
+            let link_text = text(&contents[link.range.clone()]);
+            link_text.parent.set(Some(Rc::downgrade(&a)));
+            a.children.borrow_mut().push(link_text);
+            nodes.push(a);
+            start = link.range.end;
+        }
+        if start < contents.len() {
+            nodes.push(text(&contents[start..]));
+        }
+        for node in &nodes {
+            node.parent.set(Some(Rc::downgrade(parent)));
+        }
+        Some(nodes)
+    }
+
+    /// Rewrites or unwraps a cleaned link according to `link_text_mismatch`, if its text looks
+    /// like a URL on another host than `href`. The nodes it drops are moved to `removed`.
+    fn check_link_text(
+        &self,
+        link: &Handle,
+        href: String,
+        action: LinkTextAction,
+        removed: &mut Vec<Handle>,
+        report: &mut Option<SanitizeReport>,
+    ) {
+        let text = descendant_text(link);
+        if !link_text::is_mismatch(&text, &href) {
+            return;
+        }
+        let children = mem::take(&mut *link.children.borrow_mut());
+        match action {
+            LinkTextAction::ShowDestination => {
+                removed.extend(children);
+                let destination = Node::new(NodeData::Text {
+                    contents: RefCell::new(href.as_str().into()),
+                });
+                destination.parent.set(Some(Rc::downgrade(link)));
+                link.children.borrow_mut().push(destination);
+            }
+            LinkTextAction::Unwrap => {
+                let parent = link
+                    .parent
+                    .take()
+                    .and_then(|parent| parent.upgrade())
+                    .expect("a kept link is still in the DOM");
+                for child in &children {
+                    child.parent.set(Some(Rc::downgrade(&parent)));
+                }
+                let mut siblings = parent.children.borrow_mut();
+                let i = siblings
+                    .iter()
+                    .position(|sibling| Rc::ptr_eq(sibling, link))
+                    .expect("a node is among its parent's children");
+                removed.extend(siblings.splice(i..=i, children));
+            }
+        }
+        record(report, || Removal::LinkText { text, href, action });
+    }
+
+    /// Runs the `absolute_url_rewriter` on a URL, unless it's relative to the document
+    /// or on one of the exempt hosts.
+    ///
+    /// Returns its new value, `None` to leave it as it is, or `Err` if it has to be removed.
+    fn rewrite_absolute_url(
+        &self,
+        rewriter: &dyn AbsoluteUrlRewriter,
+        element: &str,
+        attribute: &str,
+        value: &str,
+    ) -> Result<Option<String>, ()> {
+        let url = match Url::parse(value) {
+            Ok(url) => url,
+            Err(url::ParseError::RelativeUrlWithoutBase) => match RELATIVE_URL_BASE.join(value) {
+                Ok(url) if url.host() != RELATIVE_URL_BASE.host() => url,
+                _ => return Ok(None),
+            },
+            Err(_) => return Ok(None),
+        };
+        let port = url.port_or_known_default();
+        let exempt = url.host().is_some_and(|host| {
+            self.absolute_url_rewriter_exempt_hosts
+                .iter()
+                .any(|pattern| pattern.matches(&host, port))
+        });
+        if exempt {
+            return Ok(None);
+        }
+        match rewriter.rewrite(element, attribute, &url) {
+            Some(new_value) if new_value == value => Ok(None),
+            Some(new_value) => Ok(Some(new_value.into_owned())),
+            None => Err(()),
+        }
+    }
+
+    /// Filters a declaration list, like the `style` attribute of `element`, according to
+    /// `filter_style_properties` and `style_value_rules`, which allows every property if
+    /// it's not set. This is used for both HTML and [`CssSanitizer`].
+    fn clean_style_declarations(
+        &self,
+        element: &str,
+        css: &str,
+        on_reject: &mut dyn FnMut(style::StyleRejection<'_>, &mut Option<SanitizeReport>),
+        report: &mut Option<SanitizeReport>,
+    ) -> String {
+        let rules = style::StyleRules {
+            properties: self.style_properties.as_ref(),
+            values: &self.style_value_rules,
+        };
+        // Both callbacks add to the report.
+        let report = RefCell::new(report);
+        style::filter_style_attribute_with(
+            css,
+            &rules,
+            &mut |url| self.clean_style_url(element, url, &mut report.borrow_mut()),
+            &mut |rejection| on_reject(rejection, &mut report.borrow_mut()),
+        )
+    }
+
+    /// Runs a URL from a `style` attribute through the same checks and rewriting as the
+    /// value of a URL attribute, as if it was in a `style` URL attribute.
+    ///
+    /// Returns its new value, or `None` if the declaration it's in has to be removed.
+    fn clean_style_url(
+        &self,
+        element: &str,
+        url: &str,
+        report: &mut Option<SanitizeReport>,
+    ) -> Option<String> {
+        let mut url = match self.check_url(element, "style", url, report) {
+            Ok(rewritten) => rewritten.unwrap_or_else(|| url.to_string()),
+            Err(_) => return None,
+        };
+        if is_url_relative(&url) {
+            url = self.url_relative.evaluate(&url)?.to_string();
+        }
+        if let Some(ref rewriter) = self.absolute_url_rewriter {
+            if let Some(new_url) = self
+                .rewrite_absolute_url(&**rewriter, element, "style", &url)
+                .ok()?
+            {
+                url = new_url;
+            }
+        }
+        Some(url)
+    }
+
+    /// Replaces the content of a `<style>` element kept by `style_sheets` with its cleaned
+    /// style sheet. The nodes it drops are moved to `removed`.
+    fn clean_style_element(
+        &self,
+        node: &Handle,
+        removed: &mut Vec<Handle>,
+        report: &mut Option<SanitizeReport>,
+    ) {
+        match node.data {
+            NodeData::Element { ref name, .. } if self.keeps_style_sheet(name) => {}
+            _ => return,
+        }
+        let children = mem::take(&mut *node.children.borrow_mut());
+        let css: String = children
+            .iter()
+            .filter_map(|child| match child.data {
+                NodeData::Text { ref contents } => Some(contents.borrow().to_string()),
+                _ => None,
+            })
+            .collect();
+        removed.extend(children);
+        let css = self.clean_style_sheet(&css, report);
+        if !css.is_empty() {
+            let text = Node::new(NodeData::Text {
+                contents: RefCell::new(css.into()),
+            });
+            text.parent.set(Some(Rc::downgrade(node)));
+            node.children.borrow_mut().push(text);
+        }
+    }
+
+    /// Cleans the content of a `<style>` element according to `style_sheets`.
+    fn clean_style_sheet(&self, css: &str, report: &mut Option<SanitizeReport>) -> String {
+        let Some(ref policy) = self.style_sheets else {
+            return css.to_string();
+        };
+        let rules = style::StyleRules {
+            properties: self.style_properties.as_ref(),
+            values: &self.style_value_rules,
+        };
+        // All of the callbacks add to the report.
+        let report = RefCell::new(report);
+        style_sheet::clean_style_sheet(
+            css,
+            policy,
+            &rules,
+            self.id_prefix,
+            &mut |url| self.clean_style_url("style", url, &mut report.borrow_mut()),
+            &mut |rule| {
+                record(&mut report.borrow_mut(), || Removal::StyleSheetRule {
+                    rule: rule.to_string(),
+                })
+            },
+            &mut |rejection| record_style_rejection("style", rejection, &mut report.borrow_mut()),
+        )
+    }
+
+    /// Returns `true` if a node and all its content should be removed.
+    fn clean_node_content(&self, node: &Handle) -> bool {
+        match node.data {
+            NodeData::Text { .. }
+            | NodeData::Comment { .. }
+            | NodeData::Doctype { .. }
+            | NodeData::Document
+            | NodeData::ProcessingInstruction { .. } => false,
+            NodeData::Element { ref name, .. } => {
+                !self.keeps_style_sheet(name) && self.clean_content_tags.contains(&*name.local)
+            }
+        }
+    }
+
+    /// Remove unwanted attributes, and check if the node should be kept, unwrapped,
+    /// or removed along with its content.
+    ///
+    /// The root node doesn't need cleaning because we create the root node ourselves,
+    /// and it doesn't get serialized, and ... it just exists to give the parser
+    /// a context (in this case, a div-like block context).
+    fn clean_child(&self, child: &mut Handle, report: &mut Option<SanitizeReport>) -> ElementAction {
+        match child.data {
+            NodeData::Text { .. } => ElementAction::Keep,
+            NodeData::Comment { .. } => {
+                if self.strip_comments {
+                    record(report, || Removal::Comment);
+                    ElementAction::Unwrap
+                } else {
+                    ElementAction::Keep
+                }
+            }
+            NodeData::Doctype { .. }
+            | NodeData::Document
+            | NodeData::ProcessingInstruction { .. } => ElementAction::Unwrap,
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } => {
+                let embed = self.embeds_iframe(name);
+                if self.tags.contains(&*name.local) || embed || self.keeps_style_sheet(name) {
+                    let mut csp_violation = false;
+                    let mut attr_filter = |attr: &mut html5ever::Attribute| {
+                        let whitelisted = (embed
+                            && matches!(
+                                &*attr.name.local,
+                                "src" | "width" | "height" | "title" | "loading"
+                            ))
+                            || self.generic_attributes.contains(&*attr.name.local)
+                            || self.generic_attribute_prefixes.as_ref().map(|prefixes| {
+                                prefixes.iter().any(|&p| attr.name.local.starts_with(p))
+                            }) == Some(true)
+                            || self
+                                .tag_attributes
+                                .get(&*name.local)
+                                .map(|ta| ta.contains(&*attr.name.local))
+                                == Some(true)
+                            || self
+                                .tag_attribute_values
+                                .get(&*name.local)
+                                .and_then(|tav| tav.get(&*attr.name.local))
+                                .map(|vs| {
+                                    let attr_val = attr.value.to_lowercase();
+                                    vs.iter().any(|v| v.to_lowercase() == attr_val)
+                                })
+                                == Some(true);
+                        let removal = if !whitelisted {
+                            // If the class attribute is not whitelisted,
+                            // but there is a whitelisted set of allowed_classes,
+                            // do not strip out the class attribute.
+                            // Banned classes will be filtered later.
+                            if &*attr.name.local == "class"
+                                && self.allowed_classes.contains_key(&*name.local)
+                            {
+                                None
+                            } else {
+                                Some(Removal::Attribute {
+                                    element: name.local.to_string(),
+                                    attribute: attr.name.local.to_string(),
+                                })
+                            }
+                        } else if is_srcset_attr(&name.local, &attr.name.local) {
+                            let value = clean_srcset(
+                                &attr.value,
+                                report,
+                                |url, report| {
+                                    self.check_url(&name.local, &attr.name.local, url, report)
+                                        .inspect_err(|removal| {
+                                            csp_violation |=
+                                                matches!(removal, Removal::CspViolation { .. });
+                                        })
+                                },
+                                |url| Removal::InvalidUrl {
+                                    element: name.local.to_string(),
+                                    attribute: attr.name.local.to_string(),
+                                    value: url.to_string(),
+                                },
+                            );
+                            // The rejected candidates are already recorded.
+                            let Some(value) = value else {
+                                return false;
+                            };
+                            attr.value = value.into();
+                            None
+                        } else if self.is_url_attr(&name.local, &attr.name.local) {
+                            match self.check_url(&name.local, &attr.name.local, &attr.value, report) {
+                                Err(removal) => Some(removal),
+                                Ok(rewritten) => {
+                                    if let Some(url) = rewritten {
+                                        attr.value = url.into();
+                                    }
+                                    None
+                                }
+                            }
+                        } else {
+                            None
+                        };
+                        match removal {
+                            Some(removal) => {
+                                csp_violation |= matches!(removal, Removal::CspViolation { .. });
+                                record(report, || removal);
+                                false
+                            }
+                            None => true,
+                        }
+                    };
+                    attrs.borrow_mut().retain_mut(&mut attr_filter);
+                    if csp_violation && self.csp_violation_action == CspViolationAction::RemoveElement {
+                        return ElementAction::Remove;
+                    }
+                    if let Some(max_attributes) = self.max_attributes {
+                        let mut attrs = attrs.borrow_mut();
+                        for attr in attrs.iter().skip(max_attributes) {
+                            record(report, || Removal::AttributeLimit {
+                                element: name.local.to_string(),
+                                attribute: attr.name.local.to_string(),
+                            });
+                        }
+                        attrs.truncate(max_attributes);
+                    }
+                    ElementAction::Keep
+                } else {
+                    record(report, || Removal::Element {
+                        name: name.local.to_string(),
+                    });
+                    ElementAction::Unwrap
+                }
+            }
+        }
+    }
+
+    /// Given an element name and attribute name, determine if the given attribute contains a URL.
+    fn is_url_attr(&self, element: &str, attr: &str) -> bool {
+        self.generic_url_attributes.contains(attr)
+            || self
+                .url_attributes
+                .get(element)
+                .is_some_and(|attrs| attrs.contains(attr))
+    }
+
+    /// Checks a URL against `url_schemes` or the `data_uri_policy`, the `url_policies` and
+    /// the `content_security_policy`, or against `url_relative` if it's relative.
+    ///
+    /// Returns the reason to remove it, if it has to be removed, or its new value if its
+    /// confusable host has to be rewritten according to `homograph_action`.
+    fn check_url(
+        &self,
+        element: &str,
+        attribute: &str,
+        value: &str,
+        report: &mut Option<SanitizeReport>,
+    ) -> Result<Option<String>, Removal> {
+        let data_uri_policy = self
+            .data_uri_policy
+            .as_ref()
+            .filter(|_| is_image_url_attr(element, attribute));
+        let mut scheme_relative = false;
+        let (url, same_origin) = match Url::parse(value) {
+            Ok(url) if url.scheme() == "data" && data_uri_policy.is_some() => {
+                if let Some(Err(reason)) = data_uri_policy.map(|policy| policy.check(&url)) {
+                    return Err(Removal::DataUri {
+                        element: element.to_string(),
+                        attribute: attribute.to_string(),
+                        reason,
+                    });
+                }
+                (url, false)
+            }
+            Ok(url) if !self.url_schemes.contains(url.scheme()) => {
+                return Err(Removal::UrlScheme {
+                    element: element.to_string(),
+                    attribute: attribute.to_string(),
+                    scheme: url.scheme().to_string(),
+                });
+            }
+            Ok(url) => (url, false),
+            Err(url::ParseError::RelativeUrlWithoutBase) => {
+                if matches!(self.url_relative, UrlRelative::Deny) {
+                    return Err(Removal::RelativeUrl {
+                        element: element.to_string(),
+                        attribute: attribute.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+                // A scheme-relative URL like `//example.com/` names its own host,
+                // so it gets the same checks as an absolute one.
+                match RELATIVE_URL_BASE.join(value) {
+                    Ok(url) => {
+                        let same_origin = url.host() == RELATIVE_URL_BASE.host();
+                        scheme_relative = !same_origin;
+                        (url, same_origin)
+                    }
+                    Err(_) => return Ok(None),
+                }
+            }
+            Err(_) => {
+                return Err(Removal::InvalidUrl {
+                    element: element.to_string(),
+                    attribute: attribute.to_string(),
+                    value: value.to_string(),
+                });
+            }
+        };
+        let url_policy = self
+            .url_policies
+            .get(element)
+            .and_then(|attributes| attributes.get(attribute))
+            .or(self.generic_url_policy.as_ref());
+        // A `data:` URL has no host, and was already checked by its own policy.
+        let checked_data_uri = url.scheme() == "data" && data_uri_policy.is_some();
+        if !same_origin && !checked_data_uri && url_policy.is_some_and(|policy| !policy.allows(&url)) {
+            return Err(Removal::UrlHost {
+                element: element.to_string(),
+                attribute: attribute.to_string(),
+                value: value.to_string(),
+            });
+        }
+        let violated = self
+            .content_security_policy
+            .as_ref()
+            .and_then(|csp| csp.check(element, attribute, &url, same_origin));
+        if let Some(directive) = violated {
+            return Err(Removal::CspViolation {
+                element: element.to_string(),
+                attribute: attribute.to_string(),
+                directive: directive.to_string(),
+                value: value.to_string(),
+                action: self.csp_violation_action,
+            });
+        }
+        let action = match self.homograph_action {
+            Some(action) if !same_origin && url.host_str().is_some_and(homograph::is_confusable) => {
+                action
+            }
+            _ => return Ok(None),
+        };
+        let removal = Removal::Homograph {
+            element: element.to_string(),
+            attribute: attribute.to_string(),
+            value: value.to_string(),
+            action,
+        };
+        match action {
+            HomographAction::RemoveAttribute => Err(removal),
+            HomographAction::Punycode => {
+                record(report, || removal);
+                // The parsed URL already has its host in punycode.
+                if scheme_relative {
+                    Ok(Some(format!("//{}", &url[url::Position::BeforeUsername..])))
+                } else {
+                    Ok(Some(url.into()))
+                }
+            }
+            HomographAction::Report => {
+                record(report, || removal);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns a copy of an HTML element with its name replaced according to `rename_tags`,
+    /// or `None` if it isn't renamed.
+    ///
+    /// The node's data can't be changed in place, so the children are moved over to a new
+    /// node instead. The new node does not have a parent yet.
+    fn rename_node(&self, node: &Handle) -> Option<Handle> {
+        let NodeData::Element {
+            ref name,
+            ref attrs,
+            ref template_contents,
+            mathml_annotation_xml_integration_point,
+        } = node.data
+        else {
+            return None;
+        };
+        if name.ns != ns!(html) {
+            return None;
+        }
+        let &new_name = self.rename_tags.get(&*name.local)?;
+        let mut attrs = mem::take(&mut *attrs.borrow_mut());
+        if let Some(set_attrs) = self.rename_tag_attribute_values.get(&*name.local) {
+            for (&set_name, &set_value) in set_attrs {
+                if let Some(attr) = attrs.iter_mut().find(|attr| &*attr.name.local == set_name) {
+                    attr.value = set_value.into();
+                } else {
+                    attrs.push(Attribute {
+                        name: QualName::new(None, ns!(), set_name.into()),
+                        value: set_value.into(),
+                    });
+                }
+            }
+        }
+        let renamed = Node::new(NodeData::Element {
+            name: QualName::new(name.prefix.clone(), ns!(html), new_name.into()),
+            attrs: RefCell::new(attrs),
+            template_contents: RefCell::new(template_contents.borrow_mut().take()),
+            mathml_annotation_xml_integration_point,
+        });
+        let children = mem::take(&mut *node.children.borrow_mut());
+        for child in &children {
+            child.parent.set(Some(Rc::downgrade(&renamed)));
+        }
+        *renamed.children.borrow_mut() = children;
+        Some(renamed)
+    }
+
+    /// Returns `true` if an element is an HTML `<iframe>` that is handled by `allow_iframes`.
+    fn embeds_iframe(&self, name: &QualName) -> bool {
+        !self.iframe_providers.is_empty() && name.ns == ns!(html) && name.local == local_name!("iframe")
+    }
+
+    /// Whether an element is a `<style>` element kept by `style_sheets`.
+    fn keeps_style_sheet(&self, name: &QualName) -> bool {
+        self.style_sheets.is_some() && name.ns == ns!(html) && name.local == local_name!("style")
+    }
+
+    /// Checks the `src` of an iframe handled by `allow_iframes` against the providers,
+    /// and replaces its `sandbox`, `allow` and `referrerpolicy` with the provider's.
+    ///
+    /// Returns `false` if it doesn't match any provider, and has to be removed.
+    fn embed_iframe(&self, node: &Handle, report: &mut Option<SanitizeReport>) -> bool {
+        let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = node.data
+        else {
+            return true;
+        };
+        if !self.embeds_iframe(name) {
+            return true;
+        }
+        let mut attrs = attrs.borrow_mut();
+        let src = attrs
+            .iter()
+            .find(|attr| &*attr.name.local == "src")
+            .map(|attr| attr.value.to_string());
+        let provider = src.as_deref().and_then(|src| {
+            let url = Url::parse(src).ok()?;
+            self.iframe_providers
+                .iter()
+                .find(|provider| provider.matches(&url))
+        });
+        let Some(provider) = provider else {
+            record(report, || Removal::Iframe { src });
+            return false;
+        };
+        attrs.retain(|attr| match &*attr.name.local {
+            "srcdoc" | "allowfullscreen" | "allowpaymentrequest" => {
+                record(report, || Removal::Attribute {
+                    element: name.local.to_string(),
+                    attribute: attr.name.local.to_string(),
+                });
+                false
+            }
+            "sandbox" | "allow" | "referrerpolicy" => false,
+            _ => true,
+        });
+        for (name, value) in provider.forced_attributes() {
+            attrs.push(Attribute {
+                name: QualName::new(None, ns!(), name.into()),
+                value: value.into(),
+            });
+        }
+        true
+    }
+
+    /// Runs the `element_filter` callback, if there is one, on a cleaned element.
+    fn filter_element(&self, node: &Handle) -> ElementAction {
+        match (&self.element_filter, &node.data) {
+            (
+                Some(element_filter),
+                NodeData::Element {
+                    ref name,
+                    ref attrs,
+                    ..
+                },
+            ) => {
+                let attrs = attrs.borrow();
+                let attrs: Vec<(&str, &str)> = attrs
+                    .iter()
+                    .map(|attr| (&*attr.name.local, &*attr.value))
+                    .collect();
+                element_filter.filter(&name.local, &attrs)
+            }
+            _ => ElementAction::Keep,
+        }
+    }
+
+    // Check for unexpected namespace changes.
+    //
+    // The issue happens if developers added to the list of allowed tags any
+    // tag which is parsed in RCDATA state, PLAINTEXT state or RAWTEXT state,
+    // that is:
+    //
+    // * title
+    // * textarea
+    // * xmp
+    // * iframe
+    // * noembed
+    // * noframes
+    // * plaintext
+    // * noscript
+    // * style
+    // * script
+    //
+    // An example in the wild is Plume, that allows iframe [1].  So in next
+    // examples I'll assume the following policy:
+    //
+    //     Builder::new()
+    //        .add_tags(&["iframe"])
+    //
+    // In HTML namespace `<iframe>` is parsed specially; that is, its content is
+    // treated as text. For instance, the following html:
+    //
+    //     <iframe><a>test
+    //
+    // Is parsed into the following DOM tree:
+    //
+    //     iframe
+    //     └─ #text: <a>test
+    //
+    // So iframe cannot have any children other than a text node.
+    //
+    // The same is not true, though, in "foreign content"; that is, within
+    // <svg> or <math> tags. The following html:
+    //
+    //     <svg><iframe><a>test
+    //
+    // is parsed differently:
+    //
+    //    svg
+    //    └─ iframe
+    //       └─ a
+    //          └─ #text: test
+    //
+    // So in SVG namespace iframe can have children.
+    //
+    // Ammonia disallows <svg> but it keeps its content after deleting it. And
+    // the parser internally keeps track of the namespace of the element. So
+    // assume we have the following snippet:
+    //
+    //     <svg><iframe><a title="</iframe><img src onerror=alert(1)>">test
+    //
+    // It is parsed into:
+    //
+    //     svg
+    //     └─ iframe
+    //        └─ a title="</iframe><img src onerror=alert(1)>"
+    //           └─ #text: test
+    //
+    // This DOM tree is harmless from ammonia point of view because the piece
+    // of code that looks like XSS is in a title attribute. Hence, the
+    // resulting "safe" HTML from ammonia would be:
+    //
+    //     <iframe><a title="</iframe><img src onerror=alert(1)>" rel="noopener
+    // noreferrer">test</a></iframe>
+    //
+    // However, at this point, the information about namespace is lost, which
+    // means that the browser will parse this snippet into:
+    //
+    //     ├─ iframe
+    //     │  └─ #text: <a title="
+    //     ├─ img src="" onerror="alert(1)"
+    //     └─ #text: " rel="noopener noreferrer">test
+    //
+    // Leading to XSS.
+    //
+    // To solve this issue, check for unexpected namespace switches after cleanup.
+    // Elements which change namespace at an unexpected point are removed.
+    // This function returns `true` if `child` should be kept, and `false` if it
+    // should be removed.
+    //
+    // [1]: https://github.com/Plume-org/Plume/blob/main/plume-models/src/safe_string.rs#L21
+    fn check_expected_namespace(&self, parent: &Handle, child: &Handle) -> bool {
+        match (&parent.data, &child.data) {
+            (NodeData::Element { name: pn, .. }, NodeData::Element { name: cn, .. }) => {
+                self.check_expected_namespace_name(pn, cn)
+            }
+            _ => true,
+        }
+    }
+
+    /// The element name part of [`check_expected_namespace`], shared with the streaming
+    /// sanitizer, which doesn't have a parent node to look at.
+    ///
+    /// [`check_expected_namespace`]: #method.check_expected_namespace
+    fn check_expected_namespace_name(&self, parent: &QualName, child: &QualName) -> bool {
+        // The only way to switch from html to svg is with the <svg> tag
+        if parent.ns == ns!(html) && child.ns == ns!(svg) {
+            child.local == local_name!("svg")
+        // The only way to switch from html to mathml is with the <math> tag
+        } else if parent.ns == ns!(html) && child.ns == ns!(mathml) {
+            child.local == local_name!("math")
+        // The only way to switch from mathml to svg/html is with a text integration point
+        } else if parent.ns == ns!(mathml) && child.ns != ns!(mathml) {
+            // https://html.spec.whatwg.org/#mathml
+            matches!(
+                &*parent.local,
+                "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml"
+            ) && if child.ns == ns!(html) { is_html_tag(&child.local) } else { true }
+        // The only way to switch from svg to mathml/html is with an html integration point
+        } else if parent.ns == ns!(svg) && child.ns != ns!(svg) {
+            // https://html.spec.whatwg.org/#svg-0
+            matches!(&*parent.local, "foreignObject")
+                && if child.ns == ns!(html) { is_html_tag(&child.local) } else { true }
+        } else if child.ns == ns!(svg) {
+            is_svg_tag(&child.local)
+        } else if child.ns == ns!(mathml) {
+            is_mathml_tag(&child.local)
+        } else if child.ns == ns!(html) {
+            is_html_tag(&child.local)
+        } else {
+            // There are no other supported ways to switch namespace
+            parent.ns == child.ns
+        }
+    }
+
+    /// Add and transform special-cased attributes and elements.
+    ///
+    /// This function handles:
+    ///
+    /// * relative URL rewriting
+    /// * adding `<a rel>` attributes
+    /// * filtering out banned style properties
+    /// * filtering out banned classes
+    fn adjust_node_attributes(
+        &self,
+        child: &mut Handle,
+        link_rel: &Option<StrTendril>,
+        id_prefix: Option<&'a str>,
+        report: &mut Option<SanitizeReport>,
+    ) {
+        if let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = child.data
+        {
+            if let Some(set_attrs) = self.set_tag_attribute_values.get(&*name.local) {
+                let mut attrs = attrs.borrow_mut();
+                for (&set_name, &set_value) in set_attrs {
+                    // set the value of the attribute if the attribute is already present
+                    if let Some(attr) = attrs.iter_mut().find(|attr| &*attr.name.local == set_name)
+                    {
+                        if &*attr.value != set_value {
+                            attr.value = set_value.into();
+                        }
+                    } else {
+                        // otherwise, add the attribute
+                        let attr = Attribute {
+                            name: QualName::new(None, ns!(), set_name.into()),
+                            value: set_value.into(),
+                        };
+                        attrs.push(attr);
+                    }
+                }
+            }
+            if let Some(ref link_rel) = *link_rel {
+                if &*name.local == "a" {
+                    attrs.borrow_mut().push(Attribute {
+                        name: QualName::new(None, ns!(), local_name!("rel")),
+                        value: link_rel.clone(),
+                    })
+                }
+            }
+            if let Some(ref id_prefix) = id_prefix {
+                for attr in &mut *attrs.borrow_mut() {
+                    if &attr.name.local == "id" && !attr.value.starts_with(id_prefix) {
+                        attr.value = format_tendril!("{}{}", id_prefix, attr.value);
+                    }
+                }
+            }
+            if let Some(ref attr_filter) = self.attribute_filter {
+                let mut drop_attrs = Vec::new();
+                let mut attrs = attrs.borrow_mut();
+                for (i, attr) in &mut attrs.iter_mut().enumerate() {
+                    let replace_with = if let Some(new) =
+                        attr_filter.filter(&name.local, &attr.name.local, &attr.value)
+                    {
+                        if *new != *attr.value {
+                            Some(format_tendril!("{}", new))
+                        } else {
+                            None // no need to replace the attr if filter returned the same value
+                        }
+                    } else {
+                        record(report, || Removal::AttributeFilter {
+                            element: name.local.to_string(),
+                            attribute: attr.name.local.to_string(),
+                        });
+                        drop_attrs.push(i);
+                        None
+                    };
+                    if let Some(replace_with) = replace_with {
+                        attr.value = replace_with;
+                    }
+                }
+                for i in drop_attrs.into_iter().rev() {
+                    attrs.swap_remove(i);
+                }
+            }
+            {
+                let mut drop_attrs = Vec::new();
+                let mut attrs = attrs.borrow_mut();
+                for (i, attr) in attrs.iter_mut().enumerate() {
+                    if is_srcset_attr(&name.local, &attr.name.local) {
+                        let rejected = |url: &str| Removal::RelativeUrl {
+                            element: name.local.to_string(),
+                            attribute: attr.name.local.to_string(),
+                            value: url.to_string(),
+                        };
+                        let value = clean_srcset(
+                            &attr.value,
+                            report,
+                            |url, _| {
+                                if !is_url_relative(url) {
+                                    return Ok(None);
+                                }
+                                match self.url_relative.evaluate(url) {
+                                    Some(new_url) => Ok(Some(new_url.to_string())),
+                                    None => Err(rejected(url)),
+                                }
+                            },
+                            rejected,
+                        );
+                        match value {
+                            Some(value) => attr.value = value.into(),
+                            None => drop_attrs.push(i),
+                        }
+                    } else if self.is_url_attr(&name.local, &attr.name.local)
+                        && is_url_relative(&attr.value)
+                    {
+                        let new_value = self.url_relative.evaluate(&attr.value);
+                        if let Some(new_value) = new_value {
+                            attr.value = new_value;
+                        } else {
+                            record(report, || Removal::RelativeUrl {
+                                element: name.local.to_string(),
+                                attribute: attr.name.local.to_string(),
+                                value: attr.value.to_string(),
+                            });
+                            drop_attrs.push(i);
+                        }
+                    }
+                }
+                // Swap remove scrambles the vector after the current point.
+                // We will not do anything except with items before the current point.
+                // The `rev()` is, as such, necessary for correctness.
+                // We could use regular `remove(usize)` and a forward iterator,
+                // but that's slower.
+                for i in drop_attrs.into_iter().rev() {
+                    attrs.swap_remove(i);
+                }
+            }
+            if let Some(ref rewriter) = self.absolute_url_rewriter {
+                let mut drop_attrs = Vec::new();
+                let mut attrs = attrs.borrow_mut();
+                for (i, attr) in attrs.iter_mut().enumerate() {
+                    if is_srcset_attr(&name.local, &attr.name.local) {
+                        let rejected = |url: &str| Removal::UrlRewriter {
+                            element: name.local.to_string(),
+                            attribute: attr.name.local.to_string(),
+                            value: url.to_string(),
+                        };
+                        let value = clean_srcset(
+                            &attr.value,
+                            report,
+                            |url, _| {
+                                self.rewrite_absolute_url(&**rewriter, &name.local, &attr.name.local, url)
+                                    .map_err(|()| rejected(url))
+                            },
+                            rejected,
+                        );
+                        match value {
+                            Some(value) => attr.value = value.into(),
+                            None => drop_attrs.push(i),
+                        }
+                    } else if self.is_url_attr(&name.local, &attr.name.local) {
+                        match self.rewrite_absolute_url(&**rewriter, &name.local, &attr.name.local, &attr.value) {
+                            Ok(None) => {}
+                            Ok(Some(new_value)) => attr.value = new_value.into(),
+                            Err(()) => {
+                                record(report, || Removal::UrlRewriter {
+                                    element: name.local.to_string(),
+                                    attribute: attr.name.local.to_string(),
+                                    value: attr.value.to_string(),
+                                });
+                                drop_attrs.push(i);
+                            }
+                        }
+                    }
+                }
+                for i in drop_attrs.into_iter().rev() {
+                    attrs.swap_remove(i);
+                }
+            }
+            if self.style_properties.is_some() {
+                for attr in &mut *attrs.borrow_mut() {
+                    if &attr.name.local == "style" {
+                        attr.value = self
+                            .clean_style_declarations(
+                                &name.local,
+                                &attr.value,
+                                &mut |rejection, report| {
+                                    record_style_rejection(&name.local, rejection, report)
+                                },
+                                report,
+                            )
+                            .into();
+                    }
+                }
+            }
+            if let Some(allowed_values) = self.allowed_classes.get(&*name.local) {
+                for attr in &mut *attrs.borrow_mut() {
+                    if &attr.name.local == "class" {
+                        let mut classes = vec![];
+                        // https://html.spec.whatwg.org/#global-attributes:classes-2
+                        for class in attr.value.split_ascii_whitespace() {
+                            if allowed_values.contains(class) {
+                                classes.push(class.to_owned());
+                            } else {
+                                record(report, || Removal::Class {
+                                    element: name.local.to_string(),
+                                    class: class.to_string(),
+                                });
+                            }
+                        }
+                        attr.value = format_tendril!("{}", classes.join(" "));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Initializes an HTML fragment parser.
+    ///
+    /// Ammonia conforms to the HTML5 fragment parsing rules,
+    /// by parsing the given fragment as if it were included in a <div> tag.
+    fn make_parser() -> html::Parser<RcDom> {
+        html::parse_fragment(
+            RcDom::default(),
+            html::ParseOpts::default(),
+            QualName::new(None, ns!(html), local_name!("div")),
+            vec![],
+            false,
+        )
+    }
+}
+
+/// Given an element name and attribute name, determine if the given attribute contains
+/// a `srcset`, a list of image URLs with descriptors.
+fn is_srcset_attr(element: &str, attr: &str) -> bool {
+    ((element == "img" || element == "source") && attr == "srcset")
+        || (element == "link" && attr == "imagesrcset")
+}
+
+/// Runs each URL in a `srcset` through `check`, and returns the new value of the attribute,
+/// or `None` if no candidates are left.
+///
+/// `check` returns the URL to use instead, if it changes, or the removal to record for a
+/// candidate that is dropped. A new URL that would change how the rest of the srcset is split
+/// up, which a custom rewriter could return, drops its candidate too, and `rejected` gives
+/// the removal to record for the original URL.
+fn clean_srcset(
+    value: &str,
+    report: &mut Option<SanitizeReport>,
+    mut check: impl FnMut(&str, &mut Option<SanitizeReport>) -> Result<Option<String>, Removal>,
+    rejected: impl Fn(&str) -> Removal,
+) -> Option<String> {
+    let mut candidates = srcset::parse(value);
+    candidates.retain_mut(|candidate| match check(&candidate.url, report) {
+        Ok(None) => true,
+        Ok(Some(new_url))
+            if !new_url.contains(srcset::is_whitespace) && !new_url.ends_with(',') =>
+        {
+            candidate.url = new_url.into();
+            true
+        }
+        Ok(Some(_)) => {
+            record(report, || rejected(&candidate.url));
+            false
+        }
+        Err(removal) => {
+            record(report, || removal);
+            false
+        }
+    });
+    (!candidates.is_empty()).then(|| srcset::serialize(&candidates))
+}
+
+/// Cuts a cleaned fragment short, so that it serializes to at most `max_bytes` bytes.
+///
+/// Each element's end tag is counted along with its start tag, so the fragment can be cut
+/// anywhere without leaving an element unclosed. The nodes that are cut off are moved
+/// to `removed`. Returns `true` if anything was cut.
+fn truncate_output(root: &Handle, max_bytes: usize, removed: &mut Vec<Handle>) -> bool {
+    let mut remaining = max_bytes;
+    // Each entry is an element that is being walked, and the index of its next child.
+    let mut stack = vec![(root.clone(), 0)];
+    while let Some((parent, i)) = stack.pop() {
+        let Some(node) = parent.children.borrow().get(i).cloned() else {
+            continue;
+        };
+        stack.push((parent.clone(), i + 1));
+        let (fits, keep) = match node.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } => {
+                let mut start = String::new();
+                stream::write_start_tag(&mut start, name, &attrs.borrow());
+                let end = stream::end_tag_len(name);
+                let fits = start.len() + end <= remaining;
+                if fits {
+                    remaining -= start.len() + end;
+                    stack.push((node.clone(), 0));
+                }
+                (fits, fits)
+            }
+            NodeData::Text { ref contents } => {
+                let raw = matches!(parent.data, NodeData::Element { ref name, .. }
+                    if name.ns == ns!(html) && stream::is_raw_text(&name.local));
+                let mut contents = contents.borrow_mut();
+                let (cut, len) = stream::fit_text(&contents, raw, remaining);
+                remaining -= len;
+                let extra = contents.len() - cut;
+                let fits = extra == 0;
+                if !fits {
+                    contents.pop_back(extra as u32);
+                }
+                (fits, cut > 0)
+            }
+            NodeData::Comment { ref contents } => {
+                let len = "<!---->".len() + contents.len();
+                let fits = len <= remaining;
+                if fits {
+                    remaining -= len;
+                }
+                (fits, fits)
+            }
+            NodeData::Document
+            | NodeData::Doctype { .. }
+            | NodeData::ProcessingInstruction { .. } => (true, true),
+        };
+        if !fits {
+            // Drop this node, unless some of its text fit, and everything after it at every level.
+            let (parent, next) = stack.pop().expect("the parent was pushed back above");
+            let cut_from = if keep { next } else { next - 1 };
+            removed.extend(parent.children.borrow_mut().drain(cut_from..));
+            for (ancestor, next) in stack {
+                removed.extend(ancestor.children.borrow_mut().drain(next..));
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Given an element name, determine if other elements can be renamed to it without changing
+/// how their children get parsed when the output is parsed again.
+///
+/// Elements that aren't listed here either put the parser in another mode for their
+/// contents, like `textarea`, `table` or `select`, are void, or close and reopen each other
+/// in ways that could move their children around, like `a` or `button`. Custom element
+/// names are always parsed like ordinary elements.
+fn is_safe_rename_target(element: &str) -> bool {
+    matches!(
+        element,
+        "abbr"
+            | "acronym"
+            | "address"
+            | "article"
+            | "aside"
+            | "b"
+            | "bdi"
+            | "bdo"
+            | "big"
+            | "blockquote"
+            | "center"
+            | "cite"
+            | "code"
+            | "data"
+            | "dd"
+            | "del"
+            | "details"
+            | "dfn"
+            | "div"
+            | "dl"
+            | "dt"
+            | "em"
+            | "figcaption"
+            | "figure"
+            | "font"
+            | "footer"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "header"
+            | "hgroup"
+            | "i"
+            | "ins"
+            | "kbd"
+            | "li"
+            | "main"
+            | "mark"
+            | "nav"
+            | "ol"
+            | "p"
+            | "pre"
+            | "q"
+            | "s"
+            | "samp"
+            | "section"
+            | "small"
+            | "span"
+            | "strike"
+            | "strong"
+            | "sub"
+            | "summary"
+            | "sup"
+            | "time"
+            | "tt"
+            | "u"
+            | "ul"
+            | "var"
+    ) || is_custom_element_name(element)
+}
+
+/// Whether the tree builder treats this as the name of a custom element.
+///
+/// <https://html.spec.whatwg.org/multipage/custom-elements.html#valid-custom-element-name>
+fn is_custom_element_name(element: &str) -> bool {
+    element.starts_with(|c: char| c.is_ascii_lowercase())
+        && element.contains('-')
+        && element
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '-' | '.' | '_'))
+        && !matches!(
+            element,
+            "annotation-xml"
+                | "color-profile"
+                | "font-face"
+                | "font-face-src"
+                | "font-face-uri"
+                | "font-face-format"
+                | "font-face-name"
+                | "missing-glyph"
+        )
+}
+
+/// Given an element and the name of one of its URL attributes, determine if the URL is
+/// loaded as an image, which is the only place `data_uri_policy` applies. URLs in CSS, which
+/// are checked as if they were in a `style` attribute, count as images.
+fn is_image_url_attr(element: &str, attr: &str) -> bool {
+    matches!(
+        (element, attr),
+        ("img", "src") | ("img", "srcset") | ("source", "srcset") | (_, "style")
+    )
+}
+
+fn is_html_tag(element: &str) -> bool {
+    (!is_svg_tag(element) && !is_mathml_tag(element))
+        || matches!(
+            element,
+            "title" | "style" | "font" | "a" | "script" | "span"
+        )
+}
+
+/// Given an element name, check if it's SVG
+fn is_svg_tag(element: &str) -> bool {
+    // https://svgwg.org/svg2-draft/eltindex.html
+    matches!(
+        element,
+        "a" | "animate"
+            | "animateMotion"
+            | "animateTransform"
+            | "circle"
+            | "clipPath"
+            | "defs"
+            | "desc"
+            | "discard"
+            | "ellipse"
+            | "feBlend"
+            | "feColorMatrix"
+            | "feComponentTransfer"
+            | "feComposite"
+            | "feConvolveMatrix"
+            | "feDiffuseLighting"
+            | "feDisplacementMap"
+            | "feDistantLight"
+            | "feDropShadow"
+            | "feFlood"
+            | "feFuncA"
             | "feFuncB"
             | "feFuncG"
             | "feFuncR"
@@ -2310,1573 +4818,3620 @@ fn is_svg_tag(element: &str) -> bool {
             | "rect"
             | "script"
             | "set"
-            | "stop"
-            | "style"
-            | "svg"
-            | "switch"
-            | "symbol"
-            | "text"
-            | "textPath"
-            | "title"
-            | "tspan"
-            | "use"
-            | "view"
+            | "stop"
+            | "style"
+            | "svg"
+            | "switch"
+            | "symbol"
+            | "text"
+            | "textPath"
+            | "title"
+            | "tspan"
+            | "use"
+            | "view"
+    )
+}
+
+/// Given an element name, check if it's Math
+fn is_mathml_tag(element: &str) -> bool {
+    // https://svgwg.org/svg2-draft/eltindex.html
+    matches!(
+        element,
+        "abs"
+            | "and"
+            | "annotation"
+            | "annotation-xml"
+            | "apply"
+            | "approx"
+            | "arccos"
+            | "arccosh"
+            | "arccot"
+            | "arccoth"
+            | "arccsc"
+            | "arccsch"
+            | "arcsec"
+            | "arcsech"
+            | "arcsin"
+            | "arcsinh"
+            | "arctan"
+            | "arctanh"
+            | "arg"
+            | "bind"
+            | "bvar"
+            | "card"
+            | "cartesianproduct"
+            | "cbytes"
+            | "ceiling"
+            | "cerror"
+            | "ci"
+            | "cn"
+            | "codomain"
+            | "complexes"
+            | "compose"
+            | "condition"
+            | "conjugate"
+            | "cos"
+            | "cosh"
+            | "cot"
+            | "coth"
+            | "cs"
+            | "csc"
+            | "csch"
+            | "csymbol"
+            | "curl"
+            | "declare"
+            | "degree"
+            | "determinant"
+            | "diff"
+            | "divergence"
+            | "divide"
+            | "domain"
+            | "domainofapplication"
+            | "emptyset"
+            | "eq"
+            | "equivalent"
+            | "eulergamma"
+            | "exists"
+            | "exp"
+            | "exponentiale"
+            | "factorial"
+            | "factorof"
+            | "false"
+            | "floor"
+            | "fn"
+            | "forall"
+            | "gcd"
+            | "geq"
+            | "grad"
+            | "gt"
+            | "ident"
+            | "image"
+            | "imaginary"
+            | "imaginaryi"
+            | "implies"
+            | "in"
+            | "infinity"
+            | "int"
+            | "integers"
+            | "intersect"
+            | "interval"
+            | "inverse"
+            | "lambda"
+            | "laplacian"
+            | "lcm"
+            | "leq"
+            | "limit"
+            | "list"
+            | "ln"
+            | "log"
+            | "logbase"
+            | "lowlimit"
+            | "lt"
+            | "maction"
+            | "maligngroup"
+            | "malignmark"
+            | "math"
+            | "matrix"
+            | "matrixrow"
+            | "max"
+            | "mean"
+            | "median"
+            | "menclose"
+            | "merror"
+            | "mfenced"
+            | "mfrac"
+            | "mglyph"
+            | "mi"
+            | "min"
+            | "minus"
+            | "mlabeledtr"
+            | "mlongdiv"
+            | "mmultiscripts"
+            | "mn"
+            | "mo"
+            | "mode"
+            | "moment"
+            | "momentabout"
+            | "mover"
+            | "mpadded"
+            | "mphantom"
+            | "mprescripts"
+            | "mroot"
+            | "mrow"
+            | "ms"
+            | "mscarries"
+            | "mscarry"
+            | "msgroup"
+            | "msline"
+            | "mspace"
+            | "msqrt"
+            | "msrow"
+            | "mstack"
+            | "mstyle"
+            | "msub"
+            | "msubsup"
+            | "msup"
+            | "mtable"
+            | "mtd"
+            | "mtext"
+            | "mtr"
+            | "munder"
+            | "munderover"
+            | "naturalnumbers"
+            | "neq"
+            | "none"
+            | "not"
+            | "notanumber"
+            | "notin"
+            | "notprsubset"
+            | "notsubset"
+            | "or"
+            | "otherwise"
+            | "outerproduct"
+            | "partialdiff"
+            | "pi"
+            | "piece"
+            | "piecewise"
+            | "plus"
+            | "power"
+            | "primes"
+            | "product"
+            | "prsubset"
+            | "quotient"
+            | "rationals"
+            | "real"
+            | "reals"
+            | "reln"
+            | "rem"
+            | "root"
+            | "scalarproduct"
+            | "sdev"
+            | "sec"
+            | "sech"
+            | "selector"
+            | "semantics"
+            | "sep"
+            | "set"
+            | "setdiff"
+            | "share"
+            | "sin"
+            | "sinh"
+            | "span"
+            | "subset"
+            | "sum"
+            | "tan"
+            | "tanh"
+            | "tendsto"
+            | "times"
+            | "transpose"
+            | "true"
+            | "union"
+            | "uplimit"
+            | "variance"
+            | "vector"
+            | "vectorproduct"
+            | "xor"
+    )
+}
+
+fn is_url_relative(url: &str) -> bool {
+    matches!(
+        Url::parse(url),
+        Err(url::ParseError::RelativeUrlWithoutBase)
     )
 }
 
-/// Given an element name, check if it's Math
-fn is_mathml_tag(element: &str) -> bool {
-    // https://svgwg.org/svg2-draft/eltindex.html
-    matches!(
-        element,
-        "abs"
-            | "and"
-            | "annotation"
-            | "annotation-xml"
-            | "apply"
-            | "approx"
-            | "arccos"
-            | "arccosh"
-            | "arccot"
-            | "arccoth"
-            | "arccsc"
-            | "arccsch"
-            | "arcsec"
-            | "arcsech"
-            | "arcsin"
-            | "arcsinh"
-            | "arctan"
-            | "arctanh"
-            | "arg"
-            | "bind"
-            | "bvar"
-            | "card"
-            | "cartesianproduct"
-            | "cbytes"
-            | "ceiling"
-            | "cerror"
-            | "ci"
-            | "cn"
-            | "codomain"
-            | "complexes"
-            | "compose"
-            | "condition"
-            | "conjugate"
-            | "cos"
-            | "cosh"
-            | "cot"
-            | "coth"
-            | "cs"
-            | "csc"
-            | "csch"
-            | "csymbol"
-            | "curl"
-            | "declare"
-            | "degree"
-            | "determinant"
-            | "diff"
-            | "divergence"
-            | "divide"
-            | "domain"
-            | "domainofapplication"
-            | "emptyset"
-            | "eq"
-            | "equivalent"
-            | "eulergamma"
-            | "exists"
-            | "exp"
-            | "exponentiale"
-            | "factorial"
-            | "factorof"
-            | "false"
-            | "floor"
-            | "fn"
-            | "forall"
-            | "gcd"
-            | "geq"
-            | "grad"
-            | "gt"
-            | "ident"
-            | "image"
-            | "imaginary"
-            | "imaginaryi"
-            | "implies"
-            | "in"
-            | "infinity"
-            | "int"
-            | "integers"
-            | "intersect"
-            | "interval"
-            | "inverse"
-            | "lambda"
-            | "laplacian"
-            | "lcm"
-            | "leq"
-            | "limit"
-            | "list"
-            | "ln"
-            | "log"
-            | "logbase"
-            | "lowlimit"
-            | "lt"
-            | "maction"
-            | "maligngroup"
-            | "malignmark"
-            | "math"
-            | "matrix"
-            | "matrixrow"
-            | "max"
-            | "mean"
-            | "median"
-            | "menclose"
-            | "merror"
-            | "mfenced"
-            | "mfrac"
-            | "mglyph"
-            | "mi"
-            | "min"
-            | "minus"
-            | "mlabeledtr"
-            | "mlongdiv"
-            | "mmultiscripts"
-            | "mn"
-            | "mo"
-            | "mode"
-            | "moment"
-            | "momentabout"
-            | "mover"
-            | "mpadded"
-            | "mphantom"
-            | "mprescripts"
-            | "mroot"
-            | "mrow"
-            | "ms"
-            | "mscarries"
-            | "mscarry"
-            | "msgroup"
-            | "msline"
-            | "mspace"
-            | "msqrt"
-            | "msrow"
-            | "mstack"
-            | "mstyle"
-            | "msub"
-            | "msubsup"
-            | "msup"
-            | "mtable"
-            | "mtd"
-            | "mtext"
-            | "mtr"
-            | "munder"
-            | "munderover"
-            | "naturalnumbers"
-            | "neq"
-            | "none"
-            | "not"
-            | "notanumber"
-            | "notin"
-            | "notprsubset"
-            | "notsubset"
-            | "or"
-            | "otherwise"
-            | "outerproduct"
-            | "partialdiff"
-            | "pi"
-            | "piece"
-            | "piecewise"
-            | "plus"
-            | "power"
-            | "primes"
-            | "product"
-            | "prsubset"
-            | "quotient"
-            | "rationals"
-            | "real"
-            | "reals"
-            | "reln"
-            | "rem"
-            | "root"
-            | "scalarproduct"
-            | "sdev"
-            | "sec"
-            | "sech"
-            | "selector"
-            | "semantics"
-            | "sep"
-            | "set"
-            | "setdiff"
-            | "share"
-            | "sin"
-            | "sinh"
-            | "span"
-            | "subset"
-            | "sum"
-            | "tan"
-            | "tanh"
-            | "tendsto"
-            | "times"
-            | "transpose"
-            | "true"
-            | "union"
-            | "uplimit"
-            | "variance"
-            | "vector"
-            | "vectorproduct"
-            | "xor"
-    )
+/// Policy for [relative URLs], that is, URLs that do not specify the scheme in full.
+///
+/// This policy kicks in, if set, for every attribute in [`Builder::url_attributes`]
+/// and [`Builder::generic_url_attributes`], such as `href`, `src`, or the `data`
+/// attribute of an `object` tag, and for each URL in a `srcset`.
+///
+/// [relative URLs]: struct.Builder.html#method.url_relative
+///
+/// # Examples
+///
+/// ## `Deny`
+///
+/// * `<a href="test">` is a file-relative URL, and will be removed
+/// * `<a href="/test">` is a domain-relative URL, and will be removed
+/// * `<a href="//example.com/test">` is a scheme-relative URL, and will be removed
+/// * `<a href="http://example.com/test">` is an absolute URL, and will be kept
+///
+/// ## `PassThrough`
+///
+/// No changes will be made to any URLs, except if a disallowed scheme is used.
+///
+/// ## `RewriteWithBase`
+///
+/// If the base is set to `http://notriddle.com/some-directory/some-file`
+///
+/// * `<a href="test">` will be rewritten to `<a href="http://notriddle.com/some-directory/test">`
+/// * `<a href="/test">` will be rewritten to `<a href="http://notriddle.com/test">`
+/// * `<a href="//example.com/test">` will be rewritten to `<a href="http://example.com/test">`
+/// * `<a href="http://example.com/test">` is an absolute URL, so it will be kept as-is
+///
+/// ## `Custom`
+///
+/// Pass the relative URL to a function.
+/// If it returns `Some(string)`, then that one gets used.
+/// Otherwise, it will remove the attribute (like `Deny` does).
+///
+///     use std::borrow::Cow;
+///     fn is_absolute_path(url: &str) -> bool {
+///         let u = url.as_bytes();
+///         // `//a/b/c` is "protocol-relative", meaning "a" is a hostname
+///         // `/a/b/c` is an absolute path, and what we want to do stuff to.
+///         u.get(0) == Some(&b'/') && u.get(1) != Some(&b'/')
+///     }
+///     fn evaluate(url: &str) -> Option<Cow<str>> {
+///         if is_absolute_path(url) {
+///             Some(Cow::Owned(String::from("/root") + url))
+///         } else {
+///             Some(Cow::Borrowed(url))
+///         }
+///     }
+///     let a = ammonia::Builder::new()
+///         .url_relative(ammonia::UrlRelative::Custom(Box::new(evaluate)))
+///         .clean("<a href=/test/path>fixed</a><a href=path>passed</a><a href=http://google.com/>skipped</a>")
+///         .to_string();
+///     assert_eq!(a, "<a href=\"/root/test/path\" rel=\"noopener noreferrer\">fixed</a><a href=\"path\" rel=\"noopener noreferrer\">passed</a><a href=\"http://google.com/\" rel=\"noopener noreferrer\">skipped</a>");
+///
+/// This function is only applied to relative URLs.
+/// To restrict the hosts that absolute URLs can point at,
+/// use [`Builder::url_policies`].
+#[non_exhaustive]
+pub enum UrlRelative<'a> {
+    /// Relative URLs will be completely stripped from the document.
+    Deny,
+    /// Relative URLs will be passed through unchanged.
+    PassThrough,
+    /// Relative URLs will be changed into absolute URLs, based on this base URL.
+    RewriteWithBase(Url),
+    /// Force absolute and relative paths into a particular directory.
+    ///
+    /// Since the resolver does not affect fully-qualified URLs, it doesn't
+    /// prevent users from linking wherever they want. This feature only
+    /// serves to make content more portable.
+    ///
+    /// # Examples
+    ///
+    /// <table>
+    /// <thead>
+    /// <tr>
+    ///     <th>root</th>
+    ///     <th>path</th>
+    ///     <th>url</th>
+    ///     <th>result</th>
+    /// </tr>
+    /// </thead>
+    /// <tbody>
+    /// <tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    ///     <td>README.md</td>
+    ///     <td></td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/README.md</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    ///     <td>README.md</td>
+    ///     <td>/</td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    ///     <td>README.md</td>
+    ///     <td>/CONTRIBUTING.md</td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/CONTRIBUTING.md</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master</td>
+    ///     <td>README.md</td>
+    ///     <td></td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/README.md</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master</td>
+    ///     <td>README.md</td>
+    ///     <td>/</td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master</td>
+    ///     <td>README.md</td>
+    ///     <td>/CONTRIBUTING.md</td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/CONTRIBUTING.md</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    ///     <td></td>
+    ///     <td></td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    ///     <td></td>
+    ///     <td>/</td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
+    ///     <td></td>
+    ///     <td>/CONTRIBUTING.md</td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/CONTRIBUTING.md</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/</td>
+    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
+    ///     <td></td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/README.md</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/</td>
+    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
+    ///     <td>/</td>
+    ///     <td>https://github.com/</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/</td>
+    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
+    ///     <td>CONTRIBUTING.md</td>
+    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/CONTRIBUTING.md</td>
+    /// </tr><tr>
+    ///     <td>https://github.com/</td>
+    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
+    ///     <td>/CONTRIBUTING.md</td>
+    ///     <td>https://github.com/CONTRIBUTING.md</td>
+    /// </tr>
+    /// </tbody>
+    /// </table>
+    RewriteWithRoot {
+        /// The URL that is treated as the root by the resolver.
+        root: Url,
+        /// The "current path" used to resolve relative paths.
+        path: String,
+    },
+    /// Rewrite URLs with a custom function.
+    Custom(Box<dyn UrlRelativeEvaluate<'a>>),
+}
+
+impl<'a> UrlRelative<'a> {
+    fn evaluate(&self, url: &str) -> Option<html5ever::tendril::StrTendril> {
+        match self {
+            UrlRelative::RewriteWithBase(ref url_base) => url_base
+                .join(url)
+                .ok()
+                .and_then(|x| StrTendril::from_str(x.as_str()).ok()),
+            UrlRelative::RewriteWithRoot { ref root, ref path } => {
+                (match url.as_bytes() {
+                    // Scheme-relative URL
+                    [b'/', b'/', ..] => root.join(url),
+                    // Path-absolute URL
+                    b"/" => root.join("."),
+                    [b'/', ..] => root.join(&url[1..]),
+                    // Path-relative URL
+                    _ => root.join(path).and_then(|r| r.join(url)),
+                })
+                .ok()
+                .and_then(|x| StrTendril::from_str(x.as_str()).ok())
+            }
+            UrlRelative::Custom(ref evaluate) => evaluate
+                .evaluate(url)
+                .as_ref()
+                .map(Cow::as_ref)
+                .map(StrTendril::from_str)
+                .and_then(Result::ok),
+            UrlRelative::PassThrough => StrTendril::from_str(url).ok(),
+            UrlRelative::Deny => None,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for UrlRelative<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            UrlRelative::Deny => write!(f, "UrlRelative::Deny"),
+            UrlRelative::PassThrough => write!(f, "UrlRelative::PassThrough"),
+            UrlRelative::RewriteWithBase(ref base) => {
+                write!(f, "UrlRelative::RewriteWithBase({})", base)
+            }
+            UrlRelative::RewriteWithRoot { ref root, ref path } => {
+                write!(
+                    f,
+                    "UrlRelative::RewriteWithRoot {{ root: {root}, path: {path} }}"
+                )
+            }
+            UrlRelative::Custom(_) => write!(f, "UrlRelative::Custom"),
+        }
+    }
+}
+
+/// Types that implement this trait can be used to convert a relative URL into an absolute URL.
+///
+/// This evaluator is only called when the URL is relative; absolute URLs are not evaluated.
+///
+/// See [`url_relative`][url_relative] for more details.
+///
+/// [url_relative]: struct.Builder.html#method.url_relative
+pub trait UrlRelativeEvaluate<'a>: Send + Sync + 'a {
+    /// Return `None` to remove the attribute. Return `Some(str)` to replace it with a new string.
+    fn evaluate<'url>(&self, _: &'url str) -> Option<Cow<'url, str>>;
+}
+impl<'a, T> UrlRelativeEvaluate<'a> for T
+where
+    T: Fn(&str) -> Option<Cow<'_, str>> + Send + Sync + 'a,
+{
+    fn evaluate<'url>(&self, url: &'url str) -> Option<Cow<'url, str>> {
+        self(url)
+    }
+}
+
+impl fmt::Debug for dyn AbsoluteUrlRewriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AbsoluteUrlRewriter")
+    }
+}
+
+/// Types that implement this trait can be used to rewrite absolute URLs.
+///
+/// See [`absolute_url_rewriter`][absolute_url_rewriter] for more details.
+///
+/// [absolute_url_rewriter]: struct.Builder.html#method.absolute_url_rewriter
+pub trait AbsoluteUrlRewriter: Send + Sync {
+    /// Return `None` to remove the attribute. Return `Some(str)` to replace the URL with a new string.
+    fn rewrite<'u>(&self, element: &str, attribute: &str, url: &'u Url) -> Option<Cow<'u, str>>;
+}
+
+impl<T> AbsoluteUrlRewriter for T
+where
+    T: for<'u> Fn(&str, &str, &'u Url) -> Option<Cow<'u, str>> + Send + Sync + 'static,
+{
+    fn rewrite<'u>(&self, element: &str, attribute: &str, url: &'u Url) -> Option<Cow<'u, str>> {
+        self(element, attribute, url)
+    }
+}
+
+impl fmt::Debug for dyn AttributeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AttributeFilter")
+    }
+}
+
+/// Types that implement this trait can be used to remove or rewrite arbitrary attributes.
+///
+/// See [`attribute_filter`][attribute_filter] for more details.
+///
+/// [attribute_filter]: struct.Builder.html#method.attribute_filter
+pub trait AttributeFilter: Send + Sync {
+    /// Return `None` to remove the attribute. Return `Some(str)` to replace it with a new string.
+    fn filter<'a>(&self, _: &str, _: &str, _: &'a str) -> Option<Cow<'a, str>>;
+}
+
+impl<T> AttributeFilter for T
+where
+    T: for<'a> Fn(&str, &str, &'a str) -> Option<Cow<'a, str>> + Send + Sync + 'static,
+{
+    fn filter<'a>(&self, element: &str, attribute: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        self(element, attribute, value)
+    }
+}
+
+/// A contradiction or other problem in a sanitizer configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PolicyError {
+    /// `rel` is in [`generic_attributes`][generic_attributes],
+    /// while [`link_rel`][link_rel] is not `None`.
+    ///
+    /// [generic_attributes]: struct.Builder.html#method.generic_attributes
+    /// [link_rel]: struct.Builder.html#method.link_rel
+    GenericRelWithLinkRel,
+    /// `rel` is in the [`tag_attributes`][tag_attributes] for `<a>`,
+    /// while [`link_rel`][link_rel] is not `None`.
+    ///
+    /// [tag_attributes]: struct.Builder.html#method.tag_attributes
+    /// [link_rel]: struct.Builder.html#method.link_rel
+    TagRelWithLinkRel,
+    /// `class` is in [`generic_attributes`][generic_attributes],
+    /// while [`allowed_classes`][allowed_classes] is not empty.
+    ///
+    /// [generic_attributes]: struct.Builder.html#method.generic_attributes
+    /// [allowed_classes]: struct.Builder.html#method.allowed_classes
+    GenericClassWithAllowedClasses,
+    /// `class` is in the [`tag_attributes`][tag_attributes] for a tag
+    /// that also has [`allowed_classes`][allowed_classes].
+    ///
+    /// [tag_attributes]: struct.Builder.html#method.tag_attributes
+    /// [allowed_classes]: struct.Builder.html#method.allowed_classes
+    TagClassWithAllowedClasses {
+        /// The tag with both settings.
+        tag: String,
+    },
+    /// A tag is in both [`clean_content_tags`][clean_content_tags] and [`tags`][tags].
+    ///
+    /// [clean_content_tags]: struct.Builder.html#method.clean_content_tags
+    /// [tags]: struct.Builder.html#method.tags
+    CleanContentTagInTags {
+        /// The tag with both settings.
+        tag: String,
+    },
+    /// A tag is in both [`clean_content_tags`][clean_content_tags]
+    /// and [`tag_attributes`][tag_attributes].
+    ///
+    /// [clean_content_tags]: struct.Builder.html#method.clean_content_tags
+    /// [tag_attributes]: struct.Builder.html#method.tag_attributes
+    CleanContentTagInTagAttributes {
+        /// The tag with both settings.
+        tag: String,
+    },
+    /// A tag is renamed by [`rename_tags`][rename_tags] to an element that could change how
+    /// its contents are parsed, like `textarea`, `table` or `svg`, and that isn't in
+    /// [`clean_content_tags`][clean_content_tags].
+    ///
+    /// [clean_content_tags]: struct.Builder.html#method.clean_content_tags
+    ///
+    /// [rename_tags]: struct.Builder.html#method.rename_tags
+    UnsafeRenameTarget {
+        /// The new tag name.
+        tag: String,
+    },
+    /// A range of numbers in a [`StyleValueRule`] has a minimum greater than its maximum,
+    /// or a bound that is NaN.
+    ///
+    /// [`StyleValueRule`]: struct.StyleValueRule.html
+    StyleNumberRange {
+        /// The minimum, as written by `f32`'s `Display`.
+        min: String,
+        /// The maximum, as written by `f32`'s `Display`.
+        max: String,
+    },
+    /// The scope of the [`style_sheets`][style_sheets] policy has characters that would
+    /// let it break out of the selector it's put in front of.
+    ///
+    /// [style_sheets]: struct.Builder.html#method.style_sheets
+    StyleSheetScope {
+        /// The scope.
+        scope: String,
+    },
+    /// The configuration uses a callback, which can't be stored in a `Policy`.
+    Callback {
+        /// The name of the option that holds the callback.
+        option: &'static str,
+    },
+    /// The configuration sets an option that [`clean_stream`][clean_stream] can't apply.
+    ///
+    /// [clean_stream]: struct.Builder.html#method.clean_stream
+    NotStreamable {
+        /// The name of the option.
+        option: &'static str,
+    },
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::GenericRelWithLinkRel => f.write_str(
+                "`rel` appears in `generic_attributes` while `link_rel` is set",
+            ),
+            PolicyError::TagRelWithLinkRel => f.write_str(
+                "`rel` appears in `tag_attributes` for `a` while `link_rel` is set",
+            ),
+            PolicyError::GenericClassWithAllowedClasses => f.write_str(
+                "`class` appears in `generic_attributes` while `allowed_classes` is set",
+            ),
+            PolicyError::TagClassWithAllowedClasses { tag } => write!(
+                f,
+                "`class` appears in `tag_attributes` for `{tag}` while `allowed_classes` is set for it"
+            ),
+            PolicyError::CleanContentTagInTags { tag } => write!(
+                f,
+                "`{tag}` appears in `clean_content_tags` and in `tags` at the same time"
+            ),
+            PolicyError::CleanContentTagInTagAttributes { tag } => write!(
+                f,
+                "`{tag}` appears in `clean_content_tags` and in `tag_attributes` at the same time"
+            ),
+            PolicyError::UnsafeRenameTarget { tag } => write!(
+                f,
+                "`{tag}` appears in `rename_tags` as a new tag name, but its content is parsed differently"
+            ),
+            PolicyError::StyleNumberRange { min, max } => {
+                write!(f, "`{min}` to `{max}` is not a valid range of numbers for `style_value_rules`")
+            }
+            PolicyError::StyleSheetScope { scope } => {
+                write!(f, "`{scope}` is not a valid scope for `style_sheets`")
+            }
+            PolicyError::Callback { option } => {
+                write!(f, "`{option}` is set to a callback, which cannot be serialized")
+            }
+            PolicyError::NotStreamable { option } => {
+                write!(f, "`{option}` is set, which `clean_stream` does not support")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// A list of everything that was removed while sanitizing a fragment.
+///
+/// Returned by [`clean_with_report`][clean_with_report]. Removals are listed in the
+/// order they were encountered, which is document order.
+///
+/// [clean_with_report]: struct.Builder.html#method.clean_with_report
+///
+/// # Examples
+///
+///     use ammonia::Builder;
+///
+///     let (_, report) = Builder::new()
+///         .clean_with_report("<b onclick=evil()>bold</b><!-- comment -->");
+///     let messages: Vec<String> = report.removals().iter().map(ToString::to_string).collect();
+///     assert_eq!(messages, [
+///         "removed the `onclick` attribute from a `<b>` element",
+///         "removed a comment",
+///     ]);
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SanitizeReport {
+    removals: Vec<Removal>,
+}
+
+impl SanitizeReport {
+    /// Returns the recorded removals, in document order.
+    pub fn removals(&self) -> &[Removal] {
+        &self.removals
+    }
+
+    /// Returns `true` if nothing was removed.
+    pub fn is_empty(&self) -> bool {
+        self.removals.is_empty()
+    }
+
+    fn push(&mut self, removal: Removal) {
+        self.removals.push(removal);
+    }
+}
+
+impl IntoIterator for SanitizeReport {
+    type Item = Removal;
+    type IntoIter = std::vec::IntoIter<Removal>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.removals.into_iter()
+    }
+}
+
+/// A single thing removed by the sanitizer, and the reason it was removed.
+///
+/// See [`SanitizeReport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Removal {
+    /// An element that is not in [`tags`][tags] was removed. Its children were kept.
+    ///
+    /// [tags]: struct.Builder.html#method.tags
+    Element {
+        /// The local name of the element.
+        name: String,
+    },
+    /// An element in [`clean_content_tags`][clean_content_tags] was removed,
+    /// along with everything inside it.
+    ///
+    /// [clean_content_tags]: struct.Builder.html#method.clean_content_tags
+    ElementContent {
+        /// The local name of the element.
+        name: String,
+    },
+    /// An element was removed, along with everything inside it, because it
+    /// switches into (or out of) SVG or MathML in a place where the browser would not.
+    NamespaceSwitch {
+        /// The local name of the element.
+        name: String,
+    },
+    /// A comment was removed because [`strip_comments`][strip_comments] is turned on.
+    ///
+    /// [strip_comments]: struct.Builder.html#method.strip_comments
+    Comment,
+    /// An attribute that is not whitelisted was removed.
+    Attribute {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+    },
+    /// A URL attribute was removed because its scheme is not in [`url_schemes`][url_schemes].
+    ///
+    /// For a `srcset`, only the URL with this scheme was removed from the list.
+    ///
+    /// [url_schemes]: struct.Builder.html#method.url_schemes
+    UrlScheme {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// The rejected scheme, without the trailing colon.
+        scheme: String,
+    },
+    /// A URL attribute was removed because its host or port is not allowed by its
+    /// [`UrlPolicy`][UrlPolicy].
+    ///
+    /// For a `srcset`, only this URL was removed from the list.
+    ///
+    /// [UrlPolicy]: struct.UrlPolicy.html
+    UrlHost {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// The attribute value, or the URL from the `srcset`.
+        value: String,
+    },
+    /// A URL violated the [`content_security_policy`][content_security_policy].
+    ///
+    /// Depending on the [`csp_violation_action`][csp_violation_action], either the
+    /// attribute or the whole element was removed. For a `srcset`, only this URL was
+    /// removed from the list.
+    ///
+    /// [content_security_policy]: struct.Builder.html#method.content_security_policy
+    /// [csp_violation_action]: struct.Builder.html#method.csp_violation_action
+    CspViolation {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// The directive that was violated, like `img-src`.
+        directive: String,
+        /// The attribute value, or the URL from the `srcset`.
+        value: String,
+        /// What was done about it.
+        action: CspViolationAction,
+    },
+    /// A `data:` URL attribute was removed because it was rejected by the
+    /// [`data_uri_policy`][data_uri_policy].
+    ///
+    /// For a `srcset`, only this URL was removed from the list.
+    ///
+    /// [data_uri_policy]: struct.Builder.html#method.data_uri_policy
+    DataUri {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// Why the URL was rejected.
+        reason: DataUriRejection,
+    },
+    /// A URL had a host that looks like another one, according to
+    /// [`homograph_action`][homograph_action].
+    ///
+    /// Depending on the action, the attribute was removed, rewritten with its host in
+    /// punycode, or kept. For a `srcset`, only this URL was removed or rewritten.
+    ///
+    /// [homograph_action]: struct.Builder.html#method.homograph_action
+    Homograph {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// The attribute value, or the URL from the `srcset`, as it was in the input.
+        value: String,
+        /// What was done about it.
+        action: HomographAction,
+    },
+    /// A URL attribute was removed because it could not be parsed.
+    ///
+    /// For a `srcset`, only this URL was removed from the list.
+    InvalidUrl {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// The attribute value, or the URL from the `srcset`.
+        value: String,
+    },
+    /// A URL attribute was removed because its relative URL was rejected by
+    /// [`url_relative`][url_relative].
+    ///
+    /// For a `srcset`, only this URL was removed from the list.
+    ///
+    /// [url_relative]: struct.Builder.html#method.url_relative
+    RelativeUrl {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// The attribute value, or the URL from the `srcset`.
+        value: String,
+    },
+    /// An attribute was removed by the [`attribute_filter`][attribute_filter] callback.
+    ///
+    /// [attribute_filter]: struct.Builder.html#method.attribute_filter
+    AttributeFilter {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+    },
+    /// A URL attribute was removed by the [`absolute_url_rewriter`][absolute_url_rewriter]
+    /// callback.
+    ///
+    /// For a `srcset`, only this URL was removed from the list.
+    ///
+    /// [absolute_url_rewriter]: struct.Builder.html#method.absolute_url_rewriter
+    UrlRewriter {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+        /// The attribute value, or the URL from the `srcset`.
+        value: String,
+    },
+    /// An element was unwrapped or removed by the [`element_filter`][element_filter] callback.
+    ///
+    /// [element_filter]: struct.Builder.html#method.element_filter
+    ElementFilter {
+        /// The local name of the element.
+        name: String,
+        /// What the callback decided to do with it; never [`ElementAction::Keep`].
+        action: ElementAction,
+    },
+    /// A declaration was removed from a `style` attribute because its property is not in
+    /// [`filter_style_properties`][filter_style_properties].
+    ///
+    /// [filter_style_properties]: struct.Builder.html#method.filter_style_properties
+    /// [style_sheets]: struct.Builder.html#method.style_sheets
+    StyleProperty {
+        /// The local name of the element the `style` attribute was on, or `style` if the
+        /// declaration was in a `<style>` element kept by
+        /// [`style_sheets`](struct.Builder.html#method.style_sheets).
+        element: String,
+        /// The name of the rejected property.
+        property: String,
+    },
+    /// A declaration was removed from a `style` attribute because its value doesn't match
+    /// the rule for its property in [`style_value_rules`][style_value_rules].
+    ///
+    /// [style_value_rules]: struct.Builder.html#method.style_value_rules
+    /// [style_sheets]: struct.Builder.html#method.style_sheets
+    StyleValue {
+        /// The local name of the element the `style` attribute was on, or `style` if the
+        /// declaration was in a `<style>` element kept by
+        /// [`style_sheets`](struct.Builder.html#method.style_sheets).
+        element: String,
+        /// The name of the property.
+        property: String,
+        /// The rejected value, as it was in the input.
+        value: String,
+    },
+    /// A declaration was removed from a `style` attribute because a URL in its value was
+    /// rejected by the same checks as URL attributes, like [`url_schemes`][url_schemes].
+    ///
+    /// [url_schemes]: struct.Builder.html#method.url_schemes
+    /// [style_sheets]: struct.Builder.html#method.style_sheets
+    StyleUrl {
+        /// The local name of the element the `style` attribute was on, or `style` if the
+        /// declaration was in a `<style>` element kept by
+        /// [`style_sheets`](struct.Builder.html#method.style_sheets).
+        element: String,
+        /// The name of the property.
+        property: String,
+        /// The rejected URL, as it was in the input.
+        url: String,
+    },
+    /// A selector or an at-rule was removed from a `<style>` element, because it is not
+    /// allowed by [`style_sheets`][style_sheets] or couldn't be parsed.
+    ///
+    /// [style_sheets]: struct.Builder.html#method.style_sheets
+    StyleSheetRule {
+        /// The selector, the at-rule's name with its `@`, or the source of a rule that
+        /// couldn't be parsed.
+        rule: String,
+    },
+    /// A class was removed because it is not in [`allowed_classes`][allowed_classes].
+    ///
+    /// [allowed_classes]: struct.Builder.html#method.allowed_classes
+    Class {
+        /// The local name of the element the `class` attribute was on.
+        element: String,
+        /// The rejected class name.
+        class: String,
+    },
+    /// An element was unwrapped or removed because it was nested deeper than
+    /// [`max_depth`][max_depth] allows.
+    ///
+    /// [max_depth]: struct.Builder.html#method.max_depth
+    DepthLimit {
+        /// The local name of the element.
+        name: String,
+        /// What was done with it, as set by [`depth_limit_action`][depth_limit_action].
+        ///
+        /// [depth_limit_action]: struct.Builder.html#method.depth_limit_action
+        action: DepthLimitAction,
+    },
+    /// An attribute was removed because its element already had as many attributes as
+    /// [`max_attributes`][max_attributes] allows.
+    ///
+    /// [max_attributes]: struct.Builder.html#method.max_attributes
+    AttributeLimit {
+        /// The local name of the element the attribute was on.
+        element: String,
+        /// The local name of the attribute.
+        attribute: String,
+    },
+    /// An `<iframe>` was removed because its `src` doesn't match any of the providers
+    /// from [`allow_iframes`][allow_iframes].
+    ///
+    /// [allow_iframes]: struct.Builder.html#method.allow_iframes
+    Iframe {
+        /// The `src` attribute, if it had one.
+        src: Option<String>,
+    },
+    /// A link's text looked like a URL on another host than its `href`, according to
+    /// [`link_text_mismatch`][link_text_mismatch].
+    ///
+    /// Depending on the action, its text was replaced with the `href`, or the link was
+    /// unwrapped.
+    ///
+    /// [link_text_mismatch]: struct.Builder.html#method.link_text_mismatch
+    LinkText {
+        /// The text of the link, after cleaning.
+        text: String,
+        /// The `href` of the link, before [`absolute_url_rewriter`][absolute_url_rewriter]
+        /// changed it.
+        ///
+        /// [absolute_url_rewriter]: struct.Builder.html#method.absolute_url_rewriter
+        href: String,
+        /// What was done about it.
+        action: LinkTextAction,
+    },
+    /// The rest of the fragment was removed because the output already had as many nodes
+    /// as [`max_nodes`][max_nodes] allows.
+    ///
+    /// [max_nodes]: struct.Builder.html#method.max_nodes
+    NodeLimit,
+    /// The rest of the fragment was removed because the output reached
+    /// [`max_output_bytes`][max_output_bytes].
+    ///
+    /// [max_output_bytes]: struct.Builder.html#method.max_output_bytes
+    OutputLimit,
+}
+
+impl Display for Removal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Removal::Element { name } => write!(f, "removed a `<{name}>` element"),
+            Removal::ElementContent { name } => {
+                write!(f, "removed a `<{name}>` element and its content")
+            }
+            Removal::NamespaceSwitch { name } => write!(
+                f,
+                "removed a `<{name}>` element and its content, because it was in the wrong namespace"
+            ),
+            Removal::Comment => write!(f, "removed a comment"),
+            Removal::Attribute { element, attribute } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element"
+            ),
+            Removal::UrlScheme {
+                element,
+                attribute,
+                scheme,
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because the `{scheme}:` scheme is not allowed"
+            ),
+            Removal::UrlHost {
+                element, attribute, ..
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because its host is not allowed"
+            ),
+            Removal::CspViolation {
+                element,
+                attribute,
+                directive,
+                action: CspViolationAction::RemoveAttribute,
+                ..
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because it violates `{directive}`"
+            ),
+            Removal::CspViolation {
+                element,
+                attribute,
+                directive,
+                action: CspViolationAction::RemoveElement,
+                ..
+            } => write!(
+                f,
+                "removed a `<{element}>` element and its content, because its `{attribute}` attribute violates `{directive}`"
+            ),
+            Removal::Iframe { .. } => write!(
+                f,
+                "removed an `<iframe>` element, because its `src` is not from an allowed provider"
+            ),
+            Removal::LinkText {
+                action: LinkTextAction::ShowDestination,
+                ..
+            } => write!(
+                f,
+                "replaced the text of an `<a>` element with its `href`, because it looked like a link to another host"
+            ),
+            Removal::LinkText {
+                action: LinkTextAction::Unwrap,
+                ..
+            } => write!(
+                f,
+                "removed an `<a>` element, because its text looked like a link to another host"
+            ),
+            Removal::DataUri {
+                element,
+                attribute,
+                reason,
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because {reason}"
+            ),
+            Removal::Homograph {
+                element,
+                attribute,
+                action: HomographAction::RemoveAttribute,
+                ..
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because its host is confusable"
+            ),
+            Removal::Homograph {
+                element,
+                attribute,
+                action: HomographAction::Punycode,
+                ..
+            } => write!(
+                f,
+                "rewrote the confusable host in the `{attribute}` attribute of a `<{element}>` element as punycode"
+            ),
+            Removal::Homograph {
+                element,
+                attribute,
+                action: HomographAction::Report,
+                ..
+            } => write!(
+                f,
+                "kept the `{attribute}` attribute of a `<{element}>` element, but its host is confusable"
+            ),
+            Removal::InvalidUrl {
+                element, attribute, ..
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because it is not a valid URL"
+            ),
+            Removal::RelativeUrl {
+                element, attribute, ..
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because relative URLs are not allowed"
+            ),
+            Removal::AttributeFilter { element, attribute } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because the attribute filter rejected it"
+            ),
+            Removal::UrlRewriter {
+                element, attribute, ..
+            } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because the URL rewriter rejected it"
+            ),
+            Removal::ElementFilter {
+                name,
+                action: ElementAction::Remove,
+            } => write!(
+                f,
+                "removed a `<{name}>` element and its content, because the element filter rejected it"
+            ),
+            Removal::ElementFilter { name, .. } => write!(
+                f,
+                "removed a `<{name}>` element, because the element filter rejected it"
+            ),
+            Removal::StyleProperty { element, property } => write!(
+                f,
+                "removed the `{property}` style property from a `<{element}>` element"
+            ),
+            Removal::StyleValue {
+                element, property, ..
+            } => write!(
+                f,
+                "removed the `{property}` style property from a `<{element}>` element, because its value is not allowed"
+            ),
+            Removal::StyleUrl {
+                element, property, ..
+            } => write!(
+                f,
+                "removed the `{property}` style property from a `<{element}>` element, because its URL is not allowed"
+            ),
+            Removal::StyleSheetRule { rule } => {
+                write!(f, "removed `{rule}` from a `<style>` element")
+            }
+            Removal::Class { element, class } => write!(
+                f,
+                "removed the `{class}` class from a `<{element}>` element"
+            ),
+            Removal::DepthLimit {
+                name,
+                action: DepthLimitAction::Remove,
+            } => write!(
+                f,
+                "removed a `<{name}>` element and its content, because it was nested too deeply"
+            ),
+            Removal::DepthLimit { name, .. } => write!(
+                f,
+                "removed a `<{name}>` element, because it was nested too deeply"
+            ),
+            Removal::AttributeLimit { element, attribute } => write!(
+                f,
+                "removed the `{attribute}` attribute from a `<{element}>` element, because it had too many attributes"
+            ),
+            Removal::NodeLimit => write!(
+                f,
+                "removed the rest of the document, because it had too many nodes"
+            ),
+            Removal::OutputLimit => write!(
+                f,
+                "removed the rest of the document, because the output was too long"
+            ),
+        }
+    }
+}
+
+/// Adds a removal to the report, if one is being collected.
+///
+/// The removal is built lazily, so that plain `clean` doesn't pay for the allocations.
+fn record(report: &mut Option<SanitizeReport>, removal: impl FnOnce() -> Removal) {
+    if let Some(report) = report {
+        report.push(removal());
+    }
 }
 
-fn is_url_relative(url: &str) -> bool {
-    matches!(
-        Url::parse(url),
-        Err(url::ParseError::RelativeUrlWithoutBase)
-    )
+/// Records a declaration that was removed from a `style` attribute or a `<style>` element.
+fn record_style_rejection(
+    element: &str,
+    rejection: style::StyleRejection<'_>,
+    report: &mut Option<SanitizeReport>,
+) {
+    match rejection {
+        style::StyleRejection::Property(property) => record(report, || Removal::StyleProperty {
+            element: element.to_string(),
+            property: property.to_string(),
+        }),
+        style::StyleRejection::Value { property, value } => {
+            record(report, || Removal::StyleValue {
+                element: element.to_string(),
+                property: property.to_string(),
+                value: value.to_string(),
+            })
+        }
+        style::StyleRejection::Url { property, url } => record(report, || Removal::StyleUrl {
+            element: element.to_string(),
+            property: property.to_string(),
+            url: url.to_string(),
+        }),
+    }
+}
+
+/// Returns the `href` of an HTML `<a>` element, if it has one.
+fn link_href(node: &Handle) -> Option<String> {
+    match node.data {
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } if name.ns == ns!(html) && name.local == local_name!("a") => attrs
+            .borrow()
+            .iter()
+            .find(|attr| attr.name.ns == ns!() && attr.name.local == local_name!("href"))
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns all the text inside a node, concatenated.
+fn descendant_text(node: &Handle) -> String {
+    let mut text = String::new();
+    let mut stack = vec![node.clone()];
+    while let Some(node) = stack.pop() {
+        if let NodeData::Text { ref contents } = node.data {
+            text.push_str(&contents.borrow());
+        }
+        stack.extend(node.children.borrow().iter().rev().cloned());
+    }
+    text
+}
+
+/// Returns the local name of an element, or an empty string for other nodes.
+fn element_name(node: &Handle) -> String {
+    match node.data {
+        NodeData::Element { ref name, .. } => name.local.to_string(),
+        _ => String::new(),
+    }
+}
+
+impl fmt::Debug for dyn ElementFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ElementFilter")
+    }
 }
 
-/// Policy for [relative URLs], that is, URLs that do not specify the scheme in full.
+/// Types that implement this trait can be used to keep, unwrap or remove elements.
 ///
-/// This policy kicks in, if set, for any attribute named `src` or `href`,
-/// as well as the `data` attribute of an `object` tag.
-///
-/// [relative URLs]: struct.Builder.html#method.url_relative
-///
-/// # Examples
+/// See [`element_filter`][element_filter] for more details.
 ///
-/// ## `Deny`
+/// [element_filter]: struct.Builder.html#method.element_filter
+pub trait ElementFilter: Send + Sync {
+    /// Decide what to do with an element, given its name and its cleaned attributes.
+    fn filter(&self, element: &str, attributes: &[(&str, &str)]) -> ElementAction;
+}
+
+impl<T> ElementFilter for T
+where
+    T: Fn(&str, &[(&str, &str)]) -> ElementAction + Send + Sync + 'static,
+{
+    fn filter(&self, element: &str, attributes: &[(&str, &str)]) -> ElementAction {
+        self(element, attributes)
+    }
+}
+
+/// What to do with an element, as decided by an [`ElementFilter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElementAction {
+    /// Keep the element.
+    Keep,
+    /// Remove the element, but keep its children.
+    Unwrap,
+    /// Remove the element and everything inside it.
+    Remove,
+}
+
+/// What to do with elements nested deeper than [`Builder::max_depth`] allows.
 ///
-/// * `<a href="test">` is a file-relative URL, and will be removed
-/// * `<a href="/test">` is a domain-relative URL, and will be removed
-/// * `<a href="//example.com/test">` is a scheme-relative URL, and will be removed
-/// * `<a href="http://example.com/test">` is an absolute URL, and will be kept
+/// [`Builder::max_depth`]: struct.Builder.html#method.max_depth
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DepthLimitAction {
+    /// Remove the element, but keep its children, which move up to the deepest allowed level.
+    Unwrap,
+    /// Remove the element and everything inside it.
+    Remove,
+}
+
+/// A sanitized HTML document.
 ///
-/// ## `PassThrough`
+/// The `Document` type is an opaque struct representing an HTML fragment that was sanitized by
+/// `ammonia`. It can be converted to a [`String`] or written to a [`Write`] instance. This allows
+/// users to avoid buffering the serialized representation to a [`String`] when desired.
 ///
-/// No changes will be made to any URLs, except if a disallowed scheme is used.
+/// This type is opaque to insulate the caller from breaking changes in the `html5ever` interface.
 ///
-/// ## `RewriteWithBase`
+/// Note that this type wraps an `html5ever` DOM tree, so the complete fragment needs to be
+/// stored in memory during processing. To sanitize very large inputs without doing that, use
+/// [`Builder::clean_stream`] instead.
 ///
-/// If the base is set to `http://notriddle.com/some-directory/some-file`
+/// [`Builder::clean_stream`]: struct.Builder.html#method.clean_stream
 ///
-/// * `<a href="test">` will be rewritten to `<a href="http://notriddle.com/some-directory/test">`
-/// * `<a href="/test">` will be rewritten to `<a href="http://notriddle.com/test">`
-/// * `<a href="//example.com/test">` will be rewritten to `<a href="http://example.com/test">`
-/// * `<a href="http://example.com/test">` is an absolute URL, so it will be kept as-is
+/// [`String`]: https://doc.rust-lang.org/nightly/std/string/struct.String.html
+/// [`Write`]: https://doc.rust-lang.org/nightly/std/io/trait.Write.html
 ///
-/// ## `Custom`
+/// # Examples
 ///
-/// Pass the relative URL to a function.
-/// If it returns `Some(string)`, then that one gets used.
-/// Otherwise, it will remove the attribute (like `Deny` does).
+///     use ammonia::Builder;
 ///
-///     use std::borrow::Cow;
-///     fn is_absolute_path(url: &str) -> bool {
-///         let u = url.as_bytes();
-///         // `//a/b/c` is "protocol-relative", meaning "a" is a hostname
-///         // `/a/b/c` is an absolute path, and what we want to do stuff to.
-///         u.get(0) == Some(&b'/') && u.get(1) != Some(&b'/')
-///     }
-///     fn evaluate(url: &str) -> Option<Cow<str>> {
-///         if is_absolute_path(url) {
-///             Some(Cow::Owned(String::from("/root") + url))
-///         } else {
-///             Some(Cow::Borrowed(url))
-///         }
-///     }
-///     fn main() {
-///         let a = ammonia::Builder::new()
-///             .url_relative(ammonia::UrlRelative::Custom(Box::new(evaluate)))
-///             .clean("<a href=/test/path>fixed</a><a href=path>passed</a><a href=http://google.com/>skipped</a>")
-///             .to_string();
-///         assert_eq!(a, "<a href=\"/root/test/path\" rel=\"noopener noreferrer\">fixed</a><a href=\"path\" rel=\"noopener noreferrer\">passed</a><a href=\"http://google.com/\" rel=\"noopener noreferrer\">skipped</a>");
-///     }
+///     let input = "<!-- comments will be stripped -->This is an Ammonia example.";
+///     let output = "This is an Ammonia example.";
 ///
-/// This function is only applied to relative URLs.
-/// To filter all of the URLs,
-/// use the not-yet-implemented Content Security Policy.
-#[non_exhaustive]
-pub enum UrlRelative<'a> {
-    /// Relative URLs will be completely stripped from the document.
-    Deny,
-    /// Relative URLs will be passed through unchanged.
-    PassThrough,
-    /// Relative URLs will be changed into absolute URLs, based on this base URL.
-    RewriteWithBase(Url),
-    /// Force absolute and relative paths into a particular directory.
+///     let document = Builder::new()
+///         .clean(input);
+///     assert_eq!(document.to_string(), output);
+pub struct Document(RcDom);
+
+impl Document {
+    /// Serializes a `Document` instance to a writer.
     ///
-    /// Since the resolver does not affect fully-qualified URLs, it doesn't
-    /// prevent users from linking wherever they want. This feature only
-    /// serves to make content more portable.
+    /// This method writes the sanitized HTML to a [`Write`] instance, avoiding a buffering step.
+    ///
+    /// To avoid consuming the writer, a mutable reference can be passed, like in the example below.
+    ///
+    /// Note that the in-memory representation of `Document` is larger than the serialized
+    /// `String`.
+    ///
+    /// [`Write`]: https://doc.rust-lang.org/nightly/std/io/trait.Write.html
     ///
     /// # Examples
     ///
-    /// <table>
-    /// <thead>
-    /// <tr>
-    ///     <th>root</th>
-    ///     <th>path</th>
-    ///     <th>url</th>
-    ///     <th>result</th>
-    /// </tr>
-    /// </thead>
-    /// <tbody>
-    /// <tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    ///     <td>README.md</td>
-    ///     <td></td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/README.md</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    ///     <td>README.md</td>
-    ///     <td>/</td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    ///     <td>README.md</td>
-    ///     <td>/CONTRIBUTING.md</td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/CONTRIBUTING.md</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master</td>
-    ///     <td>README.md</td>
-    ///     <td></td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/README.md</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master</td>
-    ///     <td>README.md</td>
-    ///     <td>/</td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master</td>
-    ///     <td>README.md</td>
-    ///     <td>/CONTRIBUTING.md</td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/CONTRIBUTING.md</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    ///     <td></td>
-    ///     <td></td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    ///     <td></td>
-    ///     <td>/</td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/</td>
-    ///     <td></td>
-    ///     <td>/CONTRIBUTING.md</td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/CONTRIBUTING.md</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/</td>
-    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
-    ///     <td></td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/README.md</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/</td>
-    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
-    ///     <td>/</td>
-    ///     <td>https://github.com/</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/</td>
-    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
-    ///     <td>CONTRIBUTING.md</td>
-    ///     <td>https://github.com/rust-ammonia/ammonia/blob/master/CONTRIBUTING.md</td>
-    /// </tr><tr>
-    ///     <td>https://github.com/</td>
-    ///     <td>rust-ammonia/ammonia/blob/master/README.md</td>
-    ///     <td>/CONTRIBUTING.md</td>
-    ///     <td>https://github.com/CONTRIBUTING.md</td>
-    /// </tr>
-    /// </tbody>
-    /// </table>
-    RewriteWithRoot {
-        /// The URL that is treated as the root by the resolver.
-        root: Url,
-        /// The "current path" used to resolve relative paths.
-        path: String,
-    },
-    /// Rewrite URLs with a custom function.
-    Custom(Box<dyn UrlRelativeEvaluate<'a>>),
-}
+    ///     use ammonia::Builder;
+    ///
+    ///     let input = "Some <style></style>HTML here";
+    ///     let expected = b"Some HTML here";
+    ///
+    ///     let document = Builder::new()
+    ///         .clean(input);
+    ///
+    ///     let mut sanitized = Vec::new();
+    ///     document.write_to(&mut sanitized)
+    ///         .expect("Writing to a string should not fail (except on OOM)");
+    ///     assert_eq!(sanitized, expected);
+    pub fn write_to<W>(&self, writer: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let opts = Self::serialize_opts();
+        let inner: SerializableHandle = self.0.document.children.borrow()[0].clone().into();
+        serialize(writer, &inner, opts)
+    }
+
+    /// Shortens the document to at most `max_chars` characters of text, ending it with
+    /// `ellipsis` if anything was cut.
+    ///
+    /// Only text counts towards the limit, not tags or attributes, and the `ellipsis` itself
+    /// isn't counted either. Everything after the cut is removed, including elements without
+    /// text like images, and the elements around the cut are still closed properly. Whitespace
+    /// just before the cut is dropped.
+    ///
+    /// Returns `true` if the document was shortened, which is useful for deciding whether to
+    /// show a "read more" link.
+    ///
+    /// Characters are Unicode scalar values; see [`truncate_text_with`] to count grapheme
+    /// clusters or to keep words whole.
+    ///
+    /// [`truncate_text_with`]: #method.truncate_text_with
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     let mut preview = Builder::new()
+    ///         .clean("<p>Hello, <b>world</b>!</p><p>Second paragraph.</p>");
+    ///     assert!(preview.truncate_text(10, "…"));
+    ///     assert_eq!(preview.to_string(), "<p>Hello, <b>wor…</b></p>");
+    pub fn truncate_text(&mut self, max_chars: usize, ellipsis: &str) -> bool {
+        self.truncate_text_with(max_chars, ellipsis, TruncateOptions::new())
+    }
+
+    /// Shortens the document to at most `max` characters of text, like [`truncate_text`],
+    /// with options for how to count and where to cut.
+    ///
+    /// [`truncate_text`]: #method.truncate_text
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, TruncateOptions};
+    ///
+    ///     let mut preview = Builder::new()
+    ///         .clean("<p>Hello, <b>world</b>!</p><p>Second paragraph.</p>");
+    ///     preview.truncate_text_with(10, "…", TruncateOptions::new().whole_words(true));
+    ///     assert_eq!(preview.to_string(), "<p>Hello, <b>…</b></p>");
+    pub fn truncate_text_with(
+        &mut self,
+        max: usize,
+        ellipsis: &str,
+        options: TruncateOptions,
+    ) -> bool {
+        text::truncate_text(&self.root(), max, ellipsis, options)
+    }
+
+    /// Renders the document as readable plain text, for search indexes or text-only emails.
+    ///
+    /// Block elements like `<p>`, `<div>`, `<li>`, headings and table rows start new lines,
+    /// `<br>` breaks a line, list items get a `- ` bullet or their number, and block quotes
+    /// are marked with `> `. Whitespace is collapsed the way a browser would, except in
+    /// `<pre>`, and images are replaced by their `alt` text.
+    ///
+    /// The text is not escaped in any way, so it must not be put back into HTML as-is.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///
+    ///     let document = Builder::new()
+    ///         .clean("<h1>Title</h1><p>Some <b>bold</b>\n text.</p><ul><li>one<li>two</ul>");
+    ///     assert_eq!(document.to_text(), "Title\n\nSome bold text.\n\n- one\n- two");
+    pub fn to_text(&self) -> String {
+        self.to_text_with(TextOptions::new())
+    }
+
+    /// Renders the document as plain text, like [`to_text`], with options.
+    ///
+    /// [`to_text`]: #method.to_text
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Builder, TextOptions};
+    ///
+    ///     let document = Builder::new()
+    ///         .clean("<p>See <a href=\"https://example.com/\">the docs</a>.</p>");
+    ///     assert_eq!(
+    ///         document.to_text_with(TextOptions::new().link_urls(true)),
+    ///         "See the docs <https://example.com/>."
+    ///     );
+    pub fn to_text_with(&self, options: TextOptions) -> String {
+        text::to_text(&self.root(), options)
+    }
+
+    /// Exposes the `Document` instance as an [`rcdom::Handle`].
+    ///
+    /// This method returns the inner object backing the `Document` instance. This allows
+    /// making further changes to the DOM without introducing redundant serialization and
+    /// parsing.
+    ///
+    /// Note that this method should be considered unstable and sits outside of the semver
+    /// stability guarantees. It may change, break, or go away at any time, either because
+    /// of `html5ever` changes or `ammonia` implementation changes.
+    ///
+    /// For this method to be accessible, a `cfg` flag is required. The easiest way is to
+    /// use the `RUSTFLAGS` environment variable:
+    ///
+    /// ```text
+    /// RUSTFLAGS='--cfg ammonia_unstable' cargo build
+    /// ```
+    ///
+    /// on Unix-like platforms, or
+    ///
+    /// ```text
+    /// set RUSTFLAGS=--cfg ammonia_unstable
+    /// cargo build
+    /// ```
+    ///
+    /// on Windows.
+    ///
+    /// This requirement also applies to crates that transitively depend on crates that use
+    /// this flag.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::Builder;
+    ///     use maplit::hashset;
+    ///     use html5ever::serialize::{serialize, SerializeOpts};
+    ///
+    ///     # use std::error::Error;
+    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
+    ///     let input = "<a>one link</a> and <a>one more</a>";
+    ///     let expected = "<a>one more</a> and <a>one link</a>";
+    ///
+    ///     let document = Builder::new()
+    ///         .link_rel(None)
+    ///         .clean(input);
+    ///
+    ///     let mut node = document.to_dom_node();
+    ///     node.children.borrow_mut().reverse();
+    ///
+    ///     let mut buf = Vec::new();
+    ///     serialize(&mut buf, &node, SerializeOpts::default())?;
+    ///     let output = String::from_utf8(buf)?;
+    ///
+    ///     assert_eq!(output, expected);
+    ///     # Ok(())
+    ///     # }
+    ///     # fn main() { do_main().unwrap() }
+    #[cfg(ammonia_unstable)]
+    pub fn to_dom_node(&self) -> Handle {
+        self.0.document.children.borrow()[0].clone()
+    }
 
-impl<'a> UrlRelative<'a> {
-    fn evaluate(&self, url: &str) -> Option<html5ever::tendril::StrTendril> {
-        match self {
-            UrlRelative::RewriteWithBase(ref url_base) => url_base
-                .join(url)
-                .ok()
-                .and_then(|x| StrTendril::from_str(x.as_str()).ok()),
-            UrlRelative::RewriteWithRoot { ref root, ref path } => {
-                (match url.as_bytes() {
-                    // Scheme-relative URL
-                    [b'/', b'/', ..] => root.join(url),
-                    // Path-absolute URL
-                    b"/" => root.join("."),
-                    [b'/', ..] => root.join(&url[1..]),
-                    // Path-relative URL
-                    _ => root.join(path).and_then(|r| r.join(url)),
-                })
-                .ok()
-                .and_then(|x| StrTendril::from_str(x.as_str()).ok())
-            }
-            UrlRelative::Custom(ref evaluate) => evaluate
-                .evaluate(url)
-                .as_ref()
-                .map(Cow::as_ref)
-                .map(StrTendril::from_str)
-                .and_then(Result::ok),
-            UrlRelative::PassThrough => StrTendril::from_str(url).ok(),
-            UrlRelative::Deny => None,
-        }
+    /// The element that holds the sanitized fragment.
+    fn root(&self) -> Handle {
+        self.0.document.children.borrow()[0].clone()
+    }
+
+    fn serialize_opts() -> SerializeOpts {
+        SerializeOpts::default()
     }
 }
 
-impl<'a> fmt::Debug for UrlRelative<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            UrlRelative::Deny => write!(f, "UrlRelative::Deny"),
-            UrlRelative::PassThrough => write!(f, "UrlRelative::PassThrough"),
-            UrlRelative::RewriteWithBase(ref base) => {
-                write!(f, "UrlRelative::RewriteWithBase({})", base)
-            }
-            UrlRelative::RewriteWithRoot { ref root, ref path } => {
-                write!(
-                    f,
-                    "UrlRelative::RewriteWithRoot {{ root: {root}, path: {path} }}"
-                )
-            }
-            UrlRelative::Custom(_) => write!(f, "UrlRelative::Custom"),
-        }
+impl Clone for Document {
+    fn clone(&self) -> Self {
+        let parser = Builder::make_parser();
+        let dom = parser.one(&self.to_string()[..]);
+        Document(dom)
     }
 }
 
-/// Types that implement this trait can be used to convert a relative URL into an absolute URL.
+/// Convert a `Document` to stringified HTML.
 ///
-/// This evaluator is only called when the URL is relative; absolute URLs are not evaluated.
+/// Since [`Document`] implements [`Display`], it can be converted to a [`String`] using the
+/// standard [`ToString::to_string`] method. This is the simplest way to use `ammonia`.
 ///
-/// See [`url_relative`][url_relative] for more details.
+/// [`Document`]: ammonia::Document
+/// [`Display`]: std::fmt::Display
+/// [`ToString::to_string`]: std::string::ToString
 ///
-/// [url_relative]: struct.Builder.html#method.url_relative
-pub trait UrlRelativeEvaluate<'a>: Send + Sync + 'a {
-    /// Return `None` to remove the attribute. Return `Some(str)` to replace it with a new string.
-    fn evaluate<'url>(&self, _: &'url str) -> Option<Cow<'url, str>>;
+/// # Examples
+///
+///     use ammonia::Builder;
+///
+///     let input = "Some <style></style>HTML here";
+///     let output = "Some HTML here";
+///
+///     let document = Builder::new()
+///         .clean(input);
+///     assert_eq!(document.to_string(), output);
+impl Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opts = Self::serialize_opts();
+        let mut ret_val = Vec::new();
+        let inner: SerializableHandle = self.0.document.children.borrow()[0].clone().into();
+        serialize(&mut ret_val, &inner, opts)
+            .expect("Writing to a string shouldn't fail (expect on OOM)");
+        String::from_utf8(ret_val)
+            .expect("html5ever only supports UTF8")
+            .fmt(f)
+    }
 }
-impl<'a, T> UrlRelativeEvaluate<'a> for T
-where
-    T: Fn(&str) -> Option<Cow<'_, str>> + Send + Sync + 'a,
-{
-    fn evaluate<'url>(&self, url: &'url str) -> Option<Cow<'url, str>> {
-        self(url)
+
+impl fmt::Debug for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Document({})", self)
+    }
+}
+
+impl From<Document> for String {
+    fn from(document: Document) -> Self {
+        document.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn deeply_nested_whitelisted() {
+        clean(&"<b>".repeat(60_000));
+    }
+    #[test]
+    fn deeply_nested_blacklisted() {
+        clean(&"<b-b>".repeat(60_000));
+    }
+    #[test]
+    fn deeply_nested_alternating() {
+        clean(&"<b-b>".repeat(35_000));
+    }
+    #[test]
+    fn included_angles() {
+        let fragment = "1 < 2";
+        let result = clean(fragment);
+        assert_eq!(result, "1 &lt; 2");
+    }
+    #[test]
+    fn remove_script() {
+        let fragment = "an <script>evil()</script> example";
+        let result = clean(fragment);
+        assert_eq!(result, "an  example");
+    }
+    #[test]
+    fn ignore_link() {
+        let fragment = "a <a href=\"http://www.google.com\">good</a> example";
+        let expected = "a <a href=\"http://www.google.com\" rel=\"noopener noreferrer\">\
+                        good</a> example";
+        let result = clean(fragment);
+        assert_eq!(result, expected);
+    }
+    #[test]
+    fn remove_unsafe_link() {
+        let fragment = "an <a onclick=\"evil()\" href=\"http://www.google.com\">evil</a> example";
+        let result = clean(fragment);
+        assert_eq!(
+            result,
+            "an <a href=\"http://www.google.com\" rel=\"noopener noreferrer\">evil</a> example"
+        );
+    }
+    #[test]
+    fn remove_js_link() {
+        let fragment = "an <a href=\"javascript:evil()\">evil</a> example";
+        let result = clean(fragment);
+        assert_eq!(result, "an <a rel=\"noopener noreferrer\">evil</a> example");
+    }
+    #[test]
+    fn tag_rebalance() {
+        let fragment = "<b>AWESOME!";
+        let result = clean(fragment);
+        assert_eq!(result, "<b>AWESOME!</b>");
+    }
+    #[test]
+    fn allow_url_relative() {
+        let fragment = "<a href=test>Test</a>";
+        let result = Builder::new()
+            .url_relative(UrlRelative::PassThrough)
+            .clean(fragment)
+            .to_string();
+        assert_eq!(
+            result,
+            "<a href=\"test\" rel=\"noopener noreferrer\">Test</a>"
+        );
+    }
+    #[test]
+    fn rewrite_url_relative() {
+        let fragment = "<a href=test>Test</a>";
+        let result = Builder::new()
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("http://example.com/").unwrap(),
+            ))
+            .clean(fragment)
+            .to_string();
+        assert_eq!(
+            result,
+            "<a href=\"http://example.com/test\" rel=\"noopener noreferrer\">Test</a>"
+        );
+    }
+    #[test]
+    fn rewrite_url_relative_with_invalid_url() {
+        // Reduced from https://github.com/Bauke/ammonia-crash-test
+        let fragment = r##"<a href="\\"https://example.com\\"">test</a>"##;
+        let result = Builder::new()
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("http://example.com/").unwrap(),
+            ))
+            .clean(fragment)
+            .to_string();
+        assert_eq!(result, r##"<a rel="noopener noreferrer">test</a>"##);
+    }
+    #[test]
+    fn attribute_filter_nop() {
+        let fragment = "<a href=test>Test</a>";
+        let result = Builder::new()
+            .attribute_filter(|elem, attr, value| {
+                assert_eq!("a", elem);
+                assert!(
+                    matches!(
+                        (attr, value),
+                        ("href", "test") | ("rel", "noopener noreferrer")
+                    ),
+                    "{}",
+                    value.to_string()
+                );
+                Some(value.into())
+            })
+            .clean(fragment)
+            .to_string();
+        assert_eq!(
+            result,
+            "<a href=\"test\" rel=\"noopener noreferrer\">Test</a>"
+        );
     }
-}
 
-impl fmt::Debug for dyn AttributeFilter {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("AttributeFilter")
+    #[test]
+    fn attribute_filter_drop() {
+        let fragment = "Test<img alt=test src=imgtest>";
+        let result = Builder::new()
+            .attribute_filter(|elem, attr, value| {
+                assert_eq!("img", elem);
+                match (attr, value) {
+                    ("src", "imgtest") => None,
+                    ("alt", "test") => Some(value.into()),
+                    _ => panic!("unexpected"),
+                }
+            })
+            .clean(fragment)
+            .to_string();
+        assert_eq!(result, r#"Test<img alt="test">"#);
     }
-}
-
-/// Types that implement this trait can be used to remove or rewrite arbitrary attributes.
-///
-/// See [`attribute_filter`][attribute_filter] for more details.
-///
-/// [attribute_filter]: struct.Builder.html#method.attribute_filter
-pub trait AttributeFilter: Send + Sync {
-    /// Return `None` to remove the attribute. Return `Some(str)` to replace it with a new string.
-    fn filter<'a>(&self, _: &str, _: &str, _: &'a str) -> Option<Cow<'a, str>>;
-}
 
-impl<T> AttributeFilter for T
-where
-    T: for<'a> Fn(&str, &str, &'a str) -> Option<Cow<'a, str>> + Send + Sync + 'static,
-{
-    fn filter<'a>(&self, element: &str, attribute: &str, value: &'a str) -> Option<Cow<'a, str>> {
-        self(element, attribute, value)
+    #[test]
+    fn url_filter_absolute() {
+        let fragment = "Test<img alt=test src=imgtest>";
+        let result = Builder::new()
+            .attribute_filter(|elem, attr, value| {
+                assert_eq!("img", elem);
+                match (attr, value) {
+                    ("src", "imgtest") => {
+                        Some(format!("https://example.com/images/{}", value).into())
+                    }
+                    ("alt", "test") => None,
+                    _ => panic!("unexpected"),
+                }
+            })
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("http://wrong.invalid/").unwrap(),
+            ))
+            .clean(fragment)
+            .to_string();
+        assert_eq!(
+            result,
+            r#"Test<img src="https://example.com/images/imgtest">"#
+        );
     }
-}
-
-/// A sanitized HTML document.
-///
-/// The `Document` type is an opaque struct representing an HTML fragment that was sanitized by
-/// `ammonia`. It can be converted to a [`String`] or written to a [`Write`] instance. This allows
-/// users to avoid buffering the serialized representation to a [`String`] when desired.
-///
-/// This type is opaque to insulate the caller from breaking changes in the `html5ever` interface.
-///
-/// Note that this type wraps an `html5ever` DOM tree. `ammonia` does not support streaming, so
-/// the complete fragment needs to be stored in memory during processing.
-///
-/// [`String`]: https://doc.rust-lang.org/nightly/std/string/struct.String.html
-/// [`Write`]: https://doc.rust-lang.org/nightly/std/io/trait.Write.html
-///
-/// # Examples
-///
-///     use ammonia::Builder;
-///
-///     let input = "<!-- comments will be stripped -->This is an Ammonia example.";
-///     let output = "This is an Ammonia example.";
-///
-///     let document = Builder::new()
-///         .clean(input);
-///     assert_eq!(document.to_string(), output);
-pub struct Document(RcDom);
 
-impl Document {
-    /// Serializes a `Document` instance to a writer.
-    ///
-    /// This method writes the sanitized HTML to a [`Write`] instance, avoiding a buffering step.
-    ///
-    /// To avoid consuming the writer, a mutable reference can be passed, like in the example below.
-    ///
-    /// Note that the in-memory representation of `Document` is larger than the serialized
-    /// `String`.
-    ///
-    /// [`Write`]: https://doc.rust-lang.org/nightly/std/io/trait.Write.html
-    ///
-    /// # Examples
-    ///
-    ///     use ammonia::Builder;
-    ///
-    ///     let input = "Some <style></style>HTML here";
-    ///     let expected = b"Some HTML here";
-    ///
-    ///     let document = Builder::new()
-    ///         .clean(input);
-    ///
-    ///     let mut sanitized = Vec::new();
-    ///     document.write_to(&mut sanitized)
-    ///         .expect("Writing to a string should not fail (except on OOM)");
-    ///     assert_eq!(sanitized, expected);
-    pub fn write_to<W>(&self, writer: W) -> io::Result<()>
-    where
-        W: io::Write,
-    {
-        let opts = Self::serialize_opts();
-        let inner: SerializableHandle = self.0.document.children.borrow()[0].clone().into();
-        serialize(writer, &inner, opts)
+    #[test]
+    fn url_filter_relative() {
+        let fragment = "Test<img alt=test src=imgtest>";
+        let result = Builder::new()
+            .attribute_filter(|elem, attr, value| {
+                assert_eq!("img", elem);
+                match (attr, value) {
+                    ("src", "imgtest") => Some("rewrite".into()),
+                    ("alt", "test") => Some("altalt".into()),
+                    _ => panic!("unexpected"),
+                }
+            })
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("https://example.com/base/#").unwrap(),
+            ))
+            .clean(fragment)
+            .to_string();
+        assert_eq!(
+            result,
+            r#"Test<img alt="altalt" src="https://example.com/base/rewrite">"#
+        );
     }
 
-    /// Exposes the `Document` instance as an [`rcdom::Handle`].
-    ///
-    /// This method returns the inner object backing the `Document` instance. This allows
-    /// making further changes to the DOM without introducing redundant serialization and
-    /// parsing.
-    ///
-    /// Note that this method should be considered unstable and sits outside of the semver
-    /// stability guarantees. It may change, break, or go away at any time, either because
-    /// of `html5ever` changes or `ammonia` implementation changes.
-    ///
-    /// For this method to be accessible, a `cfg` flag is required. The easiest way is to
-    /// use the `RUSTFLAGS` environment variable:
-    ///
-    /// ```text
-    /// RUSTFLAGS='--cfg ammonia_unstable' cargo build
-    /// ```
-    ///
-    /// on Unix-like platforms, or
-    ///
-    /// ```text
-    /// set RUSTFLAGS=--cfg ammonia_unstable
-    /// cargo build
-    /// ```
-    ///
-    /// on Windows.
-    ///
-    /// This requirement also applies to crates that transitively depend on crates that use
-    /// this flag.
-    ///
-    /// # Examples
-    ///
-    ///     use ammonia::Builder;
-    ///     use maplit::hashset;
-    ///     use html5ever::serialize::{serialize, SerializeOpts};
-    ///
-    ///     # use std::error::Error;
-    ///     # fn do_main() -> Result<(), Box<dyn Error>> {
-    ///     let input = "<a>one link</a> and <a>one more</a>";
-    ///     let expected = "<a>one more</a> and <a>one link</a>";
-    ///
-    ///     let document = Builder::new()
-    ///         .link_rel(None)
-    ///         .clean(input);
-    ///
-    ///     let mut node = document.to_dom_node();
-    ///     node.children.borrow_mut().reverse();
-    ///
-    ///     let mut buf = Vec::new();
-    ///     serialize(&mut buf, &node, SerializeOpts::default())?;
-    ///     let output = String::from_utf8(buf)?;
-    ///
-    ///     assert_eq!(output, expected);
-    ///     # Ok(())
-    ///     # }
-    ///     # fn main() { do_main().unwrap() }
-    #[cfg(ammonia_unstable)]
-    pub fn to_dom_node(&self) -> Handle {
-        self.0.document.children.borrow()[0].clone()
+    #[test]
+    fn rewrite_url_relative_no_rel() {
+        let fragment = "<a href=test>Test</a>";
+        let result = Builder::new()
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("http://example.com/").unwrap(),
+            ))
+            .link_rel(None)
+            .clean(fragment)
+            .to_string();
+        assert_eq!(result, "<a href=\"http://example.com/test\">Test</a>");
     }
-
-    fn serialize_opts() -> SerializeOpts {
-        SerializeOpts::default()
+    #[test]
+    fn deny_url_relative() {
+        let fragment = "<a href=test>Test</a>";
+        let result = Builder::new()
+            .url_relative(UrlRelative::Deny)
+            .clean(fragment)
+            .to_string();
+        assert_eq!(result, "<a rel=\"noopener noreferrer\">Test</a>");
+    }
+    #[test]
+    fn replace_rel() {
+        let fragment = "<a href=test rel=\"garbage\">Test</a>";
+        let result = Builder::new()
+            .url_relative(UrlRelative::PassThrough)
+            .clean(fragment)
+            .to_string();
+        assert_eq!(
+            result,
+            "<a href=\"test\" rel=\"noopener noreferrer\">Test</a>"
+        );
+    }
+    #[test]
+    fn consider_rel_still_banned() {
+        let fragment = "<a href=test rel=\"garbage\">Test</a>";
+        let result = Builder::new()
+            .url_relative(UrlRelative::PassThrough)
+            .link_rel(None)
+            .clean(fragment)
+            .to_string();
+        assert_eq!(result, "<a href=\"test\">Test</a>");
+    }
+    #[test]
+    fn object_data() {
+        let fragment = "<span data=\"javascript:evil()\">Test</span>\
+                        <object data=\"javascript:evil()\"></object>M";
+        let expected = r#"<span data="javascript:evil()">Test</span><object></object>M"#;
+        let result = Builder::new()
+            .tags(hashset!["span", "object"])
+            .generic_attributes(hashset!["data"])
+            .clean(fragment)
+            .to_string();
+        assert_eq!(result, expected);
     }
-}
-
-impl Clone for Document {
-    fn clone(&self) -> Self {
-        let parser = Builder::make_parser();
-        let dom = parser.one(&self.to_string()[..]);
-        Document(dom)
+    #[test]
+    fn remove_attributes() {
+        let fragment = "<table border=\"1\"><tr></tr></table>";
+        let result = Builder::new().clean(fragment);
+        assert_eq!(
+            result.to_string(),
+            "<table><tbody><tr></tr></tbody></table>"
+        );
     }
-}
-
-/// Convert a `Document` to stringified HTML.
-///
-/// Since [`Document`] implements [`Display`], it can be converted to a [`String`] using the
-/// standard [`ToString::to_string`] method. This is the simplest way to use `ammonia`.
-///
-/// [`Document`]: ammonia::Document
-/// [`Display`]: std::fmt::Display
-/// [`ToString::to_string`]: std::string::ToString
-///
-/// # Examples
-///
-///     use ammonia::Builder;
-///
-///     let input = "Some <style></style>HTML here";
-///     let output = "Some HTML here";
-///
-///     let document = Builder::new()
-///         .clean(input);
-///     assert_eq!(document.to_string(), output);
-impl Display for Document {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let opts = Self::serialize_opts();
-        let mut ret_val = Vec::new();
-        let inner: SerializableHandle = self.0.document.children.borrow()[0].clone().into();
-        serialize(&mut ret_val, &inner, opts)
-            .expect("Writing to a string shouldn't fail (expect on OOM)");
-        String::from_utf8(ret_val)
-            .expect("html5ever only supports UTF8")
-            .fmt(f)
+    #[test]
+    fn quotes_in_attrs() {
+        let fragment = "<b title='\"'>contents</b>";
+        let result = clean(fragment);
+        assert_eq!(result, "<b title=\"&quot;\">contents</b>");
     }
-}
-
-impl fmt::Debug for Document {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Document({})", self)
+    #[test]
+    #[should_panic]
+    fn panic_if_rel_is_allowed_and_replaced_generic() {
+        Builder::new()
+            .link_rel(Some("noopener noreferrer"))
+            .generic_attributes(hashset!["rel"])
+            .clean("something");
     }
-}
-
-impl From<Document> for String {
-    fn from(document: Document) -> Self {
-        document.to_string()
+    #[test]
+    #[should_panic]
+    fn panic_if_rel_is_allowed_and_replaced_a() {
+        Builder::new()
+            .link_rel(Some("noopener noreferrer"))
+            .tag_attributes(hashmap![
+                "a" => hashset!["rel"],
+            ])
+            .clean("something");
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
     #[test]
-    fn deeply_nested_whitelisted() {
-        clean(&"<b>".repeat(60_000));
+    fn no_panic_if_rel_is_allowed_and_replaced_span() {
+        Builder::new()
+            .link_rel(Some("noopener noreferrer"))
+            .tag_attributes(hashmap![
+                "span" => hashset!["rel"],
+            ])
+            .clean("<span rel=\"what\">s</span>");
     }
     #[test]
-    fn deeply_nested_blacklisted() {
-        clean(&"<b-b>".repeat(60_000));
+    fn no_panic_if_rel_is_allowed_and_not_replaced_generic() {
+        Builder::new()
+            .link_rel(None)
+            .generic_attributes(hashset!["rel"])
+            .clean("<a rel=\"what\">s</a>");
     }
     #[test]
-    fn deeply_nested_alternating() {
-        clean(&"<b-b>".repeat(35_000));
+    fn no_panic_if_rel_is_allowed_and_not_replaced_a() {
+        Builder::new()
+            .link_rel(None)
+            .tag_attributes(hashmap![
+                "a" => hashset!["rel"],
+            ])
+            .clean("<a rel=\"what\">s</a>");
     }
     #[test]
-    fn included_angles() {
-        let fragment = "1 < 2";
+    fn dont_close_void_elements() {
+        let fragment = "<br>";
         let result = clean(fragment);
-        assert_eq!(result, "1 &lt; 2");
+        assert_eq!(result.to_string(), "<br>");
     }
+    #[should_panic]
     #[test]
-    fn remove_script() {
-        let fragment = "an <script>evil()</script> example";
-        let result = clean(fragment);
-        assert_eq!(result, "an  example");
+    fn panic_on_allowed_classes_tag_attributes() {
+        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
+        Builder::new()
+            .link_rel(None)
+            .tag_attributes(hashmap![
+                "p" => hashset!["class"],
+                "a" => hashset!["class"],
+            ])
+            .allowed_classes(hashmap![
+                "p" => hashset!["foo", "bar"],
+                "a" => hashset!["baz"],
+            ])
+            .clean(fragment);
     }
+    #[should_panic]
     #[test]
-    fn ignore_link() {
-        let fragment = "a <a href=\"http://www.google.com\">good</a> example";
-        let expected = "a <a href=\"http://www.google.com\" rel=\"noopener noreferrer\">\
-                        good</a> example";
-        let result = clean(fragment);
-        assert_eq!(result, expected);
+    fn panic_on_allowed_classes_generic_attributes() {
+        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
+        Builder::new()
+            .link_rel(None)
+            .generic_attributes(hashset!["class", "href", "some-foo"])
+            .allowed_classes(hashmap![
+                "p" => hashset!["foo", "bar"],
+                "a" => hashset!["baz"],
+            ])
+            .clean(fragment);
     }
     #[test]
-    fn remove_unsafe_link() {
-        let fragment = "an <a onclick=\"evil()\" href=\"http://www.google.com\">evil</a> example";
-        let result = clean(fragment);
+    fn remove_non_allowed_classes() {
+        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
+        let result = Builder::new()
+            .link_rel(None)
+            .allowed_classes(hashmap![
+                "p" => hashset!["foo", "bar"],
+                "a" => hashset!["baz"],
+            ])
+            .clean(fragment);
         assert_eq!(
-            result,
-            "an <a href=\"http://www.google.com\" rel=\"noopener noreferrer\">evil</a> example"
+            result.to_string(),
+            "<p class=\"foo bar\"><a class=\"baz\">Hey</a></p>"
         );
     }
     #[test]
-    fn remove_js_link() {
-        let fragment = "an <a href=\"javascript:evil()\">evil</a> example";
-        let result = clean(fragment);
-        assert_eq!(result, "an <a rel=\"noopener noreferrer\">evil</a> example");
+    fn remove_non_allowed_classes_with_tag_class() {
+        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
+        let result = Builder::new()
+            .link_rel(None)
+            .tag_attributes(hashmap![
+                "div" => hashset!["class"],
+            ])
+            .allowed_classes(hashmap![
+                "p" => hashset!["foo", "bar"],
+                "a" => hashset!["baz"],
+            ])
+            .clean(fragment);
+        assert_eq!(
+            result.to_string(),
+            "<p class=\"foo bar\"><a class=\"baz\">Hey</a></p>"
+        );
     }
     #[test]
-    fn tag_rebalance() {
-        let fragment = "<b>AWESOME!";
-        let result = clean(fragment);
-        assert_eq!(result, "<b>AWESOME!</b>");
+    fn allowed_classes_ascii_whitespace() {
+        // According to https://infra.spec.whatwg.org/#ascii-whitespace,
+        // TAB (\t), LF (\n), FF (\x0C), CR (\x0D) and SPACE (\x20) are
+        // considered to be ASCII whitespace. Unicode whitespace characters
+        // and VT (\x0B) aren't ASCII whitespace.
+        let fragment = "<p class=\"a\tb\nc\x0Cd\re f\x0B g\u{2000}\">";
+        let result = Builder::new()
+            .allowed_classes(hashmap![
+                "p" => hashset!["a", "b", "c", "d", "e", "f", "g"],
+            ])
+            .clean(fragment);
+        assert_eq!(result.to_string(), r#"<p class="a b c d e"></p>"#);
     }
     #[test]
-    fn allow_url_relative() {
-        let fragment = "<a href=test>Test</a>";
+    fn remove_non_allowed_attributes_with_tag_attribute_values() {
+        let fragment = "<p data-label=\"baz\" name=\"foo\"></p>";
+        let result = Builder::new()
+            .tag_attribute_values(hashmap![
+                "p" => hashmap![
+                    "data-label" => hashset!["bar"],
+                ],
+            ])
+            .tag_attributes(hashmap![
+                "p" => hashset!["name"],
+            ])
+            .clean(fragment);
+        assert_eq!(result.to_string(), "<p name=\"foo\"></p>",);
+    }
+    #[test]
+    fn keep_allowed_attributes_with_tag_attribute_values() {
+        let fragment = "<p data-label=\"bar\" name=\"foo\"></p>";
+        let result = Builder::new()
+            .tag_attribute_values(hashmap![
+                "p" => hashmap![
+                    "data-label" => hashset!["bar"],
+                ],
+            ])
+            .tag_attributes(hashmap![
+                "p" => hashset!["name"],
+            ])
+            .clean(fragment);
+        assert_eq!(
+            result.to_string(),
+            "<p data-label=\"bar\" name=\"foo\"></p>",
+        );
+    }
+    #[test]
+    fn tag_attribute_values_case_insensitive() {
+        let fragment = "<input type=\"CHECKBOX\" name=\"foo\">";
+        let result = Builder::new()
+            .tags(hashset!["input"])
+            .tag_attribute_values(hashmap![
+                "input" => hashmap![
+                    "type" => hashset!["checkbox"],
+                ],
+            ])
+            .tag_attributes(hashmap![
+                "input" => hashset!["name"],
+            ])
+            .clean(fragment);
+        assert_eq!(result.to_string(), "<input type=\"CHECKBOX\" name=\"foo\">",);
+    }
+    #[test]
+    fn set_tag_attribute_values() {
+        let fragment = "<a href=\"https://example.com/\">Link</a>";
         let result = Builder::new()
-            .url_relative(UrlRelative::PassThrough)
-            .clean(fragment)
-            .to_string();
+            .link_rel(None)
+            .add_tag_attributes("a", &["target"])
+            .set_tag_attribute_value("a", "target", "_blank")
+            .clean(fragment);
         assert_eq!(
-            result,
-            "<a href=\"test\" rel=\"noopener noreferrer\">Test</a>"
+            result.to_string(),
+            "<a href=\"https://example.com/\" target=\"_blank\">Link</a>",
         );
     }
     #[test]
-    fn rewrite_url_relative() {
-        let fragment = "<a href=test>Test</a>";
+    fn update_existing_set_tag_attribute_values() {
+        let fragment = "<a target=\"bad\" href=\"https://example.com/\">Link</a>";
         let result = Builder::new()
-            .url_relative(UrlRelative::RewriteWithBase(
-                Url::parse("http://example.com/").unwrap(),
-            ))
-            .clean(fragment)
-            .to_string();
+            .link_rel(None)
+            .add_tag_attributes("a", &["target"])
+            .set_tag_attribute_value("a", "target", "_blank")
+            .clean(fragment);
         assert_eq!(
-            result,
-            "<a href=\"http://example.com/test\" rel=\"noopener noreferrer\">Test</a>"
+            result.to_string(),
+            "<a target=\"_blank\" href=\"https://example.com/\">Link</a>",
         );
     }
     #[test]
-    fn rewrite_url_relative_with_invalid_url() {
-        // Reduced from https://github.com/Bauke/ammonia-crash-test
-        let fragment = r##"<a href="\\"https://example.com\\"">test</a>"##;
+    fn unwhitelisted_set_tag_attribute_values() {
+        let fragment = "<span>hi</span><my-elem>";
         let result = Builder::new()
-            .url_relative(UrlRelative::RewriteWithBase(
-                Url::parse("http://example.com/").unwrap(),
-            ))
-            .clean(fragment)
-            .to_string();
-        assert_eq!(result, r##"<a rel="noopener noreferrer">test</a>"##);
+            .set_tag_attribute_value("my-elem", "my-attr", "val")
+            .clean(fragment);
+        assert_eq!(result.to_string(), "<span>hi</span>",);
     }
     #[test]
-    fn attribute_filter_nop() {
-        let fragment = "<a href=test>Test</a>";
-        let result = Builder::new()
-            .attribute_filter(|elem, attr, value| {
-                assert_eq!("a", elem);
-                assert!(
-                    matches!(
-                        (attr, value),
-                        ("href", "test") | ("rel", "noopener noreferrer")
-                    ),
-                    "{}",
-                    value.to_string()
-                );
-                Some(value.into())
-            })
-            .clean(fragment)
-            .to_string();
+    fn remove_entity_link() {
+        let fragment = "<a href=\"&#x6A&#x61&#x76&#x61&#x73&#x63&#x72&#x69&#x70&#x74&#x3A&#x61\
+                        &#x6C&#x65&#x72&#x74&#x28&#x27&#x58&#x53&#x53&#x27&#x29\">Click me!</a>";
+        let result = clean(fragment);
         assert_eq!(
-            result,
-            "<a href=\"test\" rel=\"noopener noreferrer\">Test</a>"
+            result.to_string(),
+            "<a rel=\"noopener noreferrer\">Click me!</a>"
         );
     }
-
     #[test]
-    fn attribute_filter_drop() {
-        let fragment = "Test<img alt=test src=imgtest>";
-        let result = Builder::new()
-            .attribute_filter(|elem, attr, value| {
-                assert_eq!("img", elem);
-                match (attr, value) {
-                    ("src", "imgtest") => None,
-                    ("alt", "test") => Some(value.into()),
-                    _ => panic!("unexpected"),
-                }
-            })
-            .clean(fragment)
+    fn remove_relative_url_evaluate() {
+        fn is_absolute_path(url: &str) -> bool {
+            let u = url.as_bytes();
+            // `//a/b/c` is "protocol-relative", meaning "a" is a hostname
+            // `/a/b/c` is an absolute path, and what we want to do stuff to.
+            u.first() == Some(&b'/') && u.get(1) != Some(&b'/')
+        }
+        fn is_banned(url: &str) -> bool {
+            let u = url.as_bytes();
+            u.first() == Some(&b'b') && u.get(1) == Some(&b'a')
+        }
+        fn evaluate(url: &str) -> Option<Cow<'_, str>> {
+            if is_absolute_path(url) {
+                Some(Cow::Owned(String::from("/root") + url))
+            } else if is_banned(url) {
+                None
+            } else {
+                Some(Cow::Borrowed(url))
+            }
+        }
+        let a = Builder::new()
+            .url_relative(UrlRelative::Custom(Box::new(evaluate)))
+            .clean("<a href=banned>banned</a><a href=/test/path>fixed</a><a href=path>passed</a><a href=http://google.com/>skipped</a>")
             .to_string();
-        assert_eq!(result, r#"Test<img alt="test">"#);
+        assert_eq!(a, "<a rel=\"noopener noreferrer\">banned</a><a href=\"/root/test/path\" rel=\"noopener noreferrer\">fixed</a><a href=\"path\" rel=\"noopener noreferrer\">passed</a><a href=\"http://google.com/\" rel=\"noopener noreferrer\">skipped</a>");
     }
-
     #[test]
-    fn url_filter_absolute() {
-        let fragment = "Test<img alt=test src=imgtest>";
-        let result = Builder::new()
-            .attribute_filter(|elem, attr, value| {
-                assert_eq!("img", elem);
-                match (attr, value) {
-                    ("src", "imgtest") => {
-                        Some(format!("https://example.com/images/{}", value).into())
-                    }
-                    ("alt", "test") => None,
-                    _ => panic!("unexpected"),
-                }
-            })
-            .url_relative(UrlRelative::RewriteWithBase(
-                Url::parse("http://wrong.invalid/").unwrap(),
-            ))
-            .clean(fragment)
+    fn remove_relative_url_evaluate_b() {
+        fn is_absolute_path(url: &str) -> bool {
+            let u = url.as_bytes();
+            // `//a/b/c` is "protocol-relative", meaning "a" is a hostname
+            // `/a/b/c` is an absolute path, and what we want to do stuff to.
+            u.first() == Some(&b'/') && u.get(1) != Some(&b'/')
+        }
+        fn is_banned(url: &str) -> bool {
+            let u = url.as_bytes();
+            u.first() == Some(&b'b') && u.get(1) == Some(&b'a')
+        }
+        fn evaluate(url: &str) -> Option<Cow<'_, str>> {
+            if is_absolute_path(url) {
+                Some(Cow::Owned(String::from("/root") + url))
+            } else if is_banned(url) {
+                None
+            } else {
+                Some(Cow::Borrowed(url))
+            }
+        }
+        let a = Builder::new()
+            .url_relative(UrlRelative::Custom(Box::new(evaluate)))
+            .clean("<a href=banned>banned</a><a href=banned title=test>banned</a><a title=test href=banned>banned</a>")
             .to_string();
-        assert_eq!(
-            result,
-            r#"Test<img src="https://example.com/images/imgtest">"#
-        );
+        assert_eq!(a, "<a rel=\"noopener noreferrer\">banned</a><a rel=\"noopener noreferrer\" title=\"test\">banned</a><a title=\"test\" rel=\"noopener noreferrer\">banned</a>");
     }
-
     #[test]
-    fn url_filter_relative() {
-        let fragment = "Test<img alt=test src=imgtest>";
-        let result = Builder::new()
-            .attribute_filter(|elem, attr, value| {
-                assert_eq!("img", elem);
-                match (attr, value) {
-                    ("src", "imgtest") => Some("rewrite".into()),
-                    ("alt", "test") => Some("altalt".into()),
-                    _ => panic!("unexpected"),
-                }
-            })
-            .url_relative(UrlRelative::RewriteWithBase(
-                Url::parse("https://example.com/base/#").unwrap(),
-            ))
-            .clean(fragment)
+    fn remove_relative_url_evaluate_c() {
+        // Don't run on absolute URLs.
+        fn evaluate(_: &str) -> Option<Cow<'_, str>> {
+            Some(Cow::Owned(String::from("invalid")))
+        }
+        let a = Builder::new()
+            .url_relative(UrlRelative::Custom(Box::new(evaluate)))
+            .clean("<a href=\"https://www.google.com/\">google</a>")
             .to_string();
         assert_eq!(
-            result,
-            r#"Test<img alt="altalt" src="https://example.com/base/rewrite">"#
+            a,
+            "<a href=\"https://www.google.com/\" rel=\"noopener noreferrer\">google</a>"
         );
     }
-
     #[test]
-    fn rewrite_url_relative_no_rel() {
-        let fragment = "<a href=test>Test</a>";
-        let result = Builder::new()
-            .url_relative(UrlRelative::RewriteWithBase(
-                Url::parse("http://example.com/").unwrap(),
-            ))
-            .link_rel(None)
-            .clean(fragment)
-            .to_string();
-        assert_eq!(result, "<a href=\"http://example.com/test\">Test</a>");
+    fn clean_children_of_bad_element() {
+        let fragment = "<bad><evil>a</evil>b</bad>";
+        let result = Builder::new().clean(fragment);
+        assert_eq!(result.to_string(), "ab");
     }
     #[test]
-    fn deny_url_relative() {
-        let fragment = "<a href=test>Test</a>";
-        let result = Builder::new()
-            .url_relative(UrlRelative::Deny)
-            .clean(fragment)
-            .to_string();
-        assert_eq!(result, "<a rel=\"noopener noreferrer\">Test</a>");
+    fn reader_input() {
+        let fragment = b"an <script>evil()</script> example";
+        let result = Builder::new().clean_from_reader(&fragment[..]);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "an  example");
     }
     #[test]
-    fn replace_rel() {
-        let fragment = "<a href=test rel=\"garbage\">Test</a>";
-        let result = Builder::new()
-            .url_relative(UrlRelative::PassThrough)
-            .clean(fragment)
-            .to_string();
+    fn reader_non_utf8() {
+        let fragment = b"non-utf8 \xF0\x90\x80string";
+        let result = Builder::new().clean_from_reader(&fragment[..]);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "non-utf8 \u{fffd}string");
+    }
+    #[test]
+    fn display_impl() {
+        let fragment = r#"a <a>link</a>"#;
+        let result = Builder::new().link_rel(None).clean(fragment);
+        assert_eq!(format!("{}", result), "a <a>link</a>");
+    }
+    #[test]
+    fn debug_impl() {
+        let fragment = r#"a <a>link</a>"#;
+        let result = Builder::new().link_rel(None).clean(fragment);
+        assert_eq!(format!("{:?}", result), "Document(a <a>link</a>)");
+    }
+    #[cfg(ammonia_unstable)]
+    #[test]
+    fn to_dom_node() {
+        let fragment = r#"a <a>link</a>"#;
+        let result = Builder::new().link_rel(None).clean(fragment);
+        let _node = result.to_dom_node();
+    }
+    #[test]
+    fn string_from_document() {
+        let fragment = r#"a <a>link"#;
+        let result = String::from(Builder::new().link_rel(None).clean(fragment));
+        assert_eq!(format!("{}", result), "a <a>link</a>");
+    }
+    fn require_sync<T: Sync>(_: T) {}
+    fn require_send<T: Send>(_: T) {}
+    #[test]
+    fn require_sync_and_send() {
+        require_sync(Builder::new());
+        require_send(Builder::new());
+    }
+    #[test]
+    fn id_prefixed() {
+        let fragment = "<a id=\"hello\"></a><b id=\"hello\"></a>";
+        let result = String::from(
+            Builder::new()
+                .tag_attributes(hashmap![
+                    "a" => hashset!["id"],
+                ])
+                .id_prefix(Some("prefix-"))
+                .clean(fragment),
+        );
         assert_eq!(
-            result,
-            "<a href=\"test\" rel=\"noopener noreferrer\">Test</a>"
+            result.to_string(),
+            "<a id=\"prefix-hello\" rel=\"noopener noreferrer\"></a><b></b>"
         );
     }
     #[test]
-    fn consider_rel_still_banned() {
-        let fragment = "<a href=test rel=\"garbage\">Test</a>";
-        let result = Builder::new()
-            .url_relative(UrlRelative::PassThrough)
-            .link_rel(None)
-            .clean(fragment)
-            .to_string();
-        assert_eq!(result, "<a href=\"test\">Test</a>");
+    fn id_already_prefixed() {
+        let fragment = "<a id=\"prefix-hello\"></a>";
+        let result = String::from(
+            Builder::new()
+                .tag_attributes(hashmap![
+                    "a" => hashset!["id"],
+                ])
+                .id_prefix(Some("prefix-"))
+                .clean(fragment),
+        );
+        assert_eq!(
+            result.to_string(),
+            "<a id=\"prefix-hello\" rel=\"noopener noreferrer\"></a>"
+        );
     }
     #[test]
-    fn object_data() {
-        let fragment = "<span data=\"javascript:evil()\">Test</span>\
-                        <object data=\"javascript:evil()\"></object>M";
-        let expected = r#"<span data="javascript:evil()">Test</span><object></object>M"#;
-        let result = Builder::new()
-            .tags(hashset!["span", "object"])
-            .generic_attributes(hashset!["data"])
-            .clean(fragment)
-            .to_string();
-        assert_eq!(result, expected);
+    fn clean_content_tags() {
+        let fragment = "<script type=\"text/javascript\"><a>Hello!</a></script>";
+        let result = String::from(
+            Builder::new()
+                .clean_content_tags(hashset!["script"])
+                .clean(fragment),
+        );
+        assert_eq!(result.to_string(), "");
     }
     #[test]
-    fn remove_attributes() {
-        let fragment = "<table border=\"1\"><tr></tr></table>";
-        let result = Builder::new().clean(fragment);
-        assert_eq!(
-            result.to_string(),
-            "<table><tbody><tr></tr></tbody></table>"
+    fn only_clean_content_tags() {
+        let fragment = "<em>This is</em><script><a>Hello!</a></script><p>still here!</p>";
+        let result = String::from(
+            Builder::new()
+                .clean_content_tags(hashset!["script"])
+                .clean(fragment),
         );
+        assert_eq!(result.to_string(), "<em>This is</em><p>still here!</p>");
     }
     #[test]
-    fn quotes_in_attrs() {
-        let fragment = "<b title='\"'>contents</b>";
-        let result = clean(fragment);
-        assert_eq!(result, "<b title=\"&quot;\">contents</b>");
+    fn clean_removed_default_tag() {
+        let fragment = "<em>This is</em><script><a>Hello!</a></script><p>still here!</p>";
+        let result = String::from(
+            Builder::new()
+                .rm_tags(hashset!["a"])
+                .rm_tag_attributes("a", hashset!["href", "hreflang"])
+                .clean_content_tags(hashset!["script"])
+                .clean(fragment),
+        );
+        assert_eq!(result.to_string(), "<em>This is</em><p>still here!</p>");
     }
     #[test]
     #[should_panic]
-    fn panic_if_rel_is_allowed_and_replaced_generic() {
+    fn panic_on_clean_content_tag_attribute() {
         Builder::new()
-            .link_rel(Some("noopener noreferrer"))
-            .generic_attributes(hashset!["rel"])
-            .clean("something");
+            .rm_tags(std::iter::once("a"))
+            .clean_content_tags(hashset!["a"])
+            .clean("");
     }
     #[test]
     #[should_panic]
-    fn panic_if_rel_is_allowed_and_replaced_a() {
-        Builder::new()
-            .link_rel(Some("noopener noreferrer"))
-            .tag_attributes(hashmap![
-                "a" => hashset!["rel"],
-            ])
-            .clean("something");
-    }
-    #[test]
-    fn no_panic_if_rel_is_allowed_and_replaced_span() {
-        Builder::new()
-            .link_rel(Some("noopener noreferrer"))
-            .tag_attributes(hashmap![
-                "span" => hashset!["rel"],
-            ])
-            .clean("<span rel=\"what\">s</span>");
+    fn panic_on_clean_content_tag() {
+        Builder::new().clean_content_tags(hashset!["a"]).clean("");
     }
+
     #[test]
-    fn no_panic_if_rel_is_allowed_and_not_replaced_generic() {
-        Builder::new()
-            .link_rel(None)
-            .generic_attributes(hashset!["rel"])
-            .clean("<a rel=\"what\">s</a>");
+    fn clean_text_test() {
+        assert_eq!(
+            clean_text("<this> is <a test function"),
+            "&lt;this&gt;&#32;is&#32;&lt;a&#32;test&#32;function"
+        );
     }
+
     #[test]
-    fn no_panic_if_rel_is_allowed_and_not_replaced_a() {
-        Builder::new()
-            .link_rel(None)
-            .tag_attributes(hashmap![
-                "a" => hashset!["rel"],
-            ])
-            .clean("<a rel=\"what\">s</a>");
+    fn clean_text_spaces_test() {
+        assert_eq!(clean_text("\x09\x0a\x0c\x20"), "&#9;&#10;&#12;&#32;");
     }
+
     #[test]
-    fn dont_close_void_elements() {
-        let fragment = "<br>";
-        let result = clean(fragment);
-        assert_eq!(result.to_string(), "<br>");
+    fn ns_svg() {
+        // https://github.com/cure53/DOMPurify/pull/495
+        let fragment = r##"<svg><iframe><a title="</iframe><img src onerror=alert(1)>">test"##;
+        let result = String::from(Builder::new().add_tags(&["iframe"]).clean(fragment));
+        assert_eq!(result.to_string(), "");
+
+        let fragment = "<svg><iframe>remove me</iframe></svg><iframe>keep me</iframe>";
+        let result = String::from(Builder::new().add_tags(&["iframe"]).clean(fragment));
+        assert_eq!(result.to_string(), "<iframe>keep me</iframe>");
+
+        let fragment = "<svg><a>remove me</a></svg><iframe>keep me</iframe>";
+        let result = String::from(Builder::new().add_tags(&["iframe"]).clean(fragment));
+        assert_eq!(result.to_string(), "<iframe>keep me</iframe>");
+
+        let fragment = "<svg><a>keep me</a></svg><iframe>keep me</iframe>";
+        let result = String::from(Builder::new().add_tags(&["iframe", "svg"]).clean(fragment));
+        assert_eq!(
+            result.to_string(),
+            "<svg><a rel=\"noopener noreferrer\">keep me</a></svg><iframe>keep me</iframe>"
+        );
     }
-    #[should_panic]
+
     #[test]
-    fn panic_on_allowed_classes_tag_attributes() {
-        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
-        Builder::new()
-            .link_rel(None)
-            .tag_attributes(hashmap![
-                "p" => hashset!["class"],
-                "a" => hashset!["class"],
-            ])
-            .allowed_classes(hashmap![
-                "p" => hashset!["foo", "bar"],
-                "a" => hashset!["baz"],
-            ])
+    fn ns_svg_2() {
+        let fragment = "<svg><foreignObject><table><path><xmp><!--</xmp><img title'--&gt;&lt;img src=1 onerror=alert(1)&gt;'>";
+        let result =  Builder::default()
+            .strip_comments(false)
+            .add_tags(&["svg","foreignObject","table","path","xmp"])
             .clean(fragment);
+        assert_eq!(
+            result.to_string(),
+            "<svg><foreignObject><table></table></foreignObject></svg>"
+        );
     }
-    #[should_panic]
+
     #[test]
-    fn panic_on_allowed_classes_generic_attributes() {
-        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
-        Builder::new()
-            .link_rel(None)
-            .generic_attributes(hashset!["class", "href", "some-foo"])
-            .allowed_classes(hashmap![
-                "p" => hashset!["foo", "bar"],
-                "a" => hashset!["baz"],
-            ])
-            .clean(fragment);
+    fn ns_mathml() {
+        // https://github.com/cure53/DOMPurify/pull/495
+        let fragment = "<mglyph></mglyph>";
+        let result = String::from(
+            Builder::new()
+                .add_tags(&["math", "mtext", "mglyph"])
+                .clean(fragment),
+        );
+        assert_eq!(result.to_string(), "");
+        let fragment = "<math><mtext><div><mglyph>";
+        let result = String::from(
+            Builder::new()
+                .add_tags(&["math", "mtext", "mglyph"])
+                .clean(fragment),
+        );
+        assert_eq!(
+            result.to_string(),
+            "<math><mtext><div></div></mtext></math>"
+        );
+        let fragment = "<math><mtext><mglyph>";
+        let result = String::from(
+            Builder::new()
+                .add_tags(&["math", "mtext", "mglyph"])
+                .clean(fragment),
+        );
+        assert_eq!(
+            result.to_string(),
+            "<math><mtext><mglyph></mglyph></mtext></math>"
+        );
     }
+
     #[test]
-    fn remove_non_allowed_classes() {
-        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
-        let result = Builder::new()
-            .link_rel(None)
-            .allowed_classes(hashmap![
-                "p" => hashset!["foo", "bar"],
-                "a" => hashset!["baz"],
-            ])
+    fn ns_mathml_2() {
+        let fragment = "<math><mtext><table><mglyph><xmp><!--</xmp><img title='--&gt;&lt;img src=1 onerror=alert(1)&gt;'>";
+        let result =  Builder::default()
+            .strip_comments(false)
+            .add_tags(&["math","mtext","table","mglyph","xmp"])
             .clean(fragment);
         assert_eq!(
             result.to_string(),
-            "<p class=\"foo bar\"><a class=\"baz\">Hey</a></p>"
+            "<math><mtext><table></table></mtext></math>"
         );
     }
+
+
+    #[test]
+    fn xml_processing_instruction() {
+        // https://blog.slonser.info/posts/dompurify-node-type-confusion/
+        let fragment = r##"<svg><?xml-stylesheet src='slonser' ?></svg>"##;
+        let result = String::from(Builder::new().clean(fragment));
+        assert_eq!(result.to_string(), "");
+
+        let fragment = r##"<svg><?xml-stylesheet src='slonser' ?></svg>"##;
+        let result = String::from(Builder::new().add_tags(&["svg"]).clean(fragment));
+        assert_eq!(result.to_string(), "<svg></svg>");
+
+        let fragment = r##"<svg><?xml-stylesheet ><img src=x onerror="alert('Ammonia bypassed!!!')"> ?></svg>"##;
+        let result = String::from(Builder::new().add_tags(&["svg"]).clean(fragment));
+        assert_eq!(result.to_string(), "<svg></svg><img src=\"x\"> ?&gt;");
+    }
+
+    #[test]
+    fn generic_attribute_prefixes() {
+        let prefix_data = ["data-"];
+        let prefix_code = ["code-"];
+        let mut b = Builder::new();
+        let mut hs: HashSet<&'_ str> = HashSet::new();
+        hs.insert("data-");
+        assert!(b.generic_attribute_prefixes.is_none());
+        b.generic_attribute_prefixes(hs);
+        assert!(b.generic_attribute_prefixes.is_some());
+        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
+        b.add_generic_attribute_prefixes(&prefix_data);
+        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
+        b.add_generic_attribute_prefixes(&prefix_code);
+        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 2);
+        b.rm_generic_attribute_prefixes(&prefix_code);
+        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
+        b.rm_generic_attribute_prefixes(&prefix_code);
+        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
+        b.rm_generic_attribute_prefixes(&prefix_data);
+        assert!(b.generic_attribute_prefixes.is_none());
+    }
+
     #[test]
-    fn remove_non_allowed_classes_with_tag_class() {
-        let fragment = "<p class=\"foo bar\"><a class=\"baz bleh\">Hey</a></p>";
-        let result = Builder::new()
-            .link_rel(None)
-            .tag_attributes(hashmap![
-                "div" => hashset!["class"],
-            ])
-            .allowed_classes(hashmap![
-                "p" => hashset!["foo", "bar"],
-                "a" => hashset!["baz"],
-            ])
-            .clean(fragment);
-        assert_eq!(
-            result.to_string(),
-            "<p class=\"foo bar\"><a class=\"baz\">Hey</a></p>"
-        );
+    fn selectedcontent() {
+        // https://github.com/servo/html5ever/issues/712
+        let fragment1 = r#"<select><selectedcontent></selectedcontent><option>X"#;
+        let fragment2 = r#"<select><selectedcontent></selectedcontent><option>X</option></select>"#;
+        let expected = r#"<select><selectedcontent></selectedcontent><option>X</option></select>"#;
+        assert_eq!(String::from(Builder::new().add_tags(&["select", "selectedcontent", "option"]).clean(fragment1)), expected);
+        assert_eq!(String::from(Builder::new().add_tags(&["select", "selectedcontent", "option"]).clean(fragment2)), expected);
     }
+    
     #[test]
-    fn allowed_classes_ascii_whitespace() {
-        // According to https://infra.spec.whatwg.org/#ascii-whitespace,
-        // TAB (\t), LF (\n), FF (\x0C), CR (\x0D) and SPACE (\x20) are
-        // considered to be ASCII whitespace. Unicode whitespace characters
-        // and VT (\x0B) aren't ASCII whitespace.
-        let fragment = "<p class=\"a\tb\nc\x0Cd\re f\x0B g\u{2000}\">";
-        let result = Builder::new()
-            .allowed_classes(hashmap![
-                "p" => hashset!["a", "b", "c", "d", "e", "f", "g"],
-            ])
-            .clean(fragment);
-        assert_eq!(result.to_string(), r#"<p class="a b c d e"></p>"#);
+    fn new_select_parse() {
+        // https://github.com/whatwg/html/issues/10310#issuecomment-2304377029
+        let fragment = r#"
+<select><style></select><img src onerror=xss()></style></select>
+        "#;
+        let expected = r#"
+<select></select>
+        "#;
+        assert_eq!(String::from(Builder::new().add_tags(&["select", "new-select"]).clean_content_tags(hashset!["style"]).clean(fragment)), expected);
     }
+
     #[test]
-    fn remove_non_allowed_attributes_with_tag_attribute_values() {
-        let fragment = "<p data-label=\"baz\" name=\"foo\"></p>";
-        let result = Builder::new()
-            .tag_attribute_values(hashmap![
-                "p" => hashmap![
-                    "data-label" => hashset!["bar"],
-                ],
-            ])
-            .tag_attributes(hashmap![
-                "p" => hashset!["name"],
-            ])
-            .clean(fragment);
-        assert_eq!(result.to_string(), "<p name=\"foo\"></p>",);
+    fn selectedcontent_not_in_select() {
+        // https://github.com/whatwg/html/issues/10310#issuecomment-2304377029
+        let fragment = r#"
+<selectedcontent>first</selectedcontent>
+<div><selectedcontent>second</selectedcontent></div>
+<select><selectedcontent>third</selectedcontent></select>
+        "#;
+        let expected = r#"
+<selectedcontent>first</selectedcontent>
+<div><selectedcontent>second</selectedcontent></div>
+<select><selectedcontent></selectedcontent></select>
+        "#;
+        assert_eq!(String::from(Builder::new().add_tags(&["select", "selectedcontent"]).clean(fragment)), expected);
     }
+
     #[test]
-    fn keep_allowed_attributes_with_tag_attribute_values() {
-        let fragment = "<p data-label=\"bar\" name=\"foo\"></p>";
-        let result = Builder::new()
-            .tag_attribute_values(hashmap![
-                "p" => hashmap![
-                    "data-label" => hashset!["bar"],
-                ],
-            ])
-            .tag_attributes(hashmap![
-                "p" => hashset!["name"],
-            ])
-            .clean(fragment);
+    fn generic_attribute_prefixes_clean() {
+        let fragment = r#"<a data-1 data-2 code-1 code-2><a>Hello!</a></a>"#;
+        let result_cleaned = String::from(
+            Builder::new()
+                .add_tag_attributes("a", &["data-1"])
+                .clean(fragment),
+        );
         assert_eq!(
-            result.to_string(),
-            "<p data-label=\"bar\" name=\"foo\"></p>",
+            result_cleaned,
+            r#"<a data-1="" rel="noopener noreferrer"></a><a rel="noopener noreferrer">Hello!</a>"#
+        );
+        let result_allowed = String::from(
+            Builder::new()
+                .add_tag_attributes("a", &["data-1"])
+                .add_generic_attribute_prefixes(&["data-"])
+                .clean(fragment),
+        );
+        assert_eq!(
+            result_allowed,
+            r#"<a data-1="" data-2="" rel="noopener noreferrer"></a><a rel="noopener noreferrer">Hello!</a>"#
+        );
+        let result_allowed = String::from(
+            Builder::new()
+                .add_tag_attributes("a", &["data-1", "code-1"])
+                .add_generic_attribute_prefixes(&["data-", "code-"])
+                .clean(fragment),
+        );
+        assert_eq!(
+            result_allowed,
+            r#"<a data-1="" data-2="" code-1="" code-2="" rel="noopener noreferrer"></a><a rel="noopener noreferrer">Hello!</a>"#
         );
     }
     #[test]
-    fn tag_attribute_values_case_insensitive() {
-        let fragment = "<input type=\"CHECKBOX\" name=\"foo\">";
-        let result = Builder::new()
-            .tags(hashset!["input"])
-            .tag_attribute_values(hashmap![
-                "input" => hashmap![
-                    "type" => hashset!["checkbox"],
-                ],
-            ])
-            .tag_attributes(hashmap![
-                "input" => hashset!["name"],
-            ])
-            .clean(fragment);
-        assert_eq!(result.to_string(), "<input type=\"CHECKBOX\" name=\"foo\">",);
+    fn lesser_than_isnt_html() {
+        let fragment = "1 < 2";
+        assert!(!is_html(fragment));
     }
     #[test]
-    fn set_tag_attribute_values() {
-        let fragment = "<a href=\"https://example.com/\">Link</a>";
-        let result = Builder::new()
-            .link_rel(None)
-            .add_tag_attributes("a", &["target"])
-            .set_tag_attribute_value("a", "target", "_blank")
-            .clean(fragment);
-        assert_eq!(
-            result.to_string(),
-            "<a href=\"https://example.com/\" target=\"_blank\">Link</a>",
-        );
+    fn dense_lesser_than_isnt_html() {
+        let fragment = "1<2";
+        assert!(!is_html(fragment));
     }
     #[test]
-    fn update_existing_set_tag_attribute_values() {
-        let fragment = "<a target=\"bad\" href=\"https://example.com/\">Link</a>";
-        let result = Builder::new()
-            .link_rel(None)
-            .add_tag_attributes("a", &["target"])
-            .set_tag_attribute_value("a", "target", "_blank")
-            .clean(fragment);
-        assert_eq!(
-            result.to_string(),
-            "<a target=\"_blank\" href=\"https://example.com/\">Link</a>",
-        );
+    fn what_about_number_elements() {
+        let fragment = "foo<2>bar";
+        assert!(!is_html(fragment));
     }
     #[test]
-    fn unwhitelisted_set_tag_attribute_values() {
-        let fragment = "<span>hi</span><my-elem>";
-        let result = Builder::new()
-            .set_tag_attribute_value("my-elem", "my-attr", "val")
-            .clean(fragment);
-        assert_eq!(result.to_string(), "<span>hi</span>",);
+    fn turbofish_is_html_sadly() {
+        let fragment = "Vec::<u8>::new()";
+        assert!(is_html(fragment));
     }
     #[test]
-    fn remove_entity_link() {
-        let fragment = "<a href=\"&#x6A&#x61&#x76&#x61&#x73&#x63&#x72&#x69&#x70&#x74&#x3A&#x61\
-                        &#x6C&#x65&#x72&#x74&#x28&#x27&#x58&#x53&#x53&#x27&#x29\">Click me!</a>";
-        let result = clean(fragment);
-        assert_eq!(
-            result.to_string(),
-            "<a rel=\"noopener noreferrer\">Click me!</a>"
-        );
+    fn stop_grinning() {
+        let fragment = "did you really believe me? <g>";
+        assert!(is_html(fragment));
     }
     #[test]
-    fn remove_relative_url_evaluate() {
-        fn is_absolute_path(url: &str) -> bool {
-            let u = url.as_bytes();
-            // `//a/b/c` is "protocol-relative", meaning "a" is a hostname
-            // `/a/b/c` is an absolute path, and what we want to do stuff to.
-            u.first() == Some(&b'/') && u.get(1) != Some(&b'/')
-        }
-        fn is_banned(url: &str) -> bool {
-            let u = url.as_bytes();
-            u.first() == Some(&b'b') && u.get(1) == Some(&b'a')
-        }
-        fn evaluate(url: &str) -> Option<Cow<'_, str>> {
-            if is_absolute_path(url) {
-                Some(Cow::Owned(String::from("/root") + url))
-            } else if is_banned(url) {
-                None
-            } else {
-                Some(Cow::Borrowed(url))
-            }
-        }
-        let a = Builder::new()
-            .url_relative(UrlRelative::Custom(Box::new(evaluate)))
-            .clean("<a href=banned>banned</a><a href=/test/path>fixed</a><a href=path>passed</a><a href=http://google.com/>skipped</a>")
-            .to_string();
-        assert_eq!(a, "<a rel=\"noopener noreferrer\">banned</a><a href=\"/root/test/path\" rel=\"noopener noreferrer\">fixed</a><a href=\"path\" rel=\"noopener noreferrer\">passed</a><a href=\"http://google.com/\" rel=\"noopener noreferrer\">skipped</a>");
+    fn dont_be_bold() {
+        let fragment = "<b>";
+        assert!(is_html(fragment));
     }
+
     #[test]
-    fn remove_relative_url_evaluate_b() {
-        fn is_absolute_path(url: &str) -> bool {
-            let u = url.as_bytes();
-            // `//a/b/c` is "protocol-relative", meaning "a" is a hostname
-            // `/a/b/c` is an absolute path, and what we want to do stuff to.
-            u.first() == Some(&b'/') && u.get(1) != Some(&b'/')
-        }
-        fn is_banned(url: &str) -> bool {
-            let u = url.as_bytes();
-            u.first() == Some(&b'b') && u.get(1) == Some(&b'a')
-        }
-        fn evaluate(url: &str) -> Option<Cow<'_, str>> {
-            if is_absolute_path(url) {
-                Some(Cow::Owned(String::from("/root") + url))
-            } else if is_banned(url) {
-                None
-            } else {
-                Some(Cow::Borrowed(url))
-            }
-        }
-        let a = Builder::new()
-            .url_relative(UrlRelative::Custom(Box::new(evaluate)))
-            .clean("<a href=banned>banned</a><a href=banned title=test>banned</a><a title=test href=banned>banned</a>")
-            .to_string();
-        assert_eq!(a, "<a rel=\"noopener noreferrer\">banned</a><a rel=\"noopener noreferrer\" title=\"test\">banned</a><a title=\"test\" rel=\"noopener noreferrer\">banned</a>");
+    fn validate_conflicts() {
+        assert_eq!(Builder::new().validate(), Ok(()));
+        assert_eq!(
+            Builder::new()
+                .add_tag_attributes("a", &["rel"])
+                .validate(),
+            Err(PolicyError::TagRelWithLinkRel)
+        );
+        assert_eq!(
+            Builder::new()
+                .add_generic_attributes(&["class"])
+                .add_allowed_classes("p", &["x"])
+                .validate(),
+            Err(PolicyError::GenericClassWithAllowedClasses)
+        );
+        assert_eq!(
+            Builder::new()
+                .add_tag_attributes("p", &["class"])
+                .add_allowed_classes("p", &["x"])
+                .validate(),
+            Err(PolicyError::TagClassWithAllowedClasses {
+                tag: "p".to_string()
+            })
+        );
+        assert_eq!(
+            Builder::new()
+                .rm_tags(&["a"])
+                .add_clean_content_tags(&["a"])
+                .validate(),
+            Err(PolicyError::CleanContentTagInTagAttributes {
+                tag: "a".to_string()
+            })
+        );
     }
     #[test]
-    fn remove_relative_url_evaluate_c() {
-        // Don't run on absolute URLs.
-        fn evaluate(_: &str) -> Option<Cow<'_, str>> {
-            return Some(Cow::Owned(String::from("invalid")));
-        }
-        let a = Builder::new()
-            .url_relative(UrlRelative::Custom(Box::new(evaluate)))
-            .clean("<a href=\"https://www.google.com/\">google</a>")
+    fn try_clean_ok() {
+        let result = Builder::new().try_clean("<b onclick=x>ok</b>").unwrap();
+        assert_eq!(result.to_string(), "<b>ok</b>");
+    }
+    #[test]
+    fn try_clean_err() {
+        let result = Builder::new()
+            .generic_attributes(hashset!["rel"])
+            .try_clean("<a rel=x>");
+        assert_eq!(result.unwrap_err(), PolicyError::GenericRelWithLinkRel);
+    }
+    #[test]
+    #[should_panic(expected = "`rel` appears in `generic_attributes` while `link_rel` is set")]
+    fn panic_message_matches_policy_error() {
+        Builder::new()
+            .generic_attributes(hashset!["rel"])
+            .clean("");
+    }
+    #[test]
+    fn element_filter_sees_final_attributes() {
+        let result = Builder::new()
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("http://example.com/").unwrap(),
+            ))
+            .element_filter(|element, attributes| {
+                assert_eq!(element, "a");
+                assert_eq!(
+                    attributes,
+                    &[
+                        ("href", "http://example.com/x"),
+                        ("rel", "noopener noreferrer")
+                    ]
+                );
+                ElementAction::Keep
+            })
+            .clean("<a href=x onclick=y>z</a>")
             .to_string();
         assert_eq!(
-            a,
-            "<a href=\"https://www.google.com/\" rel=\"noopener noreferrer\">google</a>"
+            result,
+            "<a href=\"http://example.com/x\" rel=\"noopener noreferrer\">z</a>"
         );
     }
     #[test]
-    fn clean_children_of_bad_element() {
-        let fragment = "<bad><evil>a</evil>b</bad>";
-        let result = Builder::new().clean(fragment);
-        assert_eq!(result.to_string(), "ab");
+    fn element_filter_unwrap_and_remove() {
+        let (document, report) = Builder::new()
+            .element_filter(|element, _| match element {
+                "b" => ElementAction::Unwrap,
+                "i" => ElementAction::Remove,
+                _ => ElementAction::Keep,
+            })
+            .clean_with_report("<p><b>bold <i>italic</i></b> <i>gone <b>too</b></i></p>");
+        assert_eq!(document.to_string(), "<p>bold  </p>");
+        assert_eq!(
+            report.removals(),
+            &[
+                Removal::ElementFilter {
+                    name: "b".to_string(),
+                    action: ElementAction::Unwrap
+                },
+                Removal::ElementFilter {
+                    name: "i".to_string(),
+                    action: ElementAction::Remove
+                },
+                Removal::ElementFilter {
+                    name: "i".to_string(),
+                    action: ElementAction::Remove
+                },
+            ]
+        );
     }
     #[test]
-    fn reader_input() {
-        let fragment = b"an <script>evil()</script> example";
-        let result = Builder::new().clean_from_reader(&fragment[..]);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().to_string(), "an  example");
+    #[should_panic(expected = "`link_text_mismatch` is set, which `clean_stream` does not support")]
+    fn link_text_mismatch_not_streamable() {
+        let _ = Builder::new()
+            .link_text_mismatch(Some(LinkTextAction::Unwrap))
+            .clean_stream(&b"<a href=\"https://evil.example/\">bank.example</a>"[..], Vec::new());
     }
     #[test]
-    fn reader_non_utf8() {
-        let fragment = b"non-utf8 \xF0\x90\x80string";
-        let result = Builder::new().clean_from_reader(&fragment[..]);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().to_string(), "non-utf8 \u{fffd}string");
+    #[should_panic(expected = "element_filter can be set only once")]
+    fn element_filter_twice() {
+        Builder::new()
+            .element_filter(|_, _| ElementAction::Keep)
+            .element_filter(|_, _| ElementAction::Keep);
     }
     #[test]
-    fn display_impl() {
-        let fragment = r#"a <a>link</a>"#;
-        let result = Builder::new().link_rel(None).clean(fragment);
-        assert_eq!(format!("{}", result), "a <a>link</a>");
+    fn rename_checked_against_new_name() {
+        let result = Builder::new()
+            .rename_tag("b", "my-tag")
+            .rename_tag("i", "em")
+            .clean("<b>not allowed</b> <i title=t onclick=x>allowed</i>")
+            .to_string();
+        assert_eq!(result, "not allowed <em title=\"t\">allowed</em>");
     }
     #[test]
-    fn debug_impl() {
-        let fragment = r#"a <a>link</a>"#;
-        let result = Builder::new().link_rel(None).clean(fragment);
-        assert_eq!(format!("{:?}", result), "Document(a <a>link</a>)");
+    fn rename_attributes_checked_against_new_name() {
+        let result = Builder::new()
+            .rename_tag("img", "span")
+            .rename_tag("center", "div")
+            .rename_tag_attribute_value("center", "class", "center")
+            .clean("<img src=x alt=y><center class=other>c</center>")
+            .to_string();
+        assert_eq!(result, "<span></span><div>c</div>");
     }
-    #[cfg(ammonia_unstable)]
     #[test]
-    fn to_dom_node() {
-        let fragment = r#"a <a>link</a>"#;
-        let result = Builder::new().link_rel(None).clean(fragment);
-        let _node = result.to_dom_node();
+    fn rename_keeps_children() {
+        let result = Builder::new()
+            .rename_tag("font", "span")
+            .clean("<font><b>a</b><font>b</font>c</font>")
+            .to_string();
+        assert_eq!(result, "<span><b>a</b><span>b</span>c</span>");
     }
     #[test]
-    fn string_from_document() {
-        let fragment = r#"a <a>link"#;
-        let result = String::from(Builder::new().link_rel(None).clean(fragment));
-        assert_eq!(format!("{}", result), "a <a>link</a>");
+    fn rename_not_chained() {
+        let result = Builder::new()
+            .rename_tag("b", "strong")
+            .rename_tag("strong", "em")
+            .clean("<b>b</b><strong>s</strong>")
+            .to_string();
+        assert_eq!(result, "<strong>b</strong><em>s</em>");
     }
-    fn require_sync<T: Sync>(_: T) {}
-    fn require_send<T: Send>(_: T) {}
     #[test]
-    fn require_sync_and_send() {
-        require_sync(Builder::new());
-        require_send(Builder::new());
+    fn rename_only_html() {
+        let result = Builder::new()
+            .add_tags(&["svg", "circle"])
+            .rename_tag("circle", "span")
+            .clean("<svg><circle></circle></svg><circle></circle>")
+            .to_string();
+        assert_eq!(result, "<svg><circle></circle></svg><span></span>");
     }
     #[test]
-    fn id_prefixed() {
-        let fragment = "<a id=\"hello\"></a><b id=\"hello\"></a>";
-        let result = String::from(
-            Builder::new()
-                .tag_attributes(hashmap![
-                    "a" => hashset!["id"],
-                ])
-                .id_prefix(Some("prefix-"))
-                .clean(fragment),
+    fn rename_to_clean_content_tag() {
+        let result = Builder::new()
+            .rename_tag("b", "script")
+            .clean("a<b>b</b>c")
+            .to_string();
+        assert_eq!(result, "ac");
+    }
+    #[test]
+    fn rename_to_raw_text_rejected() {
+        let mut b = Builder::new();
+        b.add_tags(&["xmp"]).rename_tag("b", "xmp");
+        assert_eq!(
+            b.try_clean("<b>&lt;/xmp&gt;&lt;img src=x onerror=alert(1)&gt;</b>")
+                .unwrap_err(),
+            PolicyError::UnsafeRenameTarget {
+                tag: "xmp".to_string()
+            }
         );
+    }
+    #[test]
+    fn rename_to_rcdata_rejected() {
+        let mut b = Builder::new();
+        b.add_tags(&["textarea"])
+            .rename_tag("b", "textarea")
+            .strip_comments(false);
         assert_eq!(
-            result.to_string(),
-            "<a id=\"prefix-hello\" rel=\"noopener noreferrer\"></a><b></b>"
+            b.try_clean("<b><!-- </textarea><img src=x onerror=alert(1)> --></b>")
+                .unwrap_err(),
+            PolicyError::UnsafeRenameTarget {
+                tag: "textarea".to_string()
+            }
         );
+        for tag in ["title", "table", "select", "a", "img"] {
+            b.rename_tag("b", tag);
+            assert_eq!(
+                b.validate(),
+                Err(PolicyError::UnsafeRenameTarget {
+                    tag: tag.to_string()
+                })
+            );
+        }
     }
     #[test]
-    fn id_already_prefixed() {
-        let fragment = "<a id=\"prefix-hello\"></a>";
-        let result = String::from(
-            Builder::new()
-                .tag_attributes(hashmap![
-                    "a" => hashset!["id"],
-                ])
-                .id_prefix(Some("prefix-"))
-                .clean(fragment),
+    fn rename_to_custom_element() {
+        let result = Builder::new()
+            .add_tags(&["x-note"])
+            .rename_tag("aside", "x-note")
+            .clean("<aside>a</aside>")
+            .to_string();
+        assert_eq!(result, "<x-note>a</x-note>");
+        let mut b = Builder::new();
+        b.rename_tag("b", "font-face");
+        assert!(b.validate().is_err());
+    }
+    #[test]
+    fn max_depth_unwrap() {
+        let (document, report) = Builder::new()
+            .max_depth(Some(1))
+            .clean_with_report("<ul><li><b>x</b> <i>y</i></li></ul>z");
+        assert_eq!(document.to_string(), "<ul>x y</ul>z");
+        assert_eq!(
+            report.removals(),
+            &["li", "b", "i"].map(|name| Removal::DepthLimit {
+                name: name.to_string(),
+                action: DepthLimitAction::Unwrap,
+            })
         );
+    }
+    #[test]
+    fn max_depth_remove() {
+        let mut b = Builder::new();
+        b.max_depth(Some(2))
+            .depth_limit_action(DepthLimitAction::Remove);
         assert_eq!(
-            result.to_string(),
-            "<a id=\"prefix-hello\" rel=\"noopener noreferrer\"></a>"
+            b.clean("<div><div>a<div>b</div>c</div></div><div>d</div>")
+                .to_string(),
+            "<div><div>ac</div></div><div>d</div>"
         );
+        assert_eq!(
+            b.clean("<my-tag><p><my-tag><b>a</b></my-tag></p></my-tag>")
+                .to_string(),
+            "<p><b>a</b></p>"
+        );
+        b.max_depth(Some(0));
+        assert_eq!(b.clean("a<b>b</b>c").to_string(), "ac");
     }
     #[test]
-    fn clean_content_tags() {
-        let fragment = "<script type=\"text/javascript\"><a>Hello!</a></script>";
-        let result = String::from(
-            Builder::new()
-                .clean_content_tags(hashset!["script"])
-                .clean(fragment),
+    fn max_depth_pathological() {
+        let input = "<b>".repeat(60_000) + "x";
+        let output = Builder::new().max_depth(Some(3)).clean(&input).to_string();
+        assert_eq!(output, "<b><b><b>x</b></b></b>");
+    }
+    #[test]
+    fn max_nodes() {
+        let mut b = Builder::new();
+        b.max_nodes(Some(4));
+        let (document, report) =
+            b.clean_with_report("<p>a<!-- removed --><b>b</b></p><p>c</p><p>d</p>");
+        assert_eq!(document.to_string(), "<p>a<b>b</b></p>");
+        assert_eq!(report.removals(), &[Removal::Comment, Removal::NodeLimit]);
+        b.max_nodes(Some(0));
+        assert_eq!(b.clean("<p>a</p>").to_string(), "");
+    }
+    #[test]
+    fn max_attributes() {
+        let (document, report) = Builder::new()
+            .max_attributes(Some(2))
+            .clean_with_report("<a onclick=x href=/a title=b hreflang=en lang=en>a</a>");
+        assert_eq!(
+            document.to_string(),
+            "<a href=\"/a\" title=\"b\" rel=\"noopener noreferrer\">a</a>"
+        );
+        assert_eq!(
+            report.removals(),
+            &[
+                Removal::Attribute {
+                    element: "a".to_string(),
+                    attribute: "onclick".to_string(),
+                },
+                Removal::AttributeLimit {
+                    element: "a".to_string(),
+                    attribute: "hreflang".to_string(),
+                },
+                Removal::AttributeLimit {
+                    element: "a".to_string(),
+                    attribute: "lang".to_string(),
+                },
+            ]
         );
-        assert_eq!(result.to_string(), "");
     }
     #[test]
-    fn only_clean_content_tags() {
-        let fragment = "<em>This is</em><script><a>Hello!</a></script><p>still here!</p>";
-        let result = String::from(
-            Builder::new()
-                .clean_content_tags(hashset!["script"])
-                .clean(fragment),
+    fn max_output_bytes() {
+        let input =
+            "<p>one &amp; <b title='\"'>two</b><br>three\u{a0}<!-- four --></p><pre>&lt;five</pre>";
+        let mut b = Builder::new();
+        b.strip_comments(false);
+        let full = b.clean(input).to_string();
+        let mut previous = String::new();
+        for max in 0..=full.len() + 1 {
+            let (document, report) = b.max_output_bytes(Some(max)).clean_with_report(input);
+            let output = document.to_string();
+            assert!(output.len() <= max, "{} > {}", output, max);
+            assert_eq!(b.clean(&output).to_string(), output);
+            assert!(output.len() >= previous.len(), "{} < {}", output, previous);
+            assert_eq!(report.removals() == [Removal::OutputLimit], output != full);
+            previous = output;
+        }
+        assert_eq!(previous, full);
+        b.max_output_bytes(Some(20));
+        assert_eq!(b.clean(input).to_string(), "<p>one &amp; </p>");
+        assert_eq!(
+            b.clean("<p>\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}</p>")
+                .to_string(),
+            "<p>\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}</p>"
         );
-        assert_eq!(result.to_string(), "<em>This is</em><p>still here!</p>");
     }
     #[test]
-    fn clean_removed_default_tag() {
-        let fragment = "<em>This is</em><script><a>Hello!</a></script><p>still here!</p>";
-        let result = String::from(
-            Builder::new()
-                .rm_tags(hashset!["a"])
-                .rm_tag_attributes("a", hashset!["href", "hreflang"])
-                .clean_content_tags(hashset!["script"])
-                .clean(fragment),
+    fn srcset() {
+        let mut b = Builder::new();
+        b.add_tag_attributes("img", &["srcset"])
+            .add_tags(&["link"])
+            .add_tag_attributes("link", &["imagesrcset"]);
+        assert_eq!(
+            b.clean("<img srcset=\"a.png, javascript:alert(1) 2x,https://example.com/b.png 3x,http://[ 4x\">")
+                .to_string(),
+            "<img srcset=\"a.png, https://example.com/b.png 3x\">"
+        );
+        assert_eq!(
+            b.clean("<img srcset=\"javascript:alert(1), data:text/html,x 2x\">")
+                .to_string(),
+            "<img>"
+        );
+        // Commas are part of the URL unless they're followed by whitespace.
+        assert_eq!(
+            b.clean("<link imagesrcset=\"a.png,javascript:alert(1) 1x\">")
+                .to_string(),
+            "<link imagesrcset=\"a.png,javascript:alert(1) 1x\">"
+        );
+        b.url_relative(UrlRelative::RewriteWithBase(
+            Url::parse("https://example.com/img/").unwrap(),
+        ));
+        assert_eq!(
+            b.clean("<img srcset=\"a.png 100w,  /b.png   200w, //cdn.example.com/c.png 300w\">")
+                .to_string(),
+            "<img srcset=\"https://example.com/img/a.png 100w, https://example.com/b.png 200w, https://cdn.example.com/c.png 300w\">"
+        );
+        let (document, report) = b
+            .url_relative(UrlRelative::Deny)
+            .clean_with_report("<img srcset=\"a.png 1x, https://example.com/b.png 2x, ftp://x/c.png 3x\">");
+        assert_eq!(
+            document.to_string(),
+            "<img srcset=\"https://example.com/b.png 2x, ftp://x/c.png 3x\">"
+        );
+        assert_eq!(
+            report.removals(),
+            [Removal::RelativeUrl {
+                element: "img".to_string(),
+                attribute: "srcset".to_string(),
+                value: "a.png".to_string(),
+            }]
         );
-        assert_eq!(result.to_string(), "<em>This is</em><p>still here!</p>");
     }
     #[test]
-    #[should_panic]
-    fn panic_on_clean_content_tag_attribute() {
-        Builder::new()
-            .rm_tags(std::iter::once("a"))
-            .clean_content_tags(hashset!["a"])
-            .clean("");
+    fn srcset_custom_rewriter() {
+        fn evaluate(url: &str) -> Option<Cow<'_, str>> {
+            Some(Cow::Owned(format!("/x{url} 1x, javascript:alert(1)")))
+        }
+        let mut b = Builder::new();
+        b.add_tag_attributes("img", &["srcset"])
+            .url_relative(UrlRelative::Custom(Box::new(evaluate)));
+        assert_eq!(
+            b.clean("<img srcset=\"a.png 1x, https://example.com/b.png 2x\">")
+                .to_string(),
+            "<img srcset=\"https://example.com/b.png 2x\">"
+        );
     }
     #[test]
-    #[should_panic]
-    fn panic_on_clean_content_tag() {
-        Builder::new().clean_content_tags(hashset!["a"]).clean("");
+    fn allow_iframes() {
+        let mut b = Builder::new();
+        b.add_generic_attributes(&["srcdoc"])
+            .set_tag_attribute_value("iframe", "sandbox", "allow-top-navigation")
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("https://www.youtube.com/").unwrap(),
+            ))
+            .allow_iframes([IframeProvider::youtube()]);
+        let (document, report) = b.clean_with_report(
+            "<iframe src=/embed/x width=560 height=315 name=n srcdoc=\"<script>alert(1)</script>\" allowfullscreen>fallback</iframe>\
+             <iframe src=https://example.com/embed/x></iframe><iframe></iframe>",
+        );
+        assert_eq!(
+            document.to_string(),
+            "<iframe src=\"https://www.youtube.com/embed/x\" width=\"560\" height=\"315\" \
+             sandbox=\"allow-scripts allow-same-origin allow-presentation allow-popups\" \
+             allow=\"encrypted-media; picture-in-picture; fullscreen\" \
+             referrerpolicy=\"strict-origin-when-cross-origin\">fallback</iframe>"
+        );
+        assert_eq!(
+            report.removals(),
+            [
+                Removal::Attribute {
+                    element: "iframe".to_string(),
+                    attribute: "name".to_string(),
+                },
+                Removal::Attribute {
+                    element: "iframe".to_string(),
+                    attribute: "allowfullscreen".to_string(),
+                },
+                Removal::Attribute {
+                    element: "iframe".to_string(),
+                    attribute: "srcdoc".to_string(),
+                },
+                Removal::Iframe {
+                    src: Some("https://example.com/embed/x".to_string()),
+                },
+                Removal::Iframe { src: None },
+            ]
+        );
+        // Without providers, iframes are still unwrapped as usual.
+        assert_eq!(
+            Builder::new().clean("<iframe src=https://www.youtube.com/embed/x>a</iframe>").to_string(),
+            "a"
+        );
     }
-
     #[test]
-    fn clean_text_test() {
+    fn data_uri_policy() {
+        let png = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+        let mut b = Builder::new();
+        b.add_tag_attributes("img", &["srcset"])
+            .data_uri_policy(Some(DataUriPolicy::new().max_size(100)))
+            .generic_url_policy(Some(UrlPolicy::new().allow_hosts(["example.com"]).unwrap()));
+        let (document, report) = b.clean_with_report(&format!(
+            "<img src=\"{png}\" srcset=\"{png} 1x, data:image/png;base64,PHN2Zz4= 2x\">\
+             <img src=\"data:image/svg+xml;base64,PHN2ZyBvbmxvYWQ9YWxlcnQoMSk+\">\
+             <img src=\"data:image/png;base64,iVBOR%\">\
+             <a href=\"data:text/html,<script>alert(1)</script>\">a</a><a href=\"{png}\">b</a>"
+        ));
         assert_eq!(
-            clean_text("<this> is <a test function"),
-            "&lt;this&gt;&#32;is&#32;&lt;a&#32;test&#32;function"
+            document.to_string(),
+            format!(
+                "<img src=\"{png}\" srcset=\"{png} 1x\"><img><img>\
+                 <a rel=\"noopener noreferrer\">a</a><a rel=\"noopener noreferrer\">b</a>"
+            )
+        );
+        assert_eq!(
+            report.removals(),
+            [
+                Removal::DataUri {
+                    element: "img".to_string(),
+                    attribute: "srcset".to_string(),
+                    reason: DataUriRejection::MagicBytes,
+                },
+                Removal::DataUri {
+                    element: "img".to_string(),
+                    attribute: "src".to_string(),
+                    reason: DataUriRejection::MimeType("image/svg+xml".to_string()),
+                },
+                Removal::DataUri {
+                    element: "img".to_string(),
+                    attribute: "src".to_string(),
+                    reason: DataUriRejection::InvalidEncoding,
+                },
+                Removal::UrlScheme {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    scheme: "data".to_string(),
+                },
+                Removal::UrlScheme {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    scheme: "data".to_string(),
+                },
+            ]
+        );
+        // Only images load data: URLs through the policy, including in CSS.
+        assert_eq!(
+            b.add_tags(&["iframe"])
+                .add_tag_attributes("iframe", &["src"])
+                .add_generic_attributes(&["style"])
+                .filter_style_properties(hashset!["background"])
+                .style_value_rule("background", StyleValueRule::new().urls(true))
+                .clean(&format!(
+                    "<iframe src=\"{png}\"></iframe><p style=\"background: url({png})\">c</p>"
+                ))
+                .to_string(),
+            format!("<iframe></iframe><p style=\"background:url({png})\">c</p>")
+        );
+        assert_eq!(
+            b.data_uri_policy(Some(DataUriPolicy::new().max_size(10)))
+                .clean(&format!("<img src=\"{png}\">"))
+                .to_string(),
+            "<img>"
+        );
+        // The content security policy still applies to allowed data: URLs.
+        assert_eq!(
+            b.data_uri_policy(Some(DataUriPolicy::new()))
+                .content_security_policy(Some(ContentSecurityPolicy::parse("img-src 'self'").unwrap()))
+                .clean(&format!("<img src=\"{png}\">"))
+                .to_string(),
+            "<img>"
+        );
+        // Without a policy, data: URLs are only allowed through url_schemes.
+        assert_eq!(
+            Builder::new().clean(&format!("<img src=\"{png}\">")).to_string(),
+            "<img>"
         );
     }
-
     #[test]
-    fn clean_text_spaces_test() {
-        assert_eq!(clean_text("\x09\x0a\x0c\x20"), "&#9;&#10;&#12;&#32;");
+    fn homograph_action() {
+        let input = "<a href=\"https://\u{430}pple.com/login\">a</a><a href=\"//p\u{430}ypal.com/\">b</a>\
+                     <a href=\"https://bücher.example/\">c</a><img srcset=\"https://\u{441}\u{43e}\u{43e}\u{440}.com/x.png 2x\">";
+        let mut b = Builder::new();
+        b.add_tag_attributes("img", &["srcset"])
+            .homograph_action(Some(HomographAction::Punycode));
+        let (document, report) = b.clean_with_report(input);
+        assert_eq!(
+            document.to_string(),
+            "<a href=\"https://xn--pple-43d.com/login\" rel=\"noopener noreferrer\">a</a>\
+             <a href=\"//xn--pypal-4ve.com/\" rel=\"noopener noreferrer\">b</a>\
+             <a href=\"https://bücher.example/\" rel=\"noopener noreferrer\">c</a>\
+             <img srcset=\"https://xn--n1aagb.com/x.png 2x\">"
+        );
+        assert_eq!(
+            report.removals()[0],
+            Removal::Homograph {
+                element: "a".to_string(),
+                attribute: "href".to_string(),
+                value: "https://\u{430}pple.com/login".to_string(),
+                action: HomographAction::Punycode,
+            }
+        );
+        assert_eq!(report.removals().len(), 3);
+        assert_eq!(
+            b.homograph_action(Some(HomographAction::RemoveAttribute))
+                .clean(input)
+                .to_string(),
+            "<a rel=\"noopener noreferrer\">a</a><a rel=\"noopener noreferrer\">b</a>\
+             <a href=\"https://bücher.example/\" rel=\"noopener noreferrer\">c</a><img>"
+        );
+        let (document, report) = b
+            .homograph_action(Some(HomographAction::Report))
+            .clean_with_report(input);
+        assert_eq!(document.to_string(), Builder::new().add_tag_attributes("img", &["srcset"]).clean(input).to_string());
+        assert_eq!(report.removals().len(), 3);
+        assert_eq!(
+            report.removals()[2].to_string(),
+            "kept the `srcset` attribute of a `<img>` element, but its host is confusable"
+        );
     }
-
     #[test]
-    fn ns_svg() {
-        // https://github.com/cure53/DOMPurify/pull/495
-        let fragment = r##"<svg><iframe><a title="</iframe><img src onerror=alert(1)>">test"##;
-        let result = String::from(Builder::new().add_tags(&["iframe"]).clean(fragment));
-        assert_eq!(result.to_string(), "");
-
-        let fragment = "<svg><iframe>remove me</iframe></svg><iframe>keep me</iframe>";
-        let result = String::from(Builder::new().add_tags(&["iframe"]).clean(fragment));
-        assert_eq!(result.to_string(), "<iframe>keep me</iframe>");
-
-        let fragment = "<svg><a>remove me</a></svg><iframe>keep me</iframe>";
-        let result = String::from(Builder::new().add_tags(&["iframe"]).clean(fragment));
-        assert_eq!(result.to_string(), "<iframe>keep me</iframe>");
-
-        let fragment = "<svg><a>keep me</a></svg><iframe>keep me</iframe>";
-        let result = String::from(Builder::new().add_tags(&["iframe", "svg"]).clean(fragment));
+    fn absolute_url_rewriter() {
+        let mut b = Builder::new();
+        b.add_tag_attributes("img", &["srcset"])
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("https://example.com/blog/").unwrap(),
+            ))
+            .absolute_url_rewriter(|element, attribute, url| match (element, attribute) {
+                ("a", "href") => Some(format!("https://out.example.com/?u={}", escape_url_component(url.as_str())).into()),
+                _ if url.host_str() == Some("evil.example") => None,
+                _ => Some(url.as_str().into()),
+            })
+            .absolute_url_rewriter_exempt_hosts(["example.com", "*.example.com"])
+            .unwrap();
+        let (document, report) = b.clean_with_report(
+            "<a href=\"https://rust-lang.org/\">a</a><a href=\"//rust-lang.org/\">b</a>\
+             <a href=\"/about\">c</a><a href=\"https://cdn.example.com/\">d</a>\
+             <a href=\"mailto:me@example.com\">e</a>\
+             <img src=\"https://evil.example/x.png\" srcset=\"https://evil.example/x.png 1x, https://cdn.example.net/y.png 2x\">",
+        );
         assert_eq!(
-            result.to_string(),
-            "<svg><a rel=\"noopener noreferrer\">keep me</a></svg><iframe>keep me</iframe>"
+            document.to_string(),
+            "<a href=\"https://out.example.com/?u=https%3A%2F%2Frust-lang.org%2F\" rel=\"noopener noreferrer\">a</a>\
+             <a href=\"https://out.example.com/?u=https%3A%2F%2Frust-lang.org%2F\" rel=\"noopener noreferrer\">b</a>\
+             <a href=\"https://example.com/about\" rel=\"noopener noreferrer\">c</a>\
+             <a href=\"https://cdn.example.com/\" rel=\"noopener noreferrer\">d</a>\
+             <a href=\"https://out.example.com/?u=mailto%3Ame%40example.com\" rel=\"noopener noreferrer\">e</a>\
+             <img srcset=\"https://cdn.example.net/y.png 2x\">"
+        );
+        assert_eq!(
+            report.removals(),
+            &[
+                Removal::UrlRewriter {
+                    element: "img".to_string(),
+                    attribute: "src".to_string(),
+                    value: "https://evil.example/x.png".to_string(),
+                },
+                Removal::UrlRewriter {
+                    element: "img".to_string(),
+                    attribute: "srcset".to_string(),
+                    value: "https://evil.example/x.png".to_string(),
+                },
+            ]
+        );
+        // The link text is still compared with the real destination.
+        assert_eq!(
+            b.link_text_mismatch(Some(LinkTextAction::ShowDestination))
+                .clean("<a href=\"https://rust-lang.org/\">rust-lang.org</a><a href=\"https://evil.example/\">bank.example</a>")
+                .to_string(),
+            "<a href=\"https://out.example.com/?u=https%3A%2F%2Frust-lang.org%2F\" rel=\"noopener noreferrer\">rust-lang.org</a>\
+             <a href=\"https://out.example.com/?u=https%3A%2F%2Fevil.example%2F\" rel=\"noopener noreferrer\">https://evil.example/</a>"
         );
     }
-
     #[test]
-    fn ns_svg_2() {
-        let fragment = "<svg><foreignObject><table><path><xmp><!--</xmp><img title'--&gt;&lt;img src=1 onerror=alert(1)&gt;'>";
-        let result =  Builder::default()
-            .strip_comments(false)
-            .add_tags(&["svg","foreignObject","table","path","xmp"])
-            .clean(fragment);
+    fn link_text_mismatch() {
+        let input = "<p><a href=\"https://evil.example/\">https://<b>bank.example</b>/</a> \
+                     <a href=\"https://www.bank.example/login\">bank.example</a> \
+                     <a href=\"https://evil.example/\">click here</a> \
+                     <a href=\"/login\">bank.example</a></p>";
+        let mut b = Builder::new();
+        b.link_text_mismatch(Some(LinkTextAction::ShowDestination));
+        let (document, report) = b.clean_with_report(input);
         assert_eq!(
-            result.to_string(),
-            "<svg><foreignObject><table></table></foreignObject></svg>"
+            document.to_string(),
+            "<p><a href=\"https://evil.example/\" rel=\"noopener noreferrer\">https://evil.example/</a> \
+             <a href=\"https://www.bank.example/login\" rel=\"noopener noreferrer\">bank.example</a> \
+             <a href=\"https://evil.example/\" rel=\"noopener noreferrer\">click here</a> \
+             <a href=\"/login\" rel=\"noopener noreferrer\">bank.example</a></p>"
         );
-    }
-
-    #[test]
-    fn ns_mathml() {
-        // https://github.com/cure53/DOMPurify/pull/495
-        let fragment = "<mglyph></mglyph>";
-        let result = String::from(
-            Builder::new()
-                .add_tags(&["math", "mtext", "mglyph"])
-                .clean(fragment),
+        assert_eq!(
+            report.removals(),
+            &[Removal::LinkText {
+                text: "https://bank.example/".to_string(),
+                href: "https://evil.example/".to_string(),
+                action: LinkTextAction::ShowDestination,
+            }]
         );
-        assert_eq!(result.to_string(), "");
-        let fragment = "<math><mtext><div><mglyph>";
-        let result = String::from(
-            Builder::new()
-                .add_tags(&["math", "mtext", "mglyph"])
-                .clean(fragment),
+        let (document, report) = b
+            .link_text_mismatch(Some(LinkTextAction::Unwrap))
+            .clean_with_report(input);
+        assert!(document
+            .to_string()
+            .starts_with("<p>https://<b>bank.example</b>/ <a href=\"https://www.bank.example/login\""));
+        assert_eq!(
+            report.removals()[0].to_string(),
+            "removed an `<a>` element, because its text looked like a link to another host"
         );
+        // The text is checked after cleaning, and after other URL checks.
         assert_eq!(
-            result.to_string(),
-            "<math><mtext><div></div></mtext></math>"
+            b.url_relative(UrlRelative::Deny)
+                .clean("<a href=\"/login\"><script>x</script>bank.example</a>")
+                .to_string(),
+            "<a rel=\"noopener noreferrer\">bank.example</a>"
         );
-        let fragment = "<math><mtext><mglyph>";
-        let result = String::from(
-            Builder::new()
-                .add_tags(&["math", "mtext", "mglyph"])
-                .clean(fragment),
+        assert_eq!(
+            b.url_relative(UrlRelative::PassThrough)
+                .clean("<a href=\"https://evil.example/\">bank<script>x</script>.example</a>")
+                .to_string(),
+            "bank.example"
         );
         assert_eq!(
-            result.to_string(),
-            "<math><mtext><mglyph></mglyph></mtext></math>"
+            b.link_text_mismatch(Some(LinkTextAction::ShowDestination))
+                .clean("<a href=\"//evil.example/\">https://bank.example/</a>")
+                .to_string(),
+            "<a href=\"//evil.example/\" rel=\"noopener noreferrer\">//evil.example/</a>"
         );
     }
-
     #[test]
-    fn ns_mathml_2() {
-        let fragment = "<math><mtext><table><mglyph><xmp><!--</xmp><img title='--&gt;&lt;img src=1 onerror=alert(1)&gt;'>";
-        let result =  Builder::default()
-            .strip_comments(false)
-            .add_tags(&["math","mtext","table","mglyph","xmp"])
-            .clean(fragment);
+    fn content_security_policy() {
+        let mut b = Builder::new();
+        b.add_tags(&["audio", "iframe"])
+            .add_tag_attributes("audio", &["src"])
+            .add_tag_attributes("iframe", &["src"])
+            .content_security_policy(Some(
+                ContentSecurityPolicy::parse(
+                    "default-src 'self'; media-src 'none'; frame-src https://www.youtube-nocookie.com",
+                )
+                .unwrap(),
+            ));
         assert_eq!(
-            result.to_string(),
-            "<math><mtext><table></table></mtext></math>"
+            b.clean("<img src=/a.png><img src=//example.com/b.png><a href=https://example.com/>a</a>\
+                     <iframe src=https://www.youtube-nocookie.com/embed/x></iframe><iframe src=https://example.com/></iframe>")
+                .to_string(),
+            "<img src=\"/a.png\"><img><a href=\"https://example.com/\" rel=\"noopener noreferrer\">a</a>\
+             <iframe src=\"https://www.youtube-nocookie.com/embed/x\"></iframe><iframe></iframe>"
+        );
+        let (document, report) = b
+            .csp_violation_action(CspViolationAction::RemoveElement)
+            .clean_with_report("<p>a<audio src=a.ogg>no audio</audio>b</p>");
+        assert_eq!(document.to_string(), "<p>ab</p>");
+        assert_eq!(
+            report.removals(),
+            [Removal::CspViolation {
+                element: "audio".to_string(),
+                attribute: "src".to_string(),
+                directive: "media-src".to_string(),
+                value: "a.ogg".to_string(),
+                action: CspViolationAction::RemoveElement,
+            }]
+        );
+        assert_eq!(
+            report.removals()[0].to_string(),
+            "removed a `<audio>` element and its content, because its `src` attribute violates `media-src`"
         );
     }
-
-
     #[test]
-    fn xml_processing_instruction() {
-        // https://blog.slonser.info/posts/dompurify-node-type-confusion/
-        let fragment = r##"<svg><?xml-stylesheet src='slonser' ?></svg>"##;
-        let result = String::from(Builder::new().clean(fragment));
-        assert_eq!(result.to_string(), "");
-
-        let fragment = r##"<svg><?xml-stylesheet src='slonser' ?></svg>"##;
-        let result = String::from(Builder::new().add_tags(&["svg"]).clean(fragment));
-        assert_eq!(result.to_string(), "<svg></svg>");
-
-        let fragment = r##"<svg><?xml-stylesheet ><img src=x onerror="alert('Ammonia bypassed!!!')"> ?></svg>"##;
-        let result = String::from(Builder::new().add_tags(&["svg"]).clean(fragment));
-        assert_eq!(result.to_string(), "<svg></svg><img src=\"x\"> ?&gt;");
+    fn url_policies() {
+        let mut b = Builder::new();
+        b.add_tag_attributes("img", &["srcset"])
+            .url_policy(
+                "img",
+                "src",
+                UrlPolicy::new().allow_hosts(["*.ourcdn.com"]).unwrap(),
+            )
+            .url_policy(
+                "img",
+                "srcset",
+                UrlPolicy::new().allow_hosts(["*.ourcdn.com"]).unwrap(),
+            )
+            .generic_url_policy(Some(
+                UrlPolicy::new().deny_hosts(["phishing.example"]).unwrap(),
+            ));
+        assert_eq!(
+            b.clean("<img src=https://a.ourcdn.com/x.png><img src=/x.png><img src=//evil.example/x.png><img src=\"data:image/png;base64,AAAA\">")
+                .to_string(),
+            "<img src=\"https://a.ourcdn.com/x.png\"><img src=\"/x.png\"><img><img>"
+        );
+        assert_eq!(
+            b.clean("<img srcset=\"https://a.ourcdn.com/x.png 1x, https://ourcdn.com.evil.example/x.png 2x\">")
+                .to_string(),
+            "<img srcset=\"https://a.ourcdn.com/x.png 1x\">"
+        );
+        let (document, report) = b.clean_with_report(
+            "<a href=\"https://PHISHING.example:8080/\">a</a><a href=\"/\\phishing.example\">b</a><a href=https://example.com/>c</a>",
+        );
+        assert_eq!(
+            document.to_string(),
+            "<a rel=\"noopener noreferrer\">a</a><a rel=\"noopener noreferrer\">b</a><a href=\"https://example.com/\" rel=\"noopener noreferrer\">c</a>"
+        );
+        assert_eq!(
+            report.removals(),
+            [
+                Removal::UrlHost {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    value: "https://PHISHING.example:8080/".to_string(),
+                },
+                Removal::UrlHost {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    value: "/\\phishing.example".to_string(),
+                },
+            ]
+        );
     }
-
     #[test]
-    fn generic_attribute_prefixes() {
-        let prefix_data = ["data-"];
-        let prefix_code = ["code-"];
+    fn url_attributes() {
         let mut b = Builder::new();
-        let mut hs: HashSet<&'_ str> = HashSet::new();
-        hs.insert("data-");
-        assert!(b.generic_attribute_prefixes.is_none());
-        b.generic_attribute_prefixes(hs);
-        assert!(b.generic_attribute_prefixes.is_some());
-        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
-        b.add_generic_attribute_prefixes(&prefix_data);
-        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
-        b.add_generic_attribute_prefixes(&prefix_code);
-        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 2);
-        b.rm_generic_attribute_prefixes(&prefix_code);
-        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
-        b.rm_generic_attribute_prefixes(&prefix_code);
-        assert_eq!(b.generic_attribute_prefixes.as_ref().unwrap().len(), 1);
-        b.rm_generic_attribute_prefixes(&prefix_data);
-        assert!(b.generic_attribute_prefixes.is_none());
+        b.add_url_attributes("blockquote", &["cite"])
+            .add_generic_attributes(&["data-href"])
+            .add_generic_url_attributes(&["data-href"]);
+        assert_eq!(
+            b.clean("<blockquote cite=\"javascript:alert(1)\">a</blockquote><q cite=\"javascript:alert(1)\">b</q>")
+                .to_string(),
+            "<blockquote>a</blockquote><q cite=\"javascript:alert(1)\">b</q>"
+        );
+        let (document, report) = b
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("https://example.com/").unwrap(),
+            ))
+            .clean_with_report("<span data-href=\"/a\">a</span><span data-href=\"data:text/html,x\">b</span>");
+        assert_eq!(
+            document.to_string(),
+            "<span data-href=\"https://example.com/a\">a</span><span>b</span>"
+        );
+        assert_eq!(
+            report.removals(),
+            [Removal::UrlScheme {
+                element: "span".to_string(),
+                attribute: "data-href".to_string(),
+                scheme: "data".to_string(),
+            }]
+        );
+        b.rm_generic_url_attributes(&["href"]);
+        assert_eq!(
+            b.clean("<a href=\"javascript:alert(1)\">a</a>").to_string(),
+            "<a href=\"javascript:alert(1)\" rel=\"noopener noreferrer\">a</a>"
+        );
     }
-
     #[test]
-    fn selectedcontent() {
-        // https://github.com/servo/html5ever/issues/712
-        let fragment1 = r#"<select><selectedcontent></selectedcontent><option>X"#;
-        let fragment2 = r#"<select><selectedcontent></selectedcontent><option>X</option></select>"#;
-        let expected = r#"<select><selectedcontent></selectedcontent><option>X</option></select>"#;
-        assert_eq!(String::from(Builder::new().add_tags(&["select", "selectedcontent", "option"]).clean(fragment1)), expected);
-        assert_eq!(String::from(Builder::new().add_tags(&["select", "selectedcontent", "option"]).clean(fragment2)), expected);
+    fn linkify() {
+        let mut b = Builder::new();
+        b.linkify(true);
+        assert_eq!(
+            b.clean("Go to https://example.com/?a=1&amp;b=2, or mail me@example.com.")
+                .to_string(),
+            "Go to <a href=\"https://example.com/?a=1&amp;b=2\" rel=\"noopener noreferrer\">https://example.com/?a=1&amp;b=2</a>, \
+             or mail <a href=\"mailto:me@example.com\" rel=\"noopener noreferrer\">me@example.com</a>."
+        );
+        assert_eq!(
+            b.clean("<a href=/x>https://example.com/</a><pre>www.example.com</pre><code><b>www.example.com</b></code>")
+                .to_string(),
+            "<a href=\"/x\" rel=\"noopener noreferrer\">https://example.com/</a><pre>www.example.com</pre><code><b>www.example.com</b></code>"
+        );
+        // Links in unwrapped elements still count as links.
+        assert_eq!(
+            b.clean("<my-a><a>www.example.com</a></my-a>").to_string(),
+            "<a rel=\"noopener noreferrer\">www.example.com</a>"
+        );
+        // Decoded markup in the text can't get into the link.
+        assert_eq!(
+            b.clean("https://example.com/&quot;onmouseover=alert(1)&gt;&lt;script&gt;").to_string(),
+            "<a href=\"https://example.com/\" rel=\"noopener noreferrer\">https://example.com/</a>\"onmouseover=alert(1)&gt;&lt;script&gt;"
+        );
     }
-    
     #[test]
-    fn new_select_parse() {
-        // https://github.com/whatwg/html/issues/10310#issuecomment-2304377029
-        let fragment = r#"
-<select><style></select><img src onerror=xss()></style></select>
-        "#;
-        let expected = r#"
-<select></select>
-        "#;
-        assert_eq!(String::from(Builder::new().add_tags(&["select", "new-select"]).clean_content_tags(hashset!["style"]).clean(fragment)), expected);
+    fn linkify_uses_link_settings() {
+        let mut b = Builder::new();
+        b.linkify(true)
+            .link_rel(Some("nofollow"))
+            .url_schemes(hashset!["mailto"]);
+        assert_eq!(
+            b.clean("https://example.com/ www.example.com me@example.com").to_string(),
+            "https://example.com/ www.example.com <a href=\"mailto:me@example.com\" rel=\"nofollow\">me@example.com</a>"
+        );
+        b.rm_tags(&["a"]);
+        assert_eq!(b.clean("me@example.com").to_string(), "me@example.com");
+        let (document, report) = Builder::new()
+            .linkify(true)
+            .element_filter(|name: &str, attrs: &[(&str, &str)]| {
+                if name == "a" && attrs.contains(&("href", "https://www.example.com")) {
+                    ElementAction::Unwrap
+                } else {
+                    ElementAction::Keep
+                }
+            })
+            .clean_with_report("www.example.com www.example.org");
+        assert_eq!(
+            document.to_string(),
+            "www.example.com <a href=\"https://www.example.org\" rel=\"noopener noreferrer\">www.example.org</a>"
+        );
+        assert_eq!(
+            report.removals(),
+            [Removal::ElementFilter {
+                name: "a".to_string(),
+                action: ElementAction::Unwrap,
+            }]
+        );
     }
-
     #[test]
-    fn selectedcontent_not_in_select() {
-        // https://github.com/whatwg/html/issues/10310#issuecomment-2304377029
-        let fragment = r#"
-<selectedcontent>first</selectedcontent>
-<div><selectedcontent>second</selectedcontent></div>
-<select><selectedcontent>third</selectedcontent></select>
-        "#;
-        let expected = r#"
-<selectedcontent>first</selectedcontent>
-<div><selectedcontent>second</selectedcontent></div>
-<select><selectedcontent></selectedcontent></select>
-        "#;
-        assert_eq!(String::from(Builder::new().add_tags(&["select", "selectedcontent"]).clean(fragment)), expected);
+    fn report_empty() {
+        let (document, report) = Builder::new().clean_with_report("<b>fine</b>");
+        assert_eq!(document.to_string(), "<b>fine</b>");
+        assert!(report.is_empty());
     }
-
     #[test]
-    fn generic_attribute_prefixes_clean() {
-        let fragment = r#"<a data-1 data-2 code-1 code-2><a>Hello!</a></a>"#;
-        let result_cleaned = String::from(
-            Builder::new()
-                .add_tag_attributes("a", &["data-1"])
-                .clean(fragment),
-        );
+    fn report_elements() {
+        let (document, report) = Builder::new()
+            .clean_with_report("<my-tag>a</my-tag><script>b</script><!-- c -->");
+        assert_eq!(document.to_string(), "a");
         assert_eq!(
-            result_cleaned,
-            r#"<a data-1="" rel="noopener noreferrer"></a><a rel="noopener noreferrer">Hello!</a>"#
+            report.removals(),
+            &[
+                Removal::Element {
+                    name: "my-tag".to_string()
+                },
+                Removal::ElementContent {
+                    name: "script".to_string()
+                },
+                Removal::Comment,
+            ]
         );
-        let result_allowed = String::from(
-            Builder::new()
-                .add_tag_attributes("a", &["data-1"])
-                .add_generic_attribute_prefixes(&["data-"])
-                .clean(fragment),
+    }
+    #[test]
+    fn report_namespace_switch() {
+        let (_, report) = Builder::new()
+            .add_tags(&["svg", "iframe"])
+            .clean_with_report("<svg><iframe><a>test</a></iframe></svg>");
+        assert_eq!(
+            report.removals(),
+            &[Removal::NamespaceSwitch {
+                name: "iframe".to_string()
+            }]
         );
+    }
+    #[test]
+    fn report_urls() {
+        let (document, report) = Builder::new()
+            .url_relative(UrlRelative::Deny)
+            .link_rel(None)
+            .clean_with_report(
+                "<a href=javascript:evil()>a</a><a href=/b>b</a><a href=\"http://[\">c</a>",
+            );
+        assert_eq!(document.to_string(), "<a>a</a><a>b</a><a>c</a>");
         assert_eq!(
-            result_allowed,
-            r#"<a data-1="" data-2="" rel="noopener noreferrer"></a><a rel="noopener noreferrer">Hello!</a>"#
+            report.removals(),
+            &[
+                Removal::UrlScheme {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    scheme: "javascript".to_string(),
+                },
+                Removal::RelativeUrl {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    value: "/b".to_string(),
+                },
+                Removal::InvalidUrl {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    value: "http://[".to_string(),
+                },
+            ]
         );
-        let result_allowed = String::from(
-            Builder::new()
-                .add_tag_attributes("a", &["data-1", "code-1"])
-                .add_generic_attribute_prefixes(&["data-", "code-"])
-                .clean(fragment),
+    }
+    #[test]
+    fn report_custom_relative_url() {
+        fn evaluate(_: &str) -> Option<Cow<'_, str>> {
+            None
+        }
+        let (_, report) = Builder::new()
+            .url_relative(UrlRelative::Custom(Box::new(evaluate)))
+            .clean_with_report("<img src=x.png>");
+        assert_eq!(
+            report.removals(),
+            &[Removal::RelativeUrl {
+                element: "img".to_string(),
+                attribute: "src".to_string(),
+                value: "x.png".to_string(),
+            }]
         );
+    }
+    #[test]
+    fn report_attribute_filter() {
+        let (_, report) = Builder::new()
+            .attribute_filter(|_, attr, value| {
+                if attr == "alt" {
+                    None
+                } else {
+                    Some(value.into())
+                }
+            })
+            .clean_with_report("<img alt=x>");
         assert_eq!(
-            result_allowed,
-            r#"<a data-1="" data-2="" code-1="" code-2="" rel="noopener noreferrer"></a><a rel="noopener noreferrer">Hello!</a>"#
+            report.removals(),
+            &[Removal::AttributeFilter {
+                element: "img".to_string(),
+                attribute: "alt".to_string(),
+            }]
         );
     }
     #[test]
-    fn lesser_than_isnt_html() {
-        let fragment = "1 < 2";
-        assert!(!is_html(fragment));
+    fn report_style_and_classes() {
+        let (document, report) = Builder::new()
+            .add_generic_attributes(&["style"])
+            .filter_style_properties(hashset!["color"])
+            .add_allowed_classes("p", &["ok"])
+            .clean_with_report("<p class=\"ok bad\" style=\"color: red; position: fixed\">x</p>");
+        assert_eq!(
+            document.to_string(),
+            "<p class=\"ok\" style=\"color:red\">x</p>"
+        );
+        assert_eq!(
+            report.removals(),
+            &[
+                Removal::StyleProperty {
+                    element: "p".to_string(),
+                    property: "position".to_string(),
+                },
+                Removal::Class {
+                    element: "p".to_string(),
+                    class: "bad".to_string(),
+                },
+            ]
+        );
     }
     #[test]
-    fn dense_lesser_than_isnt_html() {
-        let fragment = "1<2";
-        assert!(!is_html(fragment));
+    fn style_value_rules() {
+        let (document, report) = Builder::new()
+            .add_generic_attributes(&["style"])
+            .filter_style_properties(hashset!["background", "width"])
+            .style_value_rule("background", StyleValueRule::new().colors(true).urls(true))
+            .url_schemes(hashset!["https"])
+            .clean_with_report(
+                "<p style=\"background: url(http://tracker.example/pixel); width: 99999px\">x</p>\
+                 <p style=\"background: red url(https://example.com/a.png)\">y</p>",
+            );
+        assert_eq!(
+            document.to_string(),
+            "<p style=\"width:99999px\">x</p><p style=\"background:red url(https://example.com/a.png)\">y</p>"
+        );
+        assert_eq!(
+            report.removals(),
+            &[Removal::StyleUrl {
+                element: "p".to_string(),
+                property: "background".to_string(),
+                url: "http://tracker.example/pixel".to_string(),
+            }]
+        );
     }
     #[test]
-    fn what_about_number_elements() {
-        let fragment = "foo<2>bar";
-        assert!(!is_html(fragment));
+    fn style_urls_use_url_pipeline() {
+        let input = "<p style=\"background: url(/a.png) no-repeat; color: red\">a</p>\
+                     <p style=\"background-image: url('javascript:alert(1)'); color: red\">b</p>\
+                     <p style=\"background-image: image-set(url(https://cdn.example/c.png) 1x)\">c</p>\
+                     <p style=\"background-image: url(https://evil.example/d.png)\">d</p>";
+        let mut b = Builder::new();
+        b.add_generic_attributes(&["style"])
+            .filter_style_properties(hashset!["background", "background-image", "color"])
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("https://example.com/blog/").unwrap(),
+            ))
+            .content_security_policy(Some(
+                "img-src 'self' https://cdn.example https://example.com".parse().unwrap(),
+            ));
+        let (document, report) = b.clean_with_report(input);
+        assert_eq!(
+            document.to_string(),
+            "<p style=\"background:url(https://example.com/a.png) no-repeat;color:red\">a</p>\
+             <p style=\"color:red\">b</p>\
+             <p style=\"background-image:image-set(url(https://cdn.example/c.png) 1x)\">c</p>\
+             <p style=\"\">d</p>"
+        );
+        assert_eq!(
+            report.removals(),
+            &[
+                Removal::StyleUrl {
+                    element: "p".to_string(),
+                    property: "background-image".to_string(),
+                    url: "javascript:alert(1)".to_string(),
+                },
+                Removal::StyleUrl {
+                    element: "p".to_string(),
+                    property: "background-image".to_string(),
+                    url: "https://evil.example/d.png".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            b.url_relative(UrlRelative::Deny)
+                .content_security_policy(None)
+                .absolute_url_rewriter(|_, _, url| Some(format!("https://proxy.example/?u={}", escape_url_component(url.as_str())).into()))
+                .clean(input)
+                .to_string(),
+            "<p style=\"color:red\">a</p><p style=\"color:red\">b</p>\
+             <p style=\"background-image:image-set(url(https://proxy.example/?u=https%3A%2F%2Fcdn.example%2Fc.png) 1x)\">c</p>\
+             <p style=\"background-image:url(https://proxy.example/?u=https%3A%2F%2Fevil.example%2Fd.png)\">d</p>"
+        );
     }
     #[test]
-    fn turbofish_is_html_sadly() {
-        let fragment = "Vec::<u8>::new()";
-        assert!(is_html(fragment));
+    fn style_sheets() {
+        let input = "<style title=t media=print>#intro p, p[x] { color: red; position: fixed } \
+                     @import url(https://a.example/x.css); a { background: url(javascript:x) }</style>\
+                     <p id=intro>a</p><svg><style>p { color: red }</style></svg>";
+        let mut b = Builder::new();
+        b.add_generic_attributes(&["title", "id"])
+            .id_prefix(Some("user-"))
+            .filter_style_properties(hashset!["color", "background"])
+            .style_sheets(Some(StyleSheetPolicy::new().selectors(["p", "a", "#intro"])));
+        let (document, report) = b.clean_with_report(input);
+        assert_eq!(
+            document.to_string(),
+            "<style title=\"t\">#user-intro p{color:red}</style><p id=\"user-intro\">a</p>"
+        );
+        assert_eq!(
+            report.removals(),
+            &[
+                Removal::Attribute {
+                    element: "style".to_string(),
+                    attribute: "media".to_string(),
+                },
+                Removal::StyleSheetRule {
+                    rule: "p[x]".to_string(),
+                },
+                Removal::StyleProperty {
+                    element: "style".to_string(),
+                    property: "position".to_string(),
+                },
+                Removal::StyleSheetRule {
+                    rule: "@import".to_string(),
+                },
+                Removal::StyleUrl {
+                    element: "style".to_string(),
+                    property: "background".to_string(),
+                    url: "javascript:x".to_string(),
+                },
+                Removal::Element {
+                    name: "svg".to_string(),
+                },
+                Removal::ElementContent {
+                    name: "style".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            b.style_sheets(Some(StyleSheetPolicy::new().scope(Some("a{"))))
+                .validate(),
+            Err(PolicyError::StyleSheetScope {
+                scope: "a{".to_string()
+            })
+        );
     }
     #[test]
-    fn stop_grinning() {
-        let fragment = "did you really believe me? <g>";
-        assert!(is_html(fragment));
+    fn css_sanitizer_matches_style_attribute() {
+        let css = "color: red; top: 0; width: 200px; width: 20px; background: url(/a.png) url(data:x)";
+        let mut b = Builder::new();
+        b.add_generic_attributes(&["style"])
+            .filter_style_properties(hashset!["color", "width", "background"])
+            .style_value_rule("width", StyleValueRule::new().numbers(0.0, 100.0, ["px"]))
+            .url_relative(UrlRelative::RewriteWithBase(
+                Url::parse("https://example.com/").unwrap(),
+            ));
+        let (clean_css, rejected) = b.css_sanitizer().clean_with_rejections(css);
+        assert_eq!(clean_css, "color:red;width:20px");
+        assert_eq!(
+            b.clean(&format!("<p style=\"{css}\">a</p>")).to_string(),
+            format!("<p style=\"{clean_css}\">a</p>")
+        );
+        assert_eq!(
+            rejected,
+            [
+                RejectedDeclaration::Property {
+                    property: "top".to_string(),
+                },
+                RejectedDeclaration::Value {
+                    property: "width".to_string(),
+                    value: "200px".to_string(),
+                },
+                RejectedDeclaration::Url {
+                    property: "background".to_string(),
+                    url: "data:x".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            Builder::new().css_sanitizer().clean("position : fixed; background: url(javascript:x)"),
+            "position:fixed"
+        );
     }
     #[test]
-    fn dont_be_bold() {
-        let fragment = "<b>";
-        assert!(is_html(fragment));
+    fn report_matches_clean() {
+        let input = "<a href=x onclick=y>z</a><script>w</script><span class=v>u</span>";
+        let (document, _) = Builder::new().clean_with_report(input);
+        assert_eq!(document.to_string(), clean(input));
     }
-
     #[test]
     fn rewrite_with_root() {
         let tests = [
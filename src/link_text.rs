@@ -0,0 +1,152 @@
+//! Detecting links whose text looks like a different URL than the one they point at,
+//! for [`Builder::link_text_mismatch`].
+//!
+//! [`Builder::link_text_mismatch`]: ../struct.Builder.html#method.link_text_mismatch
+
+use url::{Host, Url};
+
+use crate::RELATIVE_URL_BASE;
+
+/// What to do with a link whose text looks like a URL on another host, like
+/// `<a href="https://evil.example/">https://bank.example/</a>`.
+///
+/// See [`Builder::link_text_mismatch`][link_text_mismatch].
+///
+/// [link_text_mismatch]: struct.Builder.html#method.link_text_mismatch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LinkTextAction {
+    /// Replace the content of the link with its `href`, so it shows where it really goes.
+    ShowDestination,
+    /// Remove the link, and keep its content as plain text.
+    Unwrap,
+}
+
+/// Returns `true` if `text` looks like a URL, and `href` points at another host.
+///
+/// The text can be an absolute URL, like `https://bank.example/login`, or a host name or IP
+/// address with an optional path, like `bank.example`, `www.bank.example/login` or
+/// `203.0.113.5`. A leading `www.` is ignored, and the `href` can also be on a subdomain of
+/// the host in the text. An IP address only matches the same address.
+///
+/// Relative links, and links without a host, like `mailto:` links, never mismatch, since
+/// there is no host to compare. Scheme-relative links, like `//bank.example/`, do have one.
+pub(crate) fn is_mismatch(text: &str, href: &str) -> bool {
+    let href = match Url::parse(href) {
+        Ok(href) => href,
+        Err(url::ParseError::RelativeUrlWithoutBase) => match RELATIVE_URL_BASE.join(href) {
+            Ok(href) if href.host() != RELATIVE_URL_BASE.host() => href,
+            _ => return false,
+        },
+        Err(_) => return false,
+    };
+    let (Some(href_host), Some(text_host)) = (href.host(), text_host(text)) else {
+        return false;
+    };
+    let (Host::Domain(href_host), Host::Domain(text_host)) = (&href_host, &text_host) else {
+        return href_host.to_owned() != text_host;
+    };
+    let text_host = text_host.strip_prefix("www.").unwrap_or(text_host);
+    let href_host = href_host.strip_prefix("www.").unwrap_or(href_host);
+    href_host != text_host
+        && !href_host
+            .strip_suffix(text_host)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+/// Returns the host named by `text`, if it looks like a URL.
+fn text_host(text: &str) -> Option<Host> {
+    let text = text.trim();
+    if text.is_empty() || text.contains(char::is_whitespace) || text.contains('@') {
+        return None;
+    }
+    if let Ok(url) = Url::parse(text) {
+        return match url.host() {
+            Some(host) if text.contains("://") => Some(host.to_owned()),
+            _ => None,
+        };
+    }
+    let url = Url::parse(&format!("http://{text}")).ok()?;
+    match url.host()? {
+        // A bare host name needs a dot and a top-level domain made of letters, so that
+        // version numbers like `v1.2` aren't mistaken for one.
+        Host::Domain(host) => {
+            let (_, tld) = host.rsplit_once('.')?;
+            let tld_is_alphabetic = tld.len() >= 2
+                && (tld.chars().all(|c| c.is_ascii_alphabetic()) || tld.starts_with("xn--"));
+            tld_is_alphabetic.then(|| Host::Domain(host.to_owned()))
+        }
+        // An IP address has to be written out in full, since the URL parser also reads
+        // numbers like `1.2` as one.
+        host => {
+            let written = url.host_str()?;
+            let rest = text.strip_prefix(written)?;
+            (rest.is_empty() || rest.starts_with(['/', ':', '?', '#'])).then(|| host.to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatches() {
+        assert!(is_mismatch(
+            "https://bank.example/",
+            "https://evil.example/"
+        ));
+        assert!(is_mismatch("bank.example", "https://evil.example/"));
+        assert!(is_mismatch(
+            " www.bank.example/login ",
+            "https://evil.example/"
+        ));
+        assert!(is_mismatch(
+            "bank.example",
+            "https://bank.example.evil.example/"
+        ));
+        assert!(is_mismatch("login.bank.example", "https://bank.example/"));
+        assert!(is_mismatch("bücher.example", "https://bucher.example/"));
+        assert!(is_mismatch("bank.example", "http://203.0.113.5/login"));
+        assert!(is_mismatch("https://bank.example/", "http://[::1]/"));
+        assert!(is_mismatch("https://bank.example/", "//evil.example/"));
+        assert!(is_mismatch("203.0.113.5", "http://203.0.113.6/"));
+        assert!(is_mismatch("http://203.0.113.5/login", "https://bank.example/"));
+        assert!(is_mismatch("[::1]", "http://[::2]/"));
+    }
+
+    #[test]
+    fn matches() {
+        assert!(!is_mismatch(
+            "https://bank.example/",
+            "https://bank.example/login"
+        ));
+        assert!(!is_mismatch("bank.example", "https://www.bank.example/"));
+        assert!(!is_mismatch("www.bank.example", "http://bank.example/"));
+        assert!(!is_mismatch("bank.example", "https://login.bank.example/"));
+        assert!(!is_mismatch(
+            "bücher.example",
+            "https://xn--bcher-kva.example/"
+        ));
+        assert!(!is_mismatch("bank.example", "//www.bank.example/"));
+        assert!(!is_mismatch("203.0.113.5/login", "http://203.0.113.5/"));
+        assert!(!is_mismatch("http://[::1]/", "http://[0::1]/"));
+    }
+
+    #[test]
+    fn not_urls() {
+        assert!(!is_mismatch("click here", "https://evil.example/"));
+        assert!(!is_mismatch("v1.2", "https://evil.example/"));
+        assert!(!is_mismatch("me@bank.example", "mailto:me@evil.example"));
+        assert!(!is_mismatch("me@bank.example", "https://evil.example/"));
+        assert!(!is_mismatch("mailto:me", "https://evil.example/"));
+        assert!(!is_mismatch("bank.example", "/login"));
+        assert!(!is_mismatch("bank.example", "mailto:me@evil.example"));
+        assert!(!is_mismatch("", "https://evil.example/"));
+        assert!(!is_mismatch("1.2", "http://203.0.113.6/"));
+    }
+}
@@ -0,0 +1,196 @@
+//! Finding bare URLs and email addresses in text, for [`Builder::linkify`].
+//!
+//! [`Builder::linkify`]: ../struct.Builder.html#method.linkify
+
+use html5ever::{ns, QualName};
+use std::collections::HashSet;
+use std::ops::Range;
+use url::Url;
+
+/// Returns `true` if links can be looked for in text inside an element with this name.
+///
+/// Text that is already a link, or is code, is left alone, and so are elements
+/// whose text isn't parsed as HTML, where an `<a>` element would be out of place.
+pub(crate) fn linkify_inside(name: &QualName) -> bool {
+    name.ns == ns!(html)
+        && !matches!(
+            &*name.local,
+            "a" | "pre" | "code" | "textarea" | "title" | "option" | "select"
+        )
+        && !crate::stream::is_raw_text(&name.local)
+}
+
+/// A URL or email address found in text: where it is, and the address to link it to.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Link {
+    pub(crate) range: Range<usize>,
+    pub(crate) href: String,
+}
+
+/// Finds the URLs and email addresses in `text`.
+///
+/// URLs are recognized by a scheme from `schemes` followed by `://`, or by a `www.` prefix,
+/// which links to `https`. Email addresses link to `mailto`, if that's in `schemes`.
+/// Punctuation at the end, like the period at the end of a sentence, is not part of the link,
+/// and neither is a closing parenthesis without an opening one.
+pub(crate) fn find_links(text: &str, schemes: &HashSet<&str>) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut previous = None;
+    let mut start = 0;
+    while let Some(c) = text[start..].chars().next() {
+        let boundary = !previous.is_some_and(|p: char| p.is_alphanumeric() || "@.+-_%".contains(p));
+        if boundary && c.is_ascii_alphanumeric() {
+            let link = url_at(text, start, schemes).or_else(|| email_at(text, start, schemes));
+            if let Some(link) = link {
+                start = link.range.end;
+                previous = text[..start].chars().next_back();
+                links.push(link);
+                continue;
+            }
+        }
+        previous = Some(c);
+        start += c.len_utf8();
+    }
+    links
+}
+
+fn url_at(text: &str, start: usize, schemes: &HashSet<&str>) -> Option<Link> {
+    let rest = &text[start..];
+    let scheme_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "+.-".contains(c)))
+        .unwrap_or(rest.len());
+    let (href_prefix, host_start) =
+        if rest[..scheme_len].len() >= 4 && rest[..4].eq_ignore_ascii_case("www.") {
+            if !schemes.contains("https") {
+                return None;
+            }
+            ("https://", start + 4)
+        } else if rest[scheme_len..].starts_with("://")
+            && schemes.contains(&*rest[..scheme_len].to_ascii_lowercase())
+        {
+            ("", start + scheme_len + 3)
+        } else {
+            return None;
+        };
+    let end = text[start..]
+        .find(|c: char| c.is_whitespace() || c.is_control() || "<>\"`".contains(c))
+        .map_or(text.len(), |len| start + len);
+    let end = trim_punctuation(&text[..end], host_start);
+    if end == host_start || !text[host_start..].starts_with(|c: char| c.is_alphanumeric()) {
+        return None;
+    }
+    let href = format!("{href_prefix}{}", &text[start..end]);
+    Url::parse(&href).ok()?;
+    Some(Link {
+        range: start..end,
+        href,
+    })
+}
+
+/// Moves the end of a link back over trailing punctuation, but not past `min`.
+fn trim_punctuation(text: &str, min: usize) -> usize {
+    let mut end = text.len();
+    while end > min {
+        let last = text[..end].chars().next_back().unwrap();
+        let unbalanced_paren = last == ')'
+            && text[min..end].matches('(').count() < text[min..end].matches(')').count();
+        if !(unbalanced_paren || "?!.,:;*_~'".contains(last)) {
+            break;
+        }
+        end -= last.len_utf8();
+    }
+    end
+}
+
+fn email_at(text: &str, start: usize, schemes: &HashSet<&str>) -> Option<Link> {
+    if !schemes.contains("mailto") {
+        return None;
+    }
+    let rest = &text[start..];
+    let at = rest.find(|c: char| !(c.is_ascii_alphanumeric() || ".+-_%".contains(c)))?;
+    if at == 0 || !rest[at..].starts_with('@') {
+        return None;
+    }
+    let domain = &rest[at + 1..];
+    let domain_len = domain
+        .find(|c: char| !(c.is_ascii_alphanumeric() || ".-".contains(c)))
+        .unwrap_or(domain.len());
+    let domain = domain[..domain_len].trim_end_matches(['.', '-']);
+    let labels: Vec<&str> = domain.split('.').collect();
+    let valid = labels.len() >= 2
+        && labels
+            .iter()
+            .all(|label| !label.is_empty() && !label.starts_with('-') && !label.ends_with('-'))
+        && labels
+            .last()
+            .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_alphabetic()));
+    if !valid {
+        return None;
+    }
+    let end = start + at + 1 + domain.len();
+    Some(Link {
+        range: start..end,
+        href: format!("mailto:{}", &text[start..end]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(text: &str) -> Vec<(&str, String)> {
+        let schemes = HashSet::from(["http", "https", "ftp", "mailto"]);
+        find_links(text, &schemes)
+            .into_iter()
+            .map(|link| (&text[link.range], link.href))
+            .collect()
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            links("see https://example.com/a?b=c#d, or www.example.org."),
+            [
+                (
+                    "https://example.com/a?b=c#d",
+                    "https://example.com/a?b=c#d".to_owned()
+                ),
+                ("www.example.org", "https://www.example.org".to_owned()),
+            ]
+        );
+        assert_eq!(
+            links("(https://en.wikipedia.org/wiki/Rust_(programming_language))"),
+            [(
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)".to_owned()
+            )]
+        );
+        assert_eq!(
+            links("FTP://Example.com/<b>"),
+            [("FTP://Example.com/", "FTP://Example.com/".to_owned())]
+        );
+    }
+
+    #[test]
+    fn not_urls() {
+        assert_eq!(links("javascript://%0aalert(1)"), []);
+        assert_eq!(links("xhttps://example.com"), []);
+        assert_eq!(links("https://"), []);
+        assert_eq!(links("https://..."), []);
+        assert_eq!(links("awww.example.com"), []);
+    }
+
+    #[test]
+    fn emails() {
+        assert_eq!(
+            links("mail first.last+tag@mail.example.com."),
+            [(
+                "first.last+tag@mail.example.com",
+                "mailto:first.last+tag@mail.example.com".to_owned()
+            )]
+        );
+        assert_eq!(links("@example.com user@localhost user@example.123"), []);
+        let schemes = HashSet::from(["https"]);
+        assert_eq!(find_links("user@example.com", &schemes), []);
+    }
+}
@@ -0,0 +1,781 @@
+//! A sanitizer configuration that can be stored in, and loaded from, a config file.
+//!
+//! [`Builder`] borrows all of its strings and can hold callbacks, so it can't be
+//! deserialized directly. [`Policy`] is an owned copy of the data-only parts of it.
+
+use crate::{
+    Builder, ContentSecurityPolicy, CspViolationAction, DataUriPolicy, DepthLimitAction,
+    HomographAction, IframeProvider, LinkTextAction, PolicyError, StyleSheetPolicy, StyleValueRule, UrlPolicy,
+    UrlRelative,
+};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use url::Url;
+
+/// An owned, serializable sanitizer configuration.
+///
+/// A `Policy` holds the same allow-lists as a [`Builder`], using owned strings, so it can be
+/// loaded from (and dumped to) a config file using any [serde] format.
+/// Fields that are missing from the input take the same values as [`Builder::default`].
+///
+/// Contradictory settings, which [`Builder::clean`] would panic on, are rejected with an error
+/// while deserializing, using [`Builder::validate`].
+///
+/// This type is only available with the `serde` feature.
+///
+/// [serde]: https://serde.rs/
+///
+/// # Examples
+///
+///     use ammonia::{Builder, Policy};
+///
+///     let policy: Policy = serde_json::from_str(r#"{
+///         "tags": ["b", "a"],
+///         "tag_attributes": { "a": ["href"] },
+///         "url_schemes": ["https"],
+///         "link_rel": null
+///     }"#).unwrap();
+///     let html = Builder::from(&policy)
+///         .clean("<a href=http://example.com/><b>bold</b> <i>italic</i></a>")
+///         .to_string();
+///     assert_eq!(html, "<a><b>bold</b> italic</a>");
+///
+/// Invalid combinations are rejected at load time:
+///
+///     use ammonia::Policy;
+///
+///     let err = serde_json::from_str::<Policy>(r#"{ "generic_attributes": ["rel"] }"#)
+///         .unwrap_err();
+///     assert!(err.to_string().contains("`rel`"));
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self", default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Policy {
+    /// See [`Builder::tags`].
+    pub tags: BTreeSet<String>,
+    /// See [`Builder::clean_content_tags`].
+    pub clean_content_tags: BTreeSet<String>,
+    /// See [`Builder::tag_attributes`].
+    pub tag_attributes: BTreeMap<String, BTreeSet<String>>,
+    /// See [`Builder::tag_attribute_values`].
+    pub tag_attribute_values: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+    /// See [`Builder::set_tag_attribute_values`].
+    pub set_tag_attribute_values: BTreeMap<String, BTreeMap<String, String>>,
+    /// See [`Builder::generic_attributes`].
+    pub generic_attributes: BTreeSet<String>,
+    /// See [`Builder::generic_attribute_prefixes`].
+    pub generic_attribute_prefixes: Option<BTreeSet<String>>,
+    /// See [`Builder::url_schemes`].
+    pub url_schemes: BTreeSet<String>,
+    /// See [`Builder::url_attributes`].
+    pub url_attributes: BTreeMap<String, BTreeSet<String>>,
+    /// See [`Builder::generic_url_attributes`].
+    pub generic_url_attributes: BTreeSet<String>,
+    /// See [`Builder::url_policies`].
+    pub url_policies: BTreeMap<String, BTreeMap<String, UrlPolicy>>,
+    /// See [`Builder::generic_url_policy`].
+    pub generic_url_policy: Option<UrlPolicy>,
+    /// See [`Builder::content_security_policy`].
+    pub content_security_policy: Option<ContentSecurityPolicy>,
+    /// See [`Builder::csp_violation_action`].
+    pub csp_violation_action: CspViolationAction,
+    /// See [`Builder::allow_iframes`].
+    pub iframe_providers: Vec<IframeProvider>,
+    /// See [`Builder::data_uri_policy`].
+    pub data_uri_policy: Option<DataUriPolicy>,
+    /// See [`Builder::homograph_action`].
+    pub homograph_action: Option<HomographAction>,
+    /// See [`Builder::link_text_mismatch`].
+    pub link_text_mismatch: Option<LinkTextAction>,
+    /// See [`Builder::url_relative`].
+    pub url_relative: UrlRelativePolicy,
+    /// See [`Builder::link_rel`].
+    pub link_rel: Option<String>,
+    /// See [`Builder::allowed_classes`].
+    pub allowed_classes: BTreeMap<String, BTreeSet<String>>,
+    /// See [`Builder::strip_comments`].
+    pub strip_comments: bool,
+    /// See [`Builder::id_prefix`].
+    pub id_prefix: Option<String>,
+    /// See [`Builder::filter_style_properties`].
+    pub filter_style_properties: Option<BTreeSet<String>>,
+    /// See [`Builder::style_value_rules`].
+    pub style_value_rules: BTreeMap<String, StyleValueRule>,
+    /// See [`Builder::style_sheets`].
+    pub style_sheets: Option<StyleSheetPolicy>,
+    /// See [`Builder::rename_tags`].
+    pub rename_tags: BTreeMap<String, String>,
+    /// See [`Builder::rename_tag_attribute_value`].
+    pub rename_tag_attribute_values: BTreeMap<String, BTreeMap<String, String>>,
+    /// See [`Builder::max_depth`].
+    pub max_depth: Option<usize>,
+    /// See [`Builder::depth_limit_action`].
+    pub depth_limit_action: DepthLimitAction,
+    /// See [`Builder::max_nodes`].
+    pub max_nodes: Option<usize>,
+    /// See [`Builder::max_attributes`].
+    pub max_attributes: Option<usize>,
+    /// See [`Builder::max_output_bytes`].
+    pub max_output_bytes: Option<usize>,
+    /// See [`Builder::linkify`].
+    pub linkify: bool,
+}
+
+/// The serializable subset of [`UrlRelative`].
+///
+/// [`UrlRelative::Custom`] holds a function, so it has no equivalent here.
+///
+/// In a config file, unit variants are written as plain strings,
+/// like `"deny"` or `"pass_through"`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum UrlRelativePolicy {
+    /// See [`UrlRelative::Deny`].
+    Deny,
+    /// See [`UrlRelative::PassThrough`].
+    PassThrough,
+    /// See [`UrlRelative::RewriteWithBase`].
+    RewriteWithBase(Url),
+    /// See [`UrlRelative::RewriteWithRoot`].
+    RewriteWithRoot {
+        /// The URL that is treated as the root by the resolver.
+        root: Url,
+        /// The "current path" used to resolve relative paths.
+        path: String,
+    },
+}
+
+impl Policy {
+    /// Checks the policy for contradictory settings.
+    ///
+    /// Deserialization already calls this, so it's only needed after changing
+    /// the fields by hand.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::{Policy, PolicyError};
+    ///
+    ///     let mut policy = Policy::default();
+    ///     assert_eq!(policy.validate(), Ok(()));
+    ///     policy.clean_content_tags.insert("b".to_string());
+    ///     assert_eq!(
+    ///         policy.validate(),
+    ///         Err(PolicyError::CleanContentTagInTags { tag: "b".to_string() }),
+    ///     );
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        Builder::from(self).validate()
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::try_from(&Builder::default()).expect("the default builder is a valid policy")
+    }
+}
+
+impl<'de> Deserialize<'de> for Policy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let policy = Policy::deserialize(deserializer)?;
+        policy.validate().map_err(D::Error::custom)?;
+        Ok(policy)
+    }
+}
+
+impl Serialize for Policy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Policy::serialize(self, serializer)
+    }
+}
+
+fn set(strings: &BTreeSet<String>) -> HashSet<&str> {
+    strings.iter().map(String::as_str).collect()
+}
+
+fn owned_set(strings: &HashSet<&str>) -> BTreeSet<String> {
+    strings.iter().map(|s| s.to_string()).collect()
+}
+
+/// Builds a sanitizer that borrows its configuration from the policy.
+impl<'a> From<&'a Policy> for Builder<'a> {
+    fn from(policy: &'a Policy) -> Self {
+        let mut builder = Builder::default();
+        builder
+            .tags(set(&policy.tags))
+            .clean_content_tags(set(&policy.clean_content_tags))
+            .tag_attributes(
+                policy
+                    .tag_attributes
+                    .iter()
+                    .map(|(tag, attrs)| (&tag[..], set(attrs)))
+                    .collect(),
+            )
+            .tag_attribute_values(
+                policy
+                    .tag_attribute_values
+                    .iter()
+                    .map(|(tag, attrs)| {
+                        let attrs = attrs
+                            .iter()
+                            .map(|(attr, values)| (&attr[..], set(values)))
+                            .collect();
+                        (&tag[..], attrs)
+                    })
+                    .collect(),
+            )
+            .set_tag_attribute_values(
+                policy
+                    .set_tag_attribute_values
+                    .iter()
+                    .map(|(tag, attrs)| {
+                        let attrs = attrs
+                            .iter()
+                            .map(|(attr, value)| (&attr[..], &value[..]))
+                            .collect();
+                        (&tag[..], attrs)
+                    })
+                    .collect(),
+            )
+            .generic_attributes(set(&policy.generic_attributes))
+            .url_schemes(set(&policy.url_schemes))
+            .url_attributes(
+                policy
+                    .url_attributes
+                    .iter()
+                    .map(|(tag, attrs)| (&tag[..], set(attrs)))
+                    .collect(),
+            )
+            .generic_url_attributes(set(&policy.generic_url_attributes))
+            .url_policies(
+                policy
+                    .url_policies
+                    .iter()
+                    .map(|(tag, attrs)| {
+                        let attrs = attrs
+                            .iter()
+                            .map(|(attr, url_policy)| (&attr[..], url_policy.clone()))
+                            .collect();
+                        (&tag[..], attrs)
+                    })
+                    .collect(),
+            )
+            .generic_url_policy(policy.generic_url_policy.clone())
+            .content_security_policy(policy.content_security_policy.clone())
+            .csp_violation_action(policy.csp_violation_action)
+            .allow_iframes(policy.iframe_providers.iter().cloned())
+            .data_uri_policy(policy.data_uri_policy.clone())
+            .homograph_action(policy.homograph_action)
+            .link_text_mismatch(policy.link_text_mismatch)
+            .url_relative(match policy.url_relative {
+                UrlRelativePolicy::Deny => UrlRelative::Deny,
+                UrlRelativePolicy::PassThrough => UrlRelative::PassThrough,
+                UrlRelativePolicy::RewriteWithBase(ref base) => {
+                    UrlRelative::RewriteWithBase(base.clone())
+                }
+                UrlRelativePolicy::RewriteWithRoot { ref root, ref path } => {
+                    UrlRelative::RewriteWithRoot {
+                        root: root.clone(),
+                        path: path.clone(),
+                    }
+                }
+            })
+            .link_rel(policy.link_rel.as_deref())
+            .allowed_classes(
+                policy
+                    .allowed_classes
+                    .iter()
+                    .map(|(tag, classes)| (&tag[..], set(classes)))
+                    .collect(),
+            )
+            .strip_comments(policy.strip_comments)
+            .id_prefix(policy.id_prefix.as_deref())
+            .style_value_rules(
+                policy
+                    .style_value_rules
+                    .iter()
+                    .map(|(property, rule)| (&property[..], rule.clone()))
+                    .collect(),
+            )
+            .style_sheets(policy.style_sheets.clone())
+            .rename_tags(
+                policy
+                    .rename_tags
+                    .iter()
+                    .map(|(from, to)| (&from[..], &to[..]))
+                    .collect(),
+            )
+            .max_depth(policy.max_depth)
+            .depth_limit_action(policy.depth_limit_action)
+            .max_nodes(policy.max_nodes)
+            .max_attributes(policy.max_attributes)
+            .max_output_bytes(policy.max_output_bytes)
+            .linkify(policy.linkify);
+        builder.generic_attribute_prefixes = policy.generic_attribute_prefixes.as_ref().map(set);
+        builder.style_properties = policy.filter_style_properties.as_ref().map(set);
+        builder.rename_tag_attribute_values = policy
+            .rename_tag_attribute_values
+            .iter()
+            .map(|(tag, attrs)| {
+                let attrs = attrs
+                    .iter()
+                    .map(|(attr, value)| (&attr[..], &value[..]))
+                    .collect();
+                (&tag[..], attrs)
+            })
+            .collect();
+        builder
+    }
+}
+
+/// Copies a sanitizer's configuration into a policy, so that it can be saved.
+///
+/// Fails if the builder has contradictory settings (see [`Builder::validate`]),
+/// or uses a callback ([`Builder::attribute_filter`], [`Builder::element_filter`]
+/// or [`UrlRelative::Custom`]).
+impl TryFrom<&Builder<'_>> for Policy {
+    type Error = PolicyError;
+    fn try_from(builder: &Builder<'_>) -> Result<Self, PolicyError> {
+        builder.validate()?;
+        if builder.attribute_filter.is_some() {
+            return Err(PolicyError::Callback {
+                option: "attribute_filter",
+            });
+        }
+        if builder.element_filter.is_some() {
+            return Err(PolicyError::Callback {
+                option: "element_filter",
+            });
+        }
+        if builder.absolute_url_rewriter.is_some() {
+            return Err(PolicyError::Callback {
+                option: "absolute_url_rewriter",
+            });
+        }
+        let policy = Policy {
+            tags: owned_set(&builder.tags),
+            clean_content_tags: owned_set(&builder.clean_content_tags),
+            tag_attributes: builder
+                .tag_attributes
+                .iter()
+                .map(|(tag, attrs)| (tag.to_string(), owned_set(attrs)))
+                .collect(),
+            tag_attribute_values: builder
+                .tag_attribute_values
+                .iter()
+                .map(|(tag, attrs)| {
+                    let attrs = attrs
+                        .iter()
+                        .map(|(attr, values)| (attr.to_string(), owned_set(values)))
+                        .collect();
+                    (tag.to_string(), attrs)
+                })
+                .collect(),
+            set_tag_attribute_values: builder
+                .set_tag_attribute_values
+                .iter()
+                .map(|(tag, attrs)| {
+                    let attrs = attrs
+                        .iter()
+                        .map(|(attr, value)| (attr.to_string(), value.to_string()))
+                        .collect();
+                    (tag.to_string(), attrs)
+                })
+                .collect(),
+            generic_attributes: owned_set(&builder.generic_attributes),
+            generic_attribute_prefixes: builder.generic_attribute_prefixes.as_ref().map(owned_set),
+            url_schemes: owned_set(&builder.url_schemes),
+            url_attributes: builder
+                .url_attributes
+                .iter()
+                .map(|(tag, attrs)| (tag.to_string(), owned_set(attrs)))
+                .collect(),
+            generic_url_attributes: owned_set(&builder.generic_url_attributes),
+            url_policies: builder
+                .url_policies
+                .iter()
+                .map(|(tag, attrs)| {
+                    let attrs = attrs
+                        .iter()
+                        .map(|(attr, url_policy)| (attr.to_string(), url_policy.clone()))
+                        .collect();
+                    (tag.to_string(), attrs)
+                })
+                .collect(),
+            generic_url_policy: builder.generic_url_policy.clone(),
+            content_security_policy: builder.content_security_policy.clone(),
+            csp_violation_action: builder.csp_violation_action,
+            iframe_providers: builder.iframe_providers.clone(),
+            data_uri_policy: builder.data_uri_policy.clone(),
+            homograph_action: builder.homograph_action,
+            link_text_mismatch: builder.link_text_mismatch,
+            url_relative: match builder.url_relative {
+                UrlRelative::Deny => UrlRelativePolicy::Deny,
+                UrlRelative::PassThrough => UrlRelativePolicy::PassThrough,
+                UrlRelative::RewriteWithBase(ref base) => {
+                    UrlRelativePolicy::RewriteWithBase(base.clone())
+                }
+                UrlRelative::RewriteWithRoot { ref root, ref path } => {
+                    UrlRelativePolicy::RewriteWithRoot {
+                        root: root.clone(),
+                        path: path.clone(),
+                    }
+                }
+                UrlRelative::Custom(_) => {
+                    return Err(PolicyError::Callback {
+                        option: "url_relative",
+                    })
+                }
+            },
+            link_rel: builder.link_rel.map(str::to_string),
+            allowed_classes: builder
+                .allowed_classes
+                .iter()
+                .map(|(tag, classes)| (tag.to_string(), owned_set(classes)))
+                .collect(),
+            strip_comments: builder.strip_comments,
+            id_prefix: builder.id_prefix.map(str::to_string),
+            filter_style_properties: builder.style_properties.as_ref().map(owned_set),
+            style_value_rules: builder
+                .style_value_rules
+                .iter()
+                .map(|(property, rule)| (property.to_string(), rule.clone()))
+                .collect(),
+            style_sheets: builder.style_sheets.clone(),
+            rename_tags: builder
+                .rename_tags
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+            rename_tag_attribute_values: builder
+                .rename_tag_attribute_values
+                .iter()
+                .map(|(tag, attrs)| {
+                    let attrs = attrs
+                        .iter()
+                        .map(|(attr, value)| (attr.to_string(), value.to_string()))
+                        .collect();
+                    (tag.to_string(), attrs)
+                })
+                .collect(),
+            max_depth: builder.max_depth,
+            depth_limit_action: builder.depth_limit_action,
+            max_nodes: builder.max_nodes,
+            max_attributes: builder.max_attributes,
+            max_output_bytes: builder.max_output_bytes,
+            linkify: builder.linkify,
+        };
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::hashset;
+
+    #[test]
+    fn default_round_trip() {
+        let json = serde_json::to_string(&Policy::default()).unwrap();
+        let policy: Policy = serde_json::from_str(&json).unwrap();
+        assert_eq!(policy, Policy::default());
+        assert_eq!(serde_json::from_str::<Policy>("{}").unwrap(), policy);
+    }
+
+    #[test]
+    fn builder_round_trip() {
+        let mut builder = Builder::default();
+        builder
+            .add_tags(&["my-tag"])
+            .add_tag_attribute_values("my-tag", "kind", &["x", "y"])
+            .set_tag_attribute_value("a", "target", "_blank")
+            .add_generic_attribute_prefixes(&["data-"])
+            .add_allowed_classes("code", &["rs"])
+            .add_tag_attributes("q", &["cite"])
+            .add_url_attributes("q", &["cite"])
+            .url_policy(
+                "a",
+                "href",
+                UrlPolicy::new().allow_hosts(["*.example.org:443"]).unwrap(),
+            )
+            .generic_url_policy(Some(
+                UrlPolicy::new().deny_hosts(["[::1]", "bücher.example"]).unwrap(),
+            ))
+            .content_security_policy(Some(
+                ContentSecurityPolicy::parse("img-src 'self' https:; frame-src 'none'").unwrap(),
+            ))
+            .csp_violation_action(CspViolationAction::RemoveElement)
+            .allow_iframes([
+                IframeProvider::youtube(),
+                IframeProvider::new(["https://*.example.com/embed/"])
+                    .unwrap()
+                    .allow("fullscreen"),
+            ])
+            .data_uri_policy(Some(
+                DataUriPolicy::new()
+                    .mime_types(["image/png"])
+                    .check_magic_bytes(false),
+            ))
+            .homograph_action(Some(HomographAction::Punycode))
+            .link_text_mismatch(Some(LinkTextAction::Unwrap))
+            .url_relative(UrlRelative::RewriteWithRoot {
+                root: Url::parse("https://example.com/").unwrap(),
+                path: "post/".to_string(),
+            })
+            .filter_style_properties(hashset!["color", "width"])
+            .style_value_rule("width", StyleValueRule::new().numbers(0.0, 100.0, ["%"]))
+            .style_sheets(Some(StyleSheetPolicy::new().selectors(["code", "#i"]).scope(Some(".post"))))
+            .id_prefix(Some("user-"))
+            .rename_tag("center", "div")
+            .rename_tag_attribute_value("center", "title", "centered")
+            .max_depth(Some(3))
+            .depth_limit_action(DepthLimitAction::Remove)
+            .max_attributes(Some(4))
+            .linkify(true);
+        let policy = Policy::try_from(&builder).unwrap();
+        let json = serde_json::to_string(&policy).unwrap();
+        let loaded: Policy = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, policy);
+        let input = "<center>c</center><my-tag kind=x data-a=1 id=i>a</my-tag><a href=b class=c>d</a><code class=rs style='color: red; top: 0; width: 50%'>e</code><b style='width: 200%'>w</b> www.example.com<q cite='javascript:f()'>q</q><a href=https://a.example.org/>f</a><img src=//xn--bcher-kva.example/><p><img src=/x.png><img src=http://example.com/y.png>g</p><iframe src=https://maps.example.com/embed/a></iframe><iframe src=https://example.org/></iframe><img src=data:image/png,x><a href=https://\u{430}pple.com/>h</a><a href=https://evil.example/>bank.example</a><style>code, #i, b { color: red; top: 0 }</style>";
+        assert_eq!(
+            Builder::from(&loaded).clean(input).to_string(),
+            builder.clean(input).to_string()
+        );
+    }
+
+    #[test]
+    fn url_relative_config() {
+        let policy: Policy = serde_json::from_str(r#"{ "url_relative": "deny" }"#).unwrap();
+        assert_eq!(policy.url_relative, UrlRelativePolicy::Deny);
+        let policy: Policy = serde_json::from_str(
+            r#"{ "url_relative": { "rewrite_with_base": "https://example.com/" } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Builder::from(&policy).clean("<img src=x>").to_string(),
+            "<img src=\"https://example.com/x\">"
+        );
+        assert!(serde_json::from_str::<Policy>(
+            r#"{ "url_relative": { "rewrite_with_base": "not a url" } }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn url_policies_config() {
+        let policy: Policy = serde_json::from_str(
+            r#"{ "url_policies": { "img": { "src": { "allowed_hosts": ["*.OurCDN.com"] } } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.url_policies["img"]["src"],
+            UrlPolicy::new().allow_hosts(["*.ourcdn.com"]).unwrap()
+        );
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<img src=https://a.ourcdn.com/x.png><img src=https://example.com/x.png>")
+                .to_string(),
+            "<img src=\"https://a.ourcdn.com/x.png\"><img>"
+        );
+        assert!(serde_json::from_str::<Policy>(
+            r#"{ "generic_url_policy": { "denied_hosts": ["example.com:http"] } }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn content_security_policy_config() {
+        let policy: Policy = serde_json::from_str(
+            r#"{ "content_security_policy": "img-src https://cdn.example", "csp_violation_action": "remove_element" }"#,
+        )
+        .unwrap();
+        assert_eq!(policy.csp_violation_action, CspViolationAction::RemoveElement);
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<p>a<img src=https://example.com/x.png></p>")
+                .to_string(),
+            "<p>a</p>"
+        );
+        assert!(serde_json::from_str::<Policy>(
+            r#"{ "content_security_policy": "img-src https://" }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn iframe_providers_config() {
+        let policy: Policy = serde_json::from_str(
+            r#"{ "iframe_providers": [{
+                "src": ["https://maps.example.com/embed/"],
+                "sandbox": "allow-scripts",
+                "allow": "",
+                "referrer_policy": "no-referrer"
+            }] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<iframe src=https://maps.example.com/embed/x></iframe>")
+                .to_string(),
+            "<iframe src=\"https://maps.example.com/embed/x\" sandbox=\"allow-scripts\" allow=\"\" referrerpolicy=\"no-referrer\"></iframe>"
+        );
+        assert!(serde_json::from_str::<Policy>(
+            r#"{ "iframe_providers": [{ "src": ["https://"], "sandbox": "", "allow": "", "referrer_policy": "" }] }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn data_uri_policy_config() {
+        let policy: Policy = serde_json::from_str(
+            r#"{ "data_uri_policy": { "mime_types": ["image/gif"], "max_size": 16 } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.data_uri_policy,
+            Some(DataUriPolicy::new().mime_types(["image/gif"]).max_size(16))
+        );
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<img src=data:image/gif,GIF89a><img src=data:image/png,%89PNG%0D%0A%1A%0A>")
+                .to_string(),
+            "<img src=\"data:image/gif,GIF89a\"><img>"
+        );
+        assert!(serde_json::from_str::<Policy>(r#"{ "data_uri_policy": { "max_sise": 16 } }"#).is_err());
+    }
+
+    #[test]
+    fn style_value_rules_config() {
+        let policy: Policy = serde_json::from_str(
+            r#"{
+                "generic_attributes": ["style"],
+                "filter_style_properties": ["width", "color"],
+                "style_value_rules": {
+                    "width": { "keywords": ["auto"], "numbers": [{ "min": 0, "max": 10, "units": ["em"] }] },
+                    "color": { "colors": true }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.style_value_rules["width"],
+            StyleValueRule::new().keywords(["auto"]).numbers(0.0, 10.0, ["em"])
+        );
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<b style='width: 2em; color: url(x)'>a</b><i style='width: 20em; color: #123'>b</i>")
+                .to_string(),
+            "<b style=\"width:2em\">a</b><i style=\"color:#123\">b</i>"
+        );
+        let error = serde_json::from_str::<Policy>(
+            r#"{ "style_value_rules": { "width": { "numbers": [{ "min": 10, "max": 0, "units": [] }] } } }"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().starts_with(
+            &PolicyError::StyleNumberRange { min: "10".to_string(), max: "0".to_string() }.to_string()
+        ));
+    }
+
+    #[test]
+    fn style_sheets_config() {
+        let policy: Policy = serde_json::from_str(
+            r##"{
+                "filter_style_properties": ["color"],
+                "style_sheets": { "selectors": ["p"], "scope": "#post" }
+            }"##,
+        )
+        .unwrap();
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<style>p, a { color: red }</style>")
+                .to_string(),
+            "<style>#post p{color:red}</style>"
+        );
+        assert!(serde_json::from_str::<Policy>(r#"{ "style_sheets": { "scope": "a, b" } }"#).is_err());
+    }
+
+    #[test]
+    fn homograph_action_config() {
+        let policy: Policy =
+            serde_json::from_str(r#"{ "homograph_action": "remove_attribute" }"#).unwrap();
+        assert_eq!(policy.homograph_action, Some(HomographAction::RemoveAttribute));
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<a href=https://xn--pple-43d.com/>a</a>")
+                .to_string(),
+            "<a rel=\"noopener noreferrer\">a</a>"
+        );
+    }
+
+    #[test]
+    fn link_text_mismatch_config() {
+        let policy: Policy =
+            serde_json::from_str(r#"{ "link_text_mismatch": "show_destination" }"#).unwrap();
+        assert_eq!(policy.link_text_mismatch, Some(LinkTextAction::ShowDestination));
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<a href=https://evil.example/>bank.example</a>")
+                .to_string(),
+            "<a href=\"https://evil.example/\" rel=\"noopener noreferrer\">https://evil.example/</a>"
+        );
+    }
+
+    #[test]
+    fn limits_config() {
+        let policy: Policy = serde_json::from_str(
+            r#"{ "max_depth": 1, "depth_limit_action": "remove", "max_output_bytes": 15 }"#,
+        )
+        .unwrap();
+        assert_eq!(policy.depth_limit_action, DepthLimitAction::Remove);
+        assert_eq!(
+            Builder::from(&policy)
+                .clean("<p>a<b>b</b></p><p>c</p>")
+                .to_string(),
+            "<p>a</p><p></p>"
+        );
+    }
+
+    #[test]
+    fn reject_unknown_fields() {
+        assert!(serde_json::from_str::<Policy>(r#"{ "tgas": [] }"#).is_err());
+    }
+
+    #[test]
+    fn reject_conflicts() {
+        for json in [
+            r#"{ "generic_attributes": ["rel"] }"#,
+            r#"{ "tag_attributes": { "a": ["rel"] } }"#,
+            r#"{ "generic_attributes": ["class"], "allowed_classes": { "p": ["x"] } }"#,
+            r#"{ "tag_attributes": { "p": ["class"] }, "allowed_classes": { "p": ["x"] } }"#,
+            r#"{ "clean_content_tags": ["b"] }"#,
+            r#"{ "tags": [], "tag_attributes": { "b": [] }, "clean_content_tags": ["b"] }"#,
+            r#"{ "rename_tags": { "b": "xmp" } }"#,
+        ] {
+            assert!(serde_json::from_str::<Policy>(json).is_err(), "{}", json);
+        }
+        assert!(serde_json::from_str::<Policy>(
+            r#"{ "generic_attributes": ["rel"], "link_rel": null }"#
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn reject_callbacks() {
+        let mut builder = Builder::default();
+        builder.attribute_filter(|_, _, v| Some(v.into()));
+        assert_eq!(
+            Policy::try_from(&builder),
+            Err(PolicyError::Callback {
+                option: "attribute_filter"
+            })
+        );
+        let mut builder = Builder::default();
+        builder.absolute_url_rewriter(|_, _, url| Some(url.as_str().into()));
+        assert_eq!(
+            Policy::try_from(&builder),
+            Err(PolicyError::Callback {
+                option: "absolute_url_rewriter"
+            })
+        );
+    }
+}
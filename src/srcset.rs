@@ -0,0 +1,189 @@
+//! Parsing `srcset` attributes, which hold a list of image URLs.
+//!
+//! https://html.spec.whatwg.org/#parse-a-srcset-attribute
+
+use std::borrow::Cow;
+
+/// One image in a `srcset`: its URL, and descriptors like `2x` or `100w`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Candidate<'a> {
+    pub(crate) url: Cow<'a, str>,
+    pub(crate) descriptors: Vec<&'a str>,
+}
+
+/// ASCII whitespace, which separates URLs from their descriptors.
+pub(crate) fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0c' | '\r' | ' ')
+}
+
+/// Splits a `srcset` into its candidates, the same way browsers do.
+///
+/// Candidates with invalid descriptors are left out, because browsers ignore them too.
+pub(crate) fn parse(input: &str) -> Vec<Candidate<'_>> {
+    let mut candidates = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start_matches(|c| is_whitespace(c) || c == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+        let url_len = rest.find(is_whitespace).unwrap_or(rest.len());
+        let (mut url, after_url) = rest.split_at(url_len);
+        rest = after_url;
+        let mut descriptors = Vec::new();
+        if url.ends_with(',') {
+            url = url.trim_end_matches(',');
+        } else {
+            rest = rest.trim_start_matches(is_whitespace);
+            let mut start = 0;
+            let mut in_parens = false;
+            let mut end_of_candidate = rest.len();
+            for (i, c) in rest.char_indices() {
+                match c {
+                    _ if in_parens => in_parens = c != ')',
+                    '(' => in_parens = true,
+                    ',' => {
+                        end_of_candidate = i + 1;
+                        break;
+                    }
+                    _ if is_whitespace(c) => {
+                        if start < i {
+                            descriptors.push(&rest[start..i]);
+                        }
+                        start = i + 1;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            let last = &rest[start..end_of_candidate].trim_end_matches(',');
+            if !last.is_empty() {
+                descriptors.push(last);
+            }
+            rest = &rest[end_of_candidate..];
+        }
+        if valid_descriptors(&descriptors) {
+            candidates.push(Candidate {
+                url: url.into(),
+                descriptors,
+            });
+        }
+    }
+}
+
+fn valid_descriptors(descriptors: &[&str]) -> bool {
+    let is_integer = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (mut width, mut density, mut height) = (false, false, false);
+    for descriptor in descriptors {
+        let Some(value) = descriptor
+            .len()
+            .checked_sub(1)
+            .and_then(|len| descriptor.get(..len))
+        else {
+            return false;
+        };
+        match descriptor.as_bytes()[descriptor.len() - 1] {
+            b'w' if !width
+                && !density
+                && is_integer(value)
+                && !value.trim_start_matches('0').is_empty() =>
+            {
+                width = true
+            }
+            b'x' if !width
+                && !density
+                && !height
+                && value
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b))
+                && value.parse::<f64>().is_ok_and(|x| x >= 0.0) =>
+            {
+                density = true
+            }
+            b'h' if !height
+                && !density
+                && is_integer(value)
+                && !value.trim_start_matches('0').is_empty() =>
+            {
+                height = true
+            }
+            _ => return false,
+        }
+    }
+    // A height is only allowed along with a width.
+    !height || width
+}
+
+/// Writes candidates back out as a `srcset`.
+///
+/// The output parses back into the same candidates.
+pub(crate) fn serialize(candidates: &[Candidate<'_>]) -> String {
+    let mut output = String::new();
+    for candidate in candidates {
+        if !output.is_empty() {
+            output.push_str(", ");
+        }
+        output.push_str(&candidate.url);
+        for descriptor in &candidate.descriptors {
+            output.push(' ');
+            output.push_str(descriptor);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate<'a>(url: &'a str, descriptors: &[&'a str]) -> Candidate<'a> {
+        Candidate {
+            url: url.into(),
+            descriptors: descriptors.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parse_candidates() {
+        assert_eq!(
+            parse(" a.png, b.png 2x ,\n c,d.png 100w 50h, e.png 1.5x,f.png"),
+            [
+                candidate("a.png", &[]),
+                candidate("b.png", &["2x"]),
+                candidate("c,d.png", &["100w", "50h"]),
+                candidate("e.png", &["1.5x"]),
+                candidate("f.png", &[]),
+            ]
+        );
+        assert_eq!(
+            parse("a.png,,, b.png"),
+            [candidate("a.png", &[]), candidate("b.png", &[])]
+        );
+        assert_eq!(parse(",,"), []);
+        assert_eq!(
+            parse("data:image/png;base64,iVBORw0KGgo= 1x"),
+            [candidate("data:image/png;base64,iVBORw0KGgo=", &["1x"])]
+        );
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        assert_eq!(
+            parse("a 1x 2x, b 0w, c 10h, d -1x, e (x, y) 1x, f 1.5w, g 1x"),
+            [candidate("g", &["1x"])]
+        );
+        // The comma inside the parentheses doesn't end the candidate.
+        assert_eq!(parse("a (b, c 1x"), []);
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let input = "a.png,b.png 2x ,\n c,d.png 100w 50h, e.png 1.5x,f.png,g.png";
+        let output = serialize(&parse(input));
+        assert_eq!(
+            output,
+            "a.png,b.png 2x, c,d.png 100w 50h, e.png 1.5x, f.png,g.png"
+        );
+        assert_eq!(parse(&output), parse(input));
+    }
+}
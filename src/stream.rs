@@ -0,0 +1,1399 @@
+//! Sanitizing HTML as it is read, without building a DOM.
+//!
+//! [`Builder::clean`] parses the whole fragment into an `RcDom` before cleaning it. This module
+//! drives the html5ever tokenizer directly instead, and only keeps the stack of open elements.
+//! That is enough to work out which namespace each element is in, to suppress the content of
+//! `clean_content_tags`, and to check namespace switches the same way `clean_dom` does.
+//!
+//! Each start tag is turned into a one-off element node, so that all the per-element cleaning
+//! in `lib.rs` is shared with the DOM-based sanitizer.
+
+use crate::rcdom::{Node, NodeData};
+use crate::{linkify, Builder, DepthLimitAction, ElementAction};
+use html5ever::buffer_queue::BufferQueue;
+use html5ever::tendril::{format_tendril, StrTendril};
+use html5ever::tokenizer::states::RawKind;
+use html5ever::tokenizer::{
+    Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+};
+use html5ever::{local_name, ns, Attribute, LocalName, Namespace, QualName};
+use std::cell::{Cell, RefCell};
+use std::io;
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Sanitizes HTML from `src` and writes it to `dst`, one chunk at a time.
+pub(crate) fn clean_stream<R, W>(builder: &Builder<'_>, mut src: R, mut dst: W) -> io::Result<()>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    let sink = StreamSink {
+        builder,
+        link_rel: builder
+            .link_rel
+            .map(|link_rel| format_tendril!("{}", link_rel)),
+        open: RefCell::new(Vec::new()),
+        suppressed: Cell::new(0),
+        skip_newline: Cell::new(false),
+        nodes: Cell::new(0),
+        in_text: Cell::new(false),
+        remaining: Cell::new(builder.max_output_bytes.unwrap_or(usize::MAX)),
+        truncated: Cell::new(false),
+        pending_text: RefCell::new(String::new()),
+        style_sheet: RefCell::new(None),
+        output: RefCell::new(String::new()),
+    };
+    let tok = Tokenizer::new(sink, TokenizerOpts::default());
+    let input = BufferQueue::default();
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut pending = Vec::new();
+    loop {
+        let n = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buf[..n]);
+        let text = decode_utf8_lossy(&mut pending);
+        if !text.is_empty() {
+            input.push_back(StrTendril::from(text));
+            let _ = tok.feed(&input);
+            tok.sink.flush_to(&mut dst)?;
+        }
+    }
+    if !pending.is_empty() {
+        // The input ended in the middle of a character.
+        input.push_back(StrTendril::from(
+            String::from_utf8_lossy(&pending).into_owned(),
+        ));
+        let _ = tok.feed(&input);
+    }
+    tok.end();
+    tok.sink.flush_to(&mut dst)?;
+    dst.flush()
+}
+
+/// Decodes as much of `bytes` as possible, replacing invalid sequences with U+FFFD.
+///
+/// A character that's cut off at the end is left in `bytes`, so that it can be completed by
+/// the next read.
+fn decode_utf8_lossy(bytes: &mut Vec<u8>) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                rest = &[];
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).expect("checked by from_utf8"));
+                match e.error_len() {
+                    Some(len) => {
+                        text.push('\u{FFFD}');
+                        rest = &invalid[len..];
+                    }
+                    None => {
+                        rest = invalid;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    let consumed = bytes.len() - rest.len();
+    bytes.drain(..consumed);
+    text
+}
+
+/// An element that the tokenizer has seen a start tag for, but not an end tag.
+struct OpenElement {
+    /// The name the parser gave the element, which decides how its content is parsed.
+    name: QualName,
+    /// The name the start tag was written with, if it was written at all.
+    emitted: Option<QualName>,
+    /// Index of the nearest element in the stack, this one included, that was written.
+    output_parent: Option<usize>,
+    /// How many elements in the stack, this one included, were written.
+    depth: usize,
+    /// Everything inside this element is being dropped.
+    suppress: bool,
+    /// A MathML `annotation-xml` element with an HTML `encoding`.
+    html_annotation: bool,
+}
+
+impl OpenElement {
+    /// Breakout tags in foreign content close elements until they reach one of these.
+    fn is_html_or_integration_point(&self) -> bool {
+        match self.name.ns {
+            ns!(html) => true,
+            ns!(mathml) => {
+                is_mathml_text_integration_point(&self.name.local) || self.html_annotation
+            }
+            ns!(svg) => is_svg_html_integration_point(&self.name.local),
+            _ => false,
+        }
+    }
+}
+
+struct StreamSink<'b, 'a> {
+    builder: &'b Builder<'a>,
+    link_rel: Option<StrTendril>,
+    open: RefCell<Vec<OpenElement>>,
+    /// How many entries in `open` have `suppress` set.
+    suppressed: Cell<usize>,
+    /// The parser drops a newline straight after `<pre>`, `<listing>` and `<textarea>`.
+    skip_newline: Cell<bool>,
+    /// How many nodes have been written, for `max_nodes`.
+    nodes: Cell<usize>,
+    /// The last thing written was text, so more text joins the same text node.
+    in_text: Cell<bool>,
+    /// How many more bytes can be written, not counting the end tags of open elements,
+    /// which are set aside when their start tags are written.
+    remaining: Cell<usize>,
+    /// A limit was reached, so nothing else is written except end tags.
+    truncated: Cell<bool>,
+    /// Text that might still be part of a link, for `linkify`.
+    pending_text: RefCell<String>,
+    /// The content of a `<style>` element kept by `style_sheets`, which is cleaned once
+    /// the element ends.
+    style_sheet: RefCell<Option<String>>,
+    /// Sanitized HTML that hasn't been written out yet.
+    output: RefCell<String>,
+}
+
+impl StreamSink<'_, '_> {
+    fn flush_to<W: io::Write>(&self, dst: &mut W) -> io::Result<()> {
+        let mut output = self.output.borrow_mut();
+        dst.write_all(output.as_bytes())?;
+        output.clear();
+        Ok(())
+    }
+
+    /// Whether content at the current position is being dropped.
+    fn dropping(&self) -> bool {
+        self.suppressed.get() > 0 || self.truncated.get()
+    }
+
+    /// Accounts for a new node that takes up `len` bytes of the output.
+    ///
+    /// Returns `false`, and stops all further output, if that would go over a limit.
+    fn admit(&self, len: usize) -> bool {
+        let max_nodes = self.builder.max_nodes.unwrap_or(usize::MAX);
+        if self.nodes.get() >= max_nodes || len > self.remaining.get() {
+            self.truncated.set(true);
+            return false;
+        }
+        self.nodes.set(self.nodes.get() + 1);
+        self.remaining.set(self.remaining.get() - len);
+        self.in_text.set(false);
+        true
+    }
+
+    fn start_tag(&self, mut tag: Tag) -> TokenSinkResult<()> {
+        let mut open = self.open.borrow_mut();
+        if in_foreign_content(open.last(), &tag) {
+            if !is_breakout_tag(&tag) {
+                let ns = open
+                    .last()
+                    .expect("foreign content has a current node")
+                    .name
+                    .ns
+                    .clone();
+                return self.insert(&mut open, ns, tag);
+            }
+            while open
+                .last()
+                .is_some_and(|e| !e.is_html_or_integration_point())
+            {
+                self.pop(&mut open);
+            }
+        }
+        match tag.name {
+            // The fragment's context already supplies these.
+            local_name!("html")
+            | local_name!("head")
+            | local_name!("body")
+            | local_name!("frameset")
+            | local_name!("frame") => return TokenSinkResult::Continue,
+            // The tree builder ignores the parts of a table outside of one.
+            local_name!("caption")
+            | local_name!("col")
+            | local_name!("colgroup")
+            | local_name!("tbody")
+            | local_name!("td")
+            | local_name!("tfoot")
+            | local_name!("th")
+            | local_name!("thead")
+            | local_name!("tr")
+                if !has_table_in_scope(&open) =>
+            {
+                return TokenSinkResult::Continue
+            }
+            local_name!("image") => tag.name = local_name!("img"),
+            _ => {}
+        }
+        match tag.name {
+            local_name!("li") => self.close_list_item(&mut open, &["li"]),
+            local_name!("dd") | local_name!("dt") => self.close_list_item(&mut open, &["dd", "dt"]),
+            _ => {}
+        }
+        if closes_p(&tag.name) {
+            self.close_p(&mut open);
+        }
+        let ns = match tag.name {
+            local_name!("svg") => ns!(svg),
+            local_name!("math") => ns!(mathml),
+            _ => ns!(html),
+        };
+        self.insert(&mut open, ns, tag)
+    }
+
+    /// Closes an open list item before starting a new one.
+    fn close_list_item(&self, open: &mut Vec<OpenElement>, names: &[&str]) {
+        for i in (0..open.len()).rev() {
+            let name = &open[i].name;
+            if name.ns == ns!(html) && names.contains(&&*name.local) {
+                while open.len() > i {
+                    self.pop(open);
+                }
+                return;
+            }
+            if is_button_scope_boundary(name)
+                || (name.ns == ns!(html)
+                    && matches!(
+                        name.local,
+                        local_name!("ol") | local_name!("ul") | local_name!("dl")
+                    ))
+            {
+                return;
+            }
+        }
+    }
+
+    /// Closes an open `<p>`, the same way the tree builder would before starting a block.
+    ///
+    /// Returns `false` if there wasn't one.
+    fn close_p(&self, open: &mut Vec<OpenElement>) -> bool {
+        for i in (0..open.len()).rev() {
+            let name = &open[i].name;
+            if name.ns == ns!(html) && name.local == local_name!("p") {
+                while open.len() > i {
+                    self.pop(open);
+                }
+                return true;
+            }
+            if is_button_scope_boundary(name) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn insert(
+        &self,
+        open: &mut Vec<OpenElement>,
+        ns: Namespace,
+        mut tag: Tag,
+    ) -> TokenSinkResult<()> {
+        match ns {
+            ns!(svg) => {
+                if let Some(name) = adjust_svg_tag_name(&tag.name) {
+                    tag.name = LocalName::from(name);
+                }
+                adjust_attributes(&mut tag, adjust_svg_attribute);
+                adjust_foreign_attributes(&mut tag);
+            }
+            ns!(mathml) => {
+                adjust_attributes(&mut tag, |name| {
+                    (name == "definitionurl").then_some("definitionURL")
+                });
+                adjust_foreign_attributes(&mut tag);
+            }
+            _ => {}
+        }
+        let name = QualName::new(None, ns, tag.name);
+        let html_annotation = name.ns == ns!(mathml)
+            && name.local == local_name!("annotation-xml")
+            && tag.attrs.iter().any(|attr| {
+                attr.name.local == local_name!("encoding")
+                    && (attr.value.eq_ignore_ascii_case("text/html")
+                        || attr.value.eq_ignore_ascii_case("application/xhtml+xml"))
+            });
+        let (void, result) = if name.ns == ns!(html) {
+            (is_void(&name.local), tokenizer_state(&name.local))
+        } else {
+            (tag.self_closing, TokenSinkResult::Continue)
+        };
+        if name.ns == ns!(html)
+            && matches!(
+                name.local,
+                local_name!("pre") | local_name!("listing") | local_name!("textarea")
+            )
+        {
+            self.skip_newline.set(true);
+        }
+        let parent_output = open.last().and_then(|e| e.output_parent);
+        let parent_depth = open.last().map_or(0, |e| e.depth);
+        let (emitted, mut suppress) = if self.dropping() {
+            (None, false)
+        } else {
+            self.clean_start_tag(open, name.clone(), tag.attrs, html_annotation, void)
+        };
+        if emitted.as_ref().is_some_and(is_void_element) && !void {
+            // The element was renamed to a void element, so the serializer would drop
+            // its content.
+            suppress = true;
+        }
+        if emitted.is_some()
+            && name.ns == ns!(html)
+            && name.local == local_name!("selectedcontent")
+            && open
+                .iter()
+                .any(|e| e.name.ns == ns!(html) && e.name.local == local_name!("select"))
+        {
+            // Same as `clean_dom`: the browser fills this in by copying the selected option.
+            suppress = true;
+        }
+        if void {
+            if let Some(emitted) = emitted {
+                write_end_tag(&mut self.output.borrow_mut(), &emitted);
+            }
+            return result;
+        }
+        if emitted
+            .as_ref()
+            .is_some_and(|name| self.builder.keeps_style_sheet(name))
+        {
+            *self.style_sheet.borrow_mut() = Some(String::new());
+        }
+        if suppress {
+            self.suppressed.set(self.suppressed.get() + 1);
+        }
+        let output_parent = if emitted.is_some() {
+            Some(open.len())
+        } else {
+            parent_output
+        };
+        let depth = parent_depth + usize::from(emitted.is_some());
+        open.push(OpenElement {
+            name,
+            emitted,
+            output_parent,
+            depth,
+            suppress,
+            html_annotation,
+        });
+        result
+    }
+
+    /// Cleans an element and writes its start tag if it's allowed.
+    ///
+    /// Returns the name the start tag was written with, and whether the element's content
+    /// should be dropped.
+    fn clean_start_tag(
+        &self,
+        open: &[OpenElement],
+        name: QualName,
+        attrs: Vec<Attribute>,
+        html_annotation: bool,
+        void: bool,
+    ) -> (Option<QualName>, bool) {
+        let builder = self.builder;
+        let mut node = Node::new(NodeData::Element {
+            name,
+            attrs: RefCell::new(attrs),
+            template_contents: RefCell::new(None),
+            mathml_annotation_xml_integration_point: html_annotation,
+        });
+        if let Some(renamed) = builder.rename_node(&node) {
+            node = renamed;
+        }
+        if builder.clean_node_content(&node) {
+            return (None, true);
+        }
+        let NodeData::Element { ref name, .. } = node.data else {
+            unreachable!("the node was created as an element");
+        };
+        let root = QualName::new(None, ns!(html), local_name!("html"));
+        let parent = match open.last().and_then(|e| e.output_parent) {
+            Some(i) => open[i]
+                .emitted
+                .as_ref()
+                .expect("output_parent is always written"),
+            None => &root,
+        };
+        if !builder.check_expected_namespace_name(parent, name) {
+            return (None, true);
+        }
+        match builder.clean_child(&mut node, &mut None) {
+            ElementAction::Keep => {}
+            ElementAction::Unwrap => return (None, false),
+            ElementAction::Remove => return (None, !void),
+        }
+        builder.adjust_node_attributes(&mut node, &self.link_rel, builder.id_prefix, &mut None);
+        if !builder.embed_iframe(&node, &mut None) {
+            return (None, !void);
+        }
+        match builder.filter_element(&node) {
+            ElementAction::Keep => {}
+            ElementAction::Unwrap => return (None, false),
+            ElementAction::Remove => return (None, !void),
+        }
+        if builder
+            .max_depth
+            .is_some_and(|max_depth| open.last().map_or(0, |e| e.depth) >= max_depth)
+        {
+            match builder.depth_limit_action {
+                DepthLimitAction::Unwrap => return (None, false),
+                DepthLimitAction::Remove => return (None, !void),
+            }
+        }
+        let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = node.data
+        else {
+            unreachable!("the node was created as an element");
+        };
+        let mut start = String::new();
+        write_start_tag(&mut start, name, &attrs.borrow());
+        if !self.admit(start.len() + end_tag_len(name)) {
+            return (None, false);
+        }
+        self.output.borrow_mut().push_str(&start);
+        (Some(name.clone()), false)
+    }
+
+    fn end_tag(&self, mut tag: Tag) -> TokenSinkResult<()> {
+        let mut open = self.open.borrow_mut();
+        if matches!(tag.name, local_name!("p") | local_name!("br")) {
+            // These end foreign content, just like breakout start tags.
+            while open
+                .last()
+                .is_some_and(|e| !e.is_html_or_integration_point())
+            {
+                self.pop(&mut open);
+            }
+            if tag.name == local_name!("br") {
+                // The parser treats `</br>` as `<br>`.
+                tag.kind = TagKind::StartTag;
+                tag.attrs.clear();
+                return self.insert(&mut open, ns!(html), tag);
+            }
+            if !self.close_p(&mut open) {
+                // A stray `</p>` creates an empty paragraph.
+                tag.kind = TagKind::StartTag;
+                let _ = self.insert(&mut open, ns!(html), tag);
+                self.pop(&mut open);
+            }
+            return TokenSinkResult::Continue;
+        }
+        let matching = open.iter().rposition(|e| {
+            if e.name.ns == ns!(html) {
+                e.name.local == tag.name
+            } else {
+                e.name.local.eq_ignore_ascii_case(&tag.name)
+            }
+        });
+        if let Some(i) = matching {
+            while open.len() > i {
+                self.pop(&mut open);
+            }
+        }
+        TokenSinkResult::Continue
+    }
+
+    fn pop(&self, open: &mut Vec<OpenElement>) {
+        let element = open
+            .pop()
+            .expect("only called when there are open elements");
+        if element.suppress {
+            self.suppressed.set(self.suppressed.get() - 1);
+        }
+        if let Some(emitted) = element.emitted {
+            if let Some(css) = self.style_sheet.take() {
+                self.write_text_as(&self.builder.clean_style_sheet(&css, &mut None), true);
+            }
+            write_end_tag(&mut self.output.borrow_mut(), &emitted);
+            self.in_text.set(false);
+        }
+    }
+
+    fn text(&self, text: &str) {
+        if text.is_empty() || self.dropping() {
+            return;
+        }
+        if let Some(ref mut css) = *self.style_sheet.borrow_mut() {
+            css.push_str(text);
+            return;
+        }
+        let linkify = self.builder.linkify
+            && self
+                .open
+                .borrow()
+                .iter()
+                .all(|e| linkify::linkify_inside(&e.name));
+        if !linkify {
+            self.write_text(text);
+            return;
+        }
+        let mut pending = self.pending_text.borrow_mut();
+        pending.push_str(text);
+        // Links can't contain whitespace, so everything up to the last whitespace is ready.
+        if let Some((i, c)) = pending
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+        {
+            let ready: String = pending.drain(..i + c.len_utf8()).collect();
+            drop(pending);
+            self.linkify_text(&ready);
+        }
+    }
+
+    /// Writes out text held back by `linkify`; called before anything that ends a text node.
+    fn flush_text(&self) {
+        let text = std::mem::take(&mut *self.pending_text.borrow_mut());
+        if !text.is_empty() {
+            self.linkify_text(&text);
+        }
+    }
+
+    /// Writes text with its URLs turned into `<a href>` elements, the same way as `clean_dom`.
+    fn linkify_text(&self, text: &str) {
+        let mut start = 0;
+        for link in linkify::find_links(text, &self.builder.url_schemes) {
+            self.write_text(&text[start..link.range.start]);
+            let a = Tag {
+                kind: TagKind::StartTag,
+                name: local_name!("a"),
+                self_closing: false,
+                attrs: vec![Attribute {
+                    name: QualName::new(None, ns!(), local_name!("href")),
+                    value: link.href.into(),
+                }],
+            };
+            let _ = self.insert(&mut self.open.borrow_mut(), ns!(html), a);
+            self.write_text(&text[link.range.clone()]);
+            self.pop(&mut self.open.borrow_mut());
+            start = link.range.end;
+        }
+        self.write_text(&text[start..]);
+    }
+
+    fn write_text(&self, text: &str) {
+        if text.is_empty() || self.dropping() {
+            return;
+        }
+        let open = self.open.borrow();
+        let raw = open
+            .last()
+            .and_then(|e| e.output_parent)
+            .and_then(|i| open[i].emitted.as_ref())
+            .is_some_and(|name| name.ns == ns!(html) && is_raw_text(&name.local));
+        drop(open);
+        self.write_text_as(text, raw);
+    }
+
+    /// Writes text, which is only escaped if it's not `raw`.
+    fn write_text_as(&self, text: &str, raw: bool) {
+        if text.is_empty() || self.dropping() {
+            return;
+        }
+        if !self.in_text.get() {
+            if !self.admit(0) {
+                return;
+            }
+            self.in_text.set(true);
+        }
+        let (cut, len) = fit_text(text, raw, self.remaining.get());
+        self.remaining.set(self.remaining.get() - len);
+        if cut < text.len() {
+            self.truncated.set(true);
+        }
+        let mut output = self.output.borrow_mut();
+        if raw {
+            output.push_str(&text[..cut]);
+        } else {
+            write_escaped(&mut output, &text[..cut], false);
+        }
+    }
+
+    fn comment(&self, text: &str) {
+        if self.builder.strip_comments
+            || self.dropping()
+            || !self.admit("<!---->".len() + text.len())
+        {
+            return;
+        }
+        let mut output = self.output.borrow_mut();
+        output.push_str("<!--");
+        output.push_str(text);
+        output.push_str("-->");
+    }
+}
+
+impl TokenSink for StreamSink<'_, '_> {
+    type Handle = ();
+
+    fn process_token(&self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        let skip_newline = self.skip_newline.replace(false);
+        if matches!(
+            token,
+            Token::TagToken(_) | Token::CommentToken(_) | Token::EOFToken
+        ) {
+            self.flush_text();
+        }
+        match token {
+            Token::TagToken(tag) => match tag.kind {
+                TagKind::StartTag => return self.start_tag(tag),
+                TagKind::EndTag => return self.end_tag(tag),
+            },
+            Token::CharacterTokens(text) => {
+                if skip_newline && text.starts_with('\n') {
+                    self.text(&text[1..]);
+                } else {
+                    self.text(&text);
+                }
+            }
+            Token::NullCharacterToken => {
+                // Dropped in HTML content, but replaced in foreign content.
+                if self.adjusted_current_node_present_but_not_in_html_namespace() {
+                    self.text("\u{FFFD}");
+                }
+            }
+            Token::CommentToken(text) => self.comment(&text),
+            Token::EOFToken => {
+                let mut open = self.open.borrow_mut();
+                while !open.is_empty() {
+                    self.pop(&mut open);
+                }
+            }
+            Token::DoctypeToken(_) | Token::ParseError(_) => {}
+        }
+        TokenSinkResult::Continue
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.open
+            .borrow()
+            .last()
+            .is_some_and(|e| e.name.ns != ns!(html))
+    }
+}
+
+/// Whether a start tag is handled with the rules for foreign content.
+///
+/// https://html.spec.whatwg.org/#tree-construction-dispatcher
+fn in_foreign_content(current: Option<&OpenElement>, tag: &Tag) -> bool {
+    let Some(current) = current else {
+        return false;
+    };
+    match current.name.ns {
+        ns!(html) => false,
+        ns!(mathml) if is_mathml_text_integration_point(&current.name.local) => {
+            matches!(tag.name, local_name!("mglyph") | local_name!("malignmark"))
+        }
+        ns!(mathml) if current.name.local == local_name!("annotation-xml") => {
+            tag.name != local_name!("svg") && !current.html_annotation
+        }
+        ns!(svg) if is_svg_html_integration_point(&current.name.local) => false,
+        _ => true,
+    }
+}
+
+/// Start tags that end foreign content.
+///
+/// https://html.spec.whatwg.org/#parsing-main-inforeign
+fn is_breakout_tag(tag: &Tag) -> bool {
+    match &*tag.name {
+        "b" | "big" | "blockquote" | "body" | "br" | "center" | "code" | "dd" | "div" | "dl"
+        | "dt" | "em" | "embed" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "head" | "hr" | "i"
+        | "img" | "li" | "listing" | "menu" | "meta" | "nobr" | "ol" | "p" | "pre" | "ruby"
+        | "s" | "small" | "span" | "strong" | "strike" | "sub" | "sup" | "table" | "tt" | "u"
+        | "ul" | "var" => true,
+        "font" => tag
+            .attrs
+            .iter()
+            .any(|attr| matches!(&*attr.name.local, "color" | "face" | "size")),
+        _ => false,
+    }
+}
+
+fn is_mathml_text_integration_point(name: &str) -> bool {
+    matches!(name, "mi" | "mo" | "mn" | "ms" | "mtext")
+}
+
+fn is_svg_html_integration_point(name: &str) -> bool {
+    matches!(name, "foreignObject" | "desc" | "title")
+}
+
+/// Elements that stop the search for an open `<p>`.
+///
+/// https://html.spec.whatwg.org/#has-an-element-in-button-scope
+fn is_button_scope_boundary(name: &QualName) -> bool {
+    match name.ns {
+        ns!(html) => matches!(
+            &*name.local,
+            "applet"
+                | "button"
+                | "caption"
+                | "html"
+                | "marquee"
+                | "object"
+                | "table"
+                | "td"
+                | "template"
+                | "th"
+        ),
+        ns!(mathml) => {
+            is_mathml_text_integration_point(&name.local) || &*name.local == "annotation-xml"
+        }
+        ns!(svg) => is_svg_html_integration_point(&name.local),
+        _ => false,
+    }
+}
+
+/// Whether there's an open `<table>` that the parts of a table can go in.
+///
+/// Foreign elements are treated as boundaries, so table parts inside of them are dropped,
+/// even where the tree builder would close the foreign content instead.
+///
+/// https://html.spec.whatwg.org/#has-an-element-in-table-scope
+fn has_table_in_scope(open: &[OpenElement]) -> bool {
+    for e in open.iter().rev() {
+        if e.name.ns != ns!(html) {
+            return false;
+        }
+        match e.name.local {
+            local_name!("table") => return true,
+            local_name!("html") | local_name!("template") => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Start tags that close an open `<p>`.
+fn closes_p(name: &str) -> bool {
+    matches!(
+        name,
+        "address"
+            | "article"
+            | "aside"
+            | "blockquote"
+            | "center"
+            | "details"
+            | "dialog"
+            | "dir"
+            | "div"
+            | "dl"
+            | "fieldset"
+            | "figcaption"
+            | "figure"
+            | "footer"
+            | "form"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "header"
+            | "hgroup"
+            | "hr"
+            | "listing"
+            | "main"
+            | "menu"
+            | "nav"
+            | "ol"
+            | "p"
+            | "plaintext"
+            | "pre"
+            | "search"
+            | "section"
+            | "summary"
+            | "table"
+            | "ul"
+            | "xmp"
+    )
+}
+
+/// HTML elements that never have an end tag or content.
+pub(crate) fn is_void(name: &str) -> bool {
+    matches!(
+        name,
+        "area"
+            | "base"
+            | "basefont"
+            | "bgsound"
+            | "br"
+            | "col"
+            | "embed"
+            | "frame"
+            | "hr"
+            | "img"
+            | "input"
+            | "keygen"
+            | "link"
+            | "meta"
+            | "param"
+            | "source"
+            | "track"
+            | "wbr"
+    )
+}
+
+fn is_void_element(name: &QualName) -> bool {
+    name.ns == ns!(html) && is_void(&name.local)
+}
+
+/// HTML elements whose text is written without escaping, like the html5ever serializer does.
+pub(crate) fn is_raw_text(name: &str) -> bool {
+    matches!(
+        name,
+        "style" | "script" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" | "plaintext"
+    )
+}
+
+/// The tokenizer state that the tree builder switches to after an HTML start tag.
+fn tokenizer_state(name: &str) -> TokenSinkResult<()> {
+    match name {
+        "script" => TokenSinkResult::RawData(RawKind::ScriptData),
+        "style" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" => {
+            TokenSinkResult::RawData(RawKind::Rawtext)
+        }
+        "title" | "textarea" => TokenSinkResult::RawData(RawKind::Rcdata),
+        "plaintext" => TokenSinkResult::Plaintext,
+        _ => TokenSinkResult::Continue,
+    }
+}
+
+fn adjust_attributes(tag: &mut Tag, adjust: impl Fn(&str) -> Option<&'static str>) {
+    for attr in &mut tag.attrs {
+        if let Some(local) = adjust(&attr.name.local) {
+            attr.name = QualName::new(None, ns!(), LocalName::from(local));
+        }
+    }
+}
+
+fn adjust_foreign_attributes(tag: &mut Tag) {
+    for attr in &mut tag.attrs {
+        let (prefix, ns, local) = match &*attr.name.local {
+            "xlink:actuate" => ("xlink", ns!(xlink), "actuate"),
+            "xlink:arcrole" => ("xlink", ns!(xlink), "arcrole"),
+            "xlink:href" => ("xlink", ns!(xlink), "href"),
+            "xlink:role" => ("xlink", ns!(xlink), "role"),
+            "xlink:show" => ("xlink", ns!(xlink), "show"),
+            "xlink:title" => ("xlink", ns!(xlink), "title"),
+            "xlink:type" => ("xlink", ns!(xlink), "type"),
+            "xml:lang" => ("xml", ns!(xml), "lang"),
+            "xml:space" => ("xml", ns!(xml), "space"),
+            "xmlns" => {
+                attr.name = QualName::new(None, ns!(xmlns), local_name!("xmlns"));
+                continue;
+            }
+            "xmlns:xlink" => ("xmlns", ns!(xmlns), "xlink"),
+            _ => continue,
+        };
+        attr.name = QualName::new(Some(prefix.into()), ns, LocalName::from(local));
+    }
+}
+
+/// https://html.spec.whatwg.org/#parsing-main-inforeign
+fn adjust_svg_tag_name(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "altglyph" => "altGlyph",
+        "altglyphdef" => "altGlyphDef",
+        "altglyphitem" => "altGlyphItem",
+        "animatecolor" => "animateColor",
+        "animatemotion" => "animateMotion",
+        "animatetransform" => "animateTransform",
+        "clippath" => "clipPath",
+        "feblend" => "feBlend",
+        "fecolormatrix" => "feColorMatrix",
+        "fecomponenttransfer" => "feComponentTransfer",
+        "fecomposite" => "feComposite",
+        "feconvolvematrix" => "feConvolveMatrix",
+        "fediffuselighting" => "feDiffuseLighting",
+        "fedisplacementmap" => "feDisplacementMap",
+        "fedistantlight" => "feDistantLight",
+        "fedropshadow" => "feDropShadow",
+        "feflood" => "feFlood",
+        "fefunca" => "feFuncA",
+        "fefuncb" => "feFuncB",
+        "fefuncg" => "feFuncG",
+        "fefuncr" => "feFuncR",
+        "fegaussianblur" => "feGaussianBlur",
+        "feimage" => "feImage",
+        "femerge" => "feMerge",
+        "femergenode" => "feMergeNode",
+        "femorphology" => "feMorphology",
+        "feoffset" => "feOffset",
+        "fepointlight" => "fePointLight",
+        "fespecularlighting" => "feSpecularLighting",
+        "fespotlight" => "feSpotLight",
+        "fetile" => "feTile",
+        "feturbulence" => "feTurbulence",
+        "foreignobject" => "foreignObject",
+        "glyphref" => "glyphRef",
+        "lineargradient" => "linearGradient",
+        "radialgradient" => "radialGradient",
+        "textpath" => "textPath",
+        _ => return None,
+    })
+}
+
+/// https://html.spec.whatwg.org/#adjust-svg-attributes
+fn adjust_svg_attribute(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "attributename" => "attributeName",
+        "attributetype" => "attributeType",
+        "basefrequency" => "baseFrequency",
+        "baseprofile" => "baseProfile",
+        "calcmode" => "calcMode",
+        "clippathunits" => "clipPathUnits",
+        "diffuseconstant" => "diffuseConstant",
+        "edgemode" => "edgeMode",
+        "filterunits" => "filterUnits",
+        "glyphref" => "glyphRef",
+        "gradienttransform" => "gradientTransform",
+        "gradientunits" => "gradientUnits",
+        "kernelmatrix" => "kernelMatrix",
+        "kernelunitlength" => "kernelUnitLength",
+        "keypoints" => "keyPoints",
+        "keysplines" => "keySplines",
+        "keytimes" => "keyTimes",
+        "lengthadjust" => "lengthAdjust",
+        "limitingconeangle" => "limitingConeAngle",
+        "markerheight" => "markerHeight",
+        "markerunits" => "markerUnits",
+        "markerwidth" => "markerWidth",
+        "maskcontentunits" => "maskContentUnits",
+        "maskunits" => "maskUnits",
+        "numoctaves" => "numOctaves",
+        "pathlength" => "pathLength",
+        "patterncontentunits" => "patternContentUnits",
+        "patterntransform" => "patternTransform",
+        "patternunits" => "patternUnits",
+        "pointsatx" => "pointsAtX",
+        "pointsaty" => "pointsAtY",
+        "pointsatz" => "pointsAtZ",
+        "preservealpha" => "preserveAlpha",
+        "preserveaspectratio" => "preserveAspectRatio",
+        "primitiveunits" => "primitiveUnits",
+        "refx" => "refX",
+        "refy" => "refY",
+        "repeatcount" => "repeatCount",
+        "repeatdur" => "repeatDur",
+        "requiredextensions" => "requiredExtensions",
+        "requiredfeatures" => "requiredFeatures",
+        "specularconstant" => "specularConstant",
+        "specularexponent" => "specularExponent",
+        "spreadmethod" => "spreadMethod",
+        "startoffset" => "startOffset",
+        "stddeviation" => "stdDeviation",
+        "stitchtiles" => "stitchTiles",
+        "surfacescale" => "surfaceScale",
+        "systemlanguage" => "systemLanguage",
+        "tablevalues" => "tableValues",
+        "targetx" => "targetX",
+        "targety" => "targetY",
+        "textlength" => "textLength",
+        "viewbox" => "viewBox",
+        "viewtarget" => "viewTarget",
+        "xchannelselector" => "xChannelSelector",
+        "ychannelselector" => "yChannelSelector",
+        "zoomandpan" => "zoomAndPan",
+        _ => return None,
+    })
+}
+
+/// Writes a start tag the same way the html5ever serializer does.
+pub(crate) fn write_start_tag(output: &mut String, name: &QualName, attrs: &[Attribute]) {
+    output.push('<');
+    output.push_str(&name.local);
+    for attr in attrs {
+        output.push(' ');
+        match attr.name.ns {
+            ns!() => {}
+            ns!(xml) => output.push_str("xml:"),
+            ns!(xmlns) => {
+                if attr.name.local != local_name!("xmlns") {
+                    output.push_str("xmlns:");
+                }
+            }
+            ns!(xlink) => output.push_str("xlink:"),
+            _ => output.push_str("unknown_namespace:"),
+        }
+        output.push_str(&attr.name.local);
+        output.push_str("=\"");
+        write_escaped(output, &attr.value, true);
+        output.push('"');
+    }
+    output.push('>');
+}
+
+fn write_end_tag(output: &mut String, name: &QualName) {
+    if !is_void_element(name) {
+        output.push_str("</");
+        output.push_str(&name.local);
+        output.push('>');
+    }
+}
+
+pub(crate) fn end_tag_len(name: &QualName) -> usize {
+    if is_void_element(name) {
+        0
+    } else {
+        "</>".len() + name.local.len()
+    }
+}
+
+/// Finds how much of `text` fits in `budget` bytes once it is serialized.
+///
+/// Returns the number of bytes of `text` that fit, which is always at a character
+/// boundary, and the number of bytes they serialize to.
+pub(crate) fn fit_text(text: &str, raw: bool, budget: usize) -> (usize, usize) {
+    let mut len = 0;
+    for (i, c) in text.char_indices() {
+        let escaped = match c {
+            _ if raw => c.len_utf8(),
+            '&' => "&amp;".len(),
+            '\u{00A0}' => "&nbsp;".len(),
+            '<' => "&lt;".len(),
+            '>' => "&gt;".len(),
+            c => c.len_utf8(),
+        };
+        if len + escaped > budget {
+            return (i, len);
+        }
+        len += escaped;
+    }
+    (text.len(), len)
+}
+
+fn write_escaped(output: &mut String, text: &str, attr_mode: bool) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '\u{00A0}' => output.push_str("&nbsp;"),
+            '"' if attr_mode => output.push_str("&quot;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            c => output.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ContentSecurityPolicy, CspViolationAction, IframeProvider, StyleSheetPolicy, UrlRelative};
+
+    fn stream(builder: &Builder<'_>, src: &str) -> String {
+        let mut output = Vec::new();
+        clean_stream(builder, src.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn same_as_clean() {
+        let builder = Builder::default();
+        for input in [
+            "a <b onclick=x>b</b> <i>c<script>d</script></i>",
+            "<a href=\"javascript:x\">e</a><a href=\"https://example.com/\">f</a>",
+            "<!-- comment --><p>g&amp;h &lt; \u{a0}</p><img src=x>",
+            "<pre>\nline</pre><textarea>\ntext</textarea>",
+            "<svg><circle r=1></circle></svg><math><mi>x</mi></math>",
+            "<style>p { color: red }</style><xmp><b></xmp>",
+            "<table><tbody><tr><td>1</td></tr></tbody></table>",
+            "<p>a<ul><li>b<li>c</ul><dl><dt>d<dd>e<dt>f</dl>",
+            "<select><selectedcontent><b>x</b></selectedcontent></select>",
+            "<tr><td>a</td></tr><caption>b<col><frame>c",
+        ] {
+            assert_eq!(
+                stream(&builder, input),
+                builder.clean(input).to_string(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn closes_open_elements() {
+        let builder = Builder::default();
+        assert_eq!(stream(&builder, "<b><i>x"), "<b><i>x</i></b>");
+        assert_eq!(stream(&builder, "<b>x</i>y</b></b>"), "<b>xy</b>");
+        assert_eq!(stream(&builder, "<p>a<p>b"), "<p>a</p><p>b</p>");
+    }
+
+    #[test]
+    fn raw_text_kept_only_in_raw_text_elements() {
+        let mut builder = Builder::default();
+        builder
+            .rm_clean_content_tags(&["style"])
+            .add_tags(&["style"]);
+        assert_eq!(
+            stream(&builder, "<style>a > b { }</style><xmp><b>c</b></xmp>"),
+            "<style>a > b { }</style>&lt;b&gt;c&lt;/b&gt;"
+        );
+    }
+
+    #[test]
+    fn namespace_confusion() {
+        let mut builder = Builder::default();
+        builder
+            .add_tags(&["svg", "math", "mtext", "mglyph", "style"])
+            .rm_clean_content_tags(&["style"]);
+        for input in [
+            "<svg></p><style><a id=\"</style><img src=1 onerror=alert(1)>\">",
+            "<svg><foreignObject><style><img src=1 onerror=alert(1)></style></foreignObject></svg>",
+            "<math><mtext><table><mglyph><style><img src=x onerror=alert(1)></style>",
+            "<svg><p><style><img src=1 onerror=alert(1)></style></p></svg>",
+        ] {
+            assert_eq!(
+                stream(&builder, input),
+                builder.clean(input).to_string(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn foreign_names_adjusted() {
+        let mut builder = Builder::default();
+        builder
+            .add_tags(&["svg", "linearGradient"])
+            .add_tag_attributes("svg", &["viewBox"])
+            .add_tag_attributes("linearGradient", &["href"]);
+        assert_eq!(
+            stream(
+                &builder,
+                "<svg viewbox=\"0 0 1 1\"><lineargradient xlink:href=\"javascript:x\"/></svg>"
+            ),
+            "<svg viewBox=\"0 0 1 1\"><linearGradient></linearGradient></svg>"
+        );
+    }
+
+    #[test]
+    fn limits_same_as_clean() {
+        let input =
+            "<p>one &amp; <b title='\"'>two</b><br>three\u{a0}</p><ul><li><i>four</i></li></ul>";
+        let mut builder = Builder::default();
+        let full = builder.clean(input).to_string();
+        for max in 0..=full.len() {
+            builder.max_output_bytes(Some(max));
+            assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+        }
+        builder.max_output_bytes(None).max_attributes(Some(0));
+        assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+        for max in 0..8 {
+            builder.max_nodes(Some(max));
+            assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+        }
+        builder.max_nodes(None);
+        for action in [DepthLimitAction::Unwrap, DepthLimitAction::Remove] {
+            for max in 0..3 {
+                builder.max_depth(Some(max)).depth_limit_action(action);
+                assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn content_security_policy_same_as_clean() {
+        let input = "<p>a<img src=https://cdn.example/a.png>b<img src=/b.png srcset='/c.png 2x'>\
+                     <video poster=https://cdn.example/p.png><source src=https://example.com/v.webm>\
+                     fallback</video></p><img src=x>";
+        let mut builder = Builder::default();
+        builder
+            .add_tags(&["video", "source"])
+            .add_tag_attributes("img", &["srcset"])
+            .add_tag_attributes("video", &["poster"])
+            .content_security_policy(Some(
+                ContentSecurityPolicy::parse("img-src https://cdn.example; media-src 'self'")
+                    .unwrap(),
+            ));
+        for action in [
+            CspViolationAction::RemoveAttribute,
+            CspViolationAction::RemoveElement,
+        ] {
+            builder.csp_violation_action(action);
+            assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+        }
+    }
+
+    #[test]
+    fn iframes_same_as_clean() {
+        let input = "<p><iframe src=https://www.youtube.com/embed/x srcdoc=x sandbox>a<b>c</b></iframe>\
+                     <iframe src=https://example.com/>d</iframe><svg><iframe>e</iframe></svg></p>";
+        let mut builder = Builder::default();
+        builder.allow_iframes([IframeProvider::youtube()]);
+        assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+    }
+
+    /// Pieces of markup that the tree builder treats specially, for the differential test.
+    const PIECES: &[&str] = &[
+        "<svg>", "</svg>", "<math>", "</math>", "<mtext>", "<mi>", "<mglyph>", "<malignmark>",
+        "<annotation-xml encoding=text/html>", "<foreignObject>", "<desc>", "<title>", "</title>",
+        "<style>", "</style>", "<script>", "</script>", "<xmp>", "</xmp>", "<noscript>",
+        "</noscript>", "<template>", "</template>", "<textarea>", "</textarea>", "<iframe>",
+        "</iframe>", "<noembed>", "<plaintext>", "<table>", "</table>", "<tr>", "<td>", "<select>",
+        "</select>", "<option>", "<p>", "</p>", "<a href=x>", "</a>", "<b>", "</b>", "<i>", "<br>",
+        "<form>", "<li>", "<font color=red>", "<img src=x onerror=alert(1)>", "<!--", "-->",
+        "<!-- x -->", "</", "<", ">", "\"", "'", "&lt;", "&amp;", "x", " ", "\n", "\0",
+        "<a id=\"</style><img src=x onerror=alert(1)>\">", "<mo>", "<ms>", "<caption>",
+        "<colgroup>", "<col>", "<tbody>", "<th>", "<button>", "<object>", "<listing>", "<pre>",
+        "<noframes>", "<frameset>", "<body>", "<image src=x>", "<font face=x>", "<dd>", "<ul>",
+        "<selectedcontent>", "<h1>", "<div>", "</div>", "</td>", "</tr>", "</br>",
+    ];
+
+    /// Generates inputs from `PIECES` with a small xorshift generator, so that failures
+    /// can be reproduced.
+    fn generated_inputs(count: usize) -> impl Iterator<Item = String> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..count).map(move |_| {
+            let len = 1 + next() % 12;
+            (0..len)
+                .map(|_| PIECES[(next() % PIECES.len() as u64) as usize])
+                .collect()
+        })
+    }
+
+    /// The stream output doesn't always match what `clean` returns, because markup that the
+    /// tree builder would rearrange is written out in the order it was found. It must still be
+    /// as safe, though: whenever parsing the output of `clean` again and cleaning it with the
+    /// same options doesn't remove anything, the same must hold for the stream output.
+    #[test]
+    fn differential_against_clean() {
+        let mut foreign = Builder::default();
+        foreign
+            .add_tags(&["svg", "math", "mtext", "mi", "mglyph", "malignmark", "style", "title"])
+            .add_tags(&["annotation-xml", "foreignObject", "desc", "form", "select", "option"])
+            .rm_clean_content_tags(&["style"])
+            .add_tag_attributes("a", &["id"])
+            .add_tag_attributes("annotation-xml", &["encoding"])
+            .link_rel(None)
+            .strip_comments(false);
+        let mut default = Builder::default();
+        default.link_rel(None);
+        for builder in [&default, &foreign] {
+            for input in generated_inputs(5000) {
+                let cleaned = builder.clean(&input).to_string();
+                if !builder.clean_with_report(&cleaned).1.is_empty() {
+                    continue;
+                }
+                let output = stream(builder, &input);
+                let (_, report) = builder.clean_with_report(&output);
+                assert!(
+                    report.is_empty(),
+                    "input: {input:?}\noutput: {output:?}\nclean: {cleaned:?}\n{report:?}",
+                );
+            }
+        }
+    }
+
+    struct Trickle<'a>(&'a [u8]);
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn linkify_same_as_clean() {
+        let input = "<p>see https://example.com/a&amp;b, www.example.org. or \
+                     <b>me@example.com</b>!</p><a href=x>http://example.net</a>\
+                     <code>https://example.com/</code><svg><text>www.example.com</text></svg>\
+                     javascript://x http://example.com/\0x ftp://example.com/";
+        let mut builder = Builder::default();
+        builder.linkify(true);
+        let expected = builder.clean(input).to_string();
+        assert_eq!(stream(&builder, input), expected);
+        let mut output = Vec::new();
+        clean_stream(&builder, Trickle(input.as_bytes()), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        for max in 0..12 {
+            builder.max_nodes(Some(max));
+            assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+        }
+        builder.max_nodes(None).max_output_bytes(Some(80));
+        assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+    }
+
+    #[test]
+    fn absolute_url_rewriter_same_as_clean() {
+        let input = "<a href=https://example.com/>a</a><a href=/b>b</a> www.example.org \
+                     <img srcset='https://example.com/1.png 1x, /2.png 2x'>";
+        let mut builder = Builder::default();
+        builder
+            .add_tag_attributes("img", &["srcset"])
+            .linkify(true)
+            .absolute_url_rewriter(|_, _, url| Some(format!("https://out.example.net/{url}").into()));
+        assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+    }
+
+    #[test]
+    fn style_sheets_same_as_clean() {
+        let input = "<p>a<style>p.x, a[href] { color: red; position: fixed } \
+                     @media print { p { background: url(/b.png) } }</style><style></style>\
+                     <style>@import url(x.css)</style><svg><style>p {}</style></svg>";
+        let mut builder = Builder::default();
+        builder
+            .style_sheets(Some(
+                StyleSheetPolicy::new()
+                    .selectors(["p", ".x"])
+                    .scope(Some(".post"))
+                    .at_rules(["media"]),
+            ))
+            .filter_style_properties(["color", "background"].into())
+            .url_relative(UrlRelative::Deny);
+        assert_eq!(stream(&builder, input), builder.clean(input).to_string());
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        let builder = Builder::default();
+        let input =
+            "<b title='\u{e9}'>caf\u{e9} &amp; <!-- x --><script>y</script></b>&nbsp;\u{1F600}";
+        let mut output = Vec::new();
+        clean_stream(&builder, Trickle(input.as_bytes()), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            builder.clean(input).to_string()
+        );
+    }
+
+    #[test]
+    fn split_utf8() {
+        let input = "<b>\u{1F600}é</b>".as_bytes();
+        let mut bytes = input[..5].to_vec();
+        assert_eq!(decode_utf8_lossy(&mut bytes), "<b>");
+        assert_eq!(bytes, &input[3..5]);
+        bytes.extend_from_slice(&input[5..]);
+        assert_eq!(decode_utf8_lossy(&mut bytes), "\u{1F600}é</b>");
+        assert!(bytes.is_empty());
+        let mut bytes = b"a\xffb".to_vec();
+        assert_eq!(decode_utf8_lossy(&mut bytes), "a\u{FFFD}b");
+    }
+}
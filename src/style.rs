@@ -30,40 +30,317 @@
 //! > a declaration following an at-rule is *not* ignored
 //! 
 //! [CSSATTR]: https://w3c.github.io/csswg-drafts/css-style-attr/
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use cssparser::{BasicParseErrorKind, DeclarationParser, ParseError, ParseErrorKind, Parser, ParserInput, ParserState, ToCss, Token};
 
+use crate::PolicyError;
 
+/// The values a style property can have, for [`Builder::style_value_rules`].
+///
+/// A value is made of keywords, numbers, colors and URLs, separated by spaces, commas or
+/// slashes, and may end with `!important`. It's only allowed if every part of it is.
+/// Anything else, like strings or functions such as `calc()` and `var()`, is rejected.
+///
+/// [`Builder::style_value_rules`]: struct.Builder.html#method.style_value_rules
+///
+/// # Examples
+///
+///     use ammonia::StyleValueRule;
+///
+///     let width = StyleValueRule::new()
+///         .keywords(["auto"])
+///         .numbers(0.0, 800.0, ["px"])
+///         .numbers(0.0, 100.0, ["%"]);
+///     let background = StyleValueRule::new().colors(true).urls(true);
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct StyleValueRule {
+    keywords: BTreeSet<String>,
+    numbers: Vec<NumberRange>,
+    colors: bool,
+    urls: bool,
+}
+
+/// An inclusive range of numbers, with the units they can have.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "NumberRangeConfig")
+)]
+struct NumberRange {
+    min: f32,
+    max: f32,
+    units: BTreeSet<String>,
+}
+
+// The bounds are never NaN, since `NumberRange::new` checks them.
+impl Eq for NumberRange {}
+
+impl NumberRange {
+    /// Fails if `min` is greater than `max`, or either of them is NaN.
+    fn new<I: IntoIterator<Item = S>, S: AsRef<str>>(
+        min: f32,
+        max: f32,
+        units: I,
+    ) -> Result<Self, PolicyError> {
+        if min <= max {
+            Ok(NumberRange {
+                min,
+                max,
+                units: units
+                    .into_iter()
+                    .map(|unit| unit.as_ref().to_ascii_lowercase())
+                    .collect(),
+            })
+        } else {
+            Err(PolicyError::StyleNumberRange {
+                min: min.to_string(),
+                max: max.to_string(),
+            })
+        }
+    }
+}
+
+/// A [`NumberRange`] as it's written in a config file, before its bounds are checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NumberRangeConfig {
+    min: f32,
+    max: f32,
+    units: BTreeSet<String>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<NumberRangeConfig> for NumberRange {
+    type Error = PolicyError;
+    fn try_from(range: NumberRangeConfig) -> Result<Self, PolicyError> {
+        NumberRange::new(range.min, range.max, range.units)
+    }
+}
+
+impl StyleValueRule {
+    /// Creates a rule that allows no values, until some are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows these keywords, like `auto` or `bold`, in addition to any that were already
+    /// allowed. Keywords are compared case-insensitively.
+    pub fn keywords<'b, I: IntoIterator<Item = &'b str>>(mut self, value: I) -> Self {
+        self.keywords
+            .extend(value.into_iter().map(|keyword| keyword.to_ascii_lowercase()));
+        self
+    }
+
+    /// Allows numbers from `min` to `max`, inclusive, with one of these units, in addition
+    /// to any that were already allowed.
+    ///
+    /// Units are compared case-insensitively. Use `""` for numbers without a unit, and `"%"`
+    /// for percentages, which are compared as written, so `50%` is `50.0`.
+    ///
+    /// # Panics
+    ///
+    /// If `min` is greater than `max`, or either of them is NaN.
+    pub fn numbers<'b, I: IntoIterator<Item = &'b str>>(mut self, min: f32, max: f32, units: I) -> Self {
+        let range = NumberRange::new(min, max, units).unwrap_or_else(|e| panic!("{e}"));
+        self.numbers.push(range);
+        self
+    }
+
+    /// Allows colors: named colors, `transparent`, `currentcolor`, hex colors like `#f00`,
+    /// and color functions like `rgb()` or `oklch()`.
+    ///
+    /// # Defaults
+    ///
+    /// `false`
+    pub fn colors(mut self, value: bool) -> Self {
+        self.colors = value;
+        self
+    }
+
+    /// Allows `url()` and `image-set()`. Each URL in them is still checked like the URL
+    /// in a URL attribute, and the declaration is removed if it doesn't pass.
+    ///
+    /// # Defaults
+    ///
+    /// `false`
+    pub fn urls(mut self, value: bool) -> Self {
+        self.urls = value;
+        self
+    }
+
+    fn allows_number(&self, value: f32, unit: &str) -> bool {
+        self.numbers.iter().any(|range| {
+            range.min <= value && value <= range.max && range.units.contains(unit)
+        })
+    }
+
+    /// Returns `true` if a token is allowed at the top level of a value.
+    fn allows_token(&self, token: &Token) -> bool {
+        match token {
+            Token::Ident(ident) => {
+                let ident = ident.to_ascii_lowercase();
+                self.keywords.contains(&ident) || (self.colors && is_color_keyword(&ident))
+            }
+            Token::Number { value, .. } => self.allows_number(*value, ""),
+            Token::Percentage { unit_value, .. } => self.allows_number(unit_value * 100.0, "%"),
+            Token::Dimension { value, unit, .. } => {
+                self.allows_number(*value, &unit.to_ascii_lowercase())
+            }
+            Token::Hash(_) | Token::IDHash(_) => self.colors,
+            Token::UnquotedUrl(_) => self.urls,
+            Token::Comma | Token::Delim('/') => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if a function is allowed at the top level of a value.
+    fn allows_function(&self, name: &str) -> bool {
+        match &*name.to_ascii_lowercase() {
+            "rgb" | "rgba" | "hsl" | "hsla" | "hwb" | "lab" | "lch" | "oklab" | "oklch" | "color" => {
+                self.colors
+            }
+            "url" | "image-set" | "-webkit-image-set" => self.urls,
+            _ => false,
+        }
+    }
+}
+
+fn is_color_keyword(ident: &str) -> bool {
+    ident == "transparent"
+        || ident == "currentcolor"
+        || cssparser::color::parse_named_color(ident).is_ok()
+}
+
+/// Returns `true` if a function takes URLs as strings, like `url("a.png")`.
+fn is_url_function(function: &str) -> bool {
+    matches!(function, "url" | "src" | "image" | "image-set" | "-webkit-image-set")
+}
+
+/// Returns `true` if a token is allowed inside an allowed function. Color functions take
+/// numbers and keywords, and the URL functions take URLs and resolutions.
+fn allowed_in_function(function: &str, token: &Token) -> bool {
+    let url_function = matches!(function, "url" | "image-set" | "-webkit-image-set");
+    match token {
+        Token::QuotedString(_) | Token::UnquotedUrl(_) => url_function,
+        Token::Function(name) => url_function && name.eq_ignore_ascii_case("url"),
+        Token::Dimension { .. } | Token::Comma => true,
+        Token::Number { .. } | Token::Percentage { .. } | Token::Ident(_) | Token::Delim('/') => {
+            !url_function
+        }
+        _ => false,
+    }
+}
+
+/// Blocks nested deeper than this are dropped, so that hostile input can't overflow the stack.
+const MAX_NESTING: usize = 32;
+
+/// The settings `filter_style_attribute_with` checks declarations against.
+pub struct StyleRules<'r> {
+    /// The properties that are allowed, or `None` to allow all of them.
+    pub properties: Option<&'r HashSet<&'r str>>,
+    /// The values allowed for some of the properties; the others can have any value.
+    pub values: &'r HashMap<&'r str, StyleValueRule>,
+}
+
+impl StyleRules<'_> {
+    fn allows(&self, property: &str) -> bool {
+        self.properties
+            .map_or(true, |properties| properties.contains(property))
+    }
+}
+
+/// A well-formed declaration that was dropped.
+pub enum StyleRejection<'a> {
+    /// Its property is not allowed.
+    Property(&'a str),
+    /// Its value doesn't match the `StyleValueRule` for its property.
+    Value {
+        property: &'a str,
+        value: &'a str,
+    },
+    /// A URL in its value was rejected by `on_url`.
+    Url {
+        property: &'a str,
+        url: &'a str,
+    },
+}
 
 /// Filters `style` to only keep the declarations whose property name are listed in 
 /// `names`. Also normalises the style attribute by stripping broken declarations
 /// and constructs per [CSSATTR] rules.
+#[cfg(test)]
 pub fn filter_style_attribute(
     style: &str,
     names: &HashSet<&str>,
 ) -> String {
-    // add room for the trailing semicolon because we lazy
-    let mut out = String::with_capacity(style.len() + 1);
+    let values = HashMap::new();
+    let rules = StyleRules {
+        properties: Some(names),
+        values: &values,
+    };
+    filter_style_attribute_with(style, &rules, &mut |url| Some(url.to_string()), &mut |_| ())
+}
 
+/// Same as [`filter_style_attribute`], but also checks values against the
+/// [`StyleValueRule`]s in `rules`, and calls `on_reject` for every well-formed
+/// declaration that was dropped.
+///
+/// Every URL, in `url()` or as a string in a function like `image-set()`, is replaced
+/// with what `on_url` returns for it. If it returns `None`, the declaration is dropped.
+pub fn filter_style_attribute_with(
+    style: &str,
+    rules: &StyleRules<'_>,
+    on_url: &mut dyn FnMut(&str) -> Option<String>,
+    on_reject: &mut dyn FnMut(StyleRejection<'_>),
+) -> String {
     let mut input = ParserInput::new(style);
     let mut p = Parser::new(&mut input);
+    filter_declarations(&mut p, rules, on_url, on_reject)
+}
+
+/// Filters the declaration list that `p` is at the start of, like the body of a style
+/// rule, the same way as [`filter_style_attribute_with`].
+///
+/// Only the last declaration for each property is kept, since it overrides the others,
+/// unless an earlier one is `!important` and it isn't.
+pub fn filter_declarations<'i, 't>(
+    p: &mut Parser<'i, 't>,
+    rules: &StyleRules<'_>,
+    on_url: &mut dyn FnMut(&str) -> Option<String>,
+    on_reject: &mut dyn FnMut(StyleRejection<'_>),
+) -> String {
+    let mut kept: Vec<Option<ParsedDeclaration>> = Vec::new();
+    // Where the declaration for each property is in `kept`.
+    let mut index = HashMap::new();
 
     loop {
-        match parse_one_declaration(&mut p, names) {
-            Ok((name, value)) => {
-                if !name.is_empty() {
-                    out.push_str(&name);
-                    out.push(':');
-                    out.push_str(&value);
-                    out.push(';');
+        match parse_one_declaration(p, rules, on_url, on_reject) {
+            Ok(declarations) => {
+                for declaration in declarations {
+                    if let Some(&i) = index.get(&declaration.name) {
+                        let earlier: &mut Option<ParsedDeclaration> = &mut kept[i];
+                        if earlier.as_ref().is_some_and(|earlier| earlier.important && !declaration.important) {
+                            continue;
+                        }
+                        *earlier = None;
+                    }
+                    index.insert(declaration.name.clone(), kept.len());
+                    kept.push(Some(declaration));
                 }
             },
             Err(e) => match e.kind {
                 ParseErrorKind::Basic(BasicParseErrorKind::EndOfInput) => break,
                 ParseErrorKind::Basic(BasicParseErrorKind::UnexpectedToken(Token::Semicolon)) => (),
                 ParseErrorKind::Basic(BasicParseErrorKind::UnexpectedToken(_)) => {
-                    advance(&mut p);
+                    advance(p);
                 },
                 _ => unreachable!(
                     "parse_one_declaration should only attempt to parse an ident, a colon, \
@@ -72,13 +349,25 @@ pub fn filter_style_attribute(
             },
         }
     }
-    if !out.is_empty() {
-        // remove trailing semicolon (?)
-        out.pop();
+    let mut out = String::new();
+    for declaration in kept.into_iter().flatten() {
+        if !out.is_empty() {
+            out.push(';');
+        }
+        out.push_str(&declaration.name);
+        out.push(':');
+        out.push_str(&declaration.value);
     }
     out
 }
 
+/// A declaration that was kept, with its normalised value.
+pub struct ParsedDeclaration {
+    name: String,
+    value: String,
+    /// Whether it ends with `!important`.
+    important: bool,
+}
 
 /// The builtin parse_one_declaration errors on a declaration list, that is not what we want.
 /// 
@@ -86,25 +375,325 @@ pub fn filter_style_attribute(
 /// 
 /// Finally, add property filtering directly so we don't need to pay for the
 /// `DeclarationParser::parse_value` if the property is not whitelisted. If
-/// a property is filtered out, it gets parsed as `("", "")`.
+/// a property is filtered out, its value is rejected by its `StyleValueRule`,
+/// or `on_url` rejects a URL in it, nothing is returned, and it is passed to
+/// `on_reject`.
+///
+/// Property names are lowercased, except for custom properties. A box shorthand like
+/// `margin` that isn't allowed is expanded into the longhands that are, like `margin-left`.
 pub fn parse_one_declaration<'i, 't>(
     input: &mut Parser<'i, 't>,
-    valid_properties: &HashSet<&str>,
-) -> Result<(cssparser::CowRcStr<'i>, String), ParseError<'i, ()>>
+    rules: &StyleRules<'_>,
+    on_url: &mut dyn FnMut(&str) -> Option<String>,
+    on_reject: &mut dyn FnMut(StyleRejection<'_>),
+) -> Result<Vec<ParsedDeclaration>, ParseError<'i, ()>>
 {
     let name = input.expect_ident()?.clone();
-    if !valid_properties.contains(&*name) {
+    // Custom properties are case-sensitive, the others aren't.
+    let name: cssparser::CowRcStr<'i> = if name.starts_with("--") {
+        name
+    } else {
+        name.to_ascii_lowercase().into()
+    };
+    if !rules.allows(&name) {
+        if let Some(longhands) = box_longhands(&name) {
+            if longhands.iter().any(|longhand| rules.allows(longhand)) {
+                input.expect_colon()?;
+                return Ok(expand_box_shorthand(input, &name, longhands, rules, on_url, on_reject));
+            }
+        }
+        on_reject(StyleRejection::Property(&name));
         advance(input);
-        return Ok(("".into(), String::new()));
+        return Ok(Vec::new());
     }
     input.expect_colon()?;
-    Declarations.parse_value(name, input, &input.state())
+    let start = input.position();
+    let mut declarations = Declarations {
+        rule: rules.values.get(&*name),
+        on_url,
+        allowed: true,
+        rejected_url: None,
+    };
+    let property = name.clone();
+    let (name, value, allowed, important) = declarations.parse_value(name, input, &input.state())?;
+    // A rejected URL isn't written out, so the value may look empty.
+    if let Some(url) = declarations.rejected_url {
+        on_reject(StyleRejection::Url {
+            property: &property,
+            url: &url,
+        });
+        return Ok(Vec::new());
+    }
+    if !allowed {
+        let source = input.slice_from(start);
+        on_reject(StyleRejection::Value {
+            property: &name,
+            value: source.strip_suffix(';').unwrap_or(source).trim(),
+        });
+        return Ok(Vec::new());
+    }
+    if name.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(vec![ParsedDeclaration {
+        name: name.to_string(),
+        value,
+        important,
+    }])
+}
+
+/// The longhands of a shorthand that takes one to four values, for the top, right, bottom
+/// and left sides, in that order.
+fn box_longhands(shorthand: &str) -> Option<[&'static str; 4]> {
+    Some(match shorthand {
+        "margin" => ["margin-top", "margin-right", "margin-bottom", "margin-left"],
+        "padding" => ["padding-top", "padding-right", "padding-bottom", "padding-left"],
+        "inset" => ["top", "right", "bottom", "left"],
+        "border-width" => [
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+        ],
+        "border-style" => [
+            "border-top-style",
+            "border-right-style",
+            "border-bottom-style",
+            "border-left-style",
+        ],
+        "border-color" => [
+            "border-top-color",
+            "border-right-color",
+            "border-bottom-color",
+            "border-left-color",
+        ],
+        "scroll-margin" => [
+            "scroll-margin-top",
+            "scroll-margin-right",
+            "scroll-margin-bottom",
+            "scroll-margin-left",
+        ],
+        "scroll-padding" => [
+            "scroll-padding-top",
+            "scroll-padding-right",
+            "scroll-padding-bottom",
+            "scroll-padding-left",
+        ],
+        _ => return None,
+    })
+}
+
+/// Expands the value of a box shorthand into its longhands, and filters the ones that are
+/// allowed like any other declaration. The shorthand is rejected if its value can't be
+/// split into one to four values, like when it uses `var()`.
+fn expand_box_shorthand(
+    input: &mut Parser<'_, '_>,
+    shorthand: &str,
+    longhands: [&str; 4],
+    rules: &StyleRules<'_>,
+    on_url: &mut dyn FnMut(&str) -> Option<String>,
+    on_reject: &mut dyn FnMut(StyleRejection<'_>),
+) -> Vec<ParsedDeclaration> {
+    let start = input.position();
+    advance(input);
+    let source = input.slice_from(start);
+    let source = source.strip_suffix(';').unwrap_or(source);
+    let Some((values, important)) = split_box_values(source) else {
+        on_reject(StyleRejection::Property(shorthand));
+        return Vec::new();
+    };
+    let sides = match values.len() {
+        1 => [0, 0, 0, 0],
+        2 => [0, 1, 0, 1],
+        3 => [0, 1, 2, 1],
+        _ => [0, 1, 2, 3],
+    };
+    let mut declarations = Vec::new();
+    for (longhand, side) in longhands.into_iter().zip(sides) {
+        if !rules.allows(longhand) {
+            continue;
+        }
+        let important = if important { "!important" } else { "" };
+        let declaration = format!("{longhand}:{}{important}", values[side]);
+        let mut input = ParserInput::new(&declaration);
+        let mut input = Parser::new(&mut input);
+        let longhand = parse_one_declaration(&mut input, rules, on_url, on_reject);
+        declarations.extend(longhand.unwrap_or_default());
+    }
+    declarations
+}
+
+/// Splits the value of a box shorthand into its one to four values, and whether it's
+/// `!important`.
+fn split_box_values(source: &str) -> Option<(Vec<&str>, bool)> {
+    let mut input = ParserInput::new(source);
+    let mut input = Parser::new(&mut input);
+    let mut values = Vec::new();
+    let mut important = false;
+    loop {
+        input.skip_whitespace();
+        let start = input.position();
+        let t = match input.next() {
+            Ok(t) => t.clone(),
+            Err(_) => break,
+        };
+        if important || t.is_parse_error() {
+            return None;
+        }
+        match t {
+            Token::Delim('!') => {
+                input.expect_ident_matching("important").ok()?;
+                important = true;
+                continue;
+            }
+            Token::Comma | Token::Delim(_) | Token::Semicolon => return None,
+            // These can stand for more than one value.
+            Token::Function(ref name)
+                if name.eq_ignore_ascii_case("var") || name.eq_ignore_ascii_case("env") =>
+            {
+                return None
+            }
+            Token::Function(_) | Token::ParenthesisBlock | Token::SquareBracketBlock => {
+                input
+                    .parse_nested_block(|p| {
+                        while p.next().is_ok() {}
+                        Ok::<_, ParseError<'_, ()>>(())
+                    })
+                    .ok()?;
+            }
+            _ => {}
+        }
+        values.push(input.slice_from(start).trim());
+    }
+    (1..=4).contains(&values.len()).then_some((values, important))
+}
+
+
+/// Writes out the rest of `input` the same way as a declaration value, like the prelude
+/// of an at-rule, with every URL replaced by what `on_url` returns for it.
+///
+/// Returns `None` if it's malformed, or if `on_url` rejected a URL.
+pub fn serialize_tokens(
+    input: &mut Parser<'_, '_>,
+    on_url: &mut dyn FnMut(&str) -> Option<String>,
+) -> Option<String> {
+    let mut declarations = Declarations {
+        rule: None,
+        on_url,
+        allowed: true,
+        rejected_url: None,
+    };
+    let mut out = String::new();
+    while let Ok(t) = input.next() {
+        if t.is_parse_error() {
+            return None;
+        }
+        if !out.is_empty() && t != &Token::Comma {
+            out.push(' ');
+        }
+        let t = t.clone();
+        declarations.write_token(input, &t, None, &mut out, 0).ok()?;
+    }
+    declarations.rejected_url.is_none().then_some(out)
+}
+
+struct Declarations<'r> {
+    rule: Option<&'r StyleValueRule>,
+    on_url: &'r mut dyn FnMut(&str) -> Option<String>,
+    /// Whether the value parsed so far is allowed by the `rule`.
+    allowed: bool,
+    /// The first URL that `on_url` rejected.
+    rejected_url: Option<String>,
 }
 
+impl Declarations<'_> {
+    /// Writes a token to `value`, along with the content of the block it starts, if any.
+    /// `function` is the lowercase name of the function the token is in.
+    fn write_token<'i, 't>(
+        &mut self,
+        input: &mut Parser<'i, 't>,
+        t: &Token<'i>,
+        function: Option<&str>,
+        value: &mut String,
+        depth: usize,
+    ) -> Result<(), ParseError<'i, ()>> {
+        let unexpected = |input: &Parser<'i, 't>| {
+            input.new_error(cssparser::BasicParseErrorKind::UnexpectedToken(t.clone()))
+        };
+        let written = match t {
+            Token::UnquotedUrl(url) => self.write_url(url, Token::UnquotedUrl, value),
+            Token::QuotedString(url) if function.is_some_and(is_url_function) => {
+                self.write_url(url, Token::QuotedString, value)
+            }
+            _ => t.to_css(value),
+        };
+        if written.is_err() {
+            return Err(unexpected(input));
+        }
+        let close = match t {
+            Token::Function(_) | Token::ParenthesisBlock => ')',
+            Token::SquareBracketBlock => ']',
+            _ => return Ok(()),
+        };
+        if depth >= MAX_NESTING {
+            return Err(unexpected(input));
+        }
+        let function = match t {
+            Token::Function(name) => Some(name.to_ascii_lowercase()),
+            _ => None,
+        };
+        input.parse_nested_block(|p| {
+            let mut first = true;
+            loop {
+                match p.next() {
+                    Ok(t) => {
+                        if t.is_parse_error() {
+                            let err = cssparser::BasicParseErrorKind::UnexpectedToken(t.clone());
+                            return Err(p.new_error(err));
+                        }
+                        if self.rule.is_some() {
+                            self.allowed &= function
+                                .as_deref()
+                                .is_some_and(|function| allowed_in_function(function, t));
+                        }
+                        if !first && t != &Token::Comma {
+                            value.push(' ');
+                        }
+                        let t = t.clone();
+                        self.write_token(p, &t, function.as_deref(), value, depth + 1)?;
+                        first = false;
+                    }
+                    Err(e) if e.kind == BasicParseErrorKind::EndOfInput => break Ok(()),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        })?;
+        value.push(close);
+        Ok(())
+    }
+
+    /// Writes the URL that `on_url` returns as a token like the one it was in, or nothing
+    /// if it was rejected.
+    fn write_url<'a>(
+        &mut self,
+        url: &str,
+        token: fn(cssparser::CowRcStr<'a>) -> Token<'a>,
+        value: &mut String,
+    ) -> std::fmt::Result {
+        match (self.on_url)(url) {
+            Some(url) => token(url.into()).to_css(value),
+            None => {
+                self.rejected_url.get_or_insert_with(|| url.to_string());
+                Ok(())
+            }
+        }
+    }
+}
 
-struct Declarations;
-impl <'i> DeclarationParser<'i> for Declarations {
-    type Declaration = (cssparser::CowRcStr<'i>, String);
+impl <'i> DeclarationParser<'i> for Declarations<'_> {
+    /// The name, the normalised value, whether the value is allowed by the `rule`, and
+    /// whether it's `!important`.
+    type Declaration = (cssparser::CowRcStr<'i>, String, bool, bool);
     type Error = ();
 
     fn parse_value<'t>(
@@ -114,6 +703,10 @@ impl <'i> DeclarationParser<'i> for Declarations {
         _declaration_start: &ParserState,
     ) -> Result<Self::Declaration, cssparser::ParseError<'i, Self::Error>> {
         let mut value = String::new();
+        // Whether the last token was the `!` of `!important`.
+        let mut bang = false;
+        // Whether the last token ended `!important`.
+        let mut important = false;
         loop {
             let t = match input.next() {
                 Err(e) if e.kind == cssparser::BasicParseErrorKind::EndOfInput => {
@@ -125,7 +718,7 @@ impl <'i> DeclarationParser<'i> for Declarations {
             match t {
                 Semicolon => { 
                     if value.chars().all(char::is_whitespace) {
-                        return Ok(("".into(), String::new()));
+                        return Ok(("".into(), String::new(), true, false));
                     }
                     break
                 }
@@ -135,55 +728,29 @@ impl <'i> DeclarationParser<'i> for Declarations {
                     return Err(input.new_error(err));
                 }
 
-                Function(_) => {
-                    if !value.is_empty() && value.chars().last() != Some(' ') {
-                        value.push(' ');
-                    }
-                    let Ok(_) = t.to_css(&mut value) else {
-                        let err = cssparser::BasicParseErrorKind::UnexpectedToken(t.clone());
-                        return Err(input.new_error::<()>(err));
-                    };
-                    input.parse_nested_block(|p| {
-                        let mut first = true;
-                        loop {
-                            match p.next() {
-                                Ok(t) => {
-                                    if t.is_parse_error() {
-                                        let err = cssparser::BasicParseErrorKind::UnexpectedToken(t.clone());
-                                        return Err(p.new_error(err));
-                                    }
-                                    if !first && t != &Comma {
-                                        value.push(' ');
-                                    }
-                                    let Ok(_) = t.to_css(&mut value) else {
-                                        let err = cssparser::BasicParseErrorKind::UnexpectedToken(t.clone());
-                                        return Err(p.new_error::<()>(err));
-                                    };
-                                    first = false;
-                                }
-                                Err(e) if e.kind == BasicParseErrorKind::EndOfInput => break Ok(()),
-                                Err(e) => return Err(e.into()),
-                            }
-                        }
-                    })?;
-                    value.push(')');
-                    continue;
-                }
-
                 _ => (),
             }
-            if !value.is_empty() && value.chars().last() != Some(' ') {
+            important = bang && matches!(t, Ident(ident) if ident.eq_ignore_ascii_case("important"));
+            bang = t == &Delim('!');
+            if let Some(rule) = self.rule {
+                self.allowed &= important
+                    || bang
+                    || match t {
+                        Function(function) => rule.allows_function(function),
+                        t => rule.allows_token(t),
+                    };
+            }
+            if !value.is_empty() && !value.ends_with(' ') {
                 value.push(' ');
             }
-            let Ok(_) = t.to_css(&mut value) else {
-                let err = cssparser::BasicParseErrorKind::UnexpectedToken(t.clone());
-                return Err(input.new_error(err));
-            };
+            let t = t.clone();
+            self.write_token(input, &t, None, &mut value, 0)?;
         }
         if value.chars().all(char::is_whitespace) {
             Err(input.new_error(cssparser::BasicParseErrorKind::EndOfInput))
         } else {
-            Ok((name, value))
+            // A value can't end with the `!` of `!important`.
+            Ok((name, value, self.allowed && !(bang && self.rule.is_some()), important))
         }
     }
 }
@@ -206,8 +773,30 @@ fn advance<'i, 't>(p: &mut Parser<'i, 't>) {
 
 #[cfg(test)]
 mod tests {
-    use super::filter_style_attribute;
-    use std::{collections::HashSet, sync::LazyLock};
+    use super::{filter_style_attribute, filter_style_attribute_with, NumberRange, StyleRejection, StyleRules, StyleValueRule};
+    use crate::PolicyError;
+    use std::{cell::RefCell, collections::{HashMap, HashSet}, sync::LazyLock};
+
+    /// Filters `style` with a value rule for each property, and only allows `https:` URLs.
+    fn filter_style_values(style: &str, values: &HashMap<&str, StyleValueRule>) -> (String, Vec<String>) {
+        let properties = values.keys().copied().collect();
+        let rules = StyleRules {
+            properties: Some(&properties),
+            values,
+        };
+        let mut rejected = Vec::new();
+        let out = filter_style_attribute_with(
+            style,
+            &rules,
+            &mut |url| url.starts_with("https:").then(|| url.to_string()),
+            &mut |rejection| match rejection {
+                StyleRejection::Value { property, value } => rejected.push(format!("{property}: {value}")),
+                StyleRejection::Url { property, url } => rejected.push(format!("{property}: <{url}>")),
+                StyleRejection::Property(_) => (),
+            },
+        );
+        (out, rejected)
+    }
 
     #[test]
     fn single_declaration() {
@@ -299,7 +888,7 @@ mod tests {
                 "{}", decl,
             );
         }
-        // should we also keep track of properties and remove duplicates?
+        // only the last declaration for a property has any effect
         for decl in [
             "color:red;   color; color:green",
             "color:red;   color:; color:green",
@@ -307,12 +896,69 @@ mod tests {
         ] {
             assert_eq!(
                 filter_style_attribute(decl, h),
-                "color:red;color:green",
+                "color:green",
                 "{}", decl,
             );
         }
     }
 
+    #[test]
+    fn duplicates() {
+        let h = &HashSet::from(["color", "width", "--Main"]);
+        assert_eq!(
+            filter_style_attribute("color: red; width: 1px; COLOR: blue; --Main: 1; --main: 2", h),
+            "width:1px;color:blue;--Main:1",
+        );
+        assert_eq!(
+            filter_style_attribute("color: red !important; color: blue; width: 1px !important; width: 2px !IMPORTANT", h),
+            "color:red ! important;width:2px ! IMPORTANT",
+        );
+    }
+
+    #[test]
+    fn shorthands() {
+        let h = &HashSet::from(["margin-left", "margin-right", "padding", "padding-top", "border-top-color"]);
+        let rejected = RefCell::new(Vec::new());
+        let values = HashMap::new();
+        let rules = StyleRules {
+            properties: Some(h),
+            values: &values,
+        };
+        let filter = |style| {
+            filter_style_attribute_with(style, &rules, &mut |url| Some(url.to_string()), &mut |rejection| {
+                if let StyleRejection::Property(property) = rejection {
+                    rejected.borrow_mut().push(property.to_string());
+                }
+            })
+        };
+        assert_eq!(filter("margin: 1px"), "margin-right:1px;margin-left:1px");
+        assert_eq!(filter("margin: 1px 2px 3px"), "margin-right:2px;margin-left:2px");
+        assert_eq!(
+            filter("margin-left: 5px; MARGIN: 1px 2px 3px calc(4px + 1%) !important"),
+            "margin-right:2px ! important;margin-left:calc(4px + 1%) ! important"
+        );
+        // Allowed shorthands are kept as they are.
+        assert_eq!(filter("padding: 1px 2px; padding-top: 0"), "padding:1px 2px;padding-top:0");
+        assert_eq!(filter("border-color: rgb(0 0 0) red"), "border-top-color:rgb(0 0 0)");
+        assert!(rejected.borrow().is_empty(), "{rejected:?}");
+        for style in [
+            "margin: var(--m)",
+            "margin: 1px 2px 3px 4px 5px",
+            "margin: 1px, 2px",
+            "margin: 1px / 2px",
+            "margin: 1px !important 2px",
+            "margin:",
+            "inset: 0",
+            "border: 1px solid red",
+        ] {
+            assert_eq!(filter(style), "", "{style}");
+        }
+        assert_eq!(
+            *rejected.borrow(),
+            ["margin", "margin", "margin", "margin", "margin", "margin", "inset", "border"]
+        );
+    }
+
     #[ignore = "can't recover from such a BadString (servo/rust-cssparser#393)"]
     #[test]
     fn badstring_escaped_newline() {
@@ -330,4 +976,126 @@ mod tests {
     fn bad_url() {
         assert_eq!(filter_style_attribute("foo: url(x'y); color: green", &ALLOWED), "color:green");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn value_keywords_and_numbers() {
+        let values = HashMap::from([
+            ("width", StyleValueRule::new().keywords(["auto"]).numbers(0.0, 800.0, ["px", ""]).numbers(0.0, 100.0, ["%"])),
+            ("margin", StyleValueRule::new().numbers(-10.0, 10.0, ["PX"])),
+        ]);
+        for (style, expected) in [
+            ("width: AUTO", "width:AUTO"),
+            ("width: 800px", "width:800px"),
+            ("width: 0", "width:0"),
+            ("width: 50%", "width:50%"),
+            ("width: 99999px", ""),
+            ("width: 101%", ""),
+            ("width: 10em", ""),
+            ("width: inherit", ""),
+            ("width: calc(1px + 1px)", ""),
+            ("width: 'auto'", ""),
+            ("width: auto !important", "width:auto ! important"),
+            ("width: auto !", ""),
+            ("margin: -5Px 5px; width: 1px", "margin:-5Px 5px;width:1px"),
+            ("margin: 5px 11px; width: 1px", "width:1px"),
+        ] {
+            assert_eq!(filter_style_values(style, &values).0, expected, "{}", style);
+        }
+        assert_eq!(
+            filter_style_values("width: 99999px; margin: 1px", &values).1,
+            ["width: 99999px"],
+        );
+    }
+
+    #[test]
+    fn value_number_ranges() {
+        assert!(NumberRange::new(0.0, 0.0, [""]).is_ok());
+        assert_eq!(
+            NumberRange::new(10.0, 0.0, ["px"]),
+            Err(PolicyError::StyleNumberRange { min: "10".to_string(), max: "0".to_string() })
+        );
+        assert!(NumberRange::new(f32::NAN, 1.0, [""]).is_err());
+        assert!(NumberRange::new(0.0, f32::NAN, [""]).is_err());
+    }
+
+    #[test]
+    fn value_colors() {
+        let values = HashMap::from([("color", StyleValueRule::new().colors(true))]);
+        for (style, expected) in [
+            ("color: Red", "color:Red"),
+            ("color: currentColor", "color:currentColor"),
+            ("color: #fff", "color:#fff"),
+            ("color: rgb(0, 0, 0)", "color:rgb(0, 0, 0)"),
+            ("color: oklch(50% 0.1 120deg / 0.5)", "color:oklch(50% 0.1 120deg / 0.5)"),
+            ("color: rgb(var(--x), 0, 0)", ""),
+            ("color: notacolor", ""),
+            ("color: url(https://example.com/)", ""),
+            ("color: expression(alert(1))", ""),
+        ] {
+            assert_eq!(filter_style_values(style, &values).0, expected, "{}", style);
+        }
+    }
+
+    #[test]
+    fn value_urls() {
+        let values = HashMap::from([
+            ("background", StyleValueRule::new().keywords(["no-repeat"]).urls(true)),
+            ("background-color", StyleValueRule::new().keywords(["none"])),
+        ]);
+        for (style, expected) in [
+            ("background: url(https://example.com/a.png) no-repeat", "background:url(https://example.com/a.png) no-repeat"),
+            ("background: url('https://example.com/a.png')", "background:url(\"https://example.com/a.png\")"),
+            ("background: url(javascript:alert(1))", ""),
+            ("background: url('http://example.com/a.png')", ""),
+            ("background: image-set('https://example.com/a.png' 1x, url(https://example.com/b.png) 2x)", "background:image-set(\"https://example.com/a.png\" 1x, url(https://example.com/b.png) 2x)"),
+            ("background: image-set('http://example.com/a.png' 1x)", ""),
+            ("background: image-set(url('https://example.com/a.png') 1x)", "background:image-set(url(\"https://example.com/a.png\") 1x)"),
+            ("background: image-set(url('http://example.com/a.png') 1x)", ""),
+            ("background-color: url(https://example.com/a.png)", ""),
+        ] {
+            assert_eq!(filter_style_values(style, &values).0, expected, "{}", style);
+        }
+    }
+
+    #[test]
+    fn urls_are_rewritten() {
+        let properties = HashSet::from(["background", "color"]);
+        let values = HashMap::new();
+        let rules = StyleRules {
+            properties: Some(&properties),
+            values: &values,
+        };
+        let mut rejected = Vec::new();
+        let out = filter_style_attribute_with(
+            "background: url(a.png), url('b.png'), image-set('c.png' 1x, url(\"d.png\") 2x), \
+             cross-fade(url(e.png), url(javascript:x)); color: red; background: url(f.png) repeat",
+            &rules,
+            &mut |url| (!url.starts_with("javascript:")).then(|| format!("/img/{url}")),
+            &mut |rejection| {
+                if let StyleRejection::Url { property, url } = rejection {
+                    rejected.push(format!("{property}: {url}"));
+                }
+            },
+        );
+        assert_eq!(out, "color:red;background:url(/img/f.png) repeat");
+        assert_eq!(rejected, ["background: javascript:x"]);
+        let out = filter_style_attribute_with(
+            "background: url(a.png), url('b.png'), image-set('c.png' 1x, url(\"d.png\") 2x)",
+            &rules,
+            &mut |url| Some(format!("/img/{url}")),
+            &mut |_| (),
+        );
+        assert_eq!(
+            out,
+            "background:url(/img/a.png) , url(\"/img/b.png\") , image-set(\"/img/c.png\" 1x, url(\"/img/d.png\") 2x)"
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let style = format!("color: {}{}; foo: 1", "a(".repeat(100), ")".repeat(100));
+        assert_eq!(filter_style_attribute(&style, &ALLOWED), "foo:1");
+        let style = format!("color: {}{}", "a(".repeat(10), ")".repeat(10));
+        assert_eq!(filter_style_attribute(&style, &ALLOWED), format!("color:{}{}", "a(".repeat(10), ")".repeat(10)));
+    }
+}
@@ -0,0 +1,514 @@
+//! Cleaning the content of `<style>` elements, for [`Builder::style_sheets`].
+//!
+//! https://drafts.csswg.org/css-syntax/#parse-stylesheet
+//!
+//! [`Builder::style_sheets`]: ../struct.Builder.html#method.style_sheets
+
+use std::collections::BTreeSet;
+
+use cssparser::{
+    AtRuleParser, CowRcStr, Delimiter, ParseError, ParseErrorKind, Parser, ParserInput,
+    ParserState, QualifiedRuleParser, StyleSheetParser, Token,
+};
+
+use crate::style::{self, StyleRejection, StyleRules};
+
+/// Which rules are kept in `<style>` elements, by [`Builder::style_sheets`].
+///
+/// [`Builder::style_sheets`]: struct.Builder.html#method.style_sheets
+///
+/// # Examples
+///
+///     use ammonia::StyleSheetPolicy;
+///
+///     let policy = StyleSheetPolicy::new()
+///         .selectors(["p", "a", ".note", ":hover"])
+///         .scope(Some("#post-123"))
+///         .at_rules(["media"]);
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct StyleSheetPolicy {
+    selectors: BTreeSet<String>,
+    scope: Option<String>,
+    at_rules: BTreeSet<String>,
+}
+
+impl StyleSheetPolicy {
+    /// Creates a policy that allows no selectors and no at-rules, until some are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the simple selectors that rules can use: type selectors like `p`, the universal
+    /// selector `*`, classes like `.note`, IDs like `#intro`, pseudo-classes like `:hover`
+    /// and pseudo-elements like `::before`.
+    ///
+    /// A selector is kept if every simple selector in it is in this list, and they're joined
+    /// by descendant, child (`>`) or sibling (`+` and `~`) combinators. Anything else, like
+    /// attribute selectors or functional pseudo-classes such as `:not()`, is never allowed.
+    /// A rule is removed when none of its selectors are kept.
+    ///
+    /// Type selectors and pseudo-classes are compared case-insensitively, classes and IDs
+    /// are not. IDs get the [`id_prefix`], the same way as `id` attributes.
+    ///
+    /// [`id_prefix`]: struct.Builder.html#method.id_prefix
+    ///
+    /// # Defaults
+    ///
+    /// None
+    pub fn selectors<'b, I: IntoIterator<Item = &'b str>>(mut self, value: I) -> Self {
+        self.selectors = value.into_iter().map(normalize_simple_selector).collect();
+        self
+    }
+
+    /// Puts a selector, like `#post-123`, in front of every selector, so that the rules only
+    /// apply inside the element it matches.
+    ///
+    /// It's written out as it is, so it can't contain `{`, `}`, `;`, `,`, `<`, `/`,
+    /// backslashes or quotes; [`Builder::validate`] fails with
+    /// [`PolicyError::StyleSheetScope`] if it does.
+    ///
+    /// [`Builder::validate`]: struct.Builder.html#method.validate
+    /// [`PolicyError::StyleSheetScope`]: enum.PolicyError.html#variant.StyleSheetScope
+    ///
+    /// # Defaults
+    ///
+    /// `None`
+    pub fn scope(mut self, value: Option<&str>) -> Self {
+        self.scope = value.map(str::to_string);
+        self
+    }
+
+    /// Sets the at-rules that are kept, by their name without the `@`, like `media` or
+    /// `supports`. Their block is cleaned as a list of rules, so this is meant for at-rules
+    /// that contain style rules. Names are compared case-insensitively.
+    ///
+    /// `@import` and `@font-face` are always removed, because they load other resources.
+    ///
+    /// # Defaults
+    ///
+    /// None
+    pub fn at_rules<'b, I: IntoIterator<Item = &'b str>>(mut self, value: I) -> Self {
+        self.at_rules = value
+            .into_iter()
+            .map(|name| name.trim_start_matches('@').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Returns the scope if it can't be written out safely.
+    pub(crate) fn invalid_scope(&self) -> Option<&str> {
+        self.scope.as_deref().filter(|scope| {
+            scope.trim().is_empty()
+                || scope.contains(['{', '}', ';', ',', '<', '/', '\\', '"', '\''])
+        })
+    }
+
+    fn allows_at_rule(&self, name: &str) -> bool {
+        !matches!(name, "import" | "font-face") && self.at_rules.contains(name)
+    }
+}
+
+/// Type selectors and pseudo-classes are case-insensitive, classes and IDs aren't.
+fn normalize_simple_selector(selector: &str) -> String {
+    if selector.starts_with(['.', '#']) {
+        selector.to_string()
+    } else {
+        selector.to_ascii_lowercase()
+    }
+}
+
+/// Blocks of at-rules nested deeper than this are dropped, so that hostile input can't
+/// overflow the stack.
+const MAX_NESTING: usize = 32;
+
+/// Cleans a style sheet, keeping the rules that `policy` allows, with their declarations
+/// filtered like a `style` attribute's.
+///
+/// `on_rule` is called with every selector or at-rule that is removed, and `on_declaration`
+/// with every well-formed declaration that is.
+pub(crate) fn clean_style_sheet(
+    css: &str,
+    policy: &StyleSheetPolicy,
+    rules: &StyleRules<'_>,
+    id_prefix: Option<&str>,
+    on_url: &mut dyn FnMut(&str) -> Option<String>,
+    on_rule: &mut dyn FnMut(&str),
+    on_declaration: &mut dyn FnMut(StyleRejection<'_>),
+) -> String {
+    let mut input = ParserInput::new(css);
+    let mut input = Parser::new(&mut input);
+    let mut parser = RuleParser {
+        policy,
+        rules,
+        id_prefix,
+        on_url,
+        on_rule,
+        on_declaration,
+        depth: 0,
+    };
+    let out = parser.parse_rules(&mut input);
+    // The sheet is written out as raw text, so it must not end the `<style>` element.
+    // Outside of strings and URLs, tokens are separated by spaces, and inside of them,
+    // `\/` is the same as `/`.
+    out.replace("</", "<\\/")
+}
+
+struct RuleParser<'r> {
+    policy: &'r StyleSheetPolicy,
+    rules: &'r StyleRules<'r>,
+    id_prefix: Option<&'r str>,
+    on_url: &'r mut dyn FnMut(&str) -> Option<String>,
+    on_rule: &'r mut dyn FnMut(&str),
+    on_declaration: &'r mut dyn FnMut(StyleRejection<'_>),
+    depth: usize,
+}
+
+/// Rules that fail with a custom error have already been passed to `on_rule`.
+type RuleError<'i> = ParseError<'i, ()>;
+
+impl RuleParser<'_> {
+    /// Cleans a list of rules, and returns the ones that are kept.
+    fn parse_rules(&mut self, input: &mut Parser<'_, '_>) -> String {
+        let mut out = String::new();
+        let mut rules = StyleSheetParser::new(input, self);
+        while let Some(rule) = rules.next() {
+            match rule {
+                Ok(rule) => out.push_str(&rule),
+                Err((e, _)) if matches!(e.kind, ParseErrorKind::Custom(())) => {}
+                Err((_, source)) => (rules.parser.on_rule)(source.trim()),
+            }
+        }
+        out
+    }
+
+    /// Checks one selector from a selector list, and returns it the way it's written out.
+    fn parse_selector<'i>(&self, input: &mut Parser<'i, '_>) -> Result<String, RuleError<'i>> {
+        let mut out = String::new();
+        // Whether the next simple selector starts a new compound selector.
+        let mut after_combinator = true;
+        // Whether there was whitespace since the last simple selector.
+        let mut space = false;
+        loop {
+            let t = match input.next_including_whitespace() {
+                Ok(t) => t.clone(),
+                Err(_) => break,
+            };
+            let unexpected = |input: &Parser<'i, '_>| {
+                input.new_unexpected_token_error::<()>(t.clone())
+            };
+            let (key, written) = match t {
+                Token::WhiteSpace(_) => {
+                    space = !out.is_empty();
+                    continue;
+                }
+                Token::Delim(c @ ('>' | '+' | '~')) => {
+                    if after_combinator {
+                        return Err(unexpected(input));
+                    }
+                    out.push(' ');
+                    out.push(c);
+                    out.push(' ');
+                    after_combinator = true;
+                    space = false;
+                    continue;
+                }
+                Token::Ident(ref name) if after_combinator || space => {
+                    let name = name.to_ascii_lowercase();
+                    (name.clone(), serialize_identifier(&name))
+                }
+                Token::Delim('*') if after_combinator || space => ("*".to_string(), "*".to_string()),
+                Token::Delim('.') => match input.next_including_whitespace()? {
+                    Token::Ident(class) => (format!(".{class}"), format!(".{}", serialize_identifier(class))),
+                    _ => return Err(unexpected(input)),
+                },
+                Token::IDHash(ref id) => {
+                    let written = match self.id_prefix {
+                        Some(prefix) if !id.starts_with(prefix) => format!("{prefix}{id}"),
+                        _ => id.to_string(),
+                    };
+                    (format!("#{id}"), format!("#{}", serialize_identifier(&written)))
+                }
+                Token::Colon => {
+                    let mut colons = ":";
+                    let mut t = input.next_including_whitespace()?.clone();
+                    if t == Token::Colon {
+                        colons = "::";
+                        t = input.next_including_whitespace()?.clone();
+                    }
+                    match t {
+                        Token::Ident(name) => {
+                            let name = name.to_ascii_lowercase();
+                            (format!("{colons}{name}"), format!("{colons}{}", serialize_identifier(&name)))
+                        }
+                        _ => return Err(unexpected(input)),
+                    }
+                }
+                _ => return Err(unexpected(input)),
+            };
+            if !self.policy.selectors.contains(&key) {
+                return Err(unexpected(input));
+            }
+            if space && !after_combinator {
+                out.push(' ');
+            }
+            out.push_str(&written);
+            after_combinator = false;
+            space = false;
+        }
+        if after_combinator {
+            return Err(input.new_custom_error(()));
+        }
+        Ok(match self.policy.scope {
+            Some(ref scope) => format!("{} {out}", scope.trim()),
+            None => out,
+        })
+    }
+}
+
+fn serialize_identifier(ident: &str) -> String {
+    let mut out = String::new();
+    cssparser::serialize_identifier(ident, &mut out).expect("writing to a string can't fail");
+    out
+}
+
+impl<'i> QualifiedRuleParser<'i> for RuleParser<'_> {
+    /// The kept selectors, separated by commas.
+    type Prelude = String;
+    type QualifiedRule = String;
+    type Error = ();
+
+    fn parse_prelude<'t>(
+        &mut self,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::Prelude, ParseError<'i, Self::Error>> {
+        let mut selectors = Vec::new();
+        loop {
+            let start = input.position();
+            match input.parse_until_before(Delimiter::Comma, |input| self.parse_selector(input)) {
+                Ok(selector) => selectors.push(selector),
+                Err(_) => {
+                    let source = input.slice_from(start).trim();
+                    if !source.is_empty() {
+                        (self.on_rule)(source);
+                    }
+                }
+            }
+            if input.next().is_err() {
+                break;
+            }
+        }
+        if selectors.is_empty() {
+            return Err(input.new_custom_error(()));
+        }
+        Ok(selectors.join(","))
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        prelude: Self::Prelude,
+        _start: &ParserState,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::QualifiedRule, ParseError<'i, Self::Error>> {
+        let declarations = style::filter_declarations(
+            input,
+            self.rules,
+            &mut *self.on_url,
+            &mut *self.on_declaration,
+        );
+        if declarations.is_empty() {
+            return Err(input.new_custom_error(()));
+        }
+        Ok(format!("{prelude}{{{declarations}}}"))
+    }
+}
+
+impl<'i> AtRuleParser<'i> for RuleParser<'_> {
+    /// The lowercase name, and the cleaned prelude.
+    type Prelude = (String, String);
+    type AtRule = String;
+    type Error = ();
+
+    fn parse_prelude<'t>(
+        &mut self,
+        name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::Prelude, ParseError<'i, Self::Error>> {
+        let name = name.to_ascii_lowercase();
+        let prelude = if self.policy.allows_at_rule(&name) && self.depth < MAX_NESTING {
+            style::serialize_tokens(input, &mut *self.on_url)
+        } else {
+            None
+        };
+        match prelude {
+            Some(prelude) => Ok((name, prelude)),
+            None => {
+                (self.on_rule)(&format!("@{name}"));
+                Err(input.new_custom_error(()))
+            }
+        }
+    }
+
+    fn rule_without_block(
+        &mut self,
+        (name, prelude): Self::Prelude,
+        _start: &ParserState,
+    ) -> Result<Self::AtRule, ()> {
+        Ok(format!("{};", at_rule_start(&name, &prelude)))
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        (name, prelude): Self::Prelude,
+        _start: &ParserState,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::AtRule, ParseError<'i, Self::Error>> {
+        self.depth += 1;
+        let rules = self.parse_rules(input);
+        self.depth -= 1;
+        if rules.is_empty() {
+            return Err(input.new_custom_error(()));
+        }
+        Ok(format!("{}{{{rules}}}", at_rule_start(&name, &prelude)))
+    }
+}
+
+fn at_rule_start(name: &str, prelude: &str) -> String {
+    let mut out = String::from("@");
+    cssparser::serialize_identifier(name, &mut out).expect("writing to a string can't fail");
+    if !prelude.is_empty() {
+        out.push(' ');
+        out.push_str(prelude);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clean_style_sheet, StyleSheetPolicy};
+    use crate::style::StyleRules;
+    use std::collections::{HashMap, HashSet};
+
+    /// Cleans `css` with `color` and `background` allowed and `https:` URLs only, and returns
+    /// it along with the removed rules.
+    fn clean(css: &str, policy: &StyleSheetPolicy) -> (String, Vec<String>) {
+        let properties = HashSet::from(["color", "background"]);
+        let values = HashMap::new();
+        let rules = StyleRules {
+            properties: Some(&properties),
+            values: &values,
+        };
+        let mut removed = Vec::new();
+        let out = clean_style_sheet(
+            css,
+            policy,
+            &rules,
+            None,
+            &mut |url| url.starts_with("https:").then(|| url.to_string()),
+            &mut |rule| removed.push(rule.to_string()),
+            &mut |_| (),
+        );
+        (out, removed)
+    }
+
+    #[test]
+    fn selectors() {
+        let policy = StyleSheetPolicy::new().selectors(["p", "A", ".Note", "#intro", ":hover", "::before", "*"]);
+        assert_eq!(
+            clean(
+                "P.Note > a:HOVER, #intro ~ *::before, p.note, a[href], :not(p), p a + a { color: red }",
+                &policy,
+            ),
+            (
+                "p.Note > a:hover,#intro ~ *::before,p a + a{color:red}".to_string(),
+                vec!["p.note".to_string(), "a[href]".to_string(), ":not(p)".to_string()],
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_selectors() {
+        let policy = StyleSheetPolicy::new().selectors(["p", "a"]);
+        let (out, removed) = clean("> p { color: red } p > { color: red } pa { color: red } p, { color: red }", &policy);
+        assert_eq!(out, "p{color:red}");
+        assert_eq!(removed, ["> p", "p >", "pa"]);
+    }
+
+    #[test]
+    fn scope() {
+        let policy = StyleSheetPolicy::new().selectors(["p", "a"]).scope(Some(" #post-123 "));
+        assert_eq!(
+            clean("p, a { color: red }", &policy).0,
+            "#post-123 p,#post-123 a{color:red}"
+        );
+        assert_eq!(policy.invalid_scope(), None);
+        for scope in ["", "a, b", "a{}", "a /* x", "[x=\"y\"]"] {
+            let policy = StyleSheetPolicy::new().scope(Some(scope));
+            assert_eq!(policy.invalid_scope(), Some(scope));
+        }
+    }
+
+    #[test]
+    fn declarations() {
+        let policy = StyleSheetPolicy::new().selectors(["p"]);
+        assert_eq!(
+            clean(
+                "p { color: red; position: fixed; background: url(http://a.example/) } \
+                 p { position: fixed } \
+                 p { background: url(https://a.example/) }",
+                &policy,
+            ),
+            (
+                "p{color:red}p{background:url(https://a.example/)}".to_string(),
+                vec![]
+            )
+        );
+    }
+
+    #[test]
+    fn at_rules() {
+        let policy = StyleSheetPolicy::new()
+            .selectors(["p"])
+            .at_rules(["media", "@Layer", "import", "font-face"]);
+        let (out, removed) = clean(
+            "@charset \"utf-8\"; @import url(https://a.example/x.css); \
+             @font-face { font-family: x; src: url(https://a.example/x.woff) } \
+             @layer base; @MEDIA screen and (min-width: 600px) { p { color: red } a { color: red } } \
+             @media print { a { color: red } } @supports (display: grid) { p { color: red } } \
+             @keyframes x { from { color: red } }",
+            &policy,
+        );
+        assert_eq!(
+            out,
+            "@layer base;@media screen and (min-width : 600px){p{color:red}}"
+        );
+        assert_eq!(
+            removed,
+            ["@import", "@font-face", "a", "a", "@supports", "@keyframes"]
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let policy = StyleSheetPolicy::new().selectors(["p"]).at_rules(["media"]);
+        let css = format!("{}p {{ color: red }}{}", "@media print {".repeat(1000), "}".repeat(1000));
+        let (out, removed) = clean(&css, &policy);
+        assert_eq!(out, "");
+        assert_eq!(removed, ["@media"]);
+    }
+
+    #[test]
+    fn cannot_end_style_element() {
+        let policy = StyleSheetPolicy::new().selectors(["p", ".a"]);
+        let (out, _) = clean(
+            "p { background: url(\"https://a.example/</style><script>\") } .\\3c/style\\3e { color: red }",
+            &policy,
+        );
+        assert!(!out.contains("</"), "{out}");
+        assert_eq!(out, "p{background:url(\"https://a.example/<\\/style><script>\")}");
+    }
+}
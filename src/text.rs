@@ -0,0 +1,606 @@
+//! Working with the text content of a sanitized document.
+
+use crate::rcdom::{Handle, NodeData};
+use html5ever::tendril::StrTendril;
+use html5ever::{local_name, ns, LocalName};
+use std::cmp::max;
+use std::mem;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How [`Document::truncate_text_with`] counts and cuts text.
+///
+/// [`Document::truncate_text_with`]: struct.Document.html#method.truncate_text_with
+///
+/// # Examples
+///
+///     use ammonia::TruncateOptions;
+///
+///     let options = TruncateOptions::new().graphemes(true).whole_words(true);
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TruncateOptions {
+    graphemes: bool,
+    whole_words: bool,
+}
+
+impl TruncateOptions {
+    /// Counts characters (Unicode scalar values), and cuts anywhere between them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts grapheme clusters instead of characters.
+    ///
+    /// A grapheme cluster is what a reader sees as a single character, like an emoji
+    /// with a skin tone modifier, or a letter with combining accents. Counting them
+    /// means these never get split.
+    ///
+    /// # Defaults
+    ///
+    /// `false`
+    pub fn graphemes(mut self, value: bool) -> Self {
+        self.graphemes = value;
+        self
+    }
+
+    /// Avoids cutting a word in half, by cutting before it instead.
+    ///
+    /// Word boundaries follow Unicode's rules ([UAX #29]), so text without spaces,
+    /// like Chinese or Japanese, can still be cut between characters. If the very
+    /// first word is too long by itself, it's cut anyway.
+    ///
+    /// [UAX #29]: https://www.unicode.org/reports/tr29/#Word_Boundaries
+    ///
+    /// # Defaults
+    ///
+    /// `false`
+    pub fn whole_words(mut self, value: bool) -> Self {
+        self.whole_words = value;
+        self
+    }
+
+    fn count(&self, text: &str) -> usize {
+        if self.graphemes {
+            text.graphemes(true).count()
+        } else {
+            text.chars().count()
+        }
+    }
+
+    /// The byte offset just after the first `n` characters or grapheme clusters.
+    fn byte_offset(&self, text: &str, n: usize) -> usize {
+        let offset = if self.graphemes {
+            text.grapheme_indices(true).nth(n).map(|(i, _)| i)
+        } else {
+            text.char_indices().nth(n).map(|(i, _)| i)
+        };
+        offset.unwrap_or(text.len())
+    }
+}
+
+/// Moves `cut` back to the start of the word it falls in, if it's in the middle of one.
+fn word_start(text: &str, cut: usize) -> usize {
+    text.split_word_bound_indices()
+        .map(|(i, _)| i)
+        .take_while(|&i| i <= cut)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Cuts the text under `root` down to `max` characters, and drops everything after the cut.
+///
+/// Returns `true` if anything was cut.
+pub(crate) fn truncate_text(
+    root: &Handle,
+    max: usize,
+    ellipsis: &str,
+    options: TruncateOptions,
+) -> bool {
+    let mut count = 0;
+    // Each entry is an element that is being walked, and the index of its next child.
+    let mut stack = vec![(root.clone(), 0)];
+    while let Some((parent, i)) = stack.pop() {
+        let Some(node) = parent.children.borrow().get(i).cloned() else {
+            continue;
+        };
+        stack.push((parent, i + 1));
+        let contents = match node.data {
+            NodeData::Element { .. } => {
+                stack.push((node.clone(), 0));
+                continue;
+            }
+            NodeData::Text { ref contents } => contents,
+            _ => continue,
+        };
+        let mut contents = contents.borrow_mut();
+        let len = options.count(&contents);
+        if count + len <= max {
+            count += len;
+            continue;
+        }
+        let mut cut = options.byte_offset(&contents, max - count);
+        if options.whole_words {
+            let start = word_start(&contents, cut);
+            if start > 0 || count > 0 {
+                cut = start;
+            }
+        }
+        let kept = contents[..cut].trim_end();
+        *contents = StrTendril::from(format!("{kept}{ellipsis}"));
+        // Everything after this node goes, at every level. The nodes are taken apart one at a
+        // time, so that deeply nested content can't overflow the stack when it's dropped.
+        let mut removed = Vec::new();
+        for (ancestor, next) in stack {
+            removed.extend(ancestor.children.borrow_mut().drain(next..));
+        }
+        while let Some(node) = removed.pop() {
+            removed.append(&mut mem::take(&mut *node.children.borrow_mut()));
+        }
+        return true;
+    }
+    false
+}
+
+/// How [`Document::to_text_with`] renders a document as plain text.
+///
+/// [`Document::to_text_with`]: struct.Document.html#method.to_text_with
+///
+/// # Examples
+///
+///     use ammonia::TextOptions;
+///
+///     let options = TextOptions::new().link_urls(true);
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextOptions {
+    link_urls: bool,
+}
+
+impl TextOptions {
+    /// Renders links as their text only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders links as `text <url>`, so that the address isn't lost.
+    ///
+    /// A link whose text is already its address is only written once.
+    ///
+    /// # Defaults
+    ///
+    /// `false`
+    pub fn link_urls(mut self, value: bool) -> Self {
+        self.link_urls = value;
+        self
+    }
+}
+
+/// Renders the contents of `root` as plain text.
+pub(crate) fn to_text(root: &Handle, options: TextOptions) -> String {
+    let mut writer = TextWriter {
+        options,
+        output: String::new(),
+        newlines: 0,
+        space: false,
+        at_line_start: true,
+        prefixes: Vec::new(),
+        written_prefixes: 0,
+        marker: None,
+        lists: Vec::new(),
+        links: Vec::new(),
+        pre: 0,
+    };
+    // Each entry is a node, and whether it's being entered (as opposed to left).
+    let mut stack: Vec<(Handle, bool)> = root
+        .children
+        .borrow()
+        .iter()
+        .rev()
+        .map(|child| (child.clone(), true))
+        .collect();
+    while let Some((node, entering)) = stack.pop() {
+        match node.data {
+            NodeData::Element { ref name, .. } if name.ns == ns!(html) => {
+                if !entering {
+                    writer.close(&name.local);
+                } else if writer.open(&node, &name.local) {
+                    stack.push((node.clone(), false));
+                    stack.extend(
+                        node.children
+                            .borrow()
+                            .iter()
+                            .rev()
+                            .map(|child| (child.clone(), true)),
+                    );
+                }
+            }
+            // SVG and MathML only contribute their text.
+            NodeData::Element { .. } if entering => {
+                stack.extend(
+                    node.children
+                        .borrow()
+                        .iter()
+                        .rev()
+                        .map(|child| (child.clone(), true)),
+                );
+            }
+            NodeData::Text { ref contents } => writer.text(&contents.borrow()),
+            _ => {}
+        }
+    }
+    writer.output
+}
+
+/// How far apart two blocks of text are.
+fn block_break(name: &LocalName) -> Option<usize> {
+    match *name {
+        local_name!("p")
+        | local_name!("h1")
+        | local_name!("h2")
+        | local_name!("h3")
+        | local_name!("h4")
+        | local_name!("h5")
+        | local_name!("h6")
+        | local_name!("blockquote")
+        | local_name!("pre")
+        | local_name!("ul")
+        | local_name!("ol")
+        | local_name!("dl")
+        | local_name!("table")
+        | local_name!("hr")
+        | local_name!("figure")
+        | local_name!("address")
+        | local_name!("details") => Some(2),
+        local_name!("div")
+        | local_name!("li")
+        | local_name!("dt")
+        | local_name!("dd")
+        | local_name!("tr")
+        | local_name!("caption")
+        | local_name!("article")
+        | local_name!("aside")
+        | local_name!("section")
+        | local_name!("header")
+        | local_name!("footer")
+        | local_name!("main")
+        | local_name!("nav")
+        | local_name!("summary")
+        | local_name!("figcaption") => Some(1),
+        _ => None,
+    }
+}
+
+struct TextWriter {
+    options: TextOptions,
+    output: String,
+    /// Line breaks that will be written before the next text, if there is any.
+    newlines: usize,
+    /// Whether whitespace was collapsed since the last text.
+    space: bool,
+    at_line_start: bool,
+    /// What each line starts with: indentation for list items, and `> ` for block quotes.
+    prefixes: Vec<String>,
+    /// How many prefixes the last line of text had.
+    written_prefixes: usize,
+    /// The bullet or number of a list item that has no text yet, and the prefix it replaces.
+    marker: Option<(usize, String)>,
+    /// The next number of each open ordered list, or `None` for unordered lists.
+    lists: Vec<Option<i64>>,
+    /// The address of each open link, and where its text starts in the output.
+    links: Vec<Option<(String, usize)>>,
+    pre: usize,
+}
+
+impl TextWriter {
+    /// Handles an element's start; returns `false` if its contents should be skipped.
+    fn open(&mut self, node: &Handle, name: &LocalName) -> bool {
+        let attr = |attr_name: LocalName| match node.data {
+            NodeData::Element { ref attrs, .. } => attrs
+                .borrow()
+                .iter()
+                .find(|attr| attr.name.ns == ns!() && attr.name.local == attr_name)
+                .map(|attr| attr.value.to_string()),
+            _ => None,
+        };
+        self.block(name);
+        match *name {
+            local_name!("br") => {
+                if !self.output.is_empty() {
+                    self.newlines += 1;
+                }
+            }
+            local_name!("td") | local_name!("th") if !self.at_line_start && self.newlines == 0 => {
+                self.output.push('\t');
+                self.space = false;
+            }
+            local_name!("img") => {
+                if let Some(alt) = attr(local_name!("alt")) {
+                    self.text(&alt);
+                }
+            }
+            local_name!("a") => {
+                let href = attr(local_name!("href")).filter(|_| self.options.link_urls);
+                self.links.push(href.map(|href| (href, self.output.len())));
+            }
+            local_name!("pre") => self.pre += 1,
+            local_name!("blockquote") => self.prefixes.push("> ".to_owned()),
+            local_name!("ul") => self.lists.push(None),
+            local_name!("ol") => {
+                let start = attr(local_name!("start")).and_then(|start| start.parse().ok());
+                self.lists.push(Some(start.unwrap_or(1)));
+            }
+            local_name!("li") => {
+                let marker = match self.lists.last_mut() {
+                    Some(Some(next)) => {
+                        let number = *next;
+                        *next = next.saturating_add(1);
+                        format!("{number}. ")
+                    }
+                    _ => "- ".to_owned(),
+                };
+                self.prefixes.push(" ".repeat(marker.len()));
+                self.marker = Some((self.prefixes.len() - 1, marker));
+            }
+            local_name!("script")
+            | local_name!("style")
+            | local_name!("template")
+            | local_name!("noscript") => return false,
+            _ => {}
+        }
+        true
+    }
+
+    fn close(&mut self, name: &LocalName) {
+        match *name {
+            local_name!("a") => {
+                if let Some(Some((href, start))) = self.links.pop() {
+                    let text = self.output[start..].trim();
+                    if text != href && Some(text) != href.strip_prefix("mailto:") {
+                        self.space = true;
+                        self.write(&format!("<{href}>"));
+                    }
+                }
+            }
+            local_name!("pre") => self.pre -= 1,
+            local_name!("blockquote") => {
+                self.prefixes.pop();
+            }
+            local_name!("ul") | local_name!("ol") => {
+                self.lists.pop();
+            }
+            local_name!("li") => {
+                self.prefixes.pop();
+                self.marker = None;
+            }
+            _ => {}
+        }
+        self.block(name);
+    }
+
+    /// Separates the text before and after the start or end of a block element.
+    fn block(&mut self, name: &LocalName) {
+        let newlines = match *name {
+            // Nested lists aren't separated by blank lines.
+            local_name!("ul") | local_name!("ol") if !self.lists.is_empty() => 1,
+            _ => block_break(name).unwrap_or(0),
+        };
+        if !self.output.is_empty() {
+            self.newlines = max(self.newlines, newlines);
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.pre > 0 {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    self.newlines += 1;
+                }
+                if !line.is_empty() {
+                    self.write(line);
+                }
+            }
+            return;
+        }
+        if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+            self.space = true;
+        }
+        for word in text.split_ascii_whitespace() {
+            self.write(word);
+            self.space = true;
+        }
+        if !text.ends_with(|c: char| c.is_ascii_whitespace()) {
+            self.space = false;
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.newlines > 0 && !self.output.is_empty() {
+            // Blank lines only continue the prefixes that are on both sides of them.
+            let shared = self.written_prefixes.min(self.prefixes.len());
+            let blank = self.prefixes[..shared].concat();
+            for i in 0..self.newlines {
+                if i > 0 {
+                    self.output.push_str(blank.trim_end());
+                }
+                self.output.push('\n');
+            }
+            self.at_line_start = true;
+        }
+        self.newlines = 0;
+        if self.at_line_start {
+            let marker = self.marker.take();
+            for (i, prefix) in self.prefixes.iter().enumerate() {
+                match marker {
+                    Some((index, ref marker)) if index == i => self.output.push_str(marker),
+                    _ => self.output.push_str(prefix),
+                }
+            }
+            self.written_prefixes = self.prefixes.len();
+            self.at_line_start = false;
+        } else if self.space {
+            self.output.push(' ');
+        }
+        self.space = false;
+        self.output.push_str(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Builder;
+
+    fn truncate(src: &str, max: usize, options: TruncateOptions) -> String {
+        let mut document = Builder::default().clean(src);
+        document.truncate_text_with(max, "…", options);
+        document.to_string()
+    }
+
+    #[test]
+    fn counts_only_text() {
+        let src = "<p>one <a href=\"https://example.com/\">two</a></p><p>three &amp; four</p>";
+        assert_eq!(
+            truncate(src, 9, TruncateOptions::new()),
+            "<p>one <a href=\"https://example.com/\" rel=\"noopener noreferrer\">two</a></p><p>th…</p>"
+        );
+        assert_eq!(
+            truncate(src, 15, TruncateOptions::new()),
+            "<p>one <a href=\"https://example.com/\" rel=\"noopener noreferrer\">two</a></p><p>three &amp;…</p>"
+        );
+    }
+
+    #[test]
+    fn no_change_when_short_enough() {
+        let mut document = Builder::default().clean("<b>short</b><img src=x.png>");
+        assert!(!document.truncate_text(5, "…"));
+        assert_eq!(document.to_string(), "<b>short</b><img src=\"x.png\">");
+        assert!(document.truncate_text(4, "…"));
+        assert_eq!(document.to_string(), "<b>shor…</b>");
+    }
+
+    #[test]
+    fn closes_open_elements() {
+        assert_eq!(
+            truncate(
+                "<ul><li><b>first <i>item</i></b></li><li>second</li></ul>",
+                8,
+                TruncateOptions::new()
+            ),
+            "<ul><li><b>first <i>it…</i></b></li></ul>"
+        );
+    }
+
+    #[test]
+    fn graphemes() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let src = format!("{family}{family}e\u{301}");
+        assert_eq!(
+            truncate(&src, 2, TruncateOptions::new()),
+            "\u{1F468}\u{200D}…"
+        );
+        assert_eq!(
+            truncate(&src, 2, TruncateOptions::new().graphemes(true)),
+            format!("{family}{family}…")
+        );
+        assert_eq!(
+            truncate(&src, 2, TruncateOptions::new().graphemes(true)).len(),
+            2 * family.len() + "…".len()
+        );
+    }
+
+    #[test]
+    fn whole_words() {
+        let options = TruncateOptions::new().whole_words(true);
+        assert_eq!(
+            truncate("<p>the quick brown fox</p>", 12, options),
+            "<p>the quick…</p>"
+        );
+        assert_eq!(
+            truncate("<p>the quick brown fox</p>", 9, options),
+            "<p>the quick…</p>"
+        );
+        assert_eq!(
+            truncate("<p>the <b>quick</b> brown fox</p>", 6, options),
+            "<p>the <b>…</b></p>"
+        );
+        assert_eq!(
+            truncate("<p>supercalifragilistic</p>", 5, options),
+            "<p>super…</p>"
+        );
+        assert_eq!(
+            truncate("<p>日本語のテキスト</p>", 4, options),
+            "<p>日本語の…</p>"
+        );
+    }
+
+    fn text(src: &str) -> String {
+        Builder::default().clean(src).to_text()
+    }
+
+    #[test]
+    fn blocks_and_whitespace() {
+        assert_eq!(
+            text("<h2>Title</h2>\n<p>Some   <i>spaced</i>\n text.</p><div>one</div><div>two</div>"),
+            "Title\n\nSome spaced text.\n\none\ntwo"
+        );
+        assert_eq!(text("a<br><br>b<div>c</div>d"), "a\n\nb\nc\nd");
+        assert_eq!(
+            text("<p>&lt;b&gt; &amp; <img alt=\"pic\"></p>"),
+            "<b> & pic"
+        );
+        assert_eq!(
+            text("<pre>  code\n    indented</pre><p>after</p>"),
+            "  code\n    indented\n\nafter"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            text("<ul><li>one<ul><li>nested</li><li>again</li></ul></li><li>two</li></ul>"),
+            "- one\n  - nested\n  - again\n- two"
+        );
+        assert_eq!(
+            text("<ol start=9><li>nine</li><li>ten<br>more</li></ol>"),
+            "9. nine\n10. ten\n    more"
+        );
+        assert_eq!(
+            text("<ol start=-1><li>a</li><li>b</li><li>c</li></ol>"),
+            "-1. a\n0. b\n1. c"
+        );
+        assert_eq!(
+            text("<ol start=9223372036854775807><li>a</li><li>b</li></ol>"),
+            "9223372036854775807. a\n9223372036854775807. b"
+        );
+        assert_eq!(
+            text("<ol start=18446744073709551615><li>a</li><li>b</li></ol>"),
+            "1. a\n2. b"
+        );
+    }
+
+    #[test]
+    fn quotes_and_tables() {
+        assert_eq!(
+            text("<p>a</p><blockquote><p>quoted</p><p>twice</p></blockquote><p>after</p>"),
+            "a\n\n> quoted\n>\n> twice\n\nafter"
+        );
+        assert_eq!(
+            text("<table><tr><th>a</th><th>b</th></tr><tr><td>1</td><td></td><td>3</td></tr></table>"),
+            "a\tb\n1\t\t3"
+        );
+    }
+
+    #[test]
+    fn link_urls() {
+        let src = "<p><a href=\"mailto:a@example.com\">a@example.com</a> \
+                   <a href=\"https://example.com/\">https://example.com/</a> \
+                   <a href=\"/relative\">relative</a> <a href=\"y\"><img alt=\"pic\"></a></p>";
+        let document = Builder::default().clean(src);
+        assert_eq!(
+            document.to_text(),
+            "a@example.com https://example.com/ relative pic"
+        );
+        assert_eq!(
+            document.to_text_with(TextOptions::new().link_urls(true)),
+            "a@example.com https://example.com/ relative </relative> pic <y>"
+        );
+    }
+}
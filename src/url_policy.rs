@@ -0,0 +1,299 @@
+//! Restricting the hosts that URLs can point at, for [`Builder::url_policies`].
+//!
+//! [`Builder::url_policies`]: ../struct.Builder.html#method.url_policies
+
+use std::fmt;
+use url::{Host, Url};
+
+/// Restrictions on the hosts and ports that a URL attribute can point at.
+///
+/// Hosts are given as patterns:
+///
+/// * `example.com` matches that host, on any port
+/// * `*.example.com` matches every subdomain of `example.com`, but not `example.com` itself
+/// * `*` matches any host
+/// * `127.0.0.1` and `[::1]` match IP addresses
+/// * a port, like `example.com:8443` or `*:443`, only matches URLs on that port
+///
+/// Host names are compared the way browsers see them: case-insensitively, after
+/// converting international names to punycode, and ignoring a trailing dot.
+/// So `bücher.example` and `xn--bcher-kva.example` are the same pattern.
+///
+/// A URL is allowed if it matches none of the denied hosts and, if any hosts were allowed,
+/// one of the allowed hosts. Once hosts are allowed, URLs without a host, like `data:` or
+/// `mailto:` URLs, are rejected too.
+///
+/// Relative URLs that keep the document's own host are left to
+/// [`url_relative`][url_relative]. Scheme-relative URLs, like `//example.com/`,
+/// are checked against the host they name.
+///
+/// [url_relative]: struct.Builder.html#method.url_relative
+///
+/// # Examples
+///
+///     use ammonia::{Builder, UrlPolicy};
+///
+///     # fn main() -> Result<(), url::ParseError> {
+///     let a = Builder::default()
+///         .url_policy("img", "src", UrlPolicy::new().allow_hosts(["*.ourcdn.com"])?)
+///         .url_policy("a", "href", UrlPolicy::new().deny_hosts(["phishing.example"])?)
+///         .clean("<img src=https://img.ourcdn.com/a.png><img src=https://example.com/b.png>\
+///                 <a href=https://phishing.example/>x</a>")
+///         .to_string();
+///     assert_eq!(a, "<img src=\"https://img.ourcdn.com/a.png\"><img><a rel=\"noopener noreferrer\">x</a>");
+///     # Ok(())
+///     # }
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct UrlPolicy {
+    allowed_hosts: Option<Vec<HostPattern>>,
+    denied_hosts: Vec<HostPattern>,
+}
+
+impl UrlPolicy {
+    /// Creates a policy that allows every host.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only allows URLs with one of these hosts, in addition to any that were already allowed.
+    ///
+    /// Returns an error if a pattern isn't a valid host.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::UrlPolicy;
+    ///     use url::Url;
+    ///
+    ///     # fn main() -> Result<(), url::ParseError> {
+    ///     let policy = UrlPolicy::new().allow_hosts(["*.example.com", "example.org:8443"])?;
+    ///     assert!(policy.allows(&Url::parse("https://www.example.com/")?));
+    ///     assert!(!policy.allows(&Url::parse("https://example.com/")?));
+    ///     assert!(policy.allows(&Url::parse("https://example.org:8443/")?));
+    ///     assert!(!policy.allows(&Url::parse("https://example.org/")?));
+    ///     # Ok(())
+    ///     # }
+    pub fn allow_hosts<'b, I: IntoIterator<Item = &'b str>>(
+        mut self,
+        patterns: I,
+    ) -> Result<Self, url::ParseError> {
+        let allowed_hosts = self.allowed_hosts.get_or_insert_with(Vec::new);
+        for pattern in patterns {
+            allowed_hosts.push(HostPattern::parse(pattern)?);
+        }
+        Ok(self)
+    }
+
+    /// Rejects URLs with any of these hosts, in addition to any that were already denied.
+    ///
+    /// Returns an error if a pattern isn't a valid host.
+    ///
+    /// # Examples
+    ///
+    ///     use ammonia::UrlPolicy;
+    ///     use url::Url;
+    ///
+    ///     # fn main() -> Result<(), url::ParseError> {
+    ///     let policy = UrlPolicy::new().deny_hosts(["bücher.example"])?;
+    ///     assert!(!policy.allows(&Url::parse("https://xn--bcher-kva.example./")?));
+    ///     assert!(policy.allows(&Url::parse("https://example.com/")?));
+    ///     # Ok(())
+    ///     # }
+    pub fn deny_hosts<'b, I: IntoIterator<Item = &'b str>>(
+        mut self,
+        patterns: I,
+    ) -> Result<Self, url::ParseError> {
+        for pattern in patterns {
+            self.denied_hosts.push(HostPattern::parse(pattern)?);
+        }
+        Ok(self)
+    }
+
+    /// Returns `true` if the policy allows this URL.
+    pub fn allows(&self, url: &Url) -> bool {
+        let host = url.host();
+        let port = url.port_or_known_default();
+        let matches =
+            |pattern: &HostPattern| host.as_ref().is_some_and(|h| pattern.matches(h, port));
+        !self.denied_hosts.iter().any(matches)
+            && self
+                .allowed_hosts
+                .as_ref()
+                .map_or(true, |allowed| allowed.iter().any(matches))
+    }
+}
+
+/// A host, or a set of hosts, with an optional port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub(crate) struct HostPattern {
+    host: HostMatch,
+    port: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HostMatch {
+    Any,
+    Subdomains(String),
+    Exact(Host),
+}
+
+impl HostPattern {
+    pub(crate) fn parse(pattern: &str) -> Result<Self, url::ParseError> {
+        // IPv6 addresses contain colons, so the port can only come after the brackets.
+        let port_start = if pattern.starts_with('[') {
+            pattern.find(']').map_or(pattern.len(), |i| i + 1)
+        } else {
+            0
+        };
+        let (host, port) = match pattern[port_start..].find(':') {
+            Some(i) => (
+                &pattern[..port_start + i],
+                Some(&pattern[port_start + i + 1..]),
+            ),
+            None => (pattern, None),
+        };
+        let port = match port {
+            None | Some("*") => None,
+            Some(port) if port.bytes().all(|b| b.is_ascii_digit()) => {
+                Some(port.parse().map_err(|_| url::ParseError::InvalidPort)?)
+            }
+            Some(_) => return Err(url::ParseError::InvalidPort),
+        };
+        let host = if host == "*" {
+            HostMatch::Any
+        } else if let Some(domain) = host.strip_prefix("*.") {
+            match Host::parse(domain)? {
+                Host::Domain(domain) => HostMatch::Subdomains(trim_dot(&domain).to_owned()),
+                _ => return Err(url::ParseError::InvalidDomainCharacter),
+            }
+        } else {
+            match Host::parse(host)? {
+                Host::Domain(domain) => {
+                    HostMatch::Exact(Host::Domain(trim_dot(&domain).to_owned()))
+                }
+                ip => HostMatch::Exact(ip),
+            }
+        };
+        Ok(HostPattern { host, port })
+    }
+
+    pub(crate) fn matches(&self, host: &Host<&str>, port: Option<u16>) -> bool {
+        if self.port.is_some() && self.port != port {
+            return false;
+        }
+        match (&self.host, host) {
+            (HostMatch::Any, _) => true,
+            (HostMatch::Subdomains(pattern), Host::Domain(domain)) => trim_dot(domain)
+                .strip_suffix(&pattern[..])
+                .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            (HostMatch::Exact(Host::Domain(pattern)), Host::Domain(domain)) => {
+                pattern == trim_dot(domain)
+            }
+            (HostMatch::Exact(Host::Ipv4(pattern)), Host::Ipv4(ip)) => pattern == ip,
+            (HostMatch::Exact(Host::Ipv6(pattern)), Host::Ipv6(ip)) => pattern == ip,
+            _ => false,
+        }
+    }
+}
+
+fn trim_dot(domain: &str) -> &str {
+    domain.strip_suffix('.').unwrap_or(domain)
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.host {
+            HostMatch::Any => f.write_str("*")?,
+            HostMatch::Subdomains(domain) => write!(f, "*.{domain}")?,
+            HostMatch::Exact(host) => write!(f, "{host}")?,
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for HostPattern {
+    type Error = url::ParseError;
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        HostPattern::parse(&pattern)
+    }
+}
+
+impl From<HostPattern> for String {
+    fn from(pattern: HostPattern) -> String {
+        pattern.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allows(policy: &UrlPolicy, url: &str) -> bool {
+        policy.allows(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn patterns() {
+        for (pattern, normalized) in [
+            ("*", "*"),
+            ("Example.COM", "example.com"),
+            ("example.com.", "example.com"),
+            ("*.bücher.example:443", "*.xn--bcher-kva.example:443"),
+            ("*:*", "*"),
+            ("[::1]:8080", "[::1]:8080"),
+            ("127.0.0.1", "127.0.0.1"),
+        ] {
+            assert_eq!(HostPattern::parse(pattern).unwrap().to_string(), normalized);
+        }
+        for pattern in [
+            "",
+            "*.",
+            "exa mple.com",
+            "example.com:x",
+            "example.com:99999",
+            "*.[::1]",
+        ] {
+            assert!(HostPattern::parse(pattern).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn allowed_hosts() {
+        let policy = UrlPolicy::new()
+            .allow_hosts(["*.ourcdn.com", "example.com:8443", "[::1]"])
+            .unwrap();
+        assert!(allows(&policy, "https://a.ourcdn.com/x.png"));
+        assert!(allows(&policy, "https://a.b.OURCDN.com./x.png"));
+        assert!(!allows(&policy, "https://ourcdn.com/x.png"));
+        assert!(!allows(&policy, "https://evilourcdn.com/x.png"));
+        assert!(!allows(&policy, "https://ourcdn.com.evil.com/x.png"));
+        assert!(allows(&policy, "https://example.com:8443/"));
+        assert!(!allows(&policy, "https://example.com/"));
+        assert!(allows(&policy, "http://[0:0::1]:1234/"));
+        assert!(!allows(&policy, "data:image/png;base64,AAAA"));
+    }
+
+    #[test]
+    fn denied_hosts() {
+        let policy = UrlPolicy::new()
+            .deny_hosts(["phishing.example", "*.phishing.example", "*:22"])
+            .unwrap();
+        assert!(!allows(&policy, "https://phishing.example:8080/"));
+        assert!(!allows(&policy, "https://login.phishing.example/"));
+        assert!(!allows(&policy, "ssh://example.com:22/"));
+        assert!(allows(&policy, "https://example.com/"));
+        assert!(allows(&policy, "mailto:someone@phishing.example"));
+    }
+}